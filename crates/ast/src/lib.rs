@@ -7,6 +7,8 @@ pub trait TypedSyntaxNode {
     fn cast(node: SyntaxNode) -> Option<Self>
     where
         Self: Sized;
+
+    fn syntax(&self) -> &SyntaxNode;
 }
 
 #[macro_use]
@@ -14,7 +16,7 @@ mod macros {
     macro_rules! impl_typed_syntax_node {
         ($struct_name:ident) => {
             #[allow(dead_code)]
-            #[derive(Debug)]
+            #[derive(Debug, Clone)]
             pub struct $struct_name(SyntaxNode);
             impl TypedSyntaxNode for $struct_name {
                 fn cast(node: SyntaxNode) -> Option<Self>
@@ -28,6 +30,10 @@ mod macros {
                         None
                     }
                 }
+
+                fn syntax(&self) -> &SyntaxNode {
+                    &self.0
+                }
             }
             const _: () = {
                 // Simply a check that struct_name is a variant of SyntaxConstruct
@@ -48,12 +54,17 @@ impl_typed_syntax_node!(ForLoop);
 impl_typed_syntax_node!(WhileLoop);
 impl_typed_syntax_node!(BreakStmt);
 impl_typed_syntax_node!(ContinueStmt);
+impl_typed_syntax_node!(ReturnStmt);
 impl_typed_syntax_node!(SwitchStmt);
 impl_typed_syntax_node!(TryStmt);
 impl_typed_syntax_node!(UnwindProtectStmt);
 impl_typed_syntax_node!(StringLiteral);
+impl_typed_syntax_node!(FnHandle);
+impl_typed_syntax_node!(FieldExpr);
+impl_typed_syntax_node!(EndIndex);
+impl_typed_syntax_node!(Declaration);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VariableDef(SyntaxNode);
 
 impl VariableDef {
@@ -77,6 +88,17 @@ impl Root {
         self.0.children().filter_map(Stmt::cast)
     }
 
+    /// Whether the file closes its blocks with their own keywords, such as `endif`, rather than
+    /// with `end`. This is true as soon as one block does.
+    #[must_use]
+    pub fn uses_specific_terminators(&self) -> bool {
+        self.0
+            .descendants()
+            .filter_map(BlockStmt::cast)
+            .filter_map(|block| block.terminator())
+            .any(|terminator| terminator.kind() != TokenKind::EndKw.into())
+    }
+
     pub fn get_variable_definitions(&self) -> impl Iterator<Item = VariableDef> {
         self.stmts().filter_map(|stmt| {
             if let Stmt::VariableDef(var_def) = stmt {
@@ -103,6 +125,17 @@ impl Root {
 }
 
 impl VariableDef {
+    #[must_use]
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    /// Returns the left-hand side of the assignment, e.g. `[a, b]` in `[a, b] = f(x)`.
+    #[must_use]
+    pub fn lhs(&self) -> Option<Expr> {
+        self.0.children().find_map(Expr::cast)
+    }
+
     /// Returns the name of the defined variable, or None if the LHS is not a `VariableRef`.
     pub fn name(&self) -> Option<SyntaxToken> {
         self.0
@@ -141,6 +174,9 @@ impl Expr {
                 SyntaxConstruct::CallExpr => Self::CallExpr(CallExpr(node)),
                 SyntaxConstruct::PostfixExpr => Self::PostfixExpr(PostfixExpr(node)),
                 SyntaxConstruct::StringLiteral => Self::StringLiteral(StringLiteral(node)),
+                SyntaxConstruct::FnHandle => Self::FnHandle(FnHandle(node)),
+                SyntaxConstruct::FieldExpr => Self::FieldExpr(FieldExpr(node)),
+                SyntaxConstruct::EndIndex => Self::EndIndex(EndIndex(node)),
                 SyntaxConstruct::Root => unreachable!(),
                 SyntaxConstruct::Error
                 | SyntaxConstruct::Block
@@ -150,9 +186,11 @@ impl Expr {
                 | SyntaxConstruct::WhileLoop
                 | SyntaxConstruct::BreakStmt
                 | SyntaxConstruct::ContinueStmt
+                | SyntaxConstruct::ReturnStmt
                 | SyntaxConstruct::SwitchStmt
                 | SyntaxConstruct::TryStmt
-                | SyntaxConstruct::UnwindProtectStmt => return None,
+                | SyntaxConstruct::UnwindProtectStmt
+                | SyntaxConstruct::Declaration => return None,
             };
         } else {
             return None;
@@ -162,7 +200,32 @@ impl Expr {
     }
 }
 
+impl Expr {
+    #[must_use]
+    pub fn syntax(&self) -> &SyntaxNode {
+        match self {
+            Self::BinaryExpr(BinaryExpr(node))
+            | Self::Literal(Literal(node))
+            | Self::ParenExpr(ParenExpr(node))
+            | Self::UnaryExpr(UnaryExpr(node))
+            | Self::VariableRef(VariableRef(node))
+            | Self::MatrixExpr(MatrixExpr(node))
+            | Self::CallExpr(CallExpr(node))
+            | Self::PostfixExpr(PostfixExpr(node))
+            | Self::StringLiteral(StringLiteral(node))
+            | Self::FnHandle(FnHandle(node))
+            | Self::FieldExpr(FieldExpr(node))
+            | Self::EndIndex(EndIndex(node)) => node,
+        }
+    }
+}
+
 impl BinaryExpr {
+    #[must_use]
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
     pub fn lhs(&self) -> Option<Expr> {
         self.0.children().find_map(Expr::cast)
     }
@@ -339,10 +402,135 @@ impl StringLiteral {
     }
 }
 
+impl FnHandle {
+    /// Returns the name of the function the handle refers to.
+    #[must_use]
+    pub fn name(&self) -> Option<SyntaxToken> {
+        self.0
+            .children_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+            .find(|token| token.kind() == TokenKind::Identifier.into())
+    }
+}
+
+impl FieldExpr {
+    /// Returns the struct whose field is accessed.
+    #[must_use]
+    pub fn expr(&self) -> Option<Expr> {
+        self.0.children().find_map(Expr::cast)
+    }
+
+    /// Returns the name of the field, which is missing while it is being typed.
+    #[must_use]
+    pub fn field(&self) -> Option<SyntaxToken> {
+        self.0
+            .children_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+            .find(|token| token.kind() == TokenKind::Identifier.into())
+    }
+}
+
+impl Declaration {
+    /// Whether the variables are declared `global`, rather than `persistent`.
+    #[must_use]
+    pub fn is_global(&self) -> bool {
+        self.0
+            .first_token()
+            .is_some_and(|token| token.kind() == TokenKind::GlobalKw.into())
+    }
+
+    /// Returns the declared variables, in order.
+    #[must_use]
+    pub fn variables(&self) -> Vec<VariableRef> {
+        self.0
+            .children()
+            .filter_map(|node| match Expr::cast(node)? {
+                Expr::VariableRef(var) => Some(var),
+                Expr::BinaryExpr(init) => match init.lhs()? {
+                    Expr::VariableRef(var) => Some(var),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the initial values given to the variables, as `0` in `persistent n = 0`.
+    pub fn values(&self) -> impl Iterator<Item = Expr> {
+        self.0
+            .children()
+            .filter_map(VariableDef::cast)
+            .filter_map(|init| init.value())
+    }
+}
+
 impl FnDef {
+    /// Returns the signature of the function, e.g. `[a, b] = f(x, y)`.
+    #[must_use]
+    pub fn header(&self) -> Option<Expr> {
+        self.0.children().find_map(Expr::cast)
+    }
+
+    /// Returns the name of the function.
+    #[must_use]
+    pub fn name(&self) -> Option<SyntaxToken> {
+        match self.header_call()? {
+            Expr::CallExpr(call) => match call.func()? {
+                Expr::VariableRef(var) => var.name(),
+                _ => None,
+            },
+            Expr::VariableRef(var) => var.name(),
+            _ => None,
+        }
+    }
+
+    /// Returns the named input parameters of the function.
+    ///
+    /// Ignored parameters (`~`) are left out.
+    #[must_use]
+    pub fn params(&self) -> Vec<VariableRef> {
+        match self.header_call() {
+            Some(Expr::CallExpr(call)) => call
+                .args()
+                .filter_map(|arg| match arg {
+                    Expr::VariableRef(var) => Some(var),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the output variables of the function.
+    #[must_use]
+    pub fn outputs(&self) -> Vec<VariableRef> {
+        let Some(Expr::BinaryExpr(header)) = self.header() else {
+            return Vec::new();
+        };
+        match header.lhs() {
+            Some(Expr::VariableRef(var)) => vec![var],
+            Some(Expr::MatrixExpr(outputs)) => outputs
+                .elements()
+                .filter_map(|output| match output {
+                    Expr::VariableRef(var) => Some(var),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn body(&self) -> impl Iterator<Item = Stmt> {
         self.0.children().filter_map(Stmt::cast).skip(1)
     }
+
+    /// The part of the header after the outputs, e.g. `f(x, y)` in `[a, b] = f(x, y)`.
+    fn header_call(&self) -> Option<Expr> {
+        match self.header()? {
+            Expr::BinaryExpr(header) => header.rhs(),
+            header => Some(header),
+        }
+    }
 }
 
 impl IfStmt {
@@ -354,6 +542,19 @@ impl IfStmt {
     pub fn body(&self) -> impl Iterator<Item = Stmt> {
         self.0.children().filter_map(Stmt::cast).skip(1)
     }
+
+    /// Returns the `if`, `elseif` and `else` branches, in order. Only the `else` branch has no
+    /// condition.
+    #[must_use]
+    pub fn branches(&self) -> Vec<Branch> {
+        segments(
+            &self.0,
+            &[TokenKind::IfKw, TokenKind::ElseIfKw, TokenKind::ElseKw],
+        )
+        .into_iter()
+        .map(|(keyword, nodes)| Branch::new(keyword.kind() != TokenKind::ElseKw.into(), nodes))
+        .collect()
+    }
 }
 
 impl WhileLoop {
@@ -368,6 +569,12 @@ impl WhileLoop {
 }
 
 impl ForLoop {
+    /// Returns the loop variable and the values it iterates over, as in `i = 1:10`.
+    #[must_use]
+    pub fn header(&self) -> Option<VariableDef> {
+        self.0.children().next().and_then(VariableDef::cast)
+    }
+
     pub fn body(&self) -> impl Iterator<Item = Stmt> {
         self.0.children().filter_map(Stmt::cast).skip(1)
     }
@@ -382,18 +589,157 @@ impl SwitchStmt {
     pub fn body(&self) -> impl Iterator<Item = Stmt> {
         self.0.children().filter_map(Stmt::cast).skip(1)
     }
+
+    /// Returns the `case` and `otherwise` branches, in order. The condition of a `case` branch is
+    /// the value it matches; the `otherwise` branch has none.
+    #[must_use]
+    pub fn cases(&self) -> Vec<Branch> {
+        segments(&self.0, &[TokenKind::CaseKw, TokenKind::OtherwiseKw])
+            .into_iter()
+            .map(|(keyword, nodes)| Branch::new(keyword.kind() == TokenKind::CaseKw.into(), nodes))
+            .collect()
+    }
 }
 
 impl TryStmt {
+    /// Returns the statements of both the `try` and the `catch` parts.
     pub fn body(&self) -> impl Iterator<Item = Stmt> {
         self.0.children().filter_map(Stmt::cast)
     }
+
+    /// Returns the statements before `catch`.
+    #[must_use]
+    pub fn try_body(&self) -> Vec<Stmt> {
+        self.part(TokenKind::TryKw)
+            .into_iter()
+            .filter_map(Stmt::cast)
+            .collect()
+    }
+
+    /// Returns the statements after `catch`, leaving out the error variable.
+    #[must_use]
+    pub fn catch_body(&self) -> Vec<Stmt> {
+        let skip = usize::from(self.catch_var().is_some());
+        self.part(TokenKind::CatchKw)
+            .into_iter()
+            .filter_map(Stmt::cast)
+            .skip(skip)
+            .collect()
+    }
+
+    /// Returns the variable the error is assigned to, as `err` in `catch err`.
+    ///
+    /// It has to be on the same line as `catch`; on a line of its own it is a statement.
+    #[must_use]
+    pub fn catch_var(&self) -> Option<VariableRef> {
+        let catch_kw = self
+            .0
+            .children_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+            .find(|token| token.kind() == TokenKind::CatchKw.into())?;
+
+        let mut element = catch_kw.next_sibling_or_token();
+        while let Some(SyntaxElement::Token(token)) = &element {
+            if token.kind() != TokenKind::Whitespace.into() {
+                return None;
+            }
+            element = token.next_sibling_or_token();
+        }
+        VariableRef::cast(element?.into_node()?)
+    }
+
+    fn part(&self, keyword: TokenKind) -> Vec<SyntaxNode> {
+        segments(&self.0, &[TokenKind::TryKw, TokenKind::CatchKw])
+            .into_iter()
+            .find(|(token, _)| token.kind() == keyword.into())
+            .map(|(_, nodes)| nodes)
+            .unwrap_or_default()
+    }
 }
 
 impl UnwindProtectStmt {
+    /// Returns the statements of both the protected and the cleanup parts.
     pub fn body(&self) -> impl Iterator<Item = Stmt> {
         self.0.children().filter_map(Stmt::cast)
     }
+
+    /// Returns the statements before `unwind_protect_cleanup`.
+    #[must_use]
+    pub fn protected_body(&self) -> Vec<Stmt> {
+        self.part(TokenKind::UnwindProtectKw)
+    }
+
+    /// Returns the statements after `unwind_protect_cleanup`, which always run.
+    #[must_use]
+    pub fn cleanup_body(&self) -> Vec<Stmt> {
+        self.part(TokenKind::UnwindProtectCleanupKw)
+    }
+
+    fn part(&self, keyword: TokenKind) -> Vec<Stmt> {
+        segments(
+            &self.0,
+            &[
+                TokenKind::UnwindProtectKw,
+                TokenKind::UnwindProtectCleanupKw,
+            ],
+        )
+        .into_iter()
+        .find(|(token, _)| token.kind() == keyword.into())
+        .map(|(_, nodes)| nodes.into_iter().filter_map(Stmt::cast).collect())
+        .unwrap_or_default()
+    }
+}
+
+/// One branch of an `if` or `switch` statement.
+#[derive(Debug, Clone)]
+pub struct Branch {
+    condition: Option<Expr>,
+    body: Vec<Stmt>,
+}
+
+impl Branch {
+    fn new(has_condition: bool, nodes: Vec<SyntaxNode>) -> Self {
+        let mut nodes = nodes.into_iter();
+        let condition = if has_condition {
+            nodes.next().and_then(Expr::cast)
+        } else {
+            None
+        };
+        Self {
+            condition,
+            body: nodes.filter_map(Stmt::cast).collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn condition(&self) -> Option<&Expr> {
+        self.condition.as_ref()
+    }
+
+    #[must_use]
+    pub fn body(&self) -> &[Stmt] {
+        &self.body
+    }
+}
+
+/// Splits the children of a block into the parts introduced by each of the given keywords.
+fn segments(node: &SyntaxNode, keywords: &[TokenKind]) -> Vec<(SyntaxToken, Vec<SyntaxNode>)> {
+    let mut segments: Vec<(SyntaxToken, Vec<SyntaxNode>)> = Vec::new();
+    for element in node.children_with_tokens() {
+        match element {
+            SyntaxElement::Token(token) => {
+                if matches!(token.kind(), SyntaxKind::LexToken(kind) if keywords.contains(&kind)) {
+                    segments.push((token, Vec::new()));
+                }
+            }
+            SyntaxElement::Node(node) => {
+                if let Some((_, nodes)) = segments.last_mut() {
+                    nodes.push(node);
+                }
+            }
+        }
+    }
+    segments
 }
 
 impl MatrixExpr {
@@ -402,7 +748,75 @@ impl MatrixExpr {
     }
 }
 
-#[derive(Debug)]
+/// Any statement that opens a block closed by `end` or by its own `endXXX` keyword.
+#[derive(Debug, Clone)]
+pub struct BlockStmt(SyntaxNode);
+
+impl BlockStmt {
+    #[must_use]
+    pub fn cast(node: SyntaxNode) -> Option<Self> {
+        match node.kind() {
+            SyntaxKind::SyntaxConstruct(
+                SyntaxConstruct::FnDef
+                | SyntaxConstruct::IfStmt
+                | SyntaxConstruct::ForLoop
+                | SyntaxConstruct::WhileLoop
+                | SyntaxConstruct::SwitchStmt
+                | SyntaxConstruct::TryStmt
+                | SyntaxConstruct::UnwindProtectStmt,
+            ) => Some(Self(node)),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    /// Returns the keyword that opens the block, such as `if` or `function`.
+    #[must_use]
+    pub fn opener(&self) -> Option<SyntaxToken> {
+        self.0.first_token()
+    }
+
+    /// Returns the token closing the block, or None if the block is unterminated.
+    ///
+    /// This may be a terminator that does not belong to this kind of block, e.g. an `endfor`
+    /// closing an `if`.
+    #[must_use]
+    pub fn terminator(&self) -> Option<SyntaxToken> {
+        self.0
+            .children_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+            .filter(|token| {
+                !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia())
+            })
+            .last()
+            .filter(|token| {
+                matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_block_terminator())
+            })
+    }
+
+    /// Returns the `endXXX` keyword specific to this kind of block.
+    #[must_use]
+    pub fn specific_terminator(&self) -> TokenKind {
+        match self.0.kind() {
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::FnDef) => TokenKind::EndFnKw,
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::IfStmt) => TokenKind::EndIfKw,
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::ForLoop) => TokenKind::EndForKw,
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::WhileLoop) => TokenKind::EndWhileKw,
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::SwitchStmt) => TokenKind::EndSwitchKw,
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::TryStmt) => TokenKind::EndTryKw,
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::UnwindProtectStmt) => {
+                TokenKind::EndUnwindProtectKw
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Stmt {
     VariableDef(VariableDef),
     FnDef(FnDef),
@@ -411,9 +825,11 @@ pub enum Stmt {
     WhileLoop(WhileLoop),
     BreakStmt(BreakStmt),
     ContinueStmt(ContinueStmt),
+    ReturnStmt(ReturnStmt),
     SwitchStmt(SwitchStmt),
     TryStmt(TryStmt),
     UnwindProtectStmt(UnwindProtectStmt),
+    Declaration(Declaration),
     Expr(Expr),
 }
 
@@ -436,6 +852,9 @@ impl Stmt {
             SyntaxKind::SyntaxConstruct(SyntaxConstruct::ContinueStmt) => {
                 Self::ContinueStmt(ContinueStmt(node))
             }
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::ReturnStmt) => {
+                Self::ReturnStmt(ReturnStmt(node))
+            }
             SyntaxKind::SyntaxConstruct(SyntaxConstruct::SwitchStmt) => {
                 Self::SwitchStmt(SwitchStmt(node))
             }
@@ -443,13 +862,35 @@ impl Stmt {
             SyntaxKind::SyntaxConstruct(SyntaxConstruct::UnwindProtectStmt) => {
                 Self::UnwindProtectStmt(UnwindProtectStmt(node))
             }
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::Declaration) => {
+                Self::Declaration(Declaration(node))
+            }
             _ => Self::Expr(Expr::cast(node)?),
         };
         Some(result)
     }
+
+    #[must_use]
+    pub fn syntax(&self) -> &SyntaxNode {
+        match self {
+            Self::VariableDef(VariableDef(node))
+            | Self::FnDef(FnDef(node))
+            | Self::IfStmt(IfStmt(node))
+            | Self::ForLoop(ForLoop(node))
+            | Self::WhileLoop(WhileLoop(node))
+            | Self::BreakStmt(BreakStmt(node))
+            | Self::ContinueStmt(ContinueStmt(node))
+            | Self::ReturnStmt(ReturnStmt(node))
+            | Self::SwitchStmt(SwitchStmt(node))
+            | Self::TryStmt(TryStmt(node))
+            | Self::UnwindProtectStmt(UnwindProtectStmt(node))
+            | Self::Declaration(Declaration(node)) => node,
+            Self::Expr(expr) => expr.syntax(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expr {
     BinaryExpr(BinaryExpr),
     Literal(Literal),
@@ -460,18 +901,21 @@ pub enum Expr {
     CallExpr(CallExpr),
     PostfixExpr(PostfixExpr),
     StringLiteral(StringLiteral),
+    FnHandle(FnHandle),
+    FieldExpr(FieldExpr),
+    EndIndex(EndIndex),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BinaryExpr(SyntaxNode);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Literal(SyntaxNode);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParenExpr(SyntaxNode);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UnaryExpr(SyntaxNode);
 
 #[cfg(test)]
@@ -487,9 +931,9 @@ mod tests {
 
     #[test]
     fn get_variable_defs() {
-        let input = r#"a = 12;
+        let input = r"a = 12;
 b = a - 1
-c = a * b"#;
+c = a * b";
         let root = get_root(input);
         let v: Vec<_> = root.get_variable_definitions().collect();
         let output = format!("{v:?}");
@@ -502,9 +946,9 @@ c = a * b"#;
 
     #[test]
     fn get_variable_refs() {
-        let input = r#"a = 12;
+        let input = r"a = 12;
 a + 3
-b = a - 1"#;
+b = a - 1";
         let root = get_root(input);
         let v = root.get_variable_references();
 
@@ -515,4 +959,118 @@ b = a - 1"#;
 
         expected_output.assert_eq(&output);
     }
+
+    fn get_fn_def(input: &str) -> FnDef {
+        let root = get_root(input);
+        let Some(Stmt::FnDef(fn_def)) = root.stmts().next() else {
+            panic!("expected a function definition");
+        };
+        fn_def
+    }
+
+    fn names(vars: &[VariableRef]) -> Vec<String> {
+        vars.iter()
+            .map(|var| var.name().unwrap().text().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn get_fn_def_signature() {
+        let fn_def = get_fn_def("function [a, b] = f(x, y)\n  a = x;\n  b = y;\nend");
+
+        assert_eq!(fn_def.name().unwrap().text(), "f");
+        assert_eq!(names(&fn_def.params()), ["x", "y"]);
+        assert_eq!(names(&fn_def.outputs()), ["a", "b"]);
+        assert_eq!(fn_def.body().count(), 2);
+    }
+
+    #[test]
+    fn get_fn_def_single_output() {
+        let fn_def = get_fn_def("function y = f(x)\n  y = x\nend");
+
+        assert_eq!(fn_def.name().unwrap().text(), "f");
+        assert_eq!(names(&fn_def.params()), ["x"]);
+        assert_eq!(names(&fn_def.outputs()), ["y"]);
+    }
+
+    #[test]
+    fn get_fn_def_without_parens() {
+        let fn_def = get_fn_def("function f\n  x = 1\nend");
+
+        assert_eq!(fn_def.name().unwrap().text(), "f");
+        assert!(fn_def.params().is_empty());
+        assert!(fn_def.outputs().is_empty());
+    }
+
+    fn texts(stmts: &[Stmt]) -> Vec<String> {
+        stmts
+            .iter()
+            .map(|stmt| {
+                let node = match stmt {
+                    Stmt::VariableDef(var_def) => var_def.syntax(),
+                    Stmt::Expr(expr) => expr.syntax(),
+                    _ => unreachable!(),
+                };
+                node.text().to_string().trim().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn get_if_branches() {
+        let root = get_root("if a\n  x = 1;\nelseif b\n  x = 2;\nelse\n  x = 3;\nend");
+        let Some(Stmt::IfStmt(if_stmt)) = root.stmts().next() else {
+            unreachable!()
+        };
+
+        let branches = if_stmt.branches();
+        let conditions: Vec<_> = branches
+            .iter()
+            .map(|branch| branch.condition().map(|c| c.syntax().text().to_string()))
+            .collect();
+        assert_eq!(
+            conditions,
+            [Some("a\n  ".to_string()), Some("b\n  ".to_string()), None]
+        );
+        assert_eq!(texts(branches[1].body()), ["x = 2;"]);
+        assert_eq!(texts(branches[2].body()), ["x = 3;"]);
+    }
+
+    #[test]
+    fn get_switch_cases() {
+        let root = get_root("switch x\n  case 1\n    y = 1;\n  otherwise\n    y = 2;\nend");
+        let Some(Stmt::SwitchStmt(switch)) = root.stmts().next() else {
+            unreachable!()
+        };
+
+        let cases = switch.cases();
+        assert_eq!(cases.len(), 2);
+        assert!(cases[0].condition().is_some());
+        assert_eq!(texts(cases[0].body()), ["y = 1;"]);
+        assert!(cases[1].condition().is_none());
+        assert_eq!(texts(cases[1].body()), ["y = 2;"]);
+    }
+
+    #[test]
+    fn get_try_catch_parts() {
+        let root = get_root("try\n  x = 1;\ncatch err\n  x = 2;\nend");
+        let Some(Stmt::TryStmt(try_stmt)) = root.stmts().next() else {
+            unreachable!()
+        };
+
+        assert_eq!(texts(&try_stmt.try_body()), ["x = 1;"]);
+        assert_eq!(try_stmt.catch_var().unwrap().name().unwrap().text(), "err");
+        assert_eq!(texts(&try_stmt.catch_body()), ["x = 2;"]);
+    }
+
+    #[test]
+    fn catch_var_must_be_on_the_catch_line() {
+        let root = get_root("try\n  x = 1;\ncatch\n  err\nend");
+        let Some(Stmt::TryStmt(try_stmt)) = root.stmts().next() else {
+            unreachable!()
+        };
+
+        assert!(try_stmt.catch_var().is_none());
+        assert_eq!(texts(&try_stmt.catch_body()), ["err"]);
+    }
 }
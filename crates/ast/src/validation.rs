@@ -1,6 +1,6 @@
-use crate::Literal;
+use crate::{BlockStmt, Expr, FnDef, Literal, Stmt, TypedSyntaxNode};
 use std::fmt;
-use syntax::SyntaxNode;
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, TokenKind};
 use text_size::TextRange;
 
 #[allow(clippy::module_name_repetitions)]
@@ -11,10 +11,96 @@ pub struct ValidationError {
 }
 
 impl ValidationError {
+    /// The codes of every kind of error.
+    pub const CODES: [&'static str; 6] = [
+        "number-too-large",
+        "mismatched-terminator",
+        "missing-terminator",
+        "terminator-style",
+        "output-not-suppressed",
+        "negation-style",
+    ];
+
     #[must_use]
     pub fn range(&self) -> TextRange {
         self.range
     }
+
+    /// Returns the range of the keyword opening the block the error refers to, if any.
+    #[must_use]
+    pub fn opener_range(&self) -> Option<TextRange> {
+        match self.kind {
+            ValidationErrorKind::MismatchedTerminator { opener, .. }
+            | ValidationErrorKind::MissingTerminator { opener, .. } => Some(opener),
+            ValidationErrorKind::NumberLiteralTooLarge
+            | ValidationErrorKind::TerminatorStyle { .. }
+            | ValidationErrorKind::OutputNotSuppressed
+            | ValidationErrorKind::NegationStyle { .. } => None,
+        }
+    }
+
+    /// Returns the token the error asks for: the terminator of a block, or the operator of the
+    /// preferred negation style.
+    #[must_use]
+    pub fn expected(&self) -> Option<TokenKind> {
+        match self.kind {
+            ValidationErrorKind::MismatchedTerminator { expected, .. }
+            | ValidationErrorKind::MissingTerminator { expected, .. }
+            | ValidationErrorKind::TerminatorStyle { expected, .. }
+            | ValidationErrorKind::NegationStyle { expected, .. } => Some(expected),
+            ValidationErrorKind::NumberLiteralTooLarge
+            | ValidationErrorKind::OutputNotSuppressed => None,
+        }
+    }
+
+    #[must_use]
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            ValidationErrorKind::TerminatorStyle { .. }
+            | ValidationErrorKind::OutputNotSuppressed
+            | ValidationErrorKind::NegationStyle { .. } => Severity::Warning,
+            ValidationErrorKind::NumberLiteralTooLarge
+            | ValidationErrorKind::MismatchedTerminator { .. }
+            | ValidationErrorKind::MissingTerminator { .. } => Severity::Error,
+        }
+    }
+
+    /// A stable name for the kind of error, which editors show and quick fixes are tied to.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            ValidationErrorKind::NumberLiteralTooLarge => "number-too-large",
+            ValidationErrorKind::MismatchedTerminator { .. } => "mismatched-terminator",
+            ValidationErrorKind::MissingTerminator { .. } => "missing-terminator",
+            ValidationErrorKind::TerminatorStyle { .. } => "terminator-style",
+            ValidationErrorKind::OutputNotSuppressed => "output-not-suppressed",
+            ValidationErrorKind::NegationStyle { .. } => "negation-style",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Which keywords close blocks when checking a file with [`validate_terminator_style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminatorStyle {
+    /// Blocks are closed by their own keyword, such as `endif` or `endfunction`.
+    Specific,
+    /// Blocks are closed by a plain `end`.
+    Plain,
+}
+
+/// Which operators negate when checking a file with [`validate_negation_style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegationStyle {
+    /// `~` and `~=`, which MATLAB understands too.
+    Tilde,
+    /// `!` and `!=`, which only Octave understands.
+    Exclamation,
 }
 
 impl fmt::Display for ValidationErrorKind {
@@ -25,6 +111,39 @@ impl fmt::Display for ValidationErrorKind {
                 "number literal is larger than an integer's maximum value, {}",
                 u64::MAX,
             ),
+            Self::MismatchedTerminator {
+                expected, found, ..
+            } => write!(
+                f,
+                "'{}' does not match '{}', expected '{}' or 'end'",
+                keyword(*found),
+                opening_keyword(*expected),
+                keyword(*expected),
+            ),
+            Self::MissingTerminator { expected, .. } => write!(
+                f,
+                "'{}' is never closed, expected '{}' or 'end'",
+                opening_keyword(*expected),
+                keyword(*expected),
+            ),
+            Self::TerminatorStyle { expected, found } => write!(
+                f,
+                "expected '{}' instead of '{}'",
+                keyword(*expected),
+                keyword(*found),
+            ),
+            Self::OutputNotSuppressed => {
+                write!(
+                    f,
+                    "the result of the assignment is printed; end it with ';'"
+                )
+            }
+            Self::NegationStyle { expected, found } => write!(
+                f,
+                "expected '{}' instead of '{}'",
+                operator(*expected),
+                operator(*found),
+            ),
         }
     }
 }
@@ -32,6 +151,25 @@ impl fmt::Display for ValidationErrorKind {
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum ValidationErrorKind {
     NumberLiteralTooLarge,
+    MismatchedTerminator {
+        expected: TokenKind,
+        found: TokenKind,
+        opener: TextRange,
+    },
+    MissingTerminator {
+        expected: TokenKind,
+        opener: TextRange,
+    },
+    TerminatorStyle {
+        expected: TokenKind,
+        found: TokenKind,
+    },
+    /// An assignment inside a function that is not ended by `;`, so that it prints its result.
+    OutputNotSuppressed,
+    NegationStyle {
+        expected: TokenKind,
+        found: TokenKind,
+    },
 }
 
 impl fmt::Display for ValidationError {
@@ -50,15 +188,138 @@ impl fmt::Display for ValidationError {
 pub fn validate(node: &SyntaxNode) -> Vec<ValidationError> {
     let mut errors = Vec::new();
 
+    // Octave lets functions go without `endfunction`, but only if none of the functions in the
+    // file has one.
+    let functions_need_terminator = node
+        .descendants()
+        .filter(|node| node.kind() == SyntaxConstruct::FnDef.into())
+        .filter_map(BlockStmt::cast)
+        .any(|fn_def| fn_def.terminator().is_some());
+
     for node in node.descendants() {
-        if let Some(literal) = Literal::cast(node) {
+        if let Some(literal) = Literal::cast(node.clone()) {
             validate_literal(&literal, &mut errors);
+        } else if let Some(block) = BlockStmt::cast(node.clone()) {
+            validate_block_terminator(&block, functions_need_terminator, &mut errors);
+        }
+        if let Some(fn_def) = FnDef::cast(node) {
+            validate_output_suppression(fn_def.body(), &mut errors);
         }
     }
 
     errors
 }
 
+/// Checks that every terminated block in the file is closed following the given style.
+///
+/// This is a style check, not a correctness one: it is not part of [`validate`], and the errors
+/// it returns are warnings.
+#[must_use]
+pub fn validate_terminator_style(
+    node: &SyntaxNode,
+    style: TerminatorStyle,
+) -> Vec<ValidationError> {
+    node.descendants()
+        .filter_map(BlockStmt::cast)
+        .filter_map(|block| {
+            let terminator = block.terminator()?;
+            let SyntaxKind::LexToken(found) = terminator.kind() else {
+                unreachable!()
+            };
+            let expected = match style {
+                TerminatorStyle::Specific => block.specific_terminator(),
+                TerminatorStyle::Plain => TokenKind::EndKw,
+            };
+            // Mismatched terminators are already reported by `validate`.
+            let matches_block = found == TokenKind::EndKw || found == block.specific_terminator();
+
+            (matches_block && found != expected).then(|| ValidationError {
+                kind: ValidationErrorKind::TerminatorStyle { expected, found },
+                range: terminator.text_range(),
+            })
+        })
+        .collect()
+}
+
+/// Checks that negations use the operators of the given style.
+///
+/// Like [`validate_terminator_style`], this is a style check whose errors are warnings.
+#[must_use]
+pub fn validate_negation_style(node: &SyntaxNode, style: NegationStyle) -> Vec<ValidationError> {
+    node.descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter_map(|token| {
+            let SyntaxKind::LexToken(found) = token.kind() else {
+                return None;
+            };
+            let expected = match (style, found) {
+                (NegationStyle::Tilde, TokenKind::NotEquals) => TokenKind::TildeEquals,
+                (NegationStyle::Exclamation, TokenKind::TildeEquals) => TokenKind::NotEquals,
+                (NegationStyle::Tilde, TokenKind::Not) => TokenKind::Tilde,
+                (NegationStyle::Exclamation, TokenKind::Tilde) => TokenKind::Not,
+                _ => return None,
+            };
+            // `~` also stands for ignored outputs and parameters, which `!` cannot.
+            let is_negation = matches!(found, TokenKind::NotEquals | TokenKind::TildeEquals)
+                || token
+                    .parent()
+                    .filter(|parent| parent.kind() == SyntaxConstruct::PrefixExpr.into())
+                    .and_then(Expr::cast)
+                    .and_then(|expr| match expr {
+                        Expr::UnaryExpr(expr) if expr.expr().is_some() => expr.op(),
+                        _ => None,
+                    })
+                    .is_some_and(|op| op == token);
+            is_negation.then(|| ValidationError {
+                kind: ValidationErrorKind::NegationStyle { expected, found },
+                range: token.text_range(),
+            })
+        })
+        .collect()
+}
+
+/// Reports the assignments in a function body that print their result. Scripts often print on
+/// purpose, but in a function it is almost always left over from debugging.
+fn validate_output_suppression(
+    stmts: impl Iterator<Item = Stmt>,
+    errors: &mut Vec<ValidationError>,
+) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::VariableDef(def) => {
+                let mut tokens = def
+                    .syntax()
+                    .descendants_with_tokens()
+                    .filter_map(SyntaxElement::into_token)
+                    .filter(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()));
+                let Some(first) = tokens.next() else {
+                    continue;
+                };
+                let last = tokens.last().unwrap_or_else(|| first.clone());
+                if last.kind() != TokenKind::Semicolon.into() {
+                    errors.push(ValidationError {
+                        kind: ValidationErrorKind::OutputNotSuppressed,
+                        range: first.text_range().cover(last.text_range()),
+                    });
+                }
+            }
+            Stmt::IfStmt(stmt) => validate_output_suppression(stmt.body(), errors),
+            Stmt::ForLoop(stmt) => validate_output_suppression(stmt.body(), errors),
+            Stmt::WhileLoop(stmt) => validate_output_suppression(stmt.body(), errors),
+            Stmt::SwitchStmt(stmt) => validate_output_suppression(stmt.body(), errors),
+            Stmt::TryStmt(stmt) => validate_output_suppression(stmt.body(), errors),
+            Stmt::UnwindProtectStmt(stmt) => validate_output_suppression(stmt.body(), errors),
+            // Functions that follow an unterminated one are checked on their own.
+            Stmt::FnDef(_)
+            | Stmt::BreakStmt(_)
+            | Stmt::ContinueStmt(_)
+            | Stmt::ReturnStmt(_)
+            | Stmt::Declaration(_)
+            | Stmt::Expr(_) => {}
+        }
+    }
+}
+
 fn validate_literal(literal: &Literal, errors: &mut Vec<ValidationError>) {
     if literal.parse().is_none() {
         errors.push(ValidationError {
@@ -68,6 +329,75 @@ fn validate_literal(literal: &Literal, errors: &mut Vec<ValidationError>) {
     }
 }
 
+fn validate_block_terminator(
+    block: &BlockStmt,
+    functions_need_terminator: bool,
+    errors: &mut Vec<ValidationError>,
+) {
+    let opener = block.opener().unwrap().text_range();
+    let expected = block.specific_terminator();
+
+    match block.terminator() {
+        Some(terminator) => {
+            let SyntaxKind::LexToken(found) = terminator.kind() else {
+                unreachable!()
+            };
+            if found != TokenKind::EndKw && found != expected {
+                errors.push(ValidationError {
+                    kind: ValidationErrorKind::MismatchedTerminator {
+                        expected,
+                        found,
+                        opener,
+                    },
+                    range: terminator.text_range(),
+                });
+            }
+        }
+        None if expected == TokenKind::EndFnKw && !functions_need_terminator => {}
+        None => errors.push(ValidationError {
+            kind: ValidationErrorKind::MissingTerminator { expected, opener },
+            range: TextRange::empty(block.syntax().text_range().end()),
+        }),
+    }
+}
+
+fn keyword(terminator: TokenKind) -> &'static str {
+    match terminator {
+        TokenKind::EndFnKw => "endfunction",
+        TokenKind::EndIfKw => "endif",
+        TokenKind::EndForKw => "endfor",
+        TokenKind::EndWhileKw => "endwhile",
+        TokenKind::EndSwitchKw => "endswitch",
+        TokenKind::EndTryKw => "end_try_catch",
+        TokenKind::EndUnwindProtectKw => "end_unwind_protect",
+        TokenKind::EndKw => "end",
+        _ => unreachable!(),
+    }
+}
+
+fn operator(negation: TokenKind) -> &'static str {
+    match negation {
+        TokenKind::Not => "!",
+        TokenKind::Tilde => "~",
+        TokenKind::NotEquals => "!=",
+        TokenKind::TildeEquals => "~=",
+        _ => unreachable!(),
+    }
+}
+
+fn opening_keyword(terminator: TokenKind) -> &'static str {
+    match terminator {
+        TokenKind::EndFnKw => "function",
+        TokenKind::EndIfKw => "if",
+        TokenKind::EndForKw => "for",
+        TokenKind::EndWhileKw => "while",
+        TokenKind::EndSwitchKw => "switch",
+        TokenKind::EndTryKw => "try",
+        TokenKind::EndUnwindProtectKw => "unwind_protect",
+        _ => unreachable!(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +421,26 @@ mod tests {
         assert_eq!(validate(&parse.syntax()), expected_errors);
     }
 
+    fn check_style(
+        input: &str,
+        style: TerminatorStyle,
+        expected_errors: &[(ValidationErrorKind, StdRange<u32>)],
+    ) {
+        let parse = parser::parse(input);
+        let expected_errors: Vec<_> = expected_errors
+            .iter()
+            .map(|(kind, range)| ValidationError {
+                kind: *kind,
+                range: TextRange::new(range.start.into(), range.end.into()),
+            })
+            .collect();
+
+        assert_eq!(
+            validate_terminator_style(&parse.syntax(), style),
+            expected_errors
+        );
+    }
+
     #[test]
     fn validate_ok_literal() {
         check("123", &[]);
@@ -103,4 +453,157 @@ mod tests {
             &[(ValidationErrorKind::NumberLiteralTooLarge, (0..20))],
         );
     }
+
+    #[test]
+    fn validate_matching_terminators() {
+        check("if x\n  for i = 1:3\n    y\n  endfor\nend", &[]);
+    }
+
+    #[test]
+    fn validate_mismatched_terminator() {
+        check(
+            "if x\n  y\nendfor",
+            &[(
+                ValidationErrorKind::MismatchedTerminator {
+                    expected: TokenKind::EndIfKw,
+                    found: TokenKind::EndForKw,
+                    opener: TextRange::new(0.into(), 2.into()),
+                },
+                (9..15),
+            )],
+        );
+    }
+
+    #[test]
+    fn validate_mismatched_terminator_in_nested_block() {
+        check(
+            "while x\n  if y\n    z\n  endwhile\nend",
+            &[(
+                ValidationErrorKind::MismatchedTerminator {
+                    expected: TokenKind::EndIfKw,
+                    found: TokenKind::EndWhileKw,
+                    opener: TextRange::new(10.into(), 12.into()),
+                },
+                (23..31),
+            )],
+        );
+    }
+
+    #[test]
+    fn validate_missing_terminator() {
+        check(
+            "x = 1\nfor i = 1:3\n  x = x + i\n",
+            &[(
+                ValidationErrorKind::MissingTerminator {
+                    expected: TokenKind::EndForKw,
+                    opener: TextRange::new(6.into(), 9.into()),
+                },
+                (30..30),
+            )],
+        );
+    }
+
+    #[test]
+    fn validate_unterminated_functions() {
+        check("function f(x)\n  x\nfunction g(y)\n  y\n", &[]);
+    }
+
+    #[test]
+    fn validate_unterminated_function_next_to_terminated_one() {
+        check(
+            "function f(x)\n  x\nendfunction\nfunction g(y)\n  y\n",
+            &[(
+                ValidationErrorKind::MissingTerminator {
+                    expected: TokenKind::EndFnKw,
+                    opener: TextRange::new(30.into(), 38.into()),
+                },
+                (48..48),
+            )],
+        );
+    }
+
+    #[test]
+    fn validate_specific_terminator_style() {
+        check_style(
+            "if x\n  y\nend\nwhile z\nendwhile",
+            TerminatorStyle::Specific,
+            &[(
+                ValidationErrorKind::TerminatorStyle {
+                    expected: TokenKind::EndIfKw,
+                    found: TokenKind::EndKw,
+                },
+                (9..12),
+            )],
+        );
+    }
+
+    #[test]
+    fn validate_plain_terminator_style() {
+        check_style(
+            "if x\n  y\nend\nwhile z\nendwhile",
+            TerminatorStyle::Plain,
+            &[(
+                ValidationErrorKind::TerminatorStyle {
+                    expected: TokenKind::EndKw,
+                    found: TokenKind::EndWhileKw,
+                },
+                (21..29),
+            )],
+        );
+    }
+
+    #[test]
+    fn terminator_style_ignores_mismatched_terminators() {
+        check_style("if x\n  y\nendfor", TerminatorStyle::Plain, &[]);
+    }
+
+    #[test]
+    fn validate_unsuppressed_output_in_functions() {
+        check(
+            "x = 1\nfunction f\n  y = 2 % shown\n  if y\n    z = 3;\n    w = 4, v = 5;\n  end\nend",
+            &[
+                (ValidationErrorKind::OutputNotSuppressed, (19..24)),
+                (ValidationErrorKind::OutputNotSuppressed, (55..60)),
+            ],
+        );
+    }
+
+    #[test]
+    fn validate_tilde_negation_style() {
+        let parse = parser::parse("a = !b;\nif a != 1\nend\n[~, c] = f(~a);");
+        assert_eq!(
+            validate_negation_style(&parse.syntax(), NegationStyle::Tilde),
+            vec![
+                ValidationError {
+                    kind: ValidationErrorKind::NegationStyle {
+                        expected: TokenKind::Tilde,
+                        found: TokenKind::Not,
+                    },
+                    range: TextRange::new(4.into(), 5.into()),
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::NegationStyle {
+                        expected: TokenKind::TildeEquals,
+                        found: TokenKind::NotEquals,
+                    },
+                    range: TextRange::new(13.into(), 15.into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_exclamation_negation_style_skips_ignored_outputs() {
+        let parse = parser::parse("[~, c] = f(~a);");
+        assert_eq!(
+            validate_negation_style(&parse.syntax(), NegationStyle::Exclamation),
+            vec![ValidationError {
+                kind: ValidationErrorKind::NegationStyle {
+                    expected: TokenKind::Not,
+                    found: TokenKind::Tilde,
+                },
+                range: TextRange::new(11.into(), 12.into()),
+            }]
+        );
+    }
 }
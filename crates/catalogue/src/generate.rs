@@ -0,0 +1,171 @@
+//! Building a catalogue from the help texts of an Octave installation.
+
+use crate::{Catalogue, Function, texinfo};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Builds a catalogue from the Octave installed under `octave_home`, the directory that
+/// `octave-config --print OCTAVE_HOME` prints.
+///
+/// Builtins come from `share/octave/<version>/etc/built-in-docstrings`, functions written in
+/// Octave from the `.m` files under `share/octave/<version>/m`, and package functions from the
+/// packages installed under `share/octave/packages`.
+///
+/// # Errors
+///
+/// Fails if there is no Octave installation under `octave_home`, or if its files cannot be read.
+pub fn generate(octave_home: &Path) -> io::Result<Catalogue> {
+    let share = octave_home.join("share").join("octave");
+    let (version, version_dir) = fs::read_dir(&share)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.starts_with(|ch: char| ch.is_ascii_digit())
+                .then(|| (name, entry.path()))
+        })
+        .max_by(|(a, _), (b, _)| compare_versions(a, b))
+        .ok_or_else(|| {
+            let message = format!("no Octave installation in {}", octave_home.display());
+            io::Error::new(io::ErrorKind::NotFound, message)
+        })?;
+
+    let docstrings = fs::read_to_string(version_dir.join("etc").join("built-in-docstrings"))?;
+    let mut functions = texinfo::parse_docstrings(&docstrings);
+    functions.extend(m_file_functions(&version_dir.join("m"), None));
+
+    if let Ok(packages) = fs::read_dir(share.join("packages")) {
+        let mut packages: Vec<PathBuf> = packages
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect();
+        packages.sort();
+        for package in packages {
+            // Package directories are named like `statistics-1.6.5`.
+            let Some(name) = package
+                .file_name()
+                .and_then(OsStr::to_str)
+                .and_then(|name| name.rsplit_once('-'))
+                .map(|(name, _version)| name.to_string())
+            else {
+                continue;
+            };
+            functions.extend(m_file_functions(&package, Some(&name)));
+        }
+    }
+
+    // The catalogue keeps the first function of each name, so builtins win over the rest.
+    Ok(Catalogue::new(version, functions))
+}
+
+/// The documented functions of the `.m` files under `dir`. Private functions, class methods and
+/// package namespaces are left out, as they cannot be called by their bare name.
+fn m_file_functions(dir: &Path, package: Option<&str>) -> Vec<Function> {
+    let mut files = Vec::new();
+    collect_m_files(dir, &mut files);
+    files.sort();
+    files
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?;
+            let text = fs::read_to_string(path).ok()?;
+            let mut function = texinfo::parse_m_file(name, &text)?;
+            function.package = package.map(str::to_string);
+            Some(function)
+        })
+        .collect()
+}
+
+fn collect_m_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name != "private" && !name.starts_with(['@', '+']) {
+                collect_m_files(&path, files);
+            }
+        } else if path.extension() == Some(OsStr::new("m")) {
+            files.push(path);
+        }
+    }
+}
+
+/// Orders versions such as `9.2.0` and `10.1.0` by their numbers.
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let numbers = |version: &str| -> Vec<u32> {
+        version
+            .split(|ch: char| !ch.is_ascii_digit())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn installation() {
+        let home = std::env::temp_dir().join(format!("catalogue-generate-{}", std::process::id()));
+        let share = home.join("share/octave");
+        write(
+            &share.join("9.2.0/etc/built-in-docstrings"),
+            "\x1fzeros\n-*- texinfo -*-\n@deftypefn {} {@var{val} =} zeros (@var{n})\n\
+             Return a matrix whose elements are all 0.\n@end deftypefn\n",
+        );
+        let m_file = |name: &str| {
+            format!(
+                "## -*- texinfo -*-\n## @deftypefn {{}} {{}} {name} (@var{{x}})\n\
+                 ## Do something with @var{{x}}.\n## @end deftypefn\nfunction {name} (x)\nend\n"
+            )
+        };
+        write(&share.join("9.2.0/m/strings/blanks.m"), &m_file("blanks"));
+        write(
+            &share.join("9.2.0/m/strings/private/helper.m"),
+            &m_file("helper"),
+        );
+        write(&share.join("9.2.0/m/@ftp/ftp.m"), &m_file("ftp"));
+        write(&share.join("7.1.0/m/old.m"), &m_file("old"));
+        write(
+            &share.join("packages/statistics-1.6.5/normpdf.m"),
+            &m_file("normpdf"),
+        );
+        write(
+            &share.join("packages/statistics-1.6.5/zeros.m"),
+            &m_file("zeros"),
+        );
+
+        let catalogue = generate(&home).unwrap();
+        fs::remove_dir_all(&home).unwrap();
+
+        assert_eq!(catalogue.version, "9.2.0");
+        let names: Vec<_> = catalogue.functions().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["blanks", "normpdf", "zeros"]);
+        assert_eq!(catalogue.get("blanks").unwrap().usages, ["blanks (x)"]);
+        let normpdf = catalogue.get("normpdf").unwrap();
+        assert_eq!(normpdf.package.as_deref(), Some("statistics"));
+        assert_eq!(catalogue.get("zeros").unwrap().package, None);
+    }
+
+    #[test]
+    fn no_installation() {
+        assert!(generate(Path::new("/nonexistent")).is_err());
+    }
+
+    #[test]
+    fn versions() {
+        assert!(compare_versions("10.1.0", "9.2.0").is_gt());
+        assert!(compare_versions("9.2.0", "9.2.0").is_eq());
+    }
+}
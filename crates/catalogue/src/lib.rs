@@ -0,0 +1,209 @@
+//! The functions that come with Octave, for when there is no Octave around to ask.
+//!
+//! The catalogue is a JSON file bundled into the binary. For each function it lists the ways it
+//! can be called, as in the Octave manual, and the first sentence of its help text. It can be
+//! rebuilt from the help texts of an Octave installation with [`generate`], and the server can be
+//! told to use such a catalogue instead. The bundled one was not written by [`generate`] and
+//! leaves functions out, so it says it is not [complete](Catalogue::complete).
+#![warn(clippy::pedantic)]
+
+mod generate;
+mod texinfo;
+
+pub use generate::generate;
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::{Arc, OnceLock};
+
+const BUNDLED: &str = include_str!("../data/octave.json");
+
+fn bundled() -> &'static Arc<Catalogue> {
+    static CATALOGUE: OnceLock<Arc<Catalogue>> = OnceLock::new();
+    CATALOGUE.get_or_init(|| {
+        Arc::new(Catalogue::parse(BUNDLED).expect("the bundled catalogue is valid"))
+    })
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+pub struct Catalogue {
+    /// The version of Octave the catalogue describes.
+    pub version: String,
+    /// Whether the catalogue lists every function of that version. Names missing from one that
+    /// is not may still be builtins.
+    #[serde(default = "is_complete")]
+    pub complete: bool,
+    /// Sorted by name.
+    functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Function {
+    pub name: String,
+    /// The forms of calling the function, such as `val = zeros (m, n)`.
+    pub usages: Vec<String>,
+    pub summary: String,
+    /// The package the function comes from, which has to be loaded with `pkg load` first. Core
+    /// functions have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+}
+
+impl Function {
+    /// The summary, followed by the package to load if the function comes from one.
+    #[must_use]
+    pub fn documentation(&self) -> String {
+        match &self.package {
+            Some(package) => format!(
+                "{}\n\nFrom the {package} package: `pkg load {package}`.",
+                self.summary
+            ),
+            None => self.summary.clone(),
+        }
+    }
+}
+
+/// Catalogues from before `complete` was written were all generated.
+fn is_complete() -> bool {
+    true
+}
+
+impl Catalogue {
+    /// The catalogue shipped with the server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bundled file is malformed, which the tests rule out.
+    #[must_use]
+    pub fn bundled() -> Arc<Self> {
+        bundled().clone()
+    }
+
+    /// Builds a complete catalogue of `functions`, keeping the first of those with the same name.
+    #[must_use]
+    pub fn new(version: String, mut functions: Vec<Function>) -> Self {
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        functions.dedup_by(|a, b| a.name == b.name);
+        Self {
+            version,
+            complete: true,
+            functions,
+        }
+    }
+
+    /// Reads a catalogue in the format of the bundled one.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `json` is not a catalogue.
+    pub fn parse(json: &str) -> serde_json::Result<Self> {
+        let catalogue: Self = serde_json::from_str(json)?;
+        Ok(Self {
+            complete: catalogue.complete,
+            ..Self::new(catalogue.version, catalogue.functions)
+        })
+    }
+
+    /// Writes the catalogue in the format of the bundled file, with a function per line so that
+    /// changes to it are easy to review.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        let version = serde_json::to_string(&self.version).unwrap_or_default();
+        let _ = writeln!(json, "  \"version\": {version},");
+        let _ = writeln!(json, "  \"complete\": {},", self.complete);
+        json.push_str("  \"functions\": [\n");
+        for (i, function) in self.functions.iter().enumerate() {
+            let separator = if i + 1 < self.functions.len() {
+                ","
+            } else {
+                ""
+            };
+            let function = serde_json::to_string(function).unwrap_or_default();
+            let _ = writeln!(json, "    {function}{separator}");
+        }
+        json.push_str("  ]\n}\n");
+        json
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        let index = self
+            .functions
+            .binary_search_by(|function| function.name.as_str().cmp(name))
+            .ok()?;
+        Some(&self.functions[index])
+    }
+
+    /// Whether `name` is a function, or a package of functions such as `containers`.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        if self.get(name).is_some() {
+            return true;
+        }
+        let package = format!("{name}.");
+        let index = self
+            .functions
+            .partition_point(|function| function.name < package);
+        self.functions
+            .get(index)
+            .is_some_and(|function| function.name.starts_with(&package))
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_catalogue_is_valid() {
+        let catalogue = Catalogue::bundled();
+        assert!(!catalogue.version.is_empty());
+        for function in catalogue.functions() {
+            assert!(!function.usages.is_empty(), "{}", function.name);
+            assert!(
+                function
+                    .usages
+                    .iter()
+                    .all(|usage| usage.contains(&function.name)),
+                "{}",
+                function.name
+            );
+        }
+    }
+
+    #[test]
+    fn bundled_catalogue_is_written_as_generated() {
+        assert_eq!(Catalogue::bundled().to_json(), BUNDLED);
+    }
+
+    #[test]
+    fn completeness_is_read_and_written() {
+        assert!(!Catalogue::bundled().complete);
+        let generated = Catalogue::new("9.2.0".to_string(), Vec::new());
+        assert!(generated.complete);
+        assert_eq!(Catalogue::parse(&generated.to_json()).unwrap(), generated);
+        let older = Catalogue::parse(r#"{"version": "9.2.0", "functions": []}"#).unwrap();
+        assert!(older.complete);
+    }
+
+    #[test]
+    fn lookup() {
+        let catalogue = Catalogue::bundled();
+        assert_eq!(catalogue.get("zeros").unwrap().name, "zeros");
+        assert!(catalogue.contains("cellfun"));
+        assert!(!catalogue.contains("not_a_builtin"));
+        assert!(catalogue.contains("containers.Map"));
+        assert!(catalogue.contains("containers"));
+        assert!(!catalogue.contains("container"));
+        assert_eq!(catalogue.get("pi").unwrap().package, None);
+        assert_eq!(
+            catalogue.get("normpdf").unwrap().package.as_deref(),
+            Some("statistics")
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Reading the Texinfo help texts of Octave functions.
+//!
+//! Help texts start with `@deftypefn` lines, one for each way of calling the function, followed
+//! by the description:
+//!
+//! ```texinfo
+//! @deftypefn  {} {@var{y} =} fliplr (@var{x})
+//! Flip array left to right.
+//! ...
+//! @end deftypefn
+//! ```
+
+use crate::Function;
+
+/// Marks the help texts written in Texinfo, as opposed to plain text.
+const TEXINFO_MARKER: &str = "-*- texinfo -*-";
+
+/// Reads the help texts of the functions built into Octave, as installed in its
+/// `built-in-docstrings` file. Each one follows a `\x1f` and the name of the function.
+pub(crate) fn parse_docstrings(text: &str) -> Vec<Function> {
+    text.split('\x1f')
+        .filter_map(|entry| {
+            let (name, help) = entry.split_once('\n')?;
+            parse_help(name.trim(), help)
+        })
+        .collect()
+}
+
+/// Reads the help text of the function defined in a `.m` file: its first comment block marked
+/// as Texinfo.
+pub(crate) fn parse_m_file(name: &str, text: &str) -> Option<Function> {
+    let mut help = String::new();
+    let mut in_help = false;
+    for line in text.lines() {
+        let line = line.trim_start();
+        let Some(comment) = line
+            .strip_prefix(['#', '%'])
+            .map(|comment| comment.trim_start_matches(['#', '%']))
+        else {
+            if in_help {
+                break;
+            }
+            continue;
+        };
+        let comment = comment.strip_prefix(' ').unwrap_or(comment);
+        if comment.trim() == TEXINFO_MARKER {
+            in_help = true;
+        } else if in_help {
+            help.push_str(comment);
+            help.push('\n');
+        }
+    }
+    parse_help(name, &help)
+}
+
+/// Reads a Texinfo help text. Functions whose usages do not mention them are left out, as the
+/// text is then about something else.
+fn parse_help(name: &str, help: &str) -> Option<Function> {
+    let mut usages = Vec::new();
+    let mut description = Vec::new();
+    for line in help.lines() {
+        let line = line.trim();
+        if let Some(definition) = line
+            .strip_prefix("@deftypefnx")
+            .or_else(|| line.strip_prefix("@deftypefn"))
+        {
+            if description.is_empty() {
+                usages.extend(usage(definition));
+            }
+            continue;
+        }
+        if usages.is_empty() || line.starts_with("@c ") || line == TEXINFO_MARKER {
+            continue;
+        }
+        // The first paragraph ends at a blank line or at a block such as `@example`.
+        if line.is_empty() || is_block_command(line) {
+            if description.is_empty() {
+                continue;
+            }
+            break;
+        }
+        description.push(line);
+    }
+
+    if usages.is_empty() || !usages.iter().all(|usage| usage.contains(name)) {
+        return None;
+    }
+    Some(Function {
+        name: name.to_string(),
+        usages,
+        summary: first_sentence(&plain(&description.join(" "))),
+        package: None,
+    })
+}
+
+/// Whether a line is a command of its own such as `@example`, rather than text starting with an
+/// inline command such as `@var{x}`.
+fn is_block_command(line: &str) -> bool {
+    line.strip_prefix('@').is_some_and(|command| {
+        let name_end = command
+            .find(|ch: char| !ch.is_ascii_alphabetic())
+            .unwrap_or(command.len());
+        name_end > 0 && !command[name_end..].starts_with('{')
+    })
+}
+
+/// The usage in the rest of a `@deftypefn` line: `{category} {outputs =} name (args)`.
+fn usage(definition: &str) -> Option<String> {
+    let (_category, rest) = braced(definition.trim_start())?;
+    let (outputs, call) = braced(rest.trim_start())?;
+    let outputs = plain(outputs);
+    let call = plain(call.trim());
+    let usage = if outputs.trim().is_empty() {
+        call
+    } else {
+        format!("{} {call}", outputs.trim())
+    };
+    Some(usage.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Splits `{group} rest` into the text inside the braces and the rest.
+fn braced(text: &str) -> Option<(&str, &str)> {
+    let inner = text.strip_prefix('{')?;
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for (i, ch) in inner.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '@' => escaped = true,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some((&inner[..i], &inner[i + 1..])),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Turns Texinfo into plain text: `@var{x}` becomes `x`, `@dots{}` becomes `...`, and other
+/// commands are dropped.
+fn plain(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if ch != '@' {
+            if ch != '{' && ch != '}' {
+                plain.push(ch);
+            }
+            continue;
+        }
+        match chars.peek() {
+            Some(&(_, escaped @ ('@' | '{' | '}'))) => {
+                plain.push(escaped);
+                chars.next();
+                continue;
+            }
+            None => continue,
+            Some(_) => {}
+        }
+        let start = i + 1;
+        let mut end = start;
+        while let Some(&(j, ch)) = chars.peek() {
+            if !ch.is_ascii_alphabetic() {
+                break;
+            }
+            end = j + ch.len_utf8();
+            chars.next();
+        }
+        match &text[start..end] {
+            "dots" => plain.push_str("..."),
+            "result" => plain.push_str("=>"),
+            "equiv" => plain.push_str("=="),
+            "minus" => plain.push('-'),
+            "tab" => plain.push(' '),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// The text up to the first period that ends a sentence.
+fn first_sentence(text: &str) -> String {
+    let end = text
+        .match_indices(". ")
+        .map(|(i, _)| i + 1)
+        .next()
+        .unwrap_or(text.len());
+    text[..end].trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usages_and_summary() {
+        let help = "@deftypefn  {} {@var{y} =} fliplr (@var{x})\n\
+                    @deftypefnx {} {[@var{s}, @var{i}] =} fliplr (@var{x}, @dots{})\n\
+                    Flip array left to right.  Return a copy of @var{x}.\n\n\
+                    @example\nfliplr ([1, 2])\n@end example\n@end deftypefn\n";
+        assert_eq!(
+            parse_help("fliplr", help),
+            Some(Function {
+                name: "fliplr".to_string(),
+                usages: vec![
+                    "y = fliplr (x)".to_string(),
+                    "[s, i] = fliplr (x, ...)".to_string(),
+                ],
+                summary: "Flip array left to right.".to_string(),
+                package: None,
+            })
+        );
+    }
+
+    #[test]
+    fn usages_without_outputs() {
+        let help = "@deftypefn {} {} disp (@var{x})\n\
+                    Display the value of @var{x} with @code{\"like\"} and @@.\n";
+        let function = parse_help("disp", help).unwrap();
+        assert_eq!(function.usages, ["disp (x)"]);
+        assert_eq!(
+            function.summary,
+            "Display the value of x with \"like\" and @."
+        );
+    }
+
+    #[test]
+    fn docstrings_file() {
+        let text = "\x1fabs\n@c abs libinterp/corefcn/mappers.cc\n-*- texinfo -*-\n\
+                    @deftypefn {} {@var{z} =} abs (@var{z})\nCompute the magnitude of @var{z}.\n\
+                    @end deftypefn\n\
+                    \x1fnot_texinfo\nSome plain help.\n";
+        let functions = parse_docstrings(text);
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].usages, ["z = abs (z)"]);
+        assert_eq!(functions[0].summary, "Compute the magnitude of z.");
+    }
+
+    #[test]
+    fn m_file() {
+        let text = "########################################################################\n\
+                    ## Copyright (C) 2000-2024 The Octave Project Developers\n\
+                    ########################################################################\n\
+                    \n\
+                    ## -*- texinfo -*-\n\
+                    ## @deftypefn {} {@var{str} =} blanks (@var{n})\n\
+                    ## Return a string of @var{n} blanks.\n\
+                    ## @end deftypefn\n\
+                    \n\
+                    function str = blanks (n)\n";
+        let function = parse_m_file("blanks", text).unwrap();
+        assert_eq!(function.usages, ["str = blanks (n)"]);
+        assert_eq!(function.summary, "Return a string of n blanks.");
+        assert_eq!(parse_m_file("other", "function other ()\nend\n"), None);
+    }
+}
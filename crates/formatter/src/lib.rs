@@ -0,0 +1,719 @@
+//! Formatting of Octave code.
+//!
+//! The formatter works line by line on the lossless tree of `parser::parse`. It re-indents lines
+//! by the blocks they are in, normalises the spacing around binary operators and between the
+//! elements of `[]` and `{}`, and can rewrite block terminators. It never joins or splits lines,
+//! so comments, blank lines and continuation lines stay where they were written, and whatever
+//! the parser could not make sense of keeps its original spacing.
+#![warn(clippy::pedantic)]
+
+mod on_type;
+
+pub use on_type::on_newline;
+
+use std::collections::HashMap;
+use std::fmt;
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::{TextRange, TextSize};
+
+/// Keywords that start a new part of a block, at the indentation of the block itself.
+const PART_KEYWORDS: [TokenKind; 6] = [
+    TokenKind::ElseIfKw,
+    TokenKind::ElseKw,
+    TokenKind::CaseKw,
+    TokenKind::OtherwiseKw,
+    TokenKind::CatchKw,
+    TokenKind::UnwindProtectCleanupKw,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The number of spaces per level of indentation.
+    pub indent_width: usize,
+    /// Whether to indent with one tab per level instead of spaces.
+    pub use_tabs: bool,
+    /// Whether the bodies of functions are indented. Many files leave them flush with the
+    /// `function` keyword.
+    pub indent_function_bodies: bool,
+    pub end_keywords: EndKeywords,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 2,
+            use_tabs: false,
+            indent_function_bodies: true,
+            end_keywords: EndKeywords::default(),
+        }
+    }
+}
+
+/// How blocks are closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EndKeywords {
+    /// Leave terminators as they are written.
+    #[default]
+    Keep,
+    /// Close every block with `end`, which MATLAB understands too.
+    Generic,
+    /// Close every block with its own keyword, such as `endif` or `endfunction`.
+    Specific,
+}
+
+/// A replacement of part of the text. Formatting replaces whole lines, without their line break.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: TextRange,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// The lexer stopped at a character it does not know, such as the start of a double-quoted
+    /// string, so the tree does not cover the text from that line on.
+    Unlexable { line: usize },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Unlexable { line } => {
+                write!(
+                    f,
+                    "cannot format past an unrecognized character on line {line}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Formats a whole file.
+///
+/// # Errors
+///
+/// Fails if the file cannot be lexed to its end, in which case no part of it is formatted.
+pub fn format(text: &str, options: &FormatOptions) -> Result<String, FormatError> {
+    let mut formatted = String::with_capacity(text.len());
+    let mut last = 0;
+    for edit in edits(text, None, options)? {
+        formatted.push_str(&text[last..usize::from(edit.range.start())]);
+        formatted.push_str(&edit.text);
+        last = edit.range.end().into();
+    }
+    formatted.push_str(&text[last..]);
+    Ok(formatted)
+}
+
+/// Returns the edits that format a file, one per changed line, in order. With a range, only the
+/// lines it touches are formatted, though their indentation still follows the whole file.
+///
+/// # Errors
+///
+/// Fails if the file cannot be lexed to its end.
+pub fn edits(
+    text: &str,
+    range: Option<TextRange>,
+    options: &FormatOptions,
+) -> Result<Vec<Edit>, FormatError> {
+    let root = parser::parse(text).syntax();
+    let lexed = root.text_range().end();
+    if lexed != TextSize::of(text) {
+        let line = text[..usize::from(lexed)].matches('\n').count() + 1;
+        return Err(FormatError::Unlexable { line });
+    }
+
+    let formatter = Formatter::new(&root, options);
+    let mut edits = Vec::new();
+    for (line, formatted) in formatter.lines(&root) {
+        let selected = range.is_none_or(|range| {
+            range.intersect(line.range).is_some() || line.is_blank_in(text, range)
+        });
+        if selected && formatted != text[line.range] {
+            edits.push(Edit {
+                range: line.range,
+                text: formatted,
+            });
+        }
+    }
+    Ok(edits)
+}
+
+/// The tokens of one line, without the line break.
+struct Line {
+    range: TextRange,
+    tokens: Vec<SyntaxToken>,
+}
+
+impl Line {
+    /// Whether the line is blank and inside `range`, which `intersect` alone misses when both
+    /// are empty.
+    fn is_blank_in(&self, text: &str, range: TextRange) -> bool {
+        text[self.range].trim().is_empty() && range.contains_range(self.range)
+    }
+
+    fn significant(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.tokens
+            .iter()
+            .filter(|token| token.kind() != TokenKind::Whitespace.into())
+    }
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    /// Terminators to rewrite, with their new keyword.
+    terminators: HashMap<TextRange, &'static str>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(root: &SyntaxNode, options: &'a FormatOptions) -> Self {
+        let mut terminators = HashMap::new();
+        for block in root.descendants().filter_map(ast::BlockStmt::cast) {
+            let Some(terminator) = block.terminator() else {
+                continue;
+            };
+            let SyntaxKind::LexToken(kind) = terminator.kind() else {
+                continue;
+            };
+            let specific = block.specific_terminator();
+            // A mismatched terminator is an error to fix by hand, not a style to normalise.
+            let replacement = match options.end_keywords {
+                EndKeywords::Generic if kind == specific => TokenKind::EndKw,
+                EndKeywords::Specific if kind == TokenKind::EndKw => specific,
+                _ => continue,
+            };
+            if let Some(text) = replacement.keyword_text() {
+                terminators.insert(terminator.text_range(), text);
+            }
+        }
+        Self {
+            options,
+            terminators,
+        }
+    }
+
+    /// Returns every line of the file with its formatted text.
+    fn lines(&self, root: &SyntaxNode) -> Vec<(Line, String)> {
+        let mut formatted = Vec::new();
+        let mut brackets: Vec<TokenKind> = Vec::new();
+        let mut continued = false;
+        let mut in_block_comment = false;
+        // The change of indentation of the line that starts the current statement, applied to
+        // the lines that continue it.
+        let mut shift: isize = 0;
+
+        for line in split_lines(root) {
+            let original = root_text(root, line.range);
+            let comment_line = only_comment(&line);
+            let closes_block_comment = comment_line.is_some_and(|text| is_block_comment(text, '}'));
+            if in_block_comment && !closes_block_comment {
+                formatted.push((line, original));
+                continue;
+            }
+            if comment_line.is_some_and(|text| is_block_comment(text, '{')) {
+                in_block_comment = true;
+            } else if closes_block_comment {
+                in_block_comment = false;
+            }
+
+            let old_indent = indent_width(&original, self.options.indent_width);
+            let indent = match line.significant().next() {
+                None => 0,
+                Some(_) if continued => old_indent.saturating_add_signed(shift),
+                Some(first) => {
+                    let indent = self.depth(first) * self.options.indent_width;
+                    shift = indent.cast_signed() - old_indent.cast_signed();
+                    indent
+                }
+            };
+
+            let mut text = String::new();
+            if line.significant().next().is_some() {
+                text.push_str(&self.indent(indent));
+            }
+            self.push_tokens(&line, &mut brackets, &mut text);
+            let text = text.trim_end();
+            let mut text = text.to_string();
+            if original.ends_with('\r') {
+                text.push('\r');
+            }
+
+            continued = !brackets.is_empty() || ends_with_continuation(&line);
+            formatted.push((line, text));
+        }
+        formatted
+    }
+
+    /// Writes the significant tokens of a line with the gaps between them.
+    fn push_tokens(&self, line: &Line, brackets: &mut Vec<TokenKind>, text: &mut String) {
+        let mut previous: Option<(&SyntaxToken, bool)> = None;
+        let mut gap = String::new();
+        for (i, token) in line.tokens.iter().enumerate() {
+            if token.kind() == TokenKind::Whitespace.into() {
+                gap.push_str(token.text());
+                continue;
+            }
+            // Whitespace before an operator but not after it may make it unary, as in the
+            // command `hold -on`, so such operators keep their spacing.
+            let after = match line.tokens.get(i + 1) {
+                Some(next) if next.kind() == TokenKind::Whitespace.into() => next.text(),
+                Some(_) => "",
+                None => " ",
+            };
+            let respaced = gap.is_empty() || !after.is_empty();
+            if let Some((previous, previous_respaced)) = previous {
+                text.push_str(&gap_between(
+                    (previous, previous_respaced),
+                    (token, respaced),
+                    &gap,
+                    brackets.last(),
+                ));
+            }
+            match self.terminators.get(&token.text_range()) {
+                Some(keyword) => text.push_str(keyword),
+                None => text.push_str(token.text()),
+            }
+            track_brackets(token, brackets);
+            previous = Some((token, respaced));
+            gap.clear();
+        }
+    }
+
+    /// The indentation string for a width in columns.
+    fn indent(&self, width: usize) -> String {
+        if self.options.use_tabs && self.options.indent_width > 0 {
+            let tabs = width / self.options.indent_width;
+            let spaces = width % self.options.indent_width;
+            "\t".repeat(tabs) + &" ".repeat(spaces)
+        } else {
+            " ".repeat(width)
+        }
+    }
+
+    /// The number of blocks whose body contains `token`.
+    fn depth(&self, token: &SyntaxToken) -> usize {
+        let mut depth = 0;
+        let mut past_function = false;
+        for node in token.parent_ancestors() {
+            let Some(block) = ast::BlockStmt::cast(node.clone()) else {
+                continue;
+            };
+            let is_function = node.kind() == SyntaxConstruct::FnDef.into();
+            let terminator = block.terminator();
+            let opens = block.opener().as_ref() == Some(token);
+            let closes = terminator.as_ref().is_some_and(|terminator| {
+                token.text_range().start() >= terminator.text_range().start()
+            });
+
+            if is_function {
+                // The functions that follow an unterminated one are parsed into its body, but
+                // they are really its siblings.
+                let counts = !opens
+                    && !closes
+                    && self.options.indent_function_bodies
+                    && (terminator.is_some() || !past_function);
+                past_function = true;
+                depth += usize::from(counts);
+                continue;
+            }
+            if opens || closes {
+                continue;
+            }
+            let is_switch = node.kind() == SyntaxConstruct::SwitchStmt.into();
+            let is_part = token.parent().as_ref() == Some(&node)
+                && matches!(token.kind(), SyntaxKind::LexToken(kind) if PART_KEYWORDS.contains(&kind));
+            depth += match (is_switch, is_part) {
+                (false, true) => 0,
+                (false, false) | (true, true) => 1,
+                (true, false) => 2,
+            };
+        }
+        depth
+    }
+}
+
+/// Splits the tokens of the file at its line breaks.
+fn split_lines(root: &SyntaxNode) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut start = TextSize::from(0);
+    let mut tokens = Vec::new();
+    for token in root
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+    {
+        if token.kind() == TokenKind::Newline.into() {
+            lines.push(Line {
+                range: TextRange::new(start, token.text_range().start()),
+                tokens: std::mem::take(&mut tokens),
+            });
+            start = token.text_range().end();
+        } else {
+            tokens.push(token);
+        }
+    }
+    lines.push(Line {
+        range: TextRange::new(start, root.text_range().end()),
+        tokens,
+    });
+    lines
+}
+
+fn root_text(root: &SyntaxNode, range: TextRange) -> String {
+    root.text().slice(range).to_string()
+}
+
+/// The text of the comment if it is all the line holds.
+fn only_comment(line: &Line) -> Option<&str> {
+    let mut significant = line.significant();
+    let first = significant.next()?;
+    (first.kind() == TokenKind::Comment.into() && significant.next().is_none())
+        .then(|| first.text())
+}
+
+/// Whether a comment is the `%{` or `%}` line of a block comment.
+fn is_block_comment(comment: &str, brace: char) -> bool {
+    let mut chars = comment.trim_end().chars();
+    matches!(chars.next(), Some('%' | '#')) && chars.next() == Some(brace) && chars.next().is_none()
+}
+
+/// The width of the leading whitespace of a line, counting tabs as one level.
+fn indent_width(line: &str, tab_width: usize) -> usize {
+    line.chars()
+        .take_while(|c| matches!(c, ' ' | '\t'))
+        .map(|c| if c == '\t' { tab_width } else { 1 })
+        .sum()
+}
+
+/// Whether the line ends in a `...` continuation.
+fn ends_with_continuation(line: &Line) -> bool {
+    line.tokens
+        .last()
+        .is_some_and(|token| token.kind() == TokenKind::Continuation.into())
+}
+
+/// Keeps track of the open brackets, outside of strings and comments.
+fn track_brackets(token: &SyntaxToken, brackets: &mut Vec<TokenKind>) {
+    if in_string(token) {
+        return;
+    }
+    match token.kind() {
+        SyntaxKind::LexToken(
+            kind @ (TokenKind::LBracket | TokenKind::LBrace | TokenKind::LParen),
+        ) => {
+            brackets.push(kind);
+        }
+        SyntaxKind::LexToken(TokenKind::RBracket | TokenKind::RBrace | TokenKind::RParen) => {
+            brackets.pop();
+        }
+        _ => {}
+    }
+}
+
+fn in_string(token: &SyntaxToken) -> bool {
+    token
+        .parent()
+        .is_some_and(|parent| parent.kind() == SyntaxConstruct::StringLiteral.into())
+}
+
+/// The whitespace to put between two tokens on a line, given the original `gap` between them.
+/// Each token comes with whether its spacing may change if it is an operator.
+fn gap_between(
+    (previous, previous_respaced): (&SyntaxToken, bool),
+    (current, current_respaced): (&SyntaxToken, bool),
+    gap: &str,
+    bracket: Option<&TokenKind>,
+) -> String {
+    let kind = |token: &SyntaxToken| match token.kind() {
+        SyntaxKind::LexToken(kind) => Some(kind),
+        SyntaxKind::SyntaxConstruct(_) => None,
+    };
+    if (in_string(previous) && current.parent() == previous.parent())
+        || kind(current) == Some(TokenKind::Comment)
+    {
+        return gap.to_string();
+    }
+
+    let operator = |token, respaced| spaced_operator(token).filter(|_| respaced);
+    if let Some(tight) =
+        operator(current, current_respaced).or_else(|| operator(previous, previous_respaced))
+    {
+        return if tight {
+            String::new()
+        } else {
+            " ".to_string()
+        };
+    }
+
+    if matches!(bracket, Some(TokenKind::LBracket | TokenKind::LBrace)) {
+        return match (kind(previous), kind(current)) {
+            (
+                _,
+                Some(
+                    TokenKind::Comma
+                    | TokenKind::Semicolon
+                    | TokenKind::RBracket
+                    | TokenKind::RBrace,
+                ),
+            )
+            | (Some(TokenKind::LBracket | TokenKind::LBrace), _) => String::new(),
+            (Some(TokenKind::Comma | TokenKind::Semicolon), _) => " ".to_string(),
+            // Inside brackets, whitespace separates elements, so it is never added or removed.
+            _ if gap.is_empty() => String::new(),
+            _ => " ".to_string(),
+        };
+    }
+    gap.to_string()
+}
+
+/// If `token` is the operator of a binary expression whose spacing can be changed safely,
+/// returns whether it binds tightly, without spaces around it.
+///
+/// Inside matrices and cell arrays, spaces separate elements, so `[a -b]` and `[a - b]` differ,
+/// and expressions with errors may not be what they seem.
+fn spaced_operator(token: &SyntaxToken) -> Option<bool> {
+    let parent = token.parent()?;
+    if parent.kind() != SyntaxConstruct::InfixExpr.into() {
+        return None;
+    }
+    let ast::Expr::BinaryExpr(expr) = ast::Expr::cast(parent.clone())? else {
+        return None;
+    };
+    if expr.op().as_ref() != Some(token)
+        || parent
+            .descendants()
+            .any(|node| node.kind() == SyntaxConstruct::Error.into())
+    {
+        return None;
+    }
+    for ancestor in parent.ancestors() {
+        match ancestor.kind() {
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::ParenExpr | SyntaxConstruct::CallExpr) => {
+                break;
+            }
+            SyntaxKind::SyntaxConstruct(SyntaxConstruct::MatrixExpr | SyntaxConstruct::Error) => {
+                return None;
+            }
+            _ => {}
+        }
+    }
+    Some(matches!(
+        token.kind(),
+        SyntaxKind::LexToken(TokenKind::Colon | TokenKind::Caret | TokenKind::ElmtPow)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        check_with(input, &FormatOptions::default(), expected);
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check_with(input: &str, options: &FormatOptions, expected: Expect) {
+        let formatted = format(input, options).unwrap();
+        expected.assert_eq(&formatted);
+        assert_eq!(
+            format(&formatted, options).unwrap(),
+            formatted,
+            "not idempotent"
+        );
+    }
+
+    #[test]
+    fn indents_blocks() {
+        check(
+            "function y = f(x)\nif x\ny = 1;\nelseif x > 2\n      y = 2;\nelse\nfor i = 1:x\ny = i;\nend\nend\nend\n",
+            expect![[r"
+                function y = f(x)
+                  if x
+                    y = 1;
+                  elseif x > 2
+                    y = 2;
+                  else
+                    for i = 1:x
+                      y = i;
+                    end
+                  end
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn indents_switch_cases() {
+        check(
+            "switch x\ncase 1\ny = 1;\notherwise\ny = 2;\nendswitch\n",
+            expect![[r"
+                switch x
+                  case 1
+                    y = 1;
+                  otherwise
+                    y = 2;
+                endswitch
+            "]],
+        );
+    }
+
+    #[test]
+    fn unterminated_functions_are_siblings() {
+        check(
+            "function f\n    g();\n\nfunction g\ndisp(1)\n",
+            expect![[r"
+                function f
+                  g();
+
+                function g
+                  disp(1)
+            "]],
+        );
+    }
+
+    #[test]
+    fn function_bodies_can_stay_flush() {
+        let options = FormatOptions {
+            indent_function_bodies: false,
+            indent_width: 4,
+            ..FormatOptions::default()
+        };
+        check_with(
+            "function f\n  if x\n  y = 1;\n  end\nend\n",
+            &options,
+            expect![[r"
+                function f
+                if x
+                    y = 1;
+                end
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn spaces_binary_operators() {
+        check(
+            "x=a+b*c;\ny = x .^ 2 - a ^ b;\nz = (1 : 3)';\nw += f(a==b,c);\n",
+            expect![[r"
+                x = a + b * c;
+                y = x.^2 - a^b;
+                z = (1:3)';
+                w += f(a == b,c);
+            "]],
+        );
+    }
+
+    #[test]
+    fn keeps_ambiguous_spacing() {
+        // `hold -on` reads as a command, and `[a -b]` has two elements.
+        check(
+            "hold -on\nx = [a -b, c+d];\ny = 'a  +  b';\n",
+            expect![[r"
+                hold -on
+                x = [a -b, c+d];
+                y = 'a  +  b';
+            "]],
+        );
+    }
+
+    #[test]
+    fn normalises_separators_in_brackets() {
+        check(
+            "x = [ 1,2 ;3    4 ];\nc = {'a' ,'b'};\n",
+            expect![[r"
+                x = [1, 2; 3 4];
+                c = {'a', 'b'};
+            "]],
+        );
+    }
+
+    #[test]
+    fn preserves_comments_and_continuations() {
+        check(
+            "if x % why\n      % alone\ny = [1, 2, ...\n       3];\nz = 1 ... why\n  + 2;\n%{\n   kept   as is\n%}\nend\n",
+            expect![[r"
+                if x % why
+                  % alone
+                  y = [1, 2, ...
+                         3];
+                  z = 1 ... why
+                    + 2;
+                  %{
+                   kept   as is
+                  %}
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn rewrites_terminators() {
+        let input = "if x\n  for i = 1:3\n  endfor\nend\nwhile y\nendfor\n";
+        let generic = FormatOptions {
+            end_keywords: EndKeywords::Generic,
+            ..FormatOptions::default()
+        };
+        check_with(
+            input,
+            &generic,
+            expect![[r"
+                if x
+                  for i = 1:3
+                  end
+                end
+                while y
+                endfor
+            "]],
+        );
+        let specific = FormatOptions {
+            end_keywords: EndKeywords::Specific,
+            ..FormatOptions::default()
+        };
+        check_with(
+            input,
+            &specific,
+            expect![[r"
+                if x
+                  for i = 1:3
+                  endfor
+                endif
+                while y
+                endfor
+            "]],
+        );
+    }
+
+    #[test]
+    fn keeps_line_endings_and_strips_trailing_whitespace() {
+        check("if x  \r\ny=1;\r\nend", expect!["if x\r\n  y = 1;\r\nend"]);
+    }
+
+    #[test]
+    fn range_formats_only_touched_lines() {
+        let text = "if x\ny=1;\nz=2;\nend\n";
+        let edits = edits(
+            text,
+            Some(TextRange::new(6.into(), 7.into())),
+            &FormatOptions::default(),
+        );
+        assert_eq!(
+            edits.unwrap(),
+            vec![Edit {
+                range: TextRange::new(5.into(), 9.into()),
+                text: "  y = 1;".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn unlexable_text_is_an_error() {
+        assert_eq!(
+            format("x = 1;\ny = \"a\";\n", &FormatOptions::default()),
+            Err(FormatError::Unlexable { line: 2 })
+        );
+    }
+}
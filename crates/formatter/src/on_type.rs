@@ -0,0 +1,298 @@
+//! Formatting as the user types: after a line break, the line just finished and the new one are
+//! re-indented, and a block opened on the finished line gets its terminator.
+
+use crate::{Edit, EndKeywords, FormatOptions, Formatter, PART_KEYWORDS};
+use ast::TypedSyntaxNode;
+use syntax::{SyntaxConstruct, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::{TextRange, TextSize};
+
+/// Returns the edits for a line break typed right before `offset`.
+///
+/// Lines starting with `else`, `case`, `end` and the like are re-indented as they are finished,
+/// the new line is indented, and if the finished line opens a block that really is
+/// unterminated, the terminator goes on the line after the cursor.
+#[must_use]
+pub fn on_newline(text: &str, offset: TextSize, options: &FormatOptions) -> Vec<Edit> {
+    let root = parser::parse(text).syntax();
+    let cursor = usize::from(offset);
+    if root.text_range().end() != TextSize::of(text)
+        || cursor > text.len()
+        || !text[..cursor].contains('\n')
+    {
+        return Vec::new();
+    }
+    let formatter = Formatter::new(&root, options);
+    let current = line_start(text, cursor);
+    let previous = line_start(text, current - 1);
+
+    let mut edits = Vec::new();
+    let opener = first_token(&root, text, previous);
+    let mut opener_indent = None;
+    if let Some(first) = &opener {
+        let keyword = match first.kind() {
+            SyntaxKind::LexToken(kind) => {
+                PART_KEYWORDS.contains(&kind) || kind.is_block_terminator()
+            }
+            SyntaxKind::SyntaxConstruct(_) => false,
+        };
+        let indent = formatter.indent(formatter.depth(first) * options.indent_width);
+        if keyword {
+            edits.extend(reindent(text, previous, &indent));
+        }
+        opener_indent = Some(indent);
+    }
+
+    let block = opener.as_ref().and_then(|first| {
+        let block = ast::BlockStmt::cast(first.parent()?)?;
+        (block.opener().as_ref() == Some(first)).then_some(block)
+    });
+    let depth = match (&first_token(&root, text, current), &block) {
+        (Some(first), _) => formatter.depth(first),
+        // The first `case` of a switch goes one level in, not two.
+        (None, Some(block)) if block.syntax().kind() == SyntaxConstruct::SwitchStmt.into() => {
+            opener
+                .as_ref()
+                .map_or(0, |first| formatter.depth(first) + 1)
+        }
+        (None, _) => root
+            .token_at_offset(TextSize::try_from(current).unwrap_or_default())
+            .left_biased()
+            .map_or(0, |token| formatter.depth(&token)),
+    };
+    let indent = formatter.indent(depth * options.indent_width);
+
+    let terminator = block
+        .filter(|block| is_unterminated(text, &root, block, current))
+        .map(|block| terminator_text(&root, &block, options.end_keywords));
+    edits.extend(reindent(text, current, &indent));
+    if let (Some(terminator), Some(opener_indent)) = (terminator, opener_indent) {
+        edits.push(match text[current..].find('\n') {
+            Some(i) => Edit {
+                range: TextRange::empty(to_size(current + i + 1)),
+                text: format!("{opener_indent}{terminator}\n"),
+            },
+            None => Edit {
+                range: TextRange::empty(TextSize::of(text)),
+                text: format!("\n{opener_indent}{terminator}"),
+            },
+        });
+    }
+    edits
+}
+
+/// Whether inserting a terminator for `block` after the line at `line` would close a block that
+/// is open now. A block that takes the `end` of an enclosing one leaves that one open instead.
+fn is_unterminated(text: &str, root: &SyntaxNode, block: &ast::BlockStmt, line: usize) -> bool {
+    let unterminated = |root: &SyntaxNode| {
+        root.descendants()
+            .filter_map(ast::BlockStmt::cast)
+            .filter(|block| block.terminator().is_none())
+            .count()
+    };
+    // Files whose functions are not closed at all have no terminators to add.
+    let is_function = block.syntax().kind() == SyntaxConstruct::FnDef.into();
+    if is_function
+        && root.descendants().any(|node| {
+            node.kind() == SyntaxConstruct::FnDef.into()
+                && node != *block.syntax()
+                && ast::BlockStmt::cast(node).is_some_and(|other| other.terminator().is_none())
+        })
+    {
+        return false;
+    }
+
+    let candidate = match text[line..].find('\n') {
+        Some(i) => format!("{}end\n{}", &text[..=line + i], &text[line + i + 1..]),
+        None => format!("{text}\nend"),
+    };
+    unterminated(&parser::parse(&candidate).syntax()) < unterminated(root)
+}
+
+/// The terminator for a block: the configured style, or else the style the file already uses.
+fn terminator_text(root: &SyntaxNode, block: &ast::BlockStmt, style: EndKeywords) -> &'static str {
+    let specific = match style {
+        EndKeywords::Generic => false,
+        EndKeywords::Specific => true,
+        EndKeywords::Keep => {
+            ast::Root::cast(root.clone()).is_some_and(|root| root.uses_specific_terminators())
+        }
+    };
+    let kind = if specific {
+        block.specific_terminator()
+    } else {
+        TokenKind::EndKw
+    };
+    kind.keyword_text().unwrap_or("end")
+}
+
+/// Replaces the leading whitespace of the line starting at `start`, unless it already matches.
+fn reindent(text: &str, start: usize, indent: &str) -> Option<Edit> {
+    let width = text[start..]
+        .find(|c| !matches!(c, ' ' | '\t'))
+        .unwrap_or(text.len() - start);
+    (text[start..start + width] != *indent).then(|| Edit {
+        range: TextRange::at(to_size(start), to_size(width)),
+        text: indent.to_string(),
+    })
+}
+
+/// The first token on the line starting at `start`, unless the line is blank.
+fn first_token(root: &SyntaxNode, text: &str, start: usize) -> Option<SyntaxToken> {
+    let first = start + text[start..].find(|c| !matches!(c, ' ' | '\t'))?;
+    if text[first..].starts_with(['\n', '\r']) {
+        return None;
+    }
+    root.token_at_offset(to_size(first)).right_biased()
+}
+
+fn line_start(text: &str, offset: usize) -> usize {
+    text[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn to_size(offset: usize) -> TextSize {
+    TextSize::try_from(offset).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+
+    /// Applies the edits for a line break typed right before `$0`, and marks the end of the
+    /// indentation of the new line with `$0`.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check_with(input: &str, options: &FormatOptions, expected: Expect) {
+        let offset = input.find("$0").unwrap();
+        let input = input.replace("$0", "");
+        let mut actual = input.clone();
+        for edit in on_newline(&input, to_size(offset), options).iter().rev() {
+            actual.replace_range(std::ops::Range::<usize>::from(edit.range), &edit.text);
+        }
+        let line = input[..offset].matches('\n').count();
+        let start: usize = actual.split_inclusive('\n').take(line).map(str::len).sum();
+        let indent = actual[start..]
+            .find(|c| !matches!(c, ' ' | '\t'))
+            .unwrap_or(actual.len() - start);
+        actual.insert_str(start + indent, "$0");
+        expected.assert_eq(&actual);
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        check_with(input, &FormatOptions::default(), expected);
+    }
+
+    #[test]
+    fn closes_a_new_block() {
+        check(
+            "if x\n$0\ny = 1;\n",
+            expect![[r"
+                if x
+                  $0
+                end
+                y = 1;
+            "]],
+        );
+        check(
+            "for i = 1:3\n$0",
+            expect![[r"
+                for i = 1:3
+                  $0
+                end"]],
+        );
+    }
+
+    #[test]
+    fn does_not_close_a_closed_block() {
+        check(
+            "while x\n$0\nend\n",
+            expect![[r"
+                while x
+                  $0
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn closes_a_block_that_takes_the_end_of_its_parent() {
+        check(
+            "function f\n  try\n$0\nend\n",
+            expect![[r"
+                function f
+                  try
+                    $0
+                  end
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn follows_the_terminator_style() {
+        check(
+            "if a\nendif\nswitch x\n$0\n",
+            expect![[r"
+                if a
+                endif
+                switch x
+                  $0
+                endswitch
+            "]],
+        );
+        let generic = FormatOptions {
+            end_keywords: EndKeywords::Generic,
+            ..FormatOptions::default()
+        };
+        check_with(
+            "if a\nendif\nfunction f\n$0\n",
+            &generic,
+            expect![[r"
+                if a
+                endif
+                function f
+                  $0
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn leaves_functions_of_files_without_end() {
+        check(
+            "function f\n  disp(1)\n\nfunction g\n$0\n",
+            expect![[r"
+                function f
+                  disp(1)
+
+                function g
+                  $0
+            "]],
+        );
+    }
+
+    #[test]
+    fn dedents_parts_as_they_are_typed() {
+        check(
+            "if x\n  y = 1;\n  else\n$0\nend\n",
+            expect![[r"
+                if x
+                  y = 1;
+                else
+                  $0
+                end
+            "]],
+        );
+        check(
+            "switch x\n  case 1\n    y = 1;\n    case 2\n$0\nend\n",
+            expect![[r"
+                switch x
+                  case 1
+                    y = 1;
+                  case 2
+                    $0
+                end
+            "]],
+        );
+    }
+}
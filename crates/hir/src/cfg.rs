@@ -0,0 +1,672 @@
+//! Control-flow graphs of statement bodies.
+//!
+//! A [`Cfg`] splits the body of a function (or of a script) into basic blocks: runs of statements
+//! that execute one after the other. A compound statement such as `if` or `while` is the last
+//! statement of the block its condition is evaluated in, and its parts start blocks of their own.
+//! Inside `try` and `unwind_protect` any block may raise an error, which is modelled by an
+//! exception edge to the `catch` or cleanup part.
+//!
+//! `break`, `continue` and `return` jump straight to their target, even from within
+//! `unwind_protect`, whose cleanup part would run first. The cleanup part is built twice: once
+//! after the protected part completes, going on normally, and once for errors, propagating them.
+//! Its statements are then in two blocks.
+
+use crate::{Database, Expr, ExprIdx, Stmt};
+use std::fmt;
+
+/// Functions that never return normally.
+const ERROR_FUNCTIONS: [&str; 2] = ["error", "rethrow"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub(crate) usize);
+
+#[derive(Debug, Default)]
+pub struct BasicBlock<'a> {
+    /// The statements that start executing in this block.
+    pub stmts: Vec<&'a Stmt>,
+    /// The variable the error is assigned to on entering the block, for the `catch` part of a
+    /// `try`.
+    pub catch_var: Option<ExprIdx>,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: BlockId,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Normal,
+    /// Taken when an error is raised.
+    Exception,
+}
+
+#[derive(Debug)]
+pub struct Cfg<'a> {
+    db: &'a Database,
+    blocks: Vec<BasicBlock<'a>>,
+    reachable: Vec<bool>,
+    stray_jumps: Vec<&'a Stmt>,
+}
+
+impl<'a> Cfg<'a> {
+    pub const ENTRY: BlockId = BlockId(0);
+    /// Where `return`, uncaught errors and the end of the body lead to.
+    pub const EXIT: BlockId = BlockId(1);
+
+    /// Builds the graph of a body. Function definitions nested in it are left out: they have a
+    /// graph of their own.
+    #[must_use]
+    pub fn build(db: &'a Database, body: &'a [Stmt]) -> Self {
+        let mut builder = Builder {
+            db,
+            blocks: vec![BasicBlock::default(), BasicBlock::default()],
+            current: Self::ENTRY,
+            loops: Vec::new(),
+            handlers: Vec::new(),
+            stray_jumps: Vec::new(),
+        };
+        builder.body(body);
+        builder.goto(Self::EXIT);
+
+        let mut cfg = Self {
+            db,
+            reachable: vec![false; builder.blocks.len()],
+            blocks: builder.blocks,
+            stray_jumps: builder.stray_jumps,
+        };
+        cfg.mark_reachable();
+        cfg
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &BasicBlock<'a>)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (BlockId(idx), block))
+    }
+
+    #[must_use]
+    pub fn block(&self, id: BlockId) -> &BasicBlock<'a> {
+        &self.blocks[id.0]
+    }
+
+    /// Returns the first block a statement starts executing in, if it belongs to this graph.
+    #[must_use]
+    pub fn block_of(&self, stmt: &Stmt) -> Option<BlockId> {
+        self.blocks()
+            .find(|(_, block)| block.stmts.iter().any(|other| std::ptr::eq(*other, stmt)))
+            .map(|(id, _)| id)
+    }
+
+    #[must_use]
+    pub fn is_block_reachable(&self, id: BlockId) -> bool {
+        self.reachable[id.0]
+    }
+
+    /// Whether a statement of the body can ever execute, from any of its blocks.
+    #[must_use]
+    pub fn is_reachable(&self, stmt: &Stmt) -> bool {
+        self.blocks().any(|(id, block)| {
+            self.is_block_reachable(id)
+                && block.stmts.iter().any(|other| std::ptr::eq(*other, stmt))
+        })
+    }
+
+    /// Returns the `break` and `continue` statements outside of any loop.
+    #[must_use]
+    pub fn stray_jumps(&self) -> &[&'a Stmt] {
+        &self.stray_jumps
+    }
+
+    fn mark_reachable(&mut self) {
+        let mut stack = vec![Self::ENTRY];
+        while let Some(id) = stack.pop() {
+            if !std::mem::replace(&mut self.reachable[id.0], true) {
+                stack.extend(self.blocks[id.0].successors.iter().map(|edge| edge.target));
+            }
+        }
+    }
+}
+
+/// Returns the top-level body of a file followed by the bodies of all its functions, in source
+/// order.
+#[must_use]
+pub fn bodies(file: &[Stmt]) -> Vec<&[Stmt]> {
+    std::iter::once(file)
+        .chain(crate::preorder(file).filter_map(|stmt| match stmt {
+            Stmt::FnDef { body, .. } => Some(body.as_slice()),
+            _ => None,
+        }))
+        .collect()
+}
+
+struct Loop {
+    header: BlockId,
+    after: BlockId,
+}
+
+struct Builder<'a> {
+    db: &'a Database,
+    blocks: Vec<BasicBlock<'a>>,
+    current: BlockId,
+    loops: Vec<Loop>,
+    /// The entries of the enclosing `catch` and cleanup parts, innermost last.
+    handlers: Vec<BlockId>,
+    stray_jumps: Vec<&'a Stmt>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock::default());
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn edge(&mut self, from: BlockId, target: BlockId, kind: EdgeKind) {
+        let edge = Edge { target, kind };
+        let successors = &mut self.blocks[from.0].successors;
+        if !successors.contains(&edge) {
+            successors.push(edge);
+        }
+    }
+
+    fn goto(&mut self, target: BlockId) {
+        self.edge(self.current, target, EdgeKind::Normal);
+    }
+
+    /// Continues in a new block that nothing leads to, after a jump.
+    fn dead_end(&mut self) {
+        self.current = self.new_block();
+    }
+
+    fn push(&mut self, stmt: &'a Stmt) {
+        self.blocks[self.current.0].stmts.push(stmt);
+    }
+
+    fn body(&mut self, stmts: &'a [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::FnDef { .. } => {}
+            Stmt::VariableDef { .. }
+            | Stmt::Declaration { .. }
+            | Stmt::Command { .. }
+            | Stmt::Expr(_) => {
+                self.push(stmt);
+                if self.raises_error(stmt) {
+                    let handler = self.handlers.last().copied().unwrap_or(Cfg::EXIT);
+                    self.edge(self.current, handler, EdgeKind::Exception);
+                    self.dead_end();
+                }
+            }
+            Stmt::If {
+                body,
+                else_branches,
+                ..
+            } => {
+                self.push(stmt);
+                let bodies = std::iter::once(body.as_slice())
+                    .chain(else_branches.iter().map(|branch| branch.body.as_slice()));
+                let has_fallback = else_branches
+                    .iter()
+                    .any(|branch| branch.condition.is_none());
+                self.branches(bodies, has_fallback);
+            }
+            Stmt::Switch { cases, .. } => {
+                self.push(stmt);
+                let has_fallback = cases.iter().any(|case| case.condition.is_none());
+                self.branches(cases.iter().map(|case| case.body.as_slice()), has_fallback);
+            }
+            Stmt::WhileLoop { body, .. } | Stmt::ForLoop { body, .. } => {
+                let header = self.new_block();
+                self.goto(header);
+                self.current = header;
+                self.push(stmt);
+
+                let entry = self.new_block();
+                let after = self.new_block();
+                self.edge(header, entry, EdgeKind::Normal);
+                self.edge(header, after, EdgeKind::Normal);
+
+                self.loops.push(Loop { header, after });
+                self.current = entry;
+                self.body(body);
+                self.goto(header);
+                self.loops.pop();
+
+                self.current = after;
+            }
+            Stmt::Break | Stmt::Continue => {
+                self.push(stmt);
+                let target = self.loops.last().map(|target| match stmt {
+                    Stmt::Break => target.after,
+                    _ => target.header,
+                });
+                if let Some(target) = target {
+                    self.goto(target);
+                    self.dead_end();
+                } else if !self
+                    .stray_jumps
+                    .iter()
+                    .any(|other| std::ptr::eq(*other, stmt))
+                {
+                    // Octave rejects the whole file, so there is no point guessing where it goes.
+                    self.stray_jumps.push(stmt);
+                }
+            }
+            Stmt::Return => {
+                self.push(stmt);
+                self.goto(Cfg::EXIT);
+                self.dead_end();
+            }
+            Stmt::Try {
+                body,
+                catch_var,
+                catch,
+            } => {
+                self.push(stmt);
+                let handler = self.new_block();
+                let after = self.new_block();
+
+                self.protected(body, handler);
+                self.goto(after);
+
+                self.current = handler;
+                self.blocks[handler.0].catch_var = *catch_var;
+                self.body(catch);
+                self.goto(after);
+
+                self.current = after;
+            }
+            Stmt::UnwindProtect { body, cleanup } => {
+                self.push(stmt);
+                self.unwind_protect(body, cleanup);
+            }
+        }
+    }
+
+    fn unwind_protect(&mut self, body: &'a [Stmt], cleanup: &'a [Stmt]) {
+        let handler = self.new_block();
+        let completed = self.new_block();
+
+        self.protected(body, handler);
+        self.goto(completed);
+
+        // The cleanup part runs either way, and the error goes on after it.
+        self.current = completed;
+        self.body(cleanup);
+        let after = self.new_block();
+        self.goto(after);
+
+        self.current = handler;
+        self.body(cleanup);
+        let outer = self.handlers.last().copied().unwrap_or(Cfg::EXIT);
+        self.edge(self.current, outer, EdgeKind::Exception);
+
+        self.current = after;
+    }
+
+    /// Builds the branches of an `if` or a `switch`, all starting from the current block.
+    fn branches(&mut self, bodies: impl Iterator<Item = &'a [Stmt]>, has_fallback: bool) {
+        let from = self.current;
+        let after = self.new_block();
+
+        for body in bodies {
+            let entry = self.new_block();
+            self.edge(from, entry, EdgeKind::Normal);
+            self.current = entry;
+            self.body(body);
+            self.goto(after);
+        }
+        if !has_fallback {
+            self.edge(from, after, EdgeKind::Normal);
+        }
+
+        self.current = after;
+    }
+
+    /// Builds a body where any block may raise an error that is handled by `handler`.
+    fn protected(&mut self, body: &'a [Stmt], handler: BlockId) {
+        let entry = self.new_block();
+        self.goto(entry);
+        self.current = entry;
+
+        self.handlers.push(handler);
+        self.body(body);
+        self.handlers.pop();
+
+        for idx in entry.0..self.blocks.len() {
+            if BlockId(idx) != handler {
+                self.edge(BlockId(idx), handler, EdgeKind::Exception);
+            }
+        }
+    }
+
+    fn raises_error(&self, stmt: &Stmt) -> bool {
+        let func = match stmt {
+            Stmt::Expr(expr) => match &self.db[*expr] {
+                Expr::Call { func, .. } => *func,
+                _ => return false,
+            },
+            Stmt::Command { name, .. } => *name,
+            _ => return false,
+        };
+        matches!(&self.db[func], Expr::VariableRef { var } if ERROR_FUNCTIONS.contains(&var.as_str()))
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Cfg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, block) in self.blocks() {
+            write!(f, "{id}")?;
+            if id == Self::ENTRY {
+                write!(f, " (entry)")?;
+            } else if id == Self::EXIT {
+                write!(f, " (exit)")?;
+            } else if !self.is_block_reachable(id) {
+                write!(f, " (unreachable)")?;
+            }
+            writeln!(f, ":")?;
+
+            for stmt in &block.stmts {
+                writeln!(f, "    {}", describe(self.db, stmt))?;
+            }
+            if !block.successors.is_empty() {
+                let successors: Vec<_> = block
+                    .successors
+                    .iter()
+                    .map(|edge| match edge.kind {
+                        EdgeKind::Normal => edge.target.to_string(),
+                        EdgeKind::Exception => format!("{} (exception)", edge.target),
+                    })
+                    .collect();
+                writeln!(f, "    -> {}", successors.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A one-line summary of a statement, for debugging.
+fn describe(db: &Database, stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::VariableDef { targets, .. } => {
+            let names: Vec<_> = targets
+                .iter()
+                .map(|target| match target.var().map(|var| &db[var]) {
+                    Some(Expr::VariableRef { var }) => var.as_str(),
+                    _ => "_",
+                })
+                .collect();
+            match &names[..] {
+                [name] => format!("{name} = ..."),
+                names => format!("[{}] = ...", names.join(", ")),
+            }
+        }
+        Stmt::Declaration {
+            is_global, targets, ..
+        } => {
+            let keyword = if *is_global { "global" } else { "persistent" };
+            let names: Vec<_> = targets
+                .iter()
+                .filter_map(|target| match &db[target.var()?] {
+                    Expr::VariableRef { var } => Some(var.as_str()),
+                    _ => None,
+                })
+                .collect();
+            format!("{keyword} {}", names.join(" "))
+        }
+        Stmt::FnDef { .. } => "function".to_string(),
+        Stmt::If { .. } => "if".to_string(),
+        Stmt::ForLoop { .. } => "for".to_string(),
+        Stmt::WhileLoop { .. } => "while".to_string(),
+        Stmt::Switch { .. } => "switch".to_string(),
+        Stmt::Try { .. } => "try".to_string(),
+        Stmt::UnwindProtect { .. } => "unwind_protect".to_string(),
+        Stmt::Break => "break".to_string(),
+        Stmt::Continue => "continue".to_string(),
+        Stmt::Return => "return".to_string(),
+        Stmt::Command { name, .. } => match &db[*name] {
+            Expr::VariableRef { var } => format!("{var} ..."),
+            _ => "command".to_string(),
+        },
+        Stmt::Expr(expr) => match &db[*expr] {
+            Expr::Call { func, .. } => match &db[*func] {
+                Expr::VariableRef { var } => format!("{var}(...)"),
+                _ => "expression".to_string(),
+            },
+            Expr::VariableRef { var } => var.to_string(),
+            _ => "expression".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::TypedSyntaxNode;
+    use expect_test::{Expect, expect};
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let crate::Body { db, stmts, .. } = crate::lower(&root);
+        let dumps: Vec<_> = bodies(&stmts)
+            .into_iter()
+            .map(|body| Cfg::build(&db, body).to_string())
+            .collect();
+
+        expected.assert_eq(&dumps.join("\n"));
+    }
+
+    #[test]
+    fn straight_line() {
+        check(
+            "a = 1;\nb = a;\ndisp(b)",
+            expect![[r"
+                bb0 (entry):
+                    a = ...
+                    b = ...
+                    disp(...)
+                    -> bb1
+                bb1 (exit):
+            "]],
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        check(
+            "if x\n  a = 1;\nelseif y\n  a = 2;\nelse\n  a = 3;\nend\ndisp(a)",
+            expect![[r"
+                bb0 (entry):
+                    if
+                    -> bb3, bb4, bb5
+                bb1 (exit):
+                bb2:
+                    disp(...)
+                    -> bb1
+                bb3:
+                    a = ...
+                    -> bb2
+                bb4:
+                    a = ...
+                    -> bb2
+                bb5:
+                    a = ...
+                    -> bb2
+            "]],
+        );
+    }
+
+    #[test]
+    fn if_without_else() {
+        check(
+            "if x\n  a = 1;\nend",
+            expect![[r"
+                bb0 (entry):
+                    if
+                    -> bb3, bb2
+                bb1 (exit):
+                bb2:
+                    -> bb1
+                bb3:
+                    a = ...
+                    -> bb2
+            "]],
+        );
+    }
+
+    #[test]
+    fn while_with_break_and_continue() {
+        check(
+            "while x\n  if y\n    break;\n  end\n  continue;\n  z = 1;\nend",
+            expect![[r"
+                bb0 (entry):
+                    -> bb2
+                bb1 (exit):
+                bb2:
+                    while
+                    -> bb3, bb4
+                bb3:
+                    if
+                    -> bb6, bb5
+                bb4:
+                    -> bb1
+                bb5:
+                    continue
+                    -> bb2
+                bb6:
+                    break
+                    -> bb4
+                bb7 (unreachable):
+                    -> bb5
+                bb8 (unreachable):
+                    z = ...
+                    -> bb2
+            "]],
+        );
+    }
+
+    #[test]
+    fn switch_with_otherwise() {
+        check(
+            "switch x\n  case 1\n    a = 1;\n  otherwise\n    a = 2;\nend",
+            expect![[r"
+                bb0 (entry):
+                    switch
+                    -> bb3, bb4
+                bb1 (exit):
+                bb2:
+                    -> bb1
+                bb3:
+                    a = ...
+                    -> bb2
+                bb4:
+                    a = ...
+                    -> bb2
+            "]],
+        );
+    }
+
+    #[test]
+    fn try_catch() {
+        check(
+            "try\n  a = f();\ncatch\n  a = 0;\nend\ndisp(a)",
+            expect![[r"
+                bb0 (entry):
+                    try
+                    -> bb4
+                bb1 (exit):
+                bb2:
+                    a = ...
+                    -> bb3
+                bb3:
+                    disp(...)
+                    -> bb1
+                bb4:
+                    a = ...
+                    -> bb2 (exception), bb3
+            "]],
+        );
+    }
+
+    #[test]
+    fn unwind_protect() {
+        check(
+            "unwind_protect\n  error('oops');\nunwind_protect_cleanup\n  disp(1);\nend_unwind_protect\ndisp(2)",
+            expect![[r"
+                bb0 (entry):
+                    unwind_protect
+                    -> bb4
+                bb1 (exit):
+                bb2:
+                    disp(...)
+                    -> bb1 (exception)
+                bb3 (unreachable):
+                    disp(...)
+                    -> bb6
+                bb4:
+                    error(...)
+                    -> bb2 (exception)
+                bb5 (unreachable):
+                    -> bb2 (exception), bb3
+                bb6 (unreachable):
+                    disp(...)
+                    -> bb1
+            "]],
+        );
+    }
+
+    #[test]
+    fn return_and_error_end_blocks() {
+        check(
+            "function f(x)\n  if x\n    return;\n    disp(1);\n  end\n  error('no');\n  disp(2);\nend",
+            expect![[r"
+                bb0 (entry):
+                    -> bb1
+                bb1 (exit):
+
+                bb0 (entry):
+                    if
+                    -> bb3, bb2
+                bb1 (exit):
+                bb2:
+                    error(...)
+                    -> bb1 (exception)
+                bb3:
+                    return
+                    -> bb1
+                bb4 (unreachable):
+                    disp(...)
+                    -> bb2
+                bb5 (unreachable):
+                    disp(...)
+                    -> bb1
+            "]],
+        );
+    }
+
+    #[test]
+    fn stray_jumps() {
+        let root =
+            ast::Root::cast(parser::parse("break\nfor i = 1:2\n  continue\nend").syntax()).unwrap();
+        let crate::Body { db, stmts, .. } = crate::lower(&root);
+        let cfg = Cfg::build(&db, &stmts);
+
+        assert_eq!(cfg.stray_jumps(), [&Stmt::Break]);
+        assert!(cfg.is_reachable(&stmts[1]));
+    }
+}
@@ -1,60 +1,234 @@
-use crate::{BinaryOp, Expr, Stmt, TransposeOp, UnaryOp};
+use crate::source_map::SourceMap;
+use crate::{BinaryOp, Branch, Expr, ExprIdx, Stmt, Target, TransposeOp, UnaryOp};
 use la_arena::Arena;
-use syntax::{SyntaxKind, TokenKind};
+use std::ops::Index;
+use syntax::{SyntaxElement, SyntaxKind, SyntaxNode, TokenKind};
+use text_size::TextRange;
 
 #[derive(Debug, PartialEq, Default)]
 pub struct Database {
     exprs: Arena<Expr>,
+    source_map: SourceMap,
+}
+
+impl Index<ExprIdx> for Database {
+    type Output = Expr;
+
+    fn index(&self, idx: ExprIdx) -> &Expr {
+        &self.exprs[idx]
+    }
 }
 
 impl Database {
-    pub(crate) fn lower_stmt(&mut self, ast: ast::Stmt) -> Option<Stmt> {
-        let result = match ast {
-            ast::Stmt::VariableDef(ast) => Stmt::VariableDef {
-                name: ast.name()?.text().into(),
-                value: self.lower_expr(ast.value()),
-            },
-            ast::Stmt::FnDef(ast) => Stmt::FnDef {
-                body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
-            },
+    /// Returns the range of a statement of the file lowered by this database, at any depth.
+    #[must_use]
+    pub fn stmt_range(&self, stmt: &Stmt) -> Option<TextRange> {
+        self.source_map.stmt_range(self.source_map.stmt_id(stmt)?)
+    }
+
+    /// Returns the expressions in the arena, which are all the expressions of the file.
+    pub fn exprs(&self) -> impl Iterator<Item = (ExprIdx, &Expr)> {
+        self.exprs.iter()
+    }
+
+    /// Returns where the statements and expressions of the arena come from in the syntax tree.
+    #[must_use]
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Numbers the statements of the file once it is lowered, to find their ranges.
+    pub(crate) fn index_stmts(&mut self, file: &[Stmt]) {
+        self.source_map.index_stmts(file);
+    }
+
+    /// Lowers a list of statements, gathering each command and its words into one statement.
+    pub(crate) fn lower_stmts(&mut self, stmts: impl IntoIterator<Item = ast::Stmt>) -> Vec<Stmt> {
+        let mut stmts = stmts.into_iter().peekable();
+        let mut lowered = Vec::new();
+        while let Some(stmt) = stmts.next() {
+            // Command syntax, as in `hold on`, shows up as a bare name followed by statements on
+            // the same line.
+            let mut words = Vec::new();
+            if let ast::Stmt::Expr(ast::Expr::VariableRef(_)) = &stmt {
+                let mut last = stmt.syntax().clone();
+                while let Some(ast::Stmt::Expr(word)) =
+                    stmts.next_if(|next| continues_command(&last, next))
+                {
+                    last = word.syntax().clone();
+                    words.push(word);
+                }
+            }
+            lowered.push(match (stmt, words.is_empty()) {
+                (ast::Stmt::Expr(name), false) => self.lower_command(name, words),
+                (stmt, _) => self.lower_stmt(stmt),
+            });
+        }
+        lowered
+    }
+
+    fn lower_command(&mut self, name: ast::Expr, words: Vec<ast::Expr>) -> Stmt {
+        let last = words.last().unwrap().syntax();
+        let range = trimmed_range(name.syntax()).cover(trimmed_range(last));
+        self.source_map.insert_stmt(name.syntax(), range);
+
+        Stmt::Command {
+            name: self.lower_expr_idx(Some(name)),
+            words: words
+                .into_iter()
+                .map(|word| self.lower_expr_idx(Some(word)))
+                .collect(),
+        }
+    }
+
+    fn lower_stmt(&mut self, ast: ast::Stmt) -> Stmt {
+        // Statements are recorded in pre-order, so their ids line up with `crate::preorder`.
+        self.source_map
+            .insert_stmt(ast.syntax(), trimmed_range(ast.syntax()));
+
+        match ast {
+            ast::Stmt::VariableDef(ast) => {
+                // The value is evaluated before anything is assigned.
+                let value = self.lower_expr_idx(ast.value());
+                Stmt::VariableDef {
+                    targets: self.lower_targets(ast.lhs()),
+                    value,
+                }
+            }
+            ast::Stmt::FnDef(ast) => {
+                let outputs = self.lower_variables(ast.outputs());
+                Stmt::FnDef {
+                    name: ast.name().map(|name| name.text().into()),
+                    params: self.lower_variables(ast.params()),
+                    outputs,
+                    body: self.lower_stmts(ast.body()),
+                }
+            }
             ast::Stmt::IfStmt(ast) => {
-                let condition = self.lower_expr(ast.condition());
+                let mut branches = ast.branches().into_iter();
+                let first = branches.next();
+                let condition =
+                    self.lower_expr_idx(first.as_ref().and_then(|b| b.condition().cloned()));
+                let body =
+                    first.map_or_else(Vec::new, |b| self.lower_stmts(b.body().iter().cloned()));
                 Stmt::If {
-                    condition: self.exprs.alloc(condition),
-                    body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
+                    condition,
+                    body,
+                    else_branches: branches.map(|b| self.lower_branch(&b)).collect(),
+                }
+            }
+            ast::Stmt::ForLoop(ast) => {
+                let header = ast.header();
+                let value = self.lower_expr_idx(header.as_ref().and_then(ast::VariableDef::value));
+                Stmt::ForLoop {
+                    targets: self.lower_targets(header.and_then(|header| header.lhs())),
+                    value,
+                    body: self.lower_stmts(ast.body()),
                 }
             }
-            ast::Stmt::ForLoop(ast) => Stmt::ForLoop {
-                body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
-            },
             ast::Stmt::WhileLoop(ast) => {
-                let condition = self.lower_expr(ast.condition());
+                let condition = self.lower_expr_idx(ast.condition());
                 Stmt::WhileLoop {
-                    condition: self.exprs.alloc(condition),
-                    body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
+                    condition,
+                    body: self.lower_stmts(ast.body()),
                 }
             }
             ast::Stmt::BreakStmt(_) => Stmt::Break,
             ast::Stmt::ContinueStmt(_) => Stmt::Continue,
+            ast::Stmt::ReturnStmt(_) => Stmt::Return,
             ast::Stmt::TryStmt(ast) => Stmt::Try {
-                body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
-                catch: vec![], // TODO: split catch body from try body by CatchKw token
+                body: self.lower_stmts(ast.try_body()),
+                catch_var: ast
+                    .catch_var()
+                    .map(|var| self.lower_expr_idx(Some(ast::Expr::VariableRef(var)))),
+                catch: self.lower_stmts(ast.catch_body()),
             },
             ast::Stmt::UnwindProtectStmt(ast) => Stmt::UnwindProtect {
-                body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
-                cleanup: vec![], // TODO: split cleanup body by UnwindProtectCleanupKw token
+                body: self.lower_stmts(ast.protected_body()),
+                cleanup: self.lower_stmts(ast.cleanup_body()),
             },
             ast::Stmt::SwitchStmt(ast) => {
-                let condition = self.lower_expr(ast.condition());
+                let condition = self.lower_expr_idx(ast.condition());
                 Stmt::Switch {
-                    condition: self.exprs.alloc(condition),
-                    body: ast.body().filter_map(|s| self.lower_stmt(s)).collect(),
+                    condition,
+                    cases: ast.cases().iter().map(|b| self.lower_branch(b)).collect(),
                 }
             }
-            ast::Stmt::Expr(ast) => Stmt::Expr(self.lower_expr(Some(ast))),
-        };
+            ast::Stmt::Declaration(ast) => {
+                // The initial values are evaluated before anything is declared.
+                let values = ast.values().map(|value| self.lower_expr_idx(Some(value)));
+                let values = values.collect();
+                Stmt::Declaration {
+                    is_global: ast.is_global(),
+                    targets: self
+                        .lower_variables(ast.variables())
+                        .into_iter()
+                        .map(Target::Variable)
+                        .collect(),
+                    values,
+                }
+            }
+            ast::Stmt::Expr(ast) => Stmt::Expr(self.lower_expr_idx(Some(ast))),
+        }
+    }
+
+    fn lower_variables(&mut self, vars: Vec<ast::VariableRef>) -> Vec<ExprIdx> {
+        vars.into_iter()
+            .map(|var| self.lower_expr_idx(Some(ast::Expr::VariableRef(var))))
+            .collect()
+    }
 
-        Some(result)
+    fn lower_branch(&mut self, ast: &ast::Branch) -> Branch {
+        let condition = ast
+            .condition()
+            .map(|condition| self.lower_expr_idx(Some(condition.clone())));
+        Branch {
+            condition,
+            body: self.lower_stmts(ast.body().iter().cloned()),
+        }
+    }
+
+    /// Lowers the left-hand side of an assignment, which holds several targets in `[a, b] = ...`.
+    fn lower_targets(&mut self, lhs: Option<ast::Expr>) -> Vec<Target> {
+        match lhs {
+            Some(ast::Expr::MatrixExpr(targets)) => targets
+                .elements()
+                .flat_map(|target| self.lower_targets(Some(target)))
+                .collect(),
+            Some(ast::Expr::VariableRef(_)) => vec![Target::Variable(self.lower_expr_idx(lhs))],
+            Some(lhs) if assigned_var(&lhs).is_some() => {
+                let mut indices = Vec::new();
+                let var = self.lower_part(lhs, &mut indices);
+                vec![Target::Part { var, indices }]
+            }
+            Some(lhs) => vec![Target::Invalid(self.lower_expr_idx(Some(lhs)))],
+            None => vec![],
+        }
+    }
+
+    /// Lowers an indexed or field assignment down to its variable, collecting the indices in
+    /// source order.
+    fn lower_part(&mut self, ast: ast::Expr, indices: &mut Vec<ExprIdx>) -> ExprIdx {
+        match ast {
+            ast::Expr::CallExpr(call) => {
+                let var = self.lower_part(call.func().unwrap(), indices);
+                indices.extend(call.args().map(|arg| self.lower_expr_idx(Some(arg))));
+                var
+            }
+            ast::Expr::FieldExpr(field) => self.lower_part(field.expr().unwrap(), indices),
+            ast => self.lower_expr_idx(Some(ast)),
+        }
+    }
+
+    /// Lowers an expression into the arena, remembering where it came from.
+    fn lower_expr_idx(&mut self, ast: Option<ast::Expr>) -> ExprIdx {
+        let node = ast.as_ref().map(|ast| ast.syntax().clone());
+        let expr = self.lower_expr(ast);
+        let idx = self.exprs.alloc(expr);
+        if let Some(node) = node {
+            self.source_map.insert_expr(idx, &node, expr_range(&node));
+        }
+        idx
     }
 
     pub(crate) fn lower_expr(&mut self, ast: Option<ast::Expr>) -> Expr {
@@ -71,6 +245,14 @@ impl Database {
                 ast::Expr::StringLiteral(ast) => Expr::String {
                     value: ast.value().into(),
                 },
+                ast::Expr::FnHandle(ast) => match ast.name() {
+                    Some(name) => Expr::FnHandle {
+                        name: name.text().into(),
+                    },
+                    None => Expr::Missing,
+                },
+                ast::Expr::FieldExpr(ast) => self.lower_field(&ast),
+                ast::Expr::EndIndex(_) => Expr::EndIndex,
             }
         } else {
             Expr::Missing
@@ -88,14 +270,11 @@ impl Database {
             unreachable!()
         };
 
-        let lhs = self.lower_expr(ast.lhs());
-        let rhs = self.lower_expr(ast.rhs());
+        let lhs = self.lower_expr_idx(ast.lhs());
+        let rhs = self.lower_expr_idx(ast.rhs());
 
         match token_kind {
-            TokenKind::Colon => Expr::Range {
-                lhs: self.exprs.alloc(lhs),
-                rhs: self.exprs.alloc(rhs),
-            },
+            TokenKind::Colon => Expr::Range { lhs, rhs },
             kind => {
                 let op = match kind {
                     TokenKind::Plus => BinaryOp::Add,
@@ -126,11 +305,7 @@ impl Database {
                     TokenKind::ElmtPowEquals => BinaryOp::ElmtPowEquals,
                     _ => unreachable!(),
                 };
-                Expr::Binary {
-                    op,
-                    lhs: self.exprs.alloc(lhs),
-                    rhs: self.exprs.alloc(rhs),
-                }
+                Expr::Binary { op, lhs, rhs }
             }
         }
     }
@@ -148,27 +323,35 @@ impl Database {
             _ => unreachable!(),
         };
 
-        let expr = self.lower_expr(ast.expr());
-
         Expr::Unary {
             op,
-            expr: self.exprs.alloc(expr),
+            expr: self.lower_expr_idx(ast.expr()),
         }
     }
 
     fn lower_call(&mut self, ast: &ast::CallExpr) -> Expr {
-        let func = self.lower_expr(ast.func());
-        let args: Vec<_> = ast.args().map(|e| self.lower_expr(Some(e))).collect();
         Expr::Call {
-            func: self.exprs.alloc(func),
-            args: args.into_iter().map(|e| self.exprs.alloc(e)).collect(),
+            func: self.lower_expr_idx(ast.func()),
+            args: ast.args().map(|e| self.lower_expr_idx(Some(e))).collect(),
         }
     }
 
     fn lower_matrix(&mut self, ast: &ast::MatrixExpr) -> Expr {
-        let elements: Vec<_> = ast.elements().map(|e| self.lower_expr(Some(e))).collect();
         Expr::Matrix {
-            elements: elements.into_iter().map(|e| self.exprs.alloc(e)).collect(),
+            elements: ast
+                .elements()
+                .map(|e| self.lower_expr_idx(Some(e)))
+                .collect(),
+        }
+    }
+
+    fn lower_field(&mut self, ast: &ast::FieldExpr) -> Expr {
+        let Some(name) = ast.field() else {
+            return Expr::Missing;
+        };
+        Expr::Field {
+            expr: self.lower_expr_idx(ast.expr()),
+            name: name.text().into(),
         }
     }
 
@@ -178,14 +361,71 @@ impl Database {
             SyntaxKind::LexToken(TokenKind::ElmtTranspose) => TransposeOp::Elmt,
             _ => unreachable!(),
         };
-        let expr = self.lower_expr(ast.expr());
         Expr::Transpose {
             op,
-            expr: self.exprs.alloc(expr),
+            expr: self.lower_expr_idx(ast.expr()),
         }
     }
 }
 
+/// Returns the variable an indexed or field assignment writes to, as `x` in `x(2).a = 1`.
+fn assigned_var(lhs: &ast::Expr) -> Option<ast::VariableRef> {
+    match lhs {
+        ast::Expr::VariableRef(var) => Some(var.clone()),
+        ast::Expr::CallExpr(call) => assigned_var(&call.func()?),
+        ast::Expr::FieldExpr(field) => assigned_var(&field.expr()?),
+        _ => None,
+    }
+}
+
+/// Whether `next` is a word of the command ending with `last`, which it directly follows on the
+/// same line.
+fn continues_command(last: &SyntaxNode, next: &ast::Stmt) -> bool {
+    // Statements carry their trailing trivia and separators.
+    let ends_line = last
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .any(|token| {
+            matches!(
+                token.kind(),
+                SyntaxKind::LexToken(TokenKind::Newline | TokenKind::Semicolon | TokenKind::Comma)
+            )
+        });
+    matches!(next, ast::Stmt::Expr(_))
+        && !ends_line
+        && next.syntax().text_range().start() == last.text_range().end()
+}
+
+/// The range of a node without its leading and trailing trivia.
+fn trimmed_range(node: &SyntaxNode) -> TextRange {
+    let mut tokens = node
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()));
+    let Some(first) = tokens.next() else {
+        return node.text_range();
+    };
+    let last = tokens.last().unwrap_or_else(|| first.clone());
+    TextRange::new(first.text_range().start(), last.text_range().end())
+}
+
+/// The range of an expression without its trivia and the `;` it may have absorbed from the end
+/// of its statement.
+fn expr_range(node: &SyntaxNode) -> TextRange {
+    let tokens: Vec<_> = node
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()))
+        .collect();
+    let last = tokens
+        .iter()
+        .rposition(|token| token.kind() != TokenKind::Semicolon.into());
+    match (tokens.first(), last) {
+        (Some(first), Some(last)) => first.text_range().cover(tokens[last].text_range()),
+        _ => trimmed_range(node),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,46 +435,90 @@ mod tests {
         ast::Root::cast(parser::parse(input).syntax()).unwrap()
     }
 
+    #[allow(clippy::needless_pass_by_value)]
     fn check_stmt(input: &str, expected_hir: Stmt) {
         let root = parse(input);
         let ast = root.stmts().next().unwrap();
-        let hir = Database::default().lower_stmt(ast).unwrap();
+        let hir = Database::default().lower_stmt(ast);
 
         assert_eq!(hir, expected_hir);
     }
 
+    #[allow(clippy::needless_pass_by_value)]
     fn check_expr(input: &str, expected_hir: Expr, expected_database: Database) {
         let root = parse(input);
         let first_stmt = root.stmts().next().unwrap();
-        let ast = match first_stmt {
-            ast::Stmt::Expr(ast) => ast,
-            _ => unreachable!(),
+        let ast::Stmt::Expr(ast) = first_stmt else {
+            unreachable!()
         };
         let mut database = Database::default();
         let hir = database.lower_expr(Some(ast));
 
         assert_eq!(hir, expected_hir);
-        assert_eq!(database, expected_database);
+        assert_eq!(database.exprs, expected_database.exprs);
     }
 
     #[test]
     fn lower_variable_def() {
         let root = parse("foo = bar");
         let ast = root.stmts().next().unwrap();
-        let hir = Database::default().lower_stmt(ast).unwrap();
+        let mut database = Database::default();
+        let hir = database.lower_stmt(ast);
+        let mut exprs = Arena::new();
+        let bar = exprs.alloc(Expr::VariableRef { var: "bar".into() });
+        let foo = exprs.alloc(Expr::VariableRef { var: "foo".into() });
 
         assert_eq!(
             hir,
             Stmt::VariableDef {
-                name: "foo".into(),
-                value: Expr::VariableRef { var: "bar".into() },
+                targets: vec![Target::Variable(foo)],
+                value: bar,
             },
         );
+        assert_eq!(database.exprs, exprs);
+    }
+
+    #[test]
+    fn lower_every_kind_of_target() {
+        let root = parse("[a, s.b(i).c, 1] = f(x)");
+        let ast = root.stmts().next().unwrap();
+        let mut database = Database::default();
+        let hir = database.lower_stmt(ast);
+        let mut exprs = Arena::new();
+        let func = exprs.alloc(Expr::VariableRef { var: "f".into() });
+        let arg = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let value = exprs.alloc(Expr::Call {
+            func,
+            args: vec![arg],
+        });
+        let var = exprs.alloc(Expr::VariableRef { var: "a".into() });
+        let part = exprs.alloc(Expr::VariableRef { var: "s".into() });
+        let index = exprs.alloc(Expr::VariableRef { var: "i".into() });
+        let one = exprs.alloc(Expr::Literal { n: Some(1) });
+
+        assert_eq!(
+            hir,
+            Stmt::VariableDef {
+                targets: vec![
+                    Target::Variable(var),
+                    Target::Part {
+                        var: part,
+                        indices: vec![index],
+                    },
+                    Target::Invalid(one),
+                ],
+                value,
+            },
+        );
+        assert_eq!(database.exprs, exprs);
     }
 
     #[test]
     fn lower_expr_stmt() {
-        check_stmt("123", Stmt::Expr(Expr::Literal { n: Some(123) }));
+        let mut exprs = Arena::new();
+        let expr = exprs.alloc(Expr::Literal { n: Some(123) });
+
+        check_stmt("123", Stmt::Expr(expr));
     }
 
     #[test]
@@ -250,7 +534,10 @@ mod tests {
                 rhs,
                 op: BinaryOp::Add,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -279,7 +566,10 @@ mod tests {
                 expr: ten,
                 op: UnaryOp::Neg,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -294,11 +584,15 @@ mod tests {
 
     #[test]
     fn lower_variable_def_without_value() {
+        let mut exprs = Arena::new();
+        let missing = exprs.alloc(Expr::Missing);
+        let a = exprs.alloc(Expr::VariableRef { var: "a".into() });
+
         check_stmt(
             "a =",
             Stmt::VariableDef {
-                name: "a".into(),
-                value: Expr::Missing,
+                targets: vec![Target::Variable(a)],
+                value: missing,
             },
         );
     }
@@ -316,7 +610,10 @@ mod tests {
                 rhs,
                 op: BinaryOp::Sub,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -331,7 +628,10 @@ mod tests {
                 expr,
                 op: UnaryOp::Neg,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -348,7 +648,10 @@ mod tests {
                 rhs,
                 op: BinaryOp::ElmtMult,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -365,7 +668,10 @@ mod tests {
                 rhs,
                 op: BinaryOp::Pow,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -375,7 +681,14 @@ mod tests {
         let lhs = exprs.alloc(Expr::Literal { n: Some(1) });
         let rhs = exprs.alloc(Expr::Literal { n: Some(10) });
 
-        check_expr("1:10", Expr::Range { lhs, rhs }, Database { exprs });
+        check_expr(
+            "1:10",
+            Expr::Range { lhs, rhs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
+        );
     }
 
     #[test]
@@ -383,7 +696,14 @@ mod tests {
         let mut exprs = Arena::new();
         let func = exprs.alloc(Expr::VariableRef { var: "f".into() });
 
-        check_expr("f()", Expr::Call { func, args: vec![] }, Database { exprs });
+        check_expr(
+            "f()",
+            Expr::Call { func, args: vec![] },
+            Database {
+                exprs,
+                ..Database::default()
+            },
+        );
     }
 
     #[test]
@@ -398,10 +718,36 @@ mod tests {
                 func,
                 args: vec![arg],
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
+        );
+    }
+
+    #[test]
+    fn lower_field() {
+        let mut exprs = Arena::new();
+        let expr = exprs.alloc(Expr::VariableRef { var: "s".into() });
+
+        check_expr(
+            "s.name",
+            Expr::Field {
+                expr,
+                name: "name".into(),
+            },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
+    #[test]
+    fn lower_field_without_name() {
+        check_expr("s.", Expr::Missing, Database::default());
+    }
+
     #[test]
     fn lower_matrix_2d() {
         let mut exprs = Arena::new();
@@ -415,7 +761,10 @@ mod tests {
             Expr::Matrix {
                 elements: vec![e1, e2, e3, e4],
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -423,12 +772,68 @@ mod tests {
     fn lower_if_stmt() {
         let mut exprs = Arena::new();
         let condition = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let y = exprs.alloc(Expr::VariableRef { var: "y".into() });
 
         check_stmt(
             "if x\n  y\nendif",
             Stmt::If {
                 condition,
-                body: vec![Stmt::Expr(Expr::VariableRef { var: "y".into() })],
+                body: vec![Stmt::Expr(y)],
+                else_branches: vec![],
+            },
+        );
+    }
+
+    #[test]
+    fn lower_if_with_else_branches() {
+        let mut exprs = Arena::new();
+        let condition = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let a = exprs.alloc(Expr::VariableRef { var: "a".into() });
+        let elseif_condition = exprs.alloc(Expr::VariableRef { var: "y".into() });
+        let b = exprs.alloc(Expr::VariableRef { var: "b".into() });
+        let c = exprs.alloc(Expr::VariableRef { var: "c".into() });
+
+        check_stmt(
+            "if x\n  a\nelseif y\n  b\nelse\n  c\nend",
+            Stmt::If {
+                condition,
+                body: vec![Stmt::Expr(a)],
+                else_branches: vec![
+                    Branch {
+                        condition: Some(elseif_condition),
+                        body: vec![Stmt::Expr(b)],
+                    },
+                    Branch {
+                        condition: None,
+                        body: vec![Stmt::Expr(c)],
+                    },
+                ],
+            },
+        );
+    }
+
+    #[test]
+    fn lower_switch() {
+        let mut exprs = Arena::new();
+        let condition = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let case = exprs.alloc(Expr::Literal { n: Some(1) });
+        let a = exprs.alloc(Expr::VariableRef { var: "a".into() });
+        let b = exprs.alloc(Expr::VariableRef { var: "b".into() });
+
+        check_stmt(
+            "switch x\n  case 1\n    a\n  otherwise\n    b\nend",
+            Stmt::Switch {
+                condition,
+                cases: vec![
+                    Branch {
+                        condition: Some(case),
+                        body: vec![Stmt::Expr(a)],
+                    },
+                    Branch {
+                        condition: None,
+                        body: vec![Stmt::Expr(b)],
+                    },
+                ],
             },
         );
     }
@@ -438,18 +843,21 @@ mod tests {
         let mut exprs = Arena::new();
         let condition = exprs.alloc(Expr::Literal { n: Some(1) });
         let x_ref = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let one = exprs.alloc(Expr::Literal { n: Some(1) });
+        let sum = exprs.alloc(Expr::Binary {
+            op: BinaryOp::Add,
+            lhs: x_ref,
+            rhs: one,
+        });
+        let x = exprs.alloc(Expr::VariableRef { var: "x".into() });
 
         check_stmt(
             "while 1\n  x = x + 1\nendwhile",
             Stmt::WhileLoop {
                 condition,
                 body: vec![Stmt::VariableDef {
-                    name: "x".into(),
-                    value: Expr::Binary {
-                        op: BinaryOp::Add,
-                        lhs: x_ref,
-                        rhs: exprs.alloc(Expr::Literal { n: Some(1) }),
-                    },
+                    targets: vec![Target::Variable(x)],
+                    value: sum,
                 }],
             },
         );
@@ -460,6 +868,30 @@ mod tests {
         check_stmt("break", Stmt::Break);
     }
 
+    #[test]
+    fn lower_return() {
+        check_stmt("return", Stmt::Return);
+    }
+
+    #[test]
+    fn stmt_ranges_follow_preorder() {
+        let root = parse("if x\n  a = 1;\nelse\n  b\nend\nc");
+        let crate::Body { db, stmts, .. } = crate::lower(&root);
+        let ranges: Vec<_> = crate::preorder(&stmts)
+            .map(|stmt| db.stmt_range(stmt).unwrap())
+            .collect();
+
+        assert_eq!(
+            ranges,
+            [
+                TextRange::new(0.into(), 26.into()),
+                TextRange::new(7.into(), 13.into()),
+                TextRange::new(21.into(), 22.into()),
+                TextRange::new(27.into(), 28.into()),
+            ]
+        );
+    }
+
     #[test]
     fn lower_continue() {
         check_stmt("continue", Stmt::Continue);
@@ -476,7 +908,10 @@ mod tests {
                 op: TransposeOp::Normal,
                 expr: var,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
@@ -491,46 +926,56 @@ mod tests {
                 op: TransposeOp::Elmt,
                 expr: var,
             },
-            Database { exprs },
+            Database {
+                exprs,
+                ..Database::default()
+            },
         );
     }
 
     #[test]
     fn lower_try_catch() {
+        let mut exprs = Arena::new();
+        let one = exprs.alloc(Expr::Literal { n: Some(1) });
+        let x1 = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let two = exprs.alloc(Expr::Literal { n: Some(2) });
+        let x2 = exprs.alloc(Expr::VariableRef { var: "x".into() });
+
         check_stmt(
             "try\n  x = 1\ncatch\n  x = 2\nend_try_catch",
             Stmt::Try {
-                body: vec![
-                    Stmt::VariableDef {
-                        name: "x".into(),
-                        value: Expr::Literal { n: Some(1) },
-                    },
-                    Stmt::VariableDef {
-                        name: "x".into(),
-                        value: Expr::Literal { n: Some(2) },
-                    },
-                ],
-                catch: vec![],
+                body: vec![Stmt::VariableDef {
+                    targets: vec![Target::Variable(x1)],
+                    value: one,
+                }],
+                catch_var: None,
+                catch: vec![Stmt::VariableDef {
+                    targets: vec![Target::Variable(x2)],
+                    value: two,
+                }],
             },
         );
     }
 
     #[test]
     fn lower_unwind_protect() {
+        let mut exprs = Arena::new();
+        let one = exprs.alloc(Expr::Literal { n: Some(1) });
+        let x1 = exprs.alloc(Expr::VariableRef { var: "x".into() });
+        let two = exprs.alloc(Expr::Literal { n: Some(2) });
+        let x2 = exprs.alloc(Expr::VariableRef { var: "x".into() });
+
         check_stmt(
             "unwind_protect\n  x = 1\nunwind_protect_cleanup\n  x = 2\nend_unwind_protect",
             Stmt::UnwindProtect {
-                body: vec![
-                    Stmt::VariableDef {
-                        name: "x".into(),
-                        value: Expr::Literal { n: Some(1) },
-                    },
-                    Stmt::VariableDef {
-                        name: "x".into(),
-                        value: Expr::Literal { n: Some(2) },
-                    },
-                ],
-                cleanup: vec![],
+                body: vec![Stmt::VariableDef {
+                    targets: vec![Target::Variable(x1)],
+                    value: one,
+                }],
+                cleanup: vec![Stmt::VariableDef {
+                    targets: vec![Target::Variable(x2)],
+                    value: two,
+                }],
             },
         );
     }
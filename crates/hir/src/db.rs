@@ -0,0 +1,745 @@
+//! The analyses of a whole workspace, computed on demand and kept until their inputs change.
+//!
+//! Inputs are set from outside: the text of every file, where the project looks for functions and
+//! the catalogue of builtins. Everything else is a query, whose value is remembered with the revision it was last
+//! checked at and the revision it last changed at. Every change of an input starts a new
+//! revision. A query asked for in a later one first brings the queries it reads up to date, and
+//! is only computed again if one of them changed since it was last checked. A value computed
+//! again that equals the old one keeps its old revision, so the queries reading it stay as they
+//! are too: editing the body of a function changes the parse of its file but not its item tree,
+//! so the project model, and the analyses of every other file, are not computed again.
+//!
+//! Other threads query a [`Snapshot`] of the database, which shares the remembered values with
+//! it. A snapshot is cancelled as soon as an input of the database changes: its next query
+//! unwinds with [`Cancelled`] instead of answering for a revision that is already gone.
+
+use crate::Body;
+use crate::diagnostics::{self, Diagnostic, DiagnosticKind};
+use crate::item_tree::{FunctionSignature, ItemTree};
+use ast::TypedSyntaxNode;
+use catalogue::Catalogue;
+use parser::{Parse, TextEdit};
+use project::{Definition, LoadPathConfig, Project};
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::panic::{self, UnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(usize);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Revision(u64);
+
+#[derive(Debug, Clone)]
+struct Input<T> {
+    value: T,
+    changed_at: Revision,
+}
+
+#[derive(Debug)]
+struct Memo<T> {
+    value: T,
+    verified_at: Revision,
+    changed_at: Revision,
+}
+
+/// The memo of a query, locked while it is brought up to date. Queries only read the queries
+/// they depend on, so locks are always taken in the same order. Snapshots share the slots of
+/// the database they were taken from.
+type Slot<T> = Arc<Mutex<Option<Memo<T>>>>;
+
+#[derive(Debug, Clone)]
+struct File {
+    path: PathBuf,
+    /// Files are never forgotten, so that their ids stay valid, but their text is.
+    text: Option<Input<Arc<str>>>,
+    parse: Slot<Arc<Parse>>,
+    body: Slot<Arc<Body>>,
+    item_tree: Slot<Arc<ItemTree>>,
+    diagnostics: Slot<Arc<Vec<Diagnostic>>>,
+}
+
+#[derive(Debug)]
+pub struct AnalysisDatabase {
+    revision: Revision,
+    files: Vec<File>,
+    ids: HashMap<PathBuf, FileId>,
+    /// When a file was last added or removed.
+    file_set_changed_at: Revision,
+    config: Input<Arc<LoadPathConfig>>,
+    /// The functions that come with Octave.
+    catalogue: Input<Arc<Catalogue>>,
+    project: Slot<Arc<Project>>,
+    file_functions: Slot<Arc<HashMap<SmolStr, FileId>>>,
+    /// Set once the revision is over, for the snapshots taken in it.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Default for AnalysisDatabase {
+    /// A database without files, whose builtins are those of the bundled catalogue.
+    fn default() -> Self {
+        Self {
+            revision: Revision::default(),
+            files: Vec::new(),
+            ids: HashMap::new(),
+            file_set_changed_at: Revision::default(),
+            config: Input {
+                value: Arc::default(),
+                changed_at: Revision::default(),
+            },
+            catalogue: Input {
+                value: Catalogue::bundled(),
+                changed_at: Revision::default(),
+            },
+            project: Slot::default(),
+            file_functions: Slot::default(),
+            cancelled: Arc::default(),
+        }
+    }
+}
+
+impl AnalysisDatabase {
+    /// Returns a view of the database as it is now, to query from another thread.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(Self {
+            revision: self.revision,
+            files: self.files.clone(),
+            ids: self.ids.clone(),
+            file_set_changed_at: self.file_set_changed_at,
+            config: self.config.clone(),
+            catalogue: self.catalogue.clone(),
+            project: self.project.clone(),
+            file_functions: self.file_functions.clone(),
+            cancelled: self.cancelled.clone(),
+        })
+    }
+
+    /// Unwinds with [`Cancelled`] if this is a snapshot of a revision that is over.
+    pub fn unwind_if_cancelled(&self) {
+        if self.cancelled.load(Ordering::Acquire) {
+            panic::resume_unwind(Box::new(Cancelled));
+        }
+    }
+
+    /// Starts a new revision, cancelling the snapshots of the last one.
+    fn new_revision(&mut self) {
+        self.revision.0 += 1;
+        self.cancelled.store(true, Ordering::Release);
+        self.cancelled = Arc::default();
+    }
+
+    /// Adds a file, or replaces its text.
+    pub fn set_file_text(&mut self, path: PathBuf, text: &str) -> FileId {
+        let file = *self.ids.entry(path.clone()).or_insert_with(|| {
+            self.files.push(File {
+                path,
+                text: None,
+                parse: Slot::default(),
+                body: Slot::default(),
+                item_tree: Slot::default(),
+                diagnostics: Slot::default(),
+            });
+            FileId(self.files.len() - 1)
+        });
+        let old = &self.files[file.0].text;
+        if old.as_ref().is_some_and(|old| *old.value == *text) {
+            return file;
+        }
+        let is_new = old.is_none();
+        self.new_revision();
+        if is_new {
+            self.file_set_changed_at = self.revision;
+        }
+        self.files[file.0].text = Some(Input {
+            value: text.into(),
+            changed_at: self.revision,
+        });
+        file
+    }
+
+    /// Applies an edit to the text of a file. If the file was parsed since its text last
+    /// changed, only the block around the edit is parsed again.
+    pub fn edit_file(&mut self, file: FileId, edit: &TextEdit) {
+        let mut text = self.file_text(file).to_string();
+        edit.apply(&mut text);
+        let changed_at = self.text_changed_at(file);
+        let old = self.files[file.0]
+            .parse
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .filter(|memo| memo.verified_at >= changed_at);
+        let path = self.path(file).to_path_buf();
+        self.set_file_text(path, &text);
+        if let Some(old) = old {
+            *self.files[file.0]
+                .parse
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(Memo {
+                value: Arc::new(old.value.reparse(edit, &text)),
+                verified_at: self.revision,
+                changed_at: self.revision,
+            });
+        }
+    }
+
+    pub fn remove_file(&mut self, path: &Path) {
+        let Some(file) = self.file_id(path) else {
+            return;
+        };
+        self.new_revision();
+        self.file_set_changed_at = self.revision;
+        self.files[file.0].text = None;
+    }
+
+    pub fn set_load_path_config(&mut self, config: LoadPathConfig) {
+        if *self.config.value == config {
+            return;
+        }
+        self.new_revision();
+        self.config = Input {
+            value: Arc::new(config),
+            changed_at: self.revision,
+        };
+    }
+
+    /// Replaces the catalogue that builtins are looked up in.
+    pub fn set_catalogue(&mut self, catalogue: Arc<Catalogue>) {
+        if self.catalogue.value == catalogue {
+            return;
+        }
+        self.new_revision();
+        self.catalogue = Input {
+            value: catalogue,
+            changed_at: self.revision,
+        };
+    }
+
+    #[must_use]
+    pub fn load_path_config(&self) -> &LoadPathConfig {
+        &self.config.value
+    }
+
+    #[must_use]
+    pub fn catalogue(&self) -> Arc<Catalogue> {
+        self.catalogue.value.clone()
+    }
+
+    /// Returns the id of a file that has not been removed.
+    #[must_use]
+    pub fn file_id(&self, path: &Path) -> Option<FileId> {
+        let file = *self.ids.get(path)?;
+        self.files[file.0].text.is_some().then_some(file)
+    }
+
+    #[must_use]
+    pub fn path(&self, file: FileId) -> &Path {
+        &self.files[file.0].path
+    }
+
+    /// The files that have not been removed.
+    pub fn files(&self) -> impl Iterator<Item = FileId> {
+        (0..self.files.len())
+            .filter(|&index| self.files[index].text.is_some())
+            .map(FileId)
+    }
+
+    /// The text of a file, which is empty once it is removed.
+    #[must_use]
+    pub fn file_text(&self, file: FileId) -> Arc<str> {
+        self.files[file.0]
+            .text
+            .as_ref()
+            .map_or_else(Arc::default, |text| text.value.clone())
+    }
+
+    #[must_use]
+    pub fn parse(&self, file: FileId) -> Arc<Parse> {
+        self.parse_memo(file).0
+    }
+
+    /// The file lowered to statements, along with its source map.
+    #[must_use]
+    pub fn body(&self, file: FileId) -> Arc<Body> {
+        self.body_memo(file).0
+    }
+
+    #[must_use]
+    pub fn item_tree(&self, file: FileId) -> Arc<ItemTree> {
+        self.item_tree_memo(file).0
+    }
+
+    /// The project model of the files under the roots and the load path.
+    #[must_use]
+    pub fn project(&self) -> Arc<Project> {
+        self.project_memo().0
+    }
+
+    /// The signature of the function `name` defined in `file`.
+    #[must_use]
+    pub fn function_signature(&self, file: FileId, name: &str) -> Option<FunctionSignature> {
+        self.item_tree(file).function(name).cloned()
+    }
+
+    /// Finds the function or script that `name` refers to when used in `file`.
+    #[must_use]
+    pub fn resolve(&self, file: FileId, name: &str) -> Option<Definition> {
+        self.project().resolve(name, Some(self.path(file)))
+    }
+
+    /// The file that defines the function `name`. Octave finds functions by file name, so every
+    /// file defines one, even outside the project.
+    #[must_use]
+    pub fn file_function(&self, name: &str) -> Option<FileId> {
+        self.file_functions_memo().0.get(name).copied()
+    }
+
+    /// The lints of a file, with every file of the database and the builtins taken as the
+    /// functions it can call. Names that are not defined may still be builtins if the catalogue
+    /// is not complete, so they are only warned about then.
+    #[must_use]
+    pub fn diagnostics(&self, file: FileId) -> Arc<Vec<Diagnostic>> {
+        self.fetch(
+            &self.files[file.0].diagnostics,
+            || {
+                self.body_memo(file)
+                    .1
+                    .max(self.project_memo().1)
+                    .max(self.file_functions_memo().1)
+                    .max(self.catalogue.changed_at)
+            },
+            || {
+                let body = self.body(file);
+                let project = self.project();
+                let file_functions = self.file_functions_memo().0;
+                let catalogue = &self.catalogue.value;
+                let path = self.path(file);
+                let is_function = |name: &str| {
+                    file_functions.contains_key(name)
+                        || project.resolve(name, Some(path)).is_some()
+                        || catalogue.contains(name)
+                };
+                let mut diagnostics = diagnostics::diagnostics(&body, &is_function);
+                if !catalogue.complete {
+                    for diagnostic in &mut diagnostics {
+                        if let DiagnosticKind::UndefinedName { maybe_builtin, .. } =
+                            &mut diagnostic.kind
+                        {
+                            *maybe_builtin = true;
+                        }
+                    }
+                }
+                Arc::new(diagnostics)
+            },
+        )
+        .0
+    }
+
+    fn text_changed_at(&self, file: FileId) -> Revision {
+        self.files[file.0]
+            .text
+            .as_ref()
+            .map_or(self.file_set_changed_at, |text| text.changed_at)
+    }
+
+    fn parse_memo(&self, file: FileId) -> (Arc<Parse>, Revision) {
+        self.fetch(
+            &self.files[file.0].parse,
+            || self.text_changed_at(file),
+            || Arc::new(parser::parse(&self.file_text(file))),
+        )
+    }
+
+    fn body_memo(&self, file: FileId) -> (Arc<Body>, Revision) {
+        self.fetch(
+            &self.files[file.0].body,
+            || self.parse_memo(file).1,
+            || Arc::new(crate::lower(&root(&self.parse(file)))),
+        )
+    }
+
+    fn item_tree_memo(&self, file: FileId) -> (Arc<ItemTree>, Revision) {
+        self.fetch(
+            &self.files[file.0].item_tree,
+            || self.parse_memo(file).1,
+            || Arc::new(ItemTree::new(&root(&self.parse(file)))),
+        )
+    }
+
+    fn project_memo(&self) -> (Arc<Project>, Revision) {
+        let config = &self.config.value;
+        self.fetch(
+            &self.project,
+            || {
+                self.files()
+                    .filter(|&file| config.contains(self.path(file)))
+                    .map(|file| self.item_tree_memo(file).1)
+                    .fold(
+                        self.config.changed_at.max(self.file_set_changed_at),
+                        Revision::max,
+                    )
+            },
+            || {
+                let mut project = Project::with_config(LoadPathConfig::clone(config));
+                project.insert_files(
+                    self.files()
+                        .filter(|&file| config.contains(self.path(file)))
+                        .map(|file| {
+                            (
+                                self.path(file).to_path_buf(),
+                                self.item_tree(file).source_file(),
+                            )
+                        }),
+                );
+                Arc::new(project)
+            },
+        )
+    }
+
+    fn file_functions_memo(&self) -> (Arc<HashMap<SmolStr, FileId>>, Revision) {
+        self.fetch(
+            &self.file_functions,
+            || self.file_set_changed_at,
+            || {
+                let functions = self.files().filter_map(|file| {
+                    let stem = self.path(file).file_stem()?.to_str()?;
+                    Some((SmolStr::from(stem), file))
+                });
+                Arc::new(functions.collect())
+            },
+        )
+    }
+
+    /// Returns the value of a query and the revision it last changed at, computing it again if
+    /// one of its dependencies changed after it was last checked.
+    ///
+    /// `dependencies` brings the queries it reads up to date and returns the latest revision any
+    /// of them changed at.
+    fn fetch<T: Clone + PartialEq>(
+        &self,
+        slot: &Slot<T>,
+        dependencies: impl FnOnce() -> Revision,
+        compute: impl FnOnce() -> T,
+    ) -> (T, Revision) {
+        self.unwind_if_cancelled();
+        let mut memo = slot.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(memo) = memo.as_mut() {
+            // A snapshot may run into a value checked by the database in a later revision. Values
+            // stay the same from the revision they changed at to the one they were checked at.
+            if memo.verified_at >= self.revision {
+                if memo.changed_at <= self.revision {
+                    return (memo.value.clone(), memo.changed_at);
+                }
+            } else if dependencies() <= memo.verified_at {
+                memo.verified_at = self.revision;
+                return (memo.value.clone(), memo.changed_at);
+            }
+        }
+
+        let value = compute();
+        // The later revision keeps its value.
+        if memo
+            .as_ref()
+            .is_some_and(|memo| memo.verified_at > self.revision)
+        {
+            return (value, self.revision);
+        }
+        let old = memo.take();
+        let memo = memo.insert(match old {
+            // The old value is kept, so that it is still the same for whoever holds on to it.
+            Some(old) if old.value == value => Memo {
+                verified_at: self.revision,
+                ..old
+            },
+            _ => Memo {
+                value,
+                verified_at: self.revision,
+                changed_at: self.revision,
+            },
+        });
+        (memo.value.clone(), memo.changed_at)
+    }
+}
+
+/// A view of an [`AnalysisDatabase`] that can be sent to another thread. Its inputs are those of
+/// the revision it was taken in, and cannot be changed.
+#[derive(Debug)]
+pub struct Snapshot(AnalysisDatabase);
+
+impl Deref for Snapshot {
+    type Target = AnalysisDatabase;
+
+    fn deref(&self) -> &AnalysisDatabase {
+        &self.0
+    }
+}
+
+/// What the queries of a snapshot unwind with once the database has changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Cancelled {
+    /// Runs `f`, which queries a snapshot, returning `Err` if the snapshot was cancelled. Other
+    /// panics go on unwinding.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if a query of `f` was cancelled.
+    pub fn catch<T>(f: impl FnOnce() -> T + UnwindSafe) -> Result<T, Cancelled> {
+        panic::catch_unwind(f).map_err(|payload| match payload.downcast::<Cancelled>() {
+            Ok(cancelled) => *cancelled,
+            Err(payload) => panic::resume_unwind(payload),
+        })
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the analysis was cancelled by a change")
+    }
+}
+
+fn root(parse: &Parse) -> ast::Root {
+    ast::Root::cast(parse.syntax()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use catalogue::Function;
+    use project::LoadPathEntry;
+    use text_size::TextRange;
+
+    /// A complete catalogue of the functions `names`.
+    fn catalogue(names: &[&str]) -> Catalogue {
+        let functions = names.iter().map(|name| Function {
+            name: (*name).to_string(),
+            usages: vec![format!("{name} (x)")],
+            summary: String::new(),
+            package: None,
+        });
+        Catalogue::new("9.2.0".to_string(), functions.collect())
+    }
+
+    fn database(files: &[(&str, &str)]) -> AnalysisDatabase {
+        let mut db = AnalysisDatabase::default();
+        db.set_catalogue(Arc::new(catalogue(&["disp"])));
+        db.set_load_path_config(LoadPathConfig {
+            roots: vec!["/ws".into()],
+            load_path: vec![LoadPathEntry::Dir("/lib".into())],
+            excluded: Vec::new(),
+        });
+        for (path, text) in files {
+            db.set_file_text(PathBuf::from(path), text);
+        }
+        db
+    }
+
+    fn lints(db: &AnalysisDatabase, path: &str) -> Vec<String> {
+        let file = db.file_id(Path::new(path)).unwrap();
+        db.diagnostics(file)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn item_trees_hold_signatures() {
+        let db = database(&[(
+            "/ws/f.m",
+            "function [a, b] = f(x, y)\n  a = g(x);\n  b = y;\nend\nfunction r = g(x)\n  r = x;\nend\n",
+        )]);
+        let file = db.file_id(Path::new("/ws/f.m")).unwrap();
+        let signature = |name| {
+            db.function_signature(file, name)
+                .map(|signature| format!("{:?} {:?}", signature.outputs, signature.params))
+        };
+        assert_eq!(signature("f").as_deref(), Some(r#"["a", "b"] ["x", "y"]"#));
+        assert_eq!(signature("g").as_deref(), Some(r#"["r"] ["x"]"#));
+        assert_eq!(signature("h"), None);
+    }
+
+    #[test]
+    fn resolves_names_across_files() {
+        let mut db = database(&[
+            ("/ws/main.m", "function main\n  helper();\nend\n"),
+            ("/lib/helper.m", "function helper\nend\n"),
+        ]);
+        let main = db.file_id(Path::new("/ws/main.m")).unwrap();
+        assert_eq!(
+            db.resolve(main, "helper").map(|definition| definition.path),
+            Some(PathBuf::from("/lib/helper.m"))
+        );
+        assert_eq!(lints(&db, "/ws/main.m"), Vec::<String>::new());
+
+        db.remove_file(Path::new("/lib/helper.m"));
+        assert_eq!(db.resolve(main, "helper"), None);
+        assert_eq!(
+            lints(&db, "/ws/main.m"),
+            ["error at 16..22: 'helper' is undefined"]
+        );
+    }
+
+    #[test]
+    fn every_file_defines_a_function() {
+        let mut db = database(&[("/ws/main.m", "function main\n  scratch();\nend\n")]);
+        assert_eq!(
+            lints(&db, "/ws/main.m"),
+            ["error at 16..23: 'scratch' is undefined"]
+        );
+
+        let scratch = db.set_file_text("/tmp/scratch.m".into(), "disp(1);\n");
+        assert_eq!(db.file_function("scratch"), Some(scratch));
+        assert_eq!(lints(&db, "/ws/main.m"), Vec::<String>::new());
+
+        db.remove_file(Path::new("/tmp/scratch.m"));
+        assert_eq!(db.file_function("scratch"), None);
+    }
+
+    #[test]
+    fn editing_a_body_keeps_the_analyses_of_other_files() {
+        let mut db = database(&[
+            ("/ws/a.m", "function a\n  disp(1);\nend\n"),
+            ("/ws/b.m", "function b\n  a();\nend\n"),
+        ]);
+        let a = db.file_id(Path::new("/ws/a.m")).unwrap();
+        let b = db.file_id(Path::new("/ws/b.m")).unwrap();
+        let (parse, item_tree, project, diagnostics) = (
+            db.parse(a),
+            db.item_tree(a),
+            db.project(),
+            db.diagnostics(b),
+        );
+
+        db.set_file_text("/ws/a.m".into(), "function a\n  disp(2);\nend\n");
+        assert!(!Arc::ptr_eq(&parse, &db.parse(a)));
+        assert!(Arc::ptr_eq(&item_tree, &db.item_tree(a)));
+        assert!(Arc::ptr_eq(&project, &db.project()));
+        assert!(Arc::ptr_eq(&diagnostics, &db.diagnostics(b)));
+
+        // Parameters are part of the signature, but the project only knows functions by name.
+        db.set_file_text("/ws/a.m".into(), "function a(x)\n  disp(x);\nend\n");
+        assert!(!Arc::ptr_eq(&item_tree, &db.item_tree(a)));
+        assert!(Arc::ptr_eq(&project, &db.project()));
+
+        db.set_file_text(
+            "/ws/a.m".into(),
+            "function a(x)\n  disp(x);\nend\nfunction c\nend\n",
+        );
+        assert!(!Arc::ptr_eq(&project, &db.project()));
+    }
+
+    #[test]
+    fn bodies_are_lowered_once() {
+        let mut db = database(&[("/ws/a.m", "function a\n  disp(1);\nend\n")]);
+        let a = db.file_id(Path::new("/ws/a.m")).unwrap();
+        let body = db.body(a);
+        assert!(db.diagnostics(a).is_empty());
+        assert!(Arc::ptr_eq(&body, &db.body(a)));
+
+        let disp = crate::preorder(&body.stmts).nth(1).unwrap();
+        assert_eq!(
+            body.db.stmt_range(disp),
+            Some(TextRange::new(13.into(), 20.into()))
+        );
+
+        db.set_file_text("/ws/b.m".into(), "function b\nend\n");
+        assert!(Arc::ptr_eq(&body, &db.body(a)));
+    }
+
+    #[test]
+    fn edits_reparse_the_previous_parse() {
+        let text = "function a\n  x = 1;\nend\n\nfunction b\n  y = 2;\nend\n";
+        let mut db = database(&[("/ws/a.m", text)]);
+        let a = db.file_id(Path::new("/ws/a.m")).unwrap();
+        let item_tree = db.item_tree(a);
+
+        let edit = TextEdit {
+            range: TextRange::new(17.into(), 18.into()),
+            insert: "42".to_string(),
+        };
+        db.edit_file(a, &edit);
+        let mut expected = text.to_string();
+        edit.apply(&mut expected);
+        assert_eq!(*db.file_text(a), expected);
+        assert_eq!(
+            db.parse(a).debug_tree(),
+            parser::parse(&expected).debug_tree()
+        );
+        assert!(Arc::ptr_eq(&item_tree, &db.item_tree(a)));
+    }
+
+    #[test]
+    fn snapshots_share_values_until_cancelled() {
+        let mut db = database(&[("/ws/a.m", "function a\n  disp(1);\nend\n")]);
+        let a = db.file_id(Path::new("/ws/a.m")).unwrap();
+        let snapshot = db.snapshot();
+        let diagnostics = std::thread::scope(|scope| {
+            scope
+                .spawn(|| Cancelled::catch(|| snapshot.diagnostics(a)))
+                .join()
+                .unwrap()
+        })
+        .unwrap();
+        assert!(Arc::ptr_eq(&diagnostics, &db.diagnostics(a)));
+
+        db.set_file_text("/ws/a.m".into(), "function a\n  undefined();\nend\n");
+        assert_eq!(Cancelled::catch(|| snapshot.diagnostics(a)), Err(Cancelled));
+        assert_eq!(
+            lints(&db, "/ws/a.m"),
+            ["error at 13..22: 'undefined' is undefined"]
+        );
+        assert_eq!(
+            Cancelled::catch(|| db.snapshot().diagnostics(a)),
+            Ok(db.diagnostics(a))
+        );
+    }
+
+    #[test]
+    fn builtin_changes_are_seen() {
+        let mut db = database(&[("/ws/a.m", "function a\n  plot(1);\nend\n")]);
+        assert_eq!(
+            lints(&db, "/ws/a.m"),
+            ["error at 13..17: 'plot' is undefined"]
+        );
+
+        db.set_catalogue(Arc::new(catalogue(&["disp", "plot"])));
+        assert_eq!(lints(&db, "/ws/a.m"), Vec::<String>::new());
+
+        // Without Octave around, the bundled catalogue leaves functions out.
+        let mut incomplete = catalogue(&["disp"]);
+        incomplete.complete = false;
+        db.set_catalogue(Arc::new(incomplete));
+        assert_eq!(
+            lints(&db, "/ws/a.m"),
+            ["warning at 13..17: 'plot' is undefined"]
+        );
+    }
+
+    #[test]
+    fn load_path_changes_are_seen() {
+        let mut db = database(&[
+            ("/ws/main.m", "function main\n  util();\nend\n"),
+            ("/other/util.m", "function util\nend\n"),
+        ]);
+        let main = db.file_id(Path::new("/ws/main.m")).unwrap();
+        assert_eq!(db.resolve(main, "util"), None);
+        assert!(!db.project().contains(Path::new("/other/util.m")));
+
+        db.set_load_path_config(LoadPathConfig {
+            roots: vec!["/ws".into()],
+            load_path: vec![LoadPathEntry::Dir("/other".into())],
+            excluded: Vec::new(),
+        });
+        assert_eq!(
+            db.resolve(main, "util").map(|definition| definition.path),
+            Some(PathBuf::from("/other/util.m"))
+        );
+    }
+}
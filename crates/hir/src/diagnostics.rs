@@ -0,0 +1,604 @@
+//! Semantic lints, reported on top of the syntax errors from the parser and `ast::validation`.
+
+use crate::cfg::{self, Cfg};
+use crate::scope::{self, Scope};
+use crate::{Body, Database, Stmt, flow};
+use smol_str::SmolStr;
+use std::fmt;
+use text_size::TextRange;
+
+/// Functions that can read variables by name, which we cannot follow.
+const DYNAMIC_ACCESS_FUNCTIONS: [&str; 4] = ["eval", "evalc", "evalin", "save"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub range: TextRange,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    UnusedVariable {
+        name: SmolStr,
+    },
+    UnusedParameter {
+        name: SmolStr,
+    },
+    UnassignedOutput {
+        name: SmolStr,
+    },
+    /// A variable read where no path leading to it has assigned it yet.
+    UndefinedVariable {
+        name: SmolStr,
+    },
+    /// A name that is neither assigned in its scope nor a known function.
+    UndefinedName {
+        name: SmolStr,
+        /// Whether the catalogue of builtins is incomplete, so that the name may be one it misses.
+        maybe_builtin: bool,
+    },
+    /// A variable read where only some of the paths leading to it have assigned it.
+    PossiblyUndefinedVariable {
+        name: SmolStr,
+    },
+    /// Statements after a `return`, `break`, `continue` or `error()` that can never run.
+    UnreachableCode,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Diagnostic {
+    #[must_use]
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            DiagnosticKind::UnusedVariable { .. }
+            | DiagnosticKind::UnusedParameter { .. }
+            | DiagnosticKind::UnassignedOutput { .. }
+            | DiagnosticKind::PossiblyUndefinedVariable { .. }
+            | DiagnosticKind::UndefinedName {
+                maybe_builtin: true,
+                ..
+            }
+            | DiagnosticKind::UnreachableCode => Severity::Warning,
+            DiagnosticKind::UndefinedVariable { .. }
+            | DiagnosticKind::UndefinedName {
+                maybe_builtin: false,
+                ..
+            }
+            | DiagnosticKind::BreakOutsideLoop
+            | DiagnosticKind::ContinueOutsideLoop => Severity::Error,
+        }
+    }
+
+    /// Whether the diagnostic marks code that can be removed, which editors usually fade out.
+    #[must_use]
+    pub fn is_unnecessary(&self) -> bool {
+        matches!(
+            self.kind,
+            DiagnosticKind::UnusedVariable { .. }
+                | DiagnosticKind::UnusedParameter { .. }
+                | DiagnosticKind::UnreachableCode
+        )
+    }
+}
+
+impl DiagnosticKind {
+    /// The codes of every lint.
+    pub const CODES: [&'static str; 9] = [
+        "unused-variable",
+        "unused-parameter",
+        "unassigned-output",
+        "undefined-variable",
+        "undefined-name",
+        "possibly-undefined-variable",
+        "unreachable-code",
+        "break-outside-loop",
+        "continue-outside-loop",
+    ];
+
+    /// A stable name for the lint, which editors show and quick fixes are tied to.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedVariable { .. } => "unused-variable",
+            Self::UnusedParameter { .. } => "unused-parameter",
+            Self::UnassignedOutput { .. } => "unassigned-output",
+            Self::UndefinedVariable { .. } => "undefined-variable",
+            Self::UndefinedName { .. } => "undefined-name",
+            Self::PossiblyUndefinedVariable { .. } => "possibly-undefined-variable",
+            Self::UnreachableCode => "unreachable-code",
+            Self::BreakOutsideLoop => "break-outside-loop",
+            Self::ContinueOutsideLoop => "continue-outside-loop",
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnusedVariable { name } => {
+                write!(f, "variable '{name}' is assigned but never used")
+            }
+            Self::UnusedParameter { name } => write!(f, "parameter '{name}' is never used"),
+            Self::UnassignedOutput { name } => {
+                write!(f, "output '{name}' is never assigned")
+            }
+            Self::UndefinedVariable { name } => {
+                write!(f, "variable '{name}' is used before it is assigned")
+            }
+            Self::UndefinedName { name, .. } => write!(f, "'{name}' is undefined"),
+            Self::PossiblyUndefinedVariable { name } => {
+                write!(f, "variable '{name}' might be used before it is assigned")
+            }
+            Self::UnreachableCode => write!(f, "unreachable code"),
+            Self::BreakOutsideLoop => write!(f, "'break' outside of a loop"),
+            Self::ContinueOutsideLoop => write!(f, "'continue' outside of a loop"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{severity} at {}..{}: {}",
+            u32::from(self.range.start()),
+            u32::from(self.range.end()),
+            self.kind,
+        )
+    }
+}
+
+/// Runs the semantic lints on a whole lowered file.
+///
+/// `is_function` tells whether a name refers to a function defined outside of the file, so that
+/// calls to it are not mistaken for reads of undefined variables.
+#[must_use]
+pub fn diagnostics(body: &Body, is_function: &dyn Fn(&str) -> bool) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let scopes = scope::lowered_scopes(body);
+
+    let (db, file) = (&body.db, body.stmts.as_slice());
+    let is_function = |name: &str| {
+        crate::preorder(file)
+            .any(|stmt| matches!(stmt, Stmt::FnDef { name: Some(own), .. } if own == name))
+            || is_function(name)
+    };
+    flow::undefined_variables(db, file, &is_function, &mut diagnostics);
+
+    for body in cfg::bodies(file) {
+        let cfg = Cfg::build(db, body);
+        unreachable_code(db, body, &cfg, &mut diagnostics);
+        stray_jumps(db, &cfg, &mut diagnostics);
+    }
+
+    // Variables left over by a script are its results, so only functions are linted.
+    for scope in scopes.iter().filter(|scope| scope.is_function()) {
+        if !has_dynamic_access(scope) {
+            unused_variables(scope, &mut diagnostics);
+        }
+        unused_parameters(scope, &mut diagnostics);
+        unassigned_outputs(scope, &mut diagnostics);
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.range.start());
+    diagnostics
+}
+
+/// Reports the first of each run of unreachable statements, up to the last one.
+fn unreachable_code(db: &Database, body: &[Stmt], cfg: &Cfg, diagnostics: &mut Vec<Diagnostic>) {
+    let is_unreachable =
+        |stmt: &Stmt| !matches!(stmt, Stmt::FnDef { .. }) && !cfg.is_reachable(stmt);
+
+    if let Some(first) = body.iter().position(is_unreachable) {
+        let last = body[first..]
+            .iter()
+            .take_while(|stmt| is_unreachable(stmt))
+            .last()
+            .unwrap();
+        if let (Some(start), Some(end)) = (db.stmt_range(&body[first]), db.stmt_range(last)) {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::UnreachableCode,
+                range: start.cover(end),
+            });
+        }
+    }
+
+    for stmt in body.iter().filter(|stmt| cfg.is_reachable(stmt)) {
+        for nested in stmt.bodies() {
+            unreachable_code(db, nested, cfg, diagnostics);
+        }
+    }
+}
+
+fn stray_jumps(db: &Database, cfg: &Cfg, diagnostics: &mut Vec<Diagnostic>) {
+    for &jump in cfg.stray_jumps() {
+        let kind = match jump {
+            Stmt::Break => DiagnosticKind::BreakOutsideLoop,
+            _ => DiagnosticKind::ContinueOutsideLoop,
+        };
+        if let Some(range) = db.stmt_range(jump) {
+            diagnostics.push(Diagnostic { kind, range });
+        }
+    }
+}
+
+fn has_dynamic_access(scope: &Scope) -> bool {
+    DYNAMIC_ACCESS_FUNCTIONS
+        .iter()
+        .any(|function| scope.reads(function).next().is_some())
+}
+
+fn unused_variables(scope: &Scope, diagnostics: &mut Vec<Diagnostic>) {
+    let is_bound = |name: &str| {
+        scope
+            .params
+            .iter()
+            .chain(&scope.outputs)
+            .any(|binding| binding.name == name)
+    };
+
+    for write in scope
+        .accesses
+        .iter()
+        .filter(|access| access.kind == scope::AccessKind::Write && !is_bound(&access.name))
+    {
+        if scope.reads(&write.name).next().is_none() {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::UnusedVariable {
+                    name: write.name.clone(),
+                },
+                range: write.range,
+            });
+        }
+    }
+}
+
+fn unused_parameters(scope: &Scope, diagnostics: &mut Vec<Diagnostic>) {
+    for param in &scope.params {
+        let is_ignored = param.name.starts_with('_');
+        // A parameter returned as is, as in `function x = f(x)`, is used by the caller.
+        let is_output = scope.outputs.iter().any(|output| output.name == param.name);
+
+        if !is_ignored && !is_output && scope.reads(&param.name).next().is_none() {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::UnusedParameter {
+                    name: param.name.clone(),
+                },
+                range: param.range,
+            });
+        }
+    }
+}
+
+fn unassigned_outputs(scope: &Scope, diagnostics: &mut Vec<Diagnostic>) {
+    for output in &scope.outputs {
+        let is_param = scope.params.iter().any(|param| param.name == output.name);
+
+        if !is_param && scope.writes(&output.name).next().is_none() {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::UnassignedOutput {
+                    name: output.name.clone(),
+                },
+                range: output.range,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::TypedSyntaxNode;
+    use expect_test::{Expect, expect};
+
+    const KNOWN_FUNCTIONS: [&str; 8] = [
+        "all", "disp", "error", "eval", "hold", "load", "max", "numel",
+    ];
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let actual: Vec<_> = diagnostics(&crate::lower(&root), &|name| {
+            KNOWN_FUNCTIONS.contains(&name)
+        })
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+        expected.assert_eq(&actual.join("\n"));
+    }
+
+    #[test]
+    fn no_diagnostics_for_used_variables() {
+        check(
+            "function y = f(x)\n  a = x + 1;\n  y = a * 2;\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn unused_variable() {
+        check(
+            "function y = f(x)\n  a = x + 1;\n  y = x;\nend",
+            expect!["warning at 20..21: variable 'a' is assigned but never used"],
+        );
+    }
+
+    #[test]
+    fn unused_variable_in_multiple_assignment() {
+        check(
+            "function y = f(x)\n  [y, i] = max(x);\nend",
+            expect!["warning at 24..25: variable 'i' is assigned but never used"],
+        );
+    }
+
+    #[test]
+    fn loop_variable_read_in_body_is_used() {
+        check(
+            "function y = f(x)\n  y = 0;\n  for i = 1:x\n    y = y + i;\n  end\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn field_assignments_define_the_struct() {
+        check(
+            "function y = f(x)\n  s.a = x;\n  y = s.a;\nend",
+            expect![""],
+        );
+        check(
+            "function f(x)\n  s.a = x;\nend",
+            expect!["warning at 16..17: variable 's' is assigned but never used"],
+        );
+    }
+
+    #[test]
+    fn script_variables_are_not_reported() {
+        check("a = 1;\nb = 2;", expect![""]);
+    }
+
+    #[test]
+    fn eval_disables_unused_variables() {
+        check(
+            "function f(name)\n  a = 1;\n  eval(name);\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn unused_parameter() {
+        check(
+            "function y = f(x, z)\n  y = x;\nend",
+            expect!["warning at 18..19: parameter 'z' is never used"],
+        );
+    }
+
+    #[test]
+    fn ignored_parameters() {
+        check("function y = f(x, _z)\n  y = x;\nend", expect![""]);
+    }
+
+    #[test]
+    fn parameter_returned_as_output() {
+        check("function x = f(x)\nend", expect![""]);
+    }
+
+    #[test]
+    fn unassigned_output() {
+        check(
+            "function [a, b] = f(x)\n  a = x;\nend",
+            expect!["warning at 13..14: output 'b' is never assigned"],
+        );
+    }
+
+    #[test]
+    fn indexed_assignment_assigns_output() {
+        check(
+            "function y = f(n)\n  for i = 1:n\n    y(i) = i;\n  end\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn subfunctions_have_their_own_scope() {
+        check(
+            "function y = f(x)\n  y = g(x);\nend\nfunction z = g(w)\n  y = w;\nend",
+            expect![[r"
+                warning at 43..44: output 'z' is never assigned
+                warning at 54..55: variable 'y' is assigned but never used"]],
+        );
+    }
+
+    #[test]
+    fn variable_used_before_assignment() {
+        check(
+            "function y = f()\n  y = a;\n  a = 1;\nend",
+            expect!["error at 23..24: variable 'a' is used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn undefined_name() {
+        check(
+            "function y = f(x)\n  y = x + z;\nend",
+            expect!["error at 28..29: 'z' is undefined"],
+        );
+    }
+
+    #[test]
+    fn known_functions_and_subfunctions_are_defined() {
+        check(
+            "function y = f(x)\n  y = g(numel(x));\nend\nfunction y = g(x)\n  y = x;\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn variable_assigned_in_one_branch() {
+        check(
+            "function y = f(x)\n  if x\n    y = 1;\n  end\n  disp(y);\nend",
+            expect!["warning at 49..50: variable 'y' might be used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn variable_assigned_in_every_branch() {
+        check(
+            "function y = f(x)\n  switch x\n    case 1\n      a = 1;\n    otherwise\n      a = 2;\n  end\n  y = a;\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn undefined_variable_is_reported_once() {
+        check(
+            "function y = f(x)\n  if x\n    a = 1;\n  end\n  y = a + a;\nend",
+            expect!["warning at 48..49: variable 'a' might be used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn variable_assigned_later_in_loop() {
+        check(
+            "function y = f(n)\n  for i = 1:n\n    if i > 1\n      disp(prev);\n    end\n    prev = i;\n  end\n  y = prev;\nend",
+            expect![[r"
+                warning at 56..60: variable 'prev' might be used before it is assigned
+                warning at 97..101: variable 'prev' might be used before it is assigned"]],
+        );
+    }
+
+    #[test]
+    fn break_skips_rest_of_loop() {
+        check(
+            "function y = f(x)\n  while x\n    break;\n    a = 1;\n  end\n  y = 1;\nend",
+            expect![[r"
+                warning at 43..49: unreachable code
+                warning at 43..44: variable 'a' is assigned but never used"]],
+        );
+    }
+
+    #[test]
+    fn catch_sees_variables_of_the_try_part_as_possible() {
+        check(
+            "function y = f(x)\n  try\n    a = x;\n  catch err\n    disp(err);\n    disp(a);\n  end\n  y = a;\nend",
+            expect!["warning at 71..72: variable 'a' might be used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn cleanup_sees_variables_of_the_protected_part_as_possible() {
+        check(
+            "function y = f(x)\n  unwind_protect\n    a = max(x);\n  unwind_protect_cleanup\n    disp(a);\n  end_unwind_protect\n  y = a;\nend",
+            expect!["warning at 85..86: variable 'a' might be used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn loop_variables_are_possible_after_break() {
+        check(
+            "function y = f(x)\n  while x\n    if x\n      break;\n    end\n    a = 1;\n  end\n  y = a;\nend",
+            expect!["warning at 81..82: variable 'a' might be used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn eval_may_define_anything() {
+        check("function y = f(x)\n  eval(x);\n  y = a;\nend", expect![""]);
+    }
+
+    #[test]
+    fn scripts_are_checked_after_clear() {
+        check(
+            "disp(a);\nclear all\ndisp(a);\na = 1;",
+            expect!["error at 24..25: variable 'a' is used before it is assigned"],
+        );
+    }
+
+    #[test]
+    fn clearing_one_variable_keeps_the_others() {
+        check("disp(a);\nclear b\ndisp(a);", expect![""]);
+    }
+
+    #[test]
+    fn command_syntax_words_are_not_reads() {
+        check(
+            "function f(x)\n  hold on\n  disp hello world;\n  disp(x)\n  hold off, undefined\nend",
+            expect!["error at 66..75: 'undefined' is undefined"],
+        );
+    }
+
+    #[test]
+    fn variables_are_not_commands() {
+        check(
+            "function f(x)\n  x y\nend",
+            expect!["error at 18..19: 'y' is undefined"],
+        );
+    }
+
+    #[test]
+    fn declared_variables_are_defined() {
+        check(
+            "function r = f\n  global g\n  persistent n = k;\n  r = g + n;\nend",
+            expect!["error at 43..44: 'k' is undefined"],
+        );
+    }
+
+    #[test]
+    fn code_after_return() {
+        check(
+            "function f(x)\n  disp(x);\n  return;\n  disp(1);\n  disp(2);\nend",
+            expect!["warning at 37..55: unreachable code"],
+        );
+    }
+
+    #[test]
+    fn multiple_assignment_after_return() {
+        check(
+            "function [a, b] = f(x)\n  return;\n  [a, b] = g(x);\nend\nfunction [p, q] = g(x)\n  p = x;\n  q = x;\nend",
+            expect!["warning at 35..49: unreachable code"],
+        );
+    }
+
+    #[test]
+    fn code_after_error_in_branch() {
+        check(
+            "function f(x)\n  if x\n    error('bad');\n    disp(x);\n  end\n  disp(x);\nend",
+            expect!["warning at 43..50: unreachable code"],
+        );
+    }
+
+    #[test]
+    fn caught_error_keeps_code_after_try_reachable() {
+        check(
+            "function f(x)\n  try\n    error('bad');\n  catch\n    disp(x);\n  end\n  disp(x);\nend",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn unterminated_functions_after_return_are_reachable() {
+        check(
+            "function f(x)\n  disp(x);\n  return\nfunction g(x)\n  disp(x);",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn jumps_outside_of_loops() {
+        check(
+            "function f(x)\n  for i = 1:x\n    disp(i);\n    break;\n  end\n  continue;\n  disp(x);\nend",
+            expect!["error at 60..68: 'continue' outside of a loop"],
+        );
+    }
+}
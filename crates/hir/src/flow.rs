@@ -0,0 +1,340 @@
+//! Flow-sensitive tracking of which variables have been assigned at each point of a body.
+//!
+//! A forward dataflow pass over the [`Cfg`] of each body finds the variables assigned on every
+//! path leading to a statement (definitely defined) and on some path (possibly defined). Reads
+//! are then checked against the state before their statement. A statement built into several
+//! blocks, as in the cleanup part of `unwind_protect`, is checked against all of them.
+
+use crate::cfg::{BlockId, Cfg, EdgeKind};
+use crate::diagnostics::{Diagnostic, DiagnosticKind};
+use crate::{Database, Expr, ExprIdx, Stmt};
+use smol_str::SmolStr;
+use std::collections::{HashMap, HashSet};
+use text_size::TextRange;
+
+/// Bare calls to these can define any variable, so nothing can be assumed afterwards.
+const DYNAMIC_DEFINITION_FUNCTIONS: [&str; 4] = ["eval", "evalc", "evalin", "load"];
+
+/// Arguments of `clear` that remove all variables.
+const CLEAR_EVERYTHING: [&str; 4] = ["all", "-all", "variables", "-variables"];
+
+/// Names that Octave defines implicitly.
+const IMPLICIT_VARIABLES: [&str; 1] = ["ans"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct State {
+    /// True when variables may come from outside, as in a script before it runs `clear`.
+    pub(crate) unknown: bool,
+    pub(crate) definite: HashSet<SmolStr>,
+    pub(crate) possible: HashSet<SmolStr>,
+}
+
+impl State {
+    fn new(defined: impl IntoIterator<Item = SmolStr>) -> Self {
+        let definite: HashSet<_> = defined.into_iter().collect();
+        Self {
+            unknown: false,
+            possible: definite.clone(),
+            definite,
+        }
+    }
+
+    fn define(&mut self, name: &str) {
+        self.definite.insert(name.into());
+        self.possible.insert(name.into());
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Self {
+            unknown: self.unknown || other.unknown,
+            definite: self
+                .definite
+                .intersection(&other.definite)
+                .cloned()
+                .collect(),
+            possible: self.possible.union(&other.possible).cloned().collect(),
+        }
+    }
+}
+
+/// Joins `state` into `slot`, which is None while nothing reaches it. Returns whether it changed.
+fn join_into(slot: &mut Option<State>, state: &State) -> bool {
+    let joined = match slot {
+        Some(old) => old.join(state),
+        None => state.clone(),
+    };
+    let changed = slot.as_ref() != Some(&joined);
+    *slot = Some(joined);
+    changed
+}
+
+/// What the reads of one variable reference saw, over all the blocks its statement is in.
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    definite: bool,
+    possible: bool,
+}
+
+/// The states at the start of each block of a body.
+pub(crate) struct Flow<'a> {
+    db: &'a Database,
+    cfg: &'a Cfg<'a>,
+    is_function: &'a dyn Fn(&str) -> bool,
+    entries: Vec<Option<State>>,
+}
+
+impl<'a> Flow<'a> {
+    /// Runs the pass over a body, starting from `initial`, until the states settle.
+    ///
+    /// `is_function` tells whether a name refers to a function, whose reads are not variables.
+    pub(crate) fn compute(
+        db: &'a Database,
+        cfg: &'a Cfg<'a>,
+        is_function: &'a dyn Fn(&str) -> bool,
+        initial: State,
+    ) -> Self {
+        let mut flow = Self {
+            db,
+            cfg,
+            is_function,
+            entries: vec![None; cfg.blocks().count()],
+        };
+        flow.entries[Cfg::ENTRY.0] = Some(initial);
+
+        let mut worklist = vec![Cfg::ENTRY];
+        while let Some(id) = worklist.pop() {
+            let Some(mut state) = flow.entries[id.0].clone() else {
+                continue;
+            };
+            // The error may interrupt the block before any of its statements, or after them.
+            let mut raised: Option<State> = None;
+            flow.enter(id, &mut state);
+            for stmt in &cfg.block(id).stmts {
+                join_into(&mut raised, &state);
+                flow.transfer(stmt, &mut state, &mut |_, _, _| {});
+            }
+            join_into(&mut raised, &state);
+
+            for edge in &cfg.block(id).successors {
+                let out = match edge.kind {
+                    EdgeKind::Normal => &state,
+                    EdgeKind::Exception => raised.as_ref().unwrap(),
+                };
+                if join_into(&mut flow.entries[edge.target.0], out) {
+                    worklist.push(edge.target);
+                }
+            }
+        }
+        flow
+    }
+
+    /// Returns the states right before each statement of the body, for every block it is in.
+    pub(crate) fn before_stmts(&self) -> Vec<(&'a Stmt, State)> {
+        let mut states = Vec::new();
+        for (id, block) in self.cfg.blocks() {
+            let Some(mut state) = self.entries[id.0].clone() else {
+                continue;
+            };
+            self.enter(id, &mut state);
+            for stmt in &block.stmts {
+                states.push((*stmt, state.clone()));
+                self.transfer(stmt, &mut state, &mut |_, _, _| {});
+            }
+        }
+        states
+    }
+
+    fn enter(&self, id: BlockId, state: &mut State) {
+        if let Some(var) = self.cfg.block(id).catch_var
+            && let Expr::VariableRef { var } = &self.db[var]
+        {
+            state.define(var);
+        }
+    }
+
+    /// Applies a statement to the state, calling `on_read` with the state before each read of a
+    /// name that may be a variable.
+    fn transfer(
+        &self,
+        stmt: &Stmt,
+        state: &mut State,
+        on_read: &mut dyn FnMut(ExprIdx, &SmolStr, &State),
+    ) {
+        if let Stmt::Command { name, words } = stmt {
+            let name_var = self.var_name(*name);
+            // Unless the name is a variable, the words are strings.
+            let is_variable = name_var.is_some_and(|var| state.possible.contains(var));
+            self.reads(*name, state, on_read);
+            if is_variable {
+                for word in words {
+                    self.reads(*word, state, on_read);
+                }
+            } else if let Some(function) = name_var {
+                let words: Vec<_> = words.iter().map(|word| self.var_name(*word)).collect();
+                dynamic_call(function, words.iter().all(|word| clears(*word)), state);
+            }
+            return;
+        }
+
+        for expr in stmt.exprs() {
+            self.reads(expr, state, on_read);
+        }
+        for var in stmt.targets().iter().filter_map(crate::Target::var) {
+            if let Some(name) = self.var_name(var) {
+                state.define(name);
+            }
+        }
+
+        if let Stmt::Expr(expr) = stmt {
+            match &self.db[*expr] {
+                Expr::VariableRef { var } => dynamic_call(var, true, state),
+                Expr::Call { func, args } => {
+                    if let Some(function) = self.var_name(*func) {
+                        let clears_everything = args.iter().all(|arg| {
+                            matches!(&self.db[*arg], Expr::String { value }
+                                if CLEAR_EVERYTHING.contains(&value.as_str()))
+                        });
+                        dynamic_call(function, clears_everything, state);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn reads(
+        &self,
+        expr: ExprIdx,
+        state: &mut State,
+        on_read: &mut dyn FnMut(ExprIdx, &SmolStr, &State),
+    ) {
+        if let Expr::VariableRef { var } = &self.db[expr]
+            && !IMPLICIT_VARIABLES.contains(&var.as_str())
+            && !(self.is_function)(var)
+        {
+            on_read(expr, var, state);
+            // Execution only goes on past the read if the variable was defined after all.
+            if !state.unknown {
+                state.define(var);
+            }
+        }
+        for child in self.db[expr].children() {
+            self.reads(child, state, on_read);
+        }
+    }
+
+    fn var_name(&self, expr: ExprIdx) -> Option<&'a SmolStr> {
+        match &self.db[expr] {
+            Expr::VariableRef { var } => Some(var),
+            _ => None,
+        }
+    }
+}
+
+/// Handles the calls that add or remove variables behind our back.
+fn dynamic_call(function: &str, clears_everything: bool, state: &mut State) {
+    if DYNAMIC_DEFINITION_FUNCTIONS.contains(&function) {
+        state.unknown = true;
+    } else if (function == "clear" || function == "clearvars") && clears_everything {
+        *state = State::default();
+    }
+}
+
+fn clears(word: Option<&SmolStr>) -> bool {
+    word.is_some_and(|word| CLEAR_EVERYTHING.contains(&word.as_str()))
+}
+
+/// Returns the state a body starts in: a script runs in the workspace of its caller, so it may
+/// read anything until it clears it, while a function only has its parameters.
+pub(crate) fn initial_state(db: &Database, params: &[ExprIdx]) -> State {
+    State::new(params.iter().filter_map(|param| match &db[*param] {
+        Expr::VariableRef { var } => Some(var.clone()),
+        _ => None,
+    }))
+}
+
+/// Reports reads of variables that are not assigned on every path leading to them.
+pub(crate) fn undefined_variables(
+    db: &Database,
+    file: &[Stmt],
+    is_function: &dyn Fn(&str) -> bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let script = State {
+        unknown: true,
+        ..State::default()
+    };
+    body_undefined_variables(db, file, &[], script, is_function, diagnostics);
+
+    for stmt in crate::preorder(file) {
+        if let Stmt::FnDef {
+            params,
+            outputs,
+            body,
+            ..
+        } = stmt
+        {
+            let initial = initial_state(db, params);
+            let bindings: Vec<_> = params.iter().chain(outputs).copied().collect();
+            body_undefined_variables(db, body, &bindings, initial, is_function, diagnostics);
+        }
+    }
+}
+
+fn body_undefined_variables(
+    db: &Database,
+    body: &[Stmt],
+    bindings: &[ExprIdx],
+    initial: State,
+    is_function: &dyn Fn(&str) -> bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let cfg = Cfg::build(db, body);
+    let flow = Flow::compute(db, &cfg, is_function, initial);
+
+    // Every name written somewhere in the body: reads of any other name cannot be variables.
+    let assigned: HashSet<&SmolStr> = crate::assigned_vars(body)
+        .chain(bindings.iter().copied())
+        .filter_map(|var| flow.var_name(var))
+        .collect();
+
+    let mut seen: HashMap<TextRange, (SmolStr, Seen)> = HashMap::new();
+    for (stmt, mut state) in flow.before_stmts() {
+        flow.transfer(stmt, &mut state, &mut |expr, name, state| {
+            if state.unknown {
+                return;
+            }
+            let Some(range) = db.source_map().expr_range(expr) else {
+                return;
+            };
+            let here = Seen {
+                definite: state.definite.contains(name),
+                possible: state.possible.contains(name),
+            };
+            seen.entry(range)
+                .and_modify(|(_, seen)| {
+                    seen.definite &= here.definite;
+                    seen.possible |= here.possible;
+                })
+                .or_insert((name.clone(), here));
+        });
+    }
+
+    let mut reads: Vec<_> = seen.into_iter().collect();
+    reads.sort_by_key(|(range, _)| range.start());
+    for (range, (name, seen)) in reads {
+        let kind = if seen.definite {
+            continue;
+        } else if seen.possible {
+            DiagnosticKind::PossiblyUndefinedVariable { name }
+        } else if assigned.contains(&name) {
+            DiagnosticKind::UndefinedVariable { name }
+        } else {
+            DiagnosticKind::UndefinedName {
+                name,
+                maybe_builtin: false,
+            }
+        };
+        diagnostics.push(Diagnostic { kind, range });
+    }
+}
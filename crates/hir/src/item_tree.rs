@@ -0,0 +1,68 @@
+//! What a file declares to the others: its kind and the signatures of its functions.
+//!
+//! Bodies are left out, so that editing them leaves the item tree as it was and nothing that
+//! depends only on it needs to be computed again.
+
+use ast::TypedSyntaxNode;
+use project::{FileKind, SourceFile};
+use smol_str::SmolStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemTree {
+    pub kind: FileKind,
+    /// The functions defined in the file, the main one first.
+    pub functions: Vec<FunctionSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSignature {
+    pub name: SmolStr,
+    pub params: Vec<SmolStr>,
+    pub outputs: Vec<SmolStr>,
+}
+
+impl ItemTree {
+    #[must_use]
+    pub fn new(root: &ast::Root) -> Self {
+        let names = |vars: Vec<ast::VariableRef>| {
+            vars.iter()
+                .filter_map(ast::VariableRef::name)
+                .map(|name| name.text().into())
+                .collect()
+        };
+        let functions = root
+            .syntax()
+            .descendants()
+            .filter_map(ast::FnDef::cast)
+            .filter_map(|fn_def| {
+                Some(FunctionSignature {
+                    name: fn_def.name()?.text().into(),
+                    params: names(fn_def.params()),
+                    outputs: names(fn_def.outputs()),
+                })
+            })
+            .collect();
+        Self {
+            kind: FileKind::of(root),
+            functions,
+        }
+    }
+
+    #[must_use]
+    pub fn function(&self, name: &str) -> Option<&FunctionSignature> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// What the project model needs to know about the file.
+    #[must_use]
+    pub fn source_file(&self) -> SourceFile {
+        SourceFile {
+            kind: self.kind,
+            functions: self
+                .functions
+                .iter()
+                .map(|function| function.name.to_string())
+                .collect(),
+        }
+    }
+}
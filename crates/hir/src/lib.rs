@@ -1,26 +1,249 @@
 #![warn(clippy::pedantic)]
 
+pub mod cfg;
 mod database;
+pub mod db;
+pub mod diagnostics;
+mod flow;
+pub mod item_tree;
+pub mod region;
+pub mod scope;
+pub mod source_map;
 pub use database::Database;
 
+use ast::TypedSyntaxNode;
 use la_arena::Idx;
 use smol_str::SmolStr;
+use text_size::TextRange;
 
 type ExprIdx = Idx<Expr>;
 
 #[derive(Debug, PartialEq)]
 pub enum Stmt {
-    VariableDef { name: SmolStr, value: Expr },
-    FnDef { body: Vec<Stmt> },
-    If { condition: ExprIdx, body: Vec<Stmt> },
-    ForLoop { body: Vec<Stmt> },
-    WhileLoop { condition: ExprIdx, body: Vec<Stmt> },
-    Switch { condition: ExprIdx, body: Vec<Stmt> },
-    Try { body: Vec<Stmt>, catch: Vec<Stmt> },
-    UnwindProtect { body: Vec<Stmt>, cleanup: Vec<Stmt> },
+    /// An assignment, to several targets in `[a, b] = f(x)`.
+    VariableDef {
+        targets: Vec<Target>,
+        value: ExprIdx,
+    },
+    /// A function, whose parameters and outputs are [`Expr::VariableRef`]s. Ignored parameters
+    /// (`~`) are left out.
+    FnDef {
+        name: Option<SmolStr>,
+        params: Vec<ExprIdx>,
+        outputs: Vec<ExprIdx>,
+        body: Vec<Stmt>,
+    },
+    If {
+        condition: ExprIdx,
+        body: Vec<Stmt>,
+        else_branches: Vec<Branch>,
+    },
+    /// The targets are assigned each element of the value in turn.
+    ForLoop {
+        targets: Vec<Target>,
+        value: ExprIdx,
+        body: Vec<Stmt>,
+    },
+    WhileLoop {
+        condition: ExprIdx,
+        body: Vec<Stmt>,
+    },
+    Switch {
+        condition: ExprIdx,
+        cases: Vec<Branch>,
+    },
+    /// The error is assigned to the [`Expr::VariableRef`] `catch_var` when the `catch` part
+    /// starts, as `err` in `catch err`.
+    Try {
+        body: Vec<Stmt>,
+        catch_var: Option<ExprIdx>,
+        catch: Vec<Stmt>,
+    },
+    UnwindProtect {
+        body: Vec<Stmt>,
+        cleanup: Vec<Stmt>,
+    },
+    /// A `global` or `persistent` declaration, which defines the variables as empty matrices
+    /// unless they are given an initial value.
+    Declaration {
+        is_global: bool,
+        targets: Vec<Target>,
+        values: Vec<ExprIdx>,
+    },
+    /// Command syntax, as `hold on`: a name followed by words on the same line, which are passed
+    /// to it as strings. If the name is a variable, the words are expressions after all.
+    Command {
+        name: ExprIdx,
+        words: Vec<ExprIdx>,
+    },
     Break,
     Continue,
-    Expr(Expr),
+    Return,
+    Expr(ExprIdx),
+}
+
+/// An `elseif` or `else` branch of an `if`, or a `case` or `otherwise` branch of a `switch`. Only
+/// `else` and `otherwise` have no condition.
+#[derive(Debug, PartialEq)]
+pub struct Branch {
+    pub condition: Option<ExprIdx>,
+    pub body: Vec<Stmt>,
+}
+
+impl Stmt {
+    /// Returns the lists of statements nested directly in this one, such as the branches of an
+    /// `if`, in source order.
+    #[must_use]
+    pub fn bodies(&self) -> Vec<&[Stmt]> {
+        match self {
+            Self::FnDef { body, .. }
+            | Self::ForLoop { body, .. }
+            | Self::WhileLoop { body, .. } => vec![body],
+            Self::If {
+                body,
+                else_branches,
+                ..
+            } => std::iter::once(body.as_slice())
+                .chain(else_branches.iter().map(|branch| branch.body.as_slice()))
+                .collect(),
+            Self::Switch { cases, .. } => cases.iter().map(|case| case.body.as_slice()).collect(),
+            Self::Try { body, catch, .. } => vec![body, catch],
+            Self::UnwindProtect { body, cleanup } => vec![body, cleanup],
+            Self::VariableDef { .. }
+            | Self::Declaration { .. }
+            | Self::Command { .. }
+            | Self::Break
+            | Self::Continue
+            | Self::Return
+            | Self::Expr(_) => vec![],
+        }
+    }
+
+    /// Returns the expressions the statement evaluates itself, leaving out those of its bodies,
+    /// in the order they are evaluated. The words of a command are left out too.
+    #[must_use]
+    pub fn exprs(&self) -> Vec<ExprIdx> {
+        let conditions = |branches: &[Branch]| -> Vec<ExprIdx> {
+            branches
+                .iter()
+                .filter_map(|branch| branch.condition)
+                .collect()
+        };
+        match self {
+            Self::VariableDef { targets, value } | Self::ForLoop { targets, value, .. } => {
+                std::iter::once(*value)
+                    .chain(targets.iter().flat_map(Target::reads))
+                    .collect()
+            }
+            Self::If {
+                condition,
+                else_branches,
+                ..
+            } => std::iter::once(*condition)
+                .chain(conditions(else_branches))
+                .collect(),
+            Self::Switch { condition, cases } => std::iter::once(*condition)
+                .chain(conditions(cases))
+                .collect(),
+            Self::WhileLoop { condition, .. } => vec![*condition],
+            Self::Declaration {
+                targets, values, ..
+            } => values
+                .iter()
+                .copied()
+                .chain(targets.iter().flat_map(Target::reads))
+                .collect(),
+            Self::Command { name, .. } | Self::Expr(name) => vec![*name],
+            Self::FnDef { .. }
+            | Self::Try { .. }
+            | Self::UnwindProtect { .. }
+            | Self::Break
+            | Self::Continue
+            | Self::Return => vec![],
+        }
+    }
+
+    /// Returns what the statement assigns to itself, after evaluating its expressions.
+    #[must_use]
+    pub fn targets(&self) -> &[Target] {
+        match self {
+            Self::VariableDef { targets, .. }
+            | Self::ForLoop { targets, .. }
+            | Self::Declaration { targets, .. } => targets,
+            _ => &[],
+        }
+    }
+}
+
+/// What an assignment writes to.
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    /// A whole variable, as `x` in `x = 1`. The expression is its [`Expr::VariableRef`].
+    Variable(ExprIdx),
+    /// Part of a variable, as in `x(i) = 1` or `s.a = 1`, which keeps the rest of its value. The
+    /// indices are read before the variable is written.
+    Part { var: ExprIdx, indices: Vec<ExprIdx> },
+    /// Something that cannot be assigned to, which is only evaluated.
+    Invalid(ExprIdx),
+}
+
+impl Target {
+    /// Returns the [`Expr::VariableRef`] of the variable written, if any.
+    #[must_use]
+    pub fn var(&self) -> Option<ExprIdx> {
+        match self {
+            Self::Variable(var) | Self::Part { var, .. } => Some(*var),
+            Self::Invalid(_) => None,
+        }
+    }
+
+    /// Returns the expressions read before anything is assigned.
+    #[must_use]
+    pub fn reads(&self) -> Vec<ExprIdx> {
+        match self {
+            Self::Variable(_) => vec![],
+            Self::Part { indices, .. } => indices.clone(),
+            Self::Invalid(expr) => vec![*expr],
+        }
+    }
+}
+
+/// Walks the statements of `body` and all the statements nested in them, each before its
+/// children.
+pub fn preorder(body: &[Stmt]) -> impl Iterator<Item = &Stmt> {
+    walk(body, true)
+}
+
+/// Like [`preorder`], but leaving out the bodies of the functions defined in `body`, which run
+/// on their own.
+pub fn preorder_in_body(body: &[Stmt]) -> impl Iterator<Item = &Stmt> {
+    walk(body, false)
+}
+
+fn walk(body: &[Stmt], into_functions: bool) -> impl Iterator<Item = &Stmt> {
+    let mut stack: Vec<&Stmt> = body.iter().rev().collect();
+    std::iter::from_fn(move || {
+        let stmt = stack.pop()?;
+        if into_functions || !matches!(stmt, Stmt::FnDef { .. }) {
+            stack.extend(stmt.bodies().into_iter().flatten().rev());
+        }
+        Some(stmt)
+    })
+}
+
+/// Returns the [`Expr::VariableRef`]s of the variables assigned in `body`, including the error
+/// variables of `catch`, in pre-order.
+pub(crate) fn assigned_vars(body: &[Stmt]) -> impl Iterator<Item = ExprIdx> + '_ {
+    preorder_in_body(body).flat_map(|stmt| {
+        let catch_var = match stmt {
+            Stmt::Try { catch_var, .. } => *catch_var,
+            _ => None,
+        };
+        stmt.targets()
+            .iter()
+            .filter_map(Target::var)
+            .chain(catch_var)
+    })
 }
 
 #[derive(Debug, PartialEq)]
@@ -59,6 +282,38 @@ pub enum Expr {
     String {
         value: SmolStr,
     },
+    FnHandle {
+        name: SmolStr,
+    },
+    Field {
+        expr: ExprIdx,
+        name: SmolStr,
+    },
+    /// `end` inside an index, standing for the last index.
+    EndIndex,
+}
+
+impl Expr {
+    /// Returns the operands of this expression, in source order.
+    #[must_use]
+    pub fn children(&self) -> Vec<ExprIdx> {
+        match self {
+            Self::Binary { lhs, rhs, .. } | Self::Range { lhs, rhs } => vec![*lhs, *rhs],
+            Self::Unary { expr, .. } | Self::Transpose { expr, .. } | Self::Field { expr, .. } => {
+                vec![*expr]
+            }
+            Self::Call { func, args } => {
+                std::iter::once(*func).chain(args.iter().copied()).collect()
+            }
+            Self::Matrix { elements } => elements.clone(),
+            Self::Missing
+            | Self::Literal { .. }
+            | Self::VariableRef { .. }
+            | Self::String { .. }
+            | Self::FnHandle { .. }
+            | Self::EndIndex => vec![],
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -106,10 +361,25 @@ pub enum TransposeOp {
     Elmt,
 }
 
+/// A file lowered to statements, along with the expressions they hold and where everything
+/// comes from in the syntax tree.
+#[derive(Debug, PartialEq)]
+pub struct Body {
+    pub db: Database,
+    pub stmts: Vec<Stmt>,
+    /// The range of the whole file.
+    pub range: TextRange,
+}
+
 #[must_use]
-pub fn lower(ast: &ast::Root) -> (Database, Vec<Stmt>) {
+pub fn lower(ast: &ast::Root) -> Body {
     let mut db = Database::default();
-    let stmts = ast.stmts().filter_map(|stmt| db.lower_stmt(stmt)).collect();
+    let stmts = db.lower_stmts(ast.stmts());
+    db.index_stmts(&stmts);
 
-    (db, stmts)
+    Body {
+        db,
+        stmts,
+        range: ast.syntax().text_range(),
+    }
 }
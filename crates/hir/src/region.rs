@@ -0,0 +1,427 @@
+//! The variables that flow into and out of a run of statements, for moving them into a function
+//! of their own.
+//!
+//! Both come from the liveness of variables on the [`Cfg`] of the enclosing body. Inputs are the
+//! variables the region reads that are live where it starts, and may have been assigned by then.
+//! Outputs are the variables it writes that are live where control leaves it, including for
+//! later iterations of a loop enclosing it, a `catch` part or the end of the enclosing function.
+
+use crate::cfg::{BasicBlock, BlockId, Cfg, EdgeKind};
+use crate::flow::{self, Flow, State};
+use crate::scope::{self, AccessKind};
+use crate::{Database, Expr, ExprIdx, Stmt, Target};
+use smol_str::SmolStr;
+use std::collections::HashSet;
+use text_size::TextRange;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    /// In the order they are first used in the region.
+    pub inputs: Vec<SmolStr>,
+    /// In the order they are first written in the region.
+    pub outputs: Vec<SmolStr>,
+}
+
+/// Returns the inputs and outputs of the statements covered by `range`, which must not split a
+/// statement.
+#[must_use]
+pub fn region(root: &ast::Root, range: TextRange) -> Region {
+    let body = crate::lower(root);
+    let db = &body.db;
+
+    // The innermost function the region is in, if any.
+    let function = crate::preorder(&body.stmts)
+        .filter(|stmt| {
+            matches!(stmt, Stmt::FnDef { .. })
+                && db
+                    .stmt_range(stmt)
+                    .is_some_and(|stmt| stmt.contains_range(range))
+        })
+        .last();
+    let (stmts, bindings, outputs): (&[Stmt], Vec<ExprIdx>, &[ExprIdx]) = match function {
+        Some(Stmt::FnDef {
+            params,
+            outputs,
+            body,
+            ..
+        }) => (
+            body,
+            params.iter().chain(outputs).copied().collect(),
+            outputs,
+        ),
+        _ => (&body.stmts, Vec::new(), &[]),
+    };
+    let accesses = scope::accesses(db, stmts);
+
+    // Reads of names that are never written cannot be variables, they are calls.
+    let variables: HashSet<SmolStr> = bindings
+        .iter()
+        .filter_map(|binding| var_name(db, *binding).cloned())
+        .chain(
+            accesses
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| access.name.clone()),
+        )
+        .collect();
+
+    let in_region: HashSet<usize> = crate::preorder_in_body(stmts)
+        .filter(|stmt| {
+            db.stmt_range(stmt)
+                .is_some_and(|stmt| range.contains_range(stmt))
+        })
+        .map(address)
+        .collect();
+    let Some(first) =
+        crate::preorder_in_body(stmts).find(|stmt| in_region.contains(&address(stmt)))
+    else {
+        return Region::default();
+    };
+
+    let cfg = Cfg::build(db, stmts);
+    let is_function = |name: &str| !variables.contains(name);
+    let initial = match function {
+        Some(Stmt::FnDef { params, .. }) => flow::initial_state(db, params),
+        _ => State {
+            unknown: true,
+            ..State::default()
+        },
+    };
+    let flow = Flow::compute(db, &cfg, &is_function, initial);
+    let entry_states: Vec<_> = flow
+        .before_stmts()
+        .into_iter()
+        .filter(|(stmt, _)| std::ptr::eq(*stmt, first))
+        .map(|(_, state)| state)
+        .collect();
+    let possible_at_entry = |name: &SmolStr| {
+        entry_states
+            .iter()
+            .any(|state| state.unknown || state.possible.contains(name))
+    };
+
+    let exit = outputs
+        .iter()
+        .filter_map(|output| var_name(db, *output).cloned())
+        .collect();
+    let liveness = Liveness::compute(db, &cfg, &variables, exit);
+    let live_at_entry = liveness.live_before(first);
+    let live_at_exits = liveness.live_at_exits(&in_region);
+
+    let mut region = Region::default();
+    for access in accesses
+        .iter()
+        .filter(|access| range.contains_range(access.range))
+    {
+        let name = &access.name;
+        if live_at_entry.contains(name)
+            && variables.contains(name)
+            && possible_at_entry(name)
+            && !region.inputs.contains(name)
+        {
+            region.inputs.push(name.clone());
+        }
+        if access.kind == AccessKind::Write
+            && live_at_exits.contains(name)
+            && !region.outputs.contains(name)
+        {
+            region.outputs.push(name.clone());
+        }
+    }
+    region
+}
+
+/// The variables live at the start of each block of a body: those that may be read later before
+/// they are assigned.
+struct Liveness<'a> {
+    db: &'a Database,
+    cfg: &'a Cfg<'a>,
+    variables: &'a HashSet<SmolStr>,
+    live_in: Vec<HashSet<SmolStr>>,
+}
+
+impl<'a> Liveness<'a> {
+    /// `exit` holds the variables live at the end of the body, which are its outputs.
+    fn compute(
+        db: &'a Database,
+        cfg: &'a Cfg<'a>,
+        variables: &'a HashSet<SmolStr>,
+        exit: HashSet<SmolStr>,
+    ) -> Self {
+        let mut liveness = Self {
+            db,
+            cfg,
+            variables,
+            live_in: vec![HashSet::new(); cfg.blocks().count()],
+        };
+        liveness.live_in[Cfg::EXIT.0] = exit;
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, _) in cfg.blocks().collect::<Vec<_>>().into_iter().rev() {
+                if id == Cfg::EXIT {
+                    continue;
+                }
+                let (_, live_in) = liveness.block(id);
+                if live_in != liveness.live_in[id.0] {
+                    liveness.live_in[id.0] = live_in;
+                    changed = true;
+                }
+            }
+        }
+        liveness
+    }
+
+    /// Returns the variables live before each statement of a block, and at its start.
+    fn block(&self, id: BlockId) -> (Vec<HashSet<SmolStr>>, HashSet<SmolStr>) {
+        let block = self.cfg.block(id);
+        let mut live = HashSet::new();
+        // An error may leave the block before any of its statements.
+        let mut raised = HashSet::new();
+        for edge in &block.successors {
+            let target = &self.live_in[edge.target.0];
+            match edge.kind {
+                EdgeKind::Normal => live.extend(target.iter().cloned()),
+                // An uncaught error returns nothing.
+                EdgeKind::Exception if edge.target == Cfg::EXIT => {}
+                EdgeKind::Exception => raised.extend(target.iter().cloned()),
+            }
+        }
+        live.extend(raised.iter().cloned());
+
+        let mut before = vec![HashSet::new(); block.stmts.len()];
+        for (idx, stmt) in block.stmts.iter().enumerate().rev() {
+            let (uses, defs) = self.effects(stmt);
+            for def in defs {
+                live.remove(def);
+            }
+            live.extend(uses.into_iter().cloned());
+            live.extend(raised.iter().cloned());
+            before[idx].clone_from(&live);
+        }
+        if let Some(var) = block.catch_var.and_then(|var| var_name(self.db, var)) {
+            live.remove(var);
+        }
+        (before, live)
+    }
+
+    /// Returns the variables a statement reads and the ones it assigns, leaving out its bodies.
+    /// Assigning to an element or a field reads the rest of the value.
+    fn effects(&self, stmt: &Stmt) -> (Vec<&'a SmolStr>, Vec<&'a SmolStr>) {
+        let db = self.db;
+        let mut reads = stmt.exprs();
+        // The words of a command are strings, unless its name is a variable.
+        if let Stmt::Command { name, words } = stmt
+            && var_name(db, *name).is_some_and(|name| self.variables.contains(name))
+        {
+            reads.extend(words);
+        }
+        let mut uses: Vec<_> = reads
+            .into_iter()
+            .flat_map(|expr| scope::descendants(db, expr))
+            .filter_map(|expr| var_name(db, expr))
+            .collect();
+        let mut defs = Vec::new();
+        for target in stmt.targets() {
+            if let Some(var) = target.var().and_then(|var| var_name(db, var)) {
+                if matches!(target, Target::Part { .. }) {
+                    uses.push(var);
+                }
+                defs.push(var);
+            }
+        }
+        (uses, defs)
+    }
+
+    /// Returns the variables live right before `stmt`, in any of its blocks.
+    fn live_before(&self, stmt: &Stmt) -> HashSet<SmolStr> {
+        let mut live = HashSet::new();
+        for (id, block) in self.cfg.blocks() {
+            let before = self.block(id).0;
+            for (idx, other) in block.stmts.iter().enumerate() {
+                if std::ptr::eq(*other, stmt) {
+                    live.extend(before[idx].iter().cloned());
+                }
+            }
+        }
+        live
+    }
+
+    /// Returns the variables live wherever control leaves the statements of `region`, given by
+    /// address, for statements outside of it.
+    fn live_at_exits(&self, region: &HashSet<usize>) -> HashSet<SmolStr> {
+        let in_region = |stmt: &Stmt| region.contains(&address(stmt));
+        let mut live = HashSet::new();
+        for (id, block) in self.cfg.blocks() {
+            let Some(last) = block.stmts.iter().rposition(|stmt| in_region(stmt)) else {
+                continue;
+            };
+            let before = self.block(id).0;
+            for idx in 0..block.stmts.len() - 1 {
+                if in_region(block.stmts[idx]) && !in_region(block.stmts[idx + 1]) {
+                    live.extend(before[idx + 1].iter().cloned());
+                }
+            }
+            if last + 1 < block.stmts.len() {
+                continue;
+            }
+
+            // Blocks without statements belong to no one, so they are walked through.
+            let mut stack: Vec<_> = followed(block);
+            let mut seen = HashSet::new();
+            while let Some(target) = stack.pop() {
+                if !seen.insert(target) {
+                    continue;
+                }
+                let target_block = self.cfg.block(target);
+                match target_block.stmts.first() {
+                    Some(first) if in_region(first) => {}
+                    Some(_) => live.extend(self.live_in[target.0].iter().cloned()),
+                    None if target == Cfg::EXIT => {
+                        live.extend(self.live_in[target.0].iter().cloned());
+                    }
+                    None => stack.extend(followed(target_block)),
+                }
+            }
+        }
+        live
+    }
+}
+
+/// Returns where control goes after a block, leaving out uncaught errors, which return nothing.
+fn followed(block: &BasicBlock) -> Vec<BlockId> {
+    block
+        .successors
+        .iter()
+        .filter(|edge| edge.kind == EdgeKind::Normal || edge.target != Cfg::EXIT)
+        .map(|edge| edge.target)
+        .collect()
+}
+
+fn var_name(db: &Database, expr: ExprIdx) -> Option<&SmolStr> {
+    match &db[expr] {
+        Expr::VariableRef { var } => Some(var),
+        _ => None,
+    }
+}
+
+fn address(stmt: &Stmt) -> usize {
+    std::ptr::from_ref(stmt).addr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::TypedSyntaxNode;
+    use expect_test::{Expect, expect};
+
+    /// Shows the inputs and outputs of the statements between the two `$0` markers.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let start = input.find("$0").unwrap();
+        let input = input.replacen("$0", "", 1);
+        let end = input.find("$0").unwrap();
+        let input = input.replacen("$0", "", 1);
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+        let range = TextRange::new(
+            u32::try_from(start).unwrap().into(),
+            u32::try_from(end).unwrap().into(),
+        );
+
+        let region = region(&root, range);
+        expected.assert_eq(&format!(
+            "inputs: {}\noutputs: {}",
+            region.inputs.join(", "),
+            region.outputs.join(", ")
+        ));
+    }
+
+    #[test]
+    fn reads_before_writes_are_inputs() {
+        check(
+            "function y = f(a)\n  $0b = a + 1;\n  c = b * 2;$0\n  y = c;\nend\n",
+            expect![[r"
+                inputs: a
+                outputs: c"]],
+        );
+    }
+
+    #[test]
+    fn calls_are_not_inputs() {
+        check(
+            "function f(x)\n  $0disp(x); y = max(x);$0\n  disp(y)\nend\n",
+            expect![[r"
+                inputs: x
+                outputs: y"]],
+        );
+    }
+
+    #[test]
+    fn returned_variables_are_outputs() {
+        check(
+            "function [a, b] = f(x)\n  a = 1;\n  $0b = x;\n  t = b;$0\nend\n",
+            expect![[r"
+                inputs: x
+                outputs: b"]],
+        );
+    }
+
+    #[test]
+    fn conditional_writes_keep_the_previous_value() {
+        check(
+            "x = 1;\nc = true;\n$0if c\n  x = 2;\nend$0\ndisp(x)\n",
+            expect![[r"
+                inputs: c, x
+                outputs: x"]],
+        );
+    }
+
+    #[test]
+    fn partial_writes_read_the_previous_value() {
+        check(
+            "function s = f(s)\n  $0s.a = 1;\n  t.b = 2;$0\n  s.t = t;\nend\n",
+            expect![[r"
+                inputs: s
+                outputs: s, t"]],
+        );
+    }
+
+    #[test]
+    fn writes_that_an_error_or_a_return_skips_keep_the_previous_value() {
+        check(
+            "function y = f(x)\n  y = 0;\n  $0try\n    y = g(x);\n  catch\n  end$0\n  disp(y)\nend\n",
+            expect![[r"
+                inputs: x, y
+                outputs: y"]],
+        );
+        check(
+            "function y = f(c)\n  y = 0;\n  $0if c\n    return;\n  end\n  y = 1;$0\nend\n",
+            expect![[r"
+                inputs: c, y
+                outputs: y"]],
+        );
+        check(
+            "function y = f(x)\n  $0y = g(x);\n  error('no');$0\nend\n",
+            expect![[r"
+                inputs: x
+                outputs: "]],
+        );
+    }
+
+    #[test]
+    fn loops_read_what_their_body_writes() {
+        check(
+            "total = 0;\nfor i = 1:3\n  $0total = total + i;$0\nend\ndisp(total)\n",
+            expect![[r"
+                inputs: total, i
+                outputs: total"]],
+        );
+        check(
+            "k = 0;\nwhile k < 3\n  $0k = 3;$0\nend\n",
+            expect![[r"
+                inputs: 
+                outputs: k"]],
+        );
+    }
+}
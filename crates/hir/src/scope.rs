@@ -0,0 +1,264 @@
+//! Scope-aware name resolution.
+//!
+//! Every function has a workspace of its own in Octave, so the variables of a file are split into
+//! one [`Scope`] for the script code at the top level and one for each function definition.
+//! Within a scope we record every access to a name, in the order they happen, together with its
+//! range. Scopes are built from the lowered file, and find their ranges through its source map.
+
+use crate::{Body, Database, Expr, ExprIdx, Stmt, Target};
+use smol_str::SmolStr;
+use std::collections::HashSet;
+use text_size::TextRange;
+
+#[derive(Debug, PartialEq)]
+pub struct Scope {
+    /// The name of the function, or None for the script code at the top level of a file.
+    pub name: Option<SmolStr>,
+    pub range: TextRange,
+    pub params: Vec<Binding>,
+    pub outputs: Vec<Binding>,
+    pub accesses: Vec<Access>,
+}
+
+/// A name bound by a function header, such as a parameter or an output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub name: SmolStr,
+    pub range: TextRange,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    pub name: SmolStr,
+    pub range: TextRange,
+    pub kind: AccessKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+impl Scope {
+    #[must_use]
+    pub fn is_function(&self) -> bool {
+        self.name.is_some()
+    }
+
+    pub fn reads(&self, name: &str) -> impl Iterator<Item = &Access> {
+        self.accesses_of(name, AccessKind::Read)
+    }
+
+    pub fn writes(&self, name: &str) -> impl Iterator<Item = &Access> {
+        self.accesses_of(name, AccessKind::Write)
+    }
+
+    fn accesses_of(&self, name: &str, kind: AccessKind) -> impl Iterator<Item = &Access> {
+        self.accesses
+            .iter()
+            .filter(move |access| access.kind == kind && access.name == name)
+    }
+}
+
+/// Splits a file into its scopes, in source order. The script scope always comes first, even if
+/// empty.
+#[must_use]
+pub fn scopes(root: &ast::Root) -> Vec<Scope> {
+    lowered_scopes(&crate::lower(root))
+}
+
+/// Like [`scopes`], for a file already lowered.
+#[must_use]
+pub fn lowered_scopes(body: &Body) -> Vec<Scope> {
+    let (db, file) = (&body.db, body.stmts.as_slice());
+    let mut scopes = vec![Scope {
+        name: None,
+        range: body.range,
+        params: Vec::new(),
+        outputs: Vec::new(),
+        accesses: accesses(db, file),
+    }];
+
+    // Functions defined in the same file (including nested ones) get their own workspace.
+    for stmt in crate::preorder(file) {
+        let Stmt::FnDef {
+            name,
+            params,
+            outputs,
+            body,
+        } = stmt
+        else {
+            continue;
+        };
+        let Some(range) = db
+            .source_map()
+            .stmt_id(stmt)
+            .and_then(|id| db.source_map().stmt_syntax(id))
+            .map(|ptr| ptr.text_range())
+        else {
+            continue;
+        };
+        scopes.push(Scope {
+            name: Some(name.clone().unwrap_or_default()),
+            range,
+            params: bindings(db, params),
+            outputs: bindings(db, outputs),
+            accesses: accesses(db, body),
+        });
+    }
+
+    scopes.sort_by_key(|scope| scope.range.start());
+    scopes
+}
+
+fn bindings(db: &Database, vars: &[ExprIdx]) -> Vec<Binding> {
+    vars.iter()
+        .filter_map(|var| {
+            let Expr::VariableRef { var: name } = &db[*var] else {
+                return None;
+            };
+            Some(Binding {
+                name: name.clone(),
+                range: db.source_map().expr_range(*var)?,
+            })
+        })
+        .collect()
+}
+
+/// Records the accesses of the statements of a body, leaving out the functions defined in it.
+pub(crate) fn accesses(db: &Database, body: &[Stmt]) -> Vec<Access> {
+    let written: HashSet<&SmolStr> = crate::assigned_vars(body)
+        .filter_map(|var| match &db[var] {
+            Expr::VariableRef { var } => Some(var),
+            _ => None,
+        })
+        .collect();
+    let mut walker = Walker {
+        db,
+        written,
+        accesses: Vec::new(),
+    };
+    walker.stmts(body);
+    walker.accesses
+}
+
+struct Walker<'a> {
+    db: &'a Database,
+    /// Every name written in the scope.
+    written: HashSet<&'a SmolStr>,
+    accesses: Vec<Access>,
+}
+
+impl Walker<'_> {
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let mut reads = stmt.exprs();
+        // The words of a command are strings, unless its name is a variable of the scope.
+        if let Stmt::Command { name, words } = stmt
+            && matches!(&self.db[*name], Expr::VariableRef { var } if self.written.contains(var))
+        {
+            reads.extend(words);
+        }
+        for expr in reads {
+            for var in descendants(self.db, expr) {
+                self.access(var, AccessKind::Read);
+            }
+        }
+        for var in stmt.targets().iter().filter_map(Target::var) {
+            self.access(var, AccessKind::Write);
+        }
+
+        match stmt {
+            Stmt::FnDef { .. } => {}
+            Stmt::Try {
+                body,
+                catch_var,
+                catch,
+            } => {
+                self.stmts(body);
+                if let Some(var) = catch_var {
+                    self.access(*var, AccessKind::Write);
+                }
+                self.stmts(catch);
+            }
+            _ => {
+                for body in stmt.bodies() {
+                    self.stmts(body);
+                }
+            }
+        }
+    }
+
+    fn access(&mut self, var: ExprIdx, kind: AccessKind) {
+        if let (Expr::VariableRef { var: name }, Some(range)) =
+            (&self.db[var], self.db.source_map().expr_range(var))
+        {
+            self.accesses.push(Access {
+                name: name.clone(),
+                range,
+                kind,
+            });
+        }
+    }
+}
+
+/// Returns `expr` and all the expressions nested in it, in source order.
+pub(crate) fn descendants(db: &Database, expr: ExprIdx) -> Vec<ExprIdx> {
+    let mut stack = vec![expr];
+    let mut exprs = Vec::new();
+    while let Some(expr) = stack.pop() {
+        stack.extend(db[expr].children().into_iter().rev());
+        exprs.push(expr);
+    }
+    exprs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::TypedSyntaxNode;
+
+    fn accesses(input: &str) -> Vec<String> {
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        scopes(&root)
+            .iter()
+            .flat_map(|scope| &scope.accesses)
+            .map(|access| format!("{:?} {} at {:?}", access.kind, access.name, access.range))
+            .collect()
+    }
+
+    #[test]
+    fn records_accesses_in_the_order_they_happen() {
+        assert_eq!(
+            accesses("x(i) = x + 1;\ntry\n  hold on\ncatch err\n  disp(err)\nend"),
+            [
+                "Read x at 7..8",
+                "Read i at 2..3",
+                "Write x at 0..1",
+                "Read hold at 20..24",
+                "Write err at 34..37",
+                "Read disp at 40..44",
+                "Read err at 45..48",
+            ]
+        );
+    }
+
+    #[test]
+    fn words_of_a_variable_are_reads() {
+        assert_eq!(
+            accesses("a = 1;\nb = 2;\na b"),
+            [
+                "Write a at 0..1",
+                "Write b at 7..8",
+                "Read a at 14..15",
+                "Read b at 16..17",
+            ]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Where the lowered statements and expressions come from in the syntax tree, and back.
+//!
+//! Pointers stay valid after the tree is dropped and are resolved against its root again, as
+//! long as the text has not changed.
+
+use crate::{ExprIdx, Stmt};
+use la_arena::ArenaMap;
+use std::collections::HashMap;
+use syntax::{SyntaxNode, SyntaxNodePtr};
+use text_size::TextRange;
+
+/// A statement of a lowered file, numbered in the order of `crate::preorder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StmtId(usize);
+
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    expr_ptrs: ArenaMap<ExprIdx, SyntaxNodePtr>,
+    expr_ranges: ArenaMap<ExprIdx, TextRange>,
+    exprs: HashMap<SyntaxNodePtr, ExprIdx>,
+    stmt_ptrs: Vec<SyntaxNodePtr>,
+    stmt_ranges: Vec<TextRange>,
+    stmts: HashMap<SyntaxNodePtr, StmtId>,
+    /// The statements of the lowered file by address, which stays the same as long as the file
+    /// is not changed.
+    stmt_ids: HashMap<usize, StmtId>,
+}
+
+impl SourceMap {
+    /// Returns the node an expression was lowered from, including its parentheses. Expressions
+    /// missing from the source have none.
+    #[must_use]
+    pub fn expr_syntax(&self, idx: ExprIdx) -> Option<SyntaxNodePtr> {
+        self.expr_ptrs.get(idx).copied()
+    }
+
+    /// Returns the range of an expression without its surrounding trivia but with its
+    /// parentheses.
+    #[must_use]
+    pub fn expr_range(&self, idx: ExprIdx) -> Option<TextRange> {
+        self.expr_ranges.get(idx).copied()
+    }
+
+    /// Returns the expression lowered from `node`, which may be any of the parentheses around
+    /// it.
+    #[must_use]
+    pub fn node_expr(&self, node: &SyntaxNode) -> Option<ExprIdx> {
+        self.exprs.get(&SyntaxNodePtr::new(node)).copied()
+    }
+
+    #[must_use]
+    pub fn stmt_syntax(&self, id: StmtId) -> Option<SyntaxNodePtr> {
+        self.stmt_ptrs.get(id.0).copied()
+    }
+
+    /// Returns the range of a statement without its surrounding trivia.
+    #[must_use]
+    pub fn stmt_range(&self, id: StmtId) -> Option<TextRange> {
+        self.stmt_ranges.get(id.0).copied()
+    }
+
+    /// Returns the id of a statement of the file lowered along with this map, at any depth.
+    #[must_use]
+    pub fn stmt_id(&self, stmt: &Stmt) -> Option<StmtId> {
+        self.stmt_ids.get(&address(stmt)).copied()
+    }
+
+    /// Returns the statement lowered from `node`.
+    #[must_use]
+    pub fn node_stmt(&self, node: &SyntaxNode) -> Option<StmtId> {
+        self.stmts.get(&SyntaxNodePtr::new(node)).copied()
+    }
+
+    /// Records `idx` as lowered from `node`, and from the expression inside it if it is
+    /// parenthesised.
+    pub(crate) fn insert_expr(&mut self, idx: ExprIdx, node: &SyntaxNode, range: TextRange) {
+        self.expr_ptrs.insert(idx, SyntaxNodePtr::new(node));
+        self.expr_ranges.insert(idx, range);
+        let mut node = Some(node.clone());
+        while let Some(inner) = node {
+            self.exprs.insert(SyntaxNodePtr::new(&inner), idx);
+            node = match ast::Expr::cast(inner) {
+                Some(ast::Expr::ParenExpr(paren)) => paren.expr().map(|expr| expr.syntax().clone()),
+                _ => None,
+            };
+        }
+    }
+
+    /// Records the next statement in pre-order as lowered from `node`.
+    pub(crate) fn insert_stmt(&mut self, node: &SyntaxNode, range: TextRange) {
+        let id = StmtId(self.stmt_ptrs.len());
+        self.stmt_ptrs.push(SyntaxNodePtr::new(node));
+        self.stmt_ranges.push(range);
+        self.stmts.insert(SyntaxNodePtr::new(node), id);
+    }
+
+    /// Numbers the statements of the whole lowered file, once it is complete.
+    pub(crate) fn index_stmts(&mut self, file: &[Stmt]) {
+        self.stmt_ids = crate::preorder(file)
+            .enumerate()
+            .map(|(idx, stmt)| (address(stmt), StmtId(idx)))
+            .collect();
+    }
+}
+
+/// The ids by address are left out: they follow from the statements, wherever those are.
+impl PartialEq for SourceMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr_ptrs == other.expr_ptrs
+            && self.expr_ranges == other.expr_ranges
+            && self.exprs == other.exprs
+            && self.stmt_ptrs == other.stmt_ptrs
+            && self.stmt_ranges == other.stmt_ranges
+            && self.stmts == other.stmts
+    }
+}
+
+fn address(stmt: &Stmt) -> usize {
+    std::ptr::from_ref(stmt).addr()
+}
+
+/// Returns the statement of `file` with the id `id`.
+#[must_use]
+pub fn stmt(file: &[Stmt], id: StmtId) -> Option<&Stmt> {
+    crate::preorder(file).nth(id.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Expr;
+    use ast::TypedSyntaxNode;
+
+    fn lower(input: &str) -> (ast::Root, crate::Database, Vec<Stmt>) {
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let crate::Body { db, stmts, .. } = crate::lower(&root);
+        (root, db, stmts)
+    }
+
+    #[test]
+    fn records_expr_ranges() {
+        let input = "y = f(a + 1, (b))';";
+        let (_, db, _) = lower(input);
+
+        let ranges: Vec<_> = db
+            .exprs()
+            .map(|(idx, _)| db.source_map().expr_range(idx).map(|range| &input[range]))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                Some("f"),
+                Some("a"),
+                Some("1"),
+                Some("a + 1"),
+                Some("(b)"),
+                Some("f(a + 1, (b))"),
+                Some("f(a + 1, (b))'"),
+                Some("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_exprs_to_nodes_and_back() {
+        let (root, db, _) = lower("y = f(a + 1, ((b)));\n");
+        let source_map = db.source_map();
+
+        for (idx, _) in db.exprs() {
+            let node = source_map.expr_syntax(idx).unwrap().to_node(root.syntax());
+            assert_eq!(source_map.node_expr(&node), Some(idx));
+        }
+        // Every pair of parentheses stands for the expression inside.
+        let b = db
+            .exprs()
+            .find(|(_, expr)| matches!(expr, Expr::VariableRef { var } if var == "b"))
+            .unwrap()
+            .0;
+        let nodes: Vec<_> = root
+            .syntax()
+            .descendants()
+            .filter(|node| source_map.node_expr(node) == Some(b))
+            .map(|node| node.text().to_string())
+            .collect();
+        assert_eq!(nodes, ["((b))", "(b)", "b"]);
+    }
+
+    #[test]
+    fn maps_stmts_to_nodes_and_back() {
+        let input = "if a\n  x = 1;\nelse\n  disp(2)\nend\n";
+        let (root, db, file) = lower(input);
+        let source_map = db.source_map();
+
+        let stmts: Vec<_> = crate::preorder(&file)
+            .map(|stmt| {
+                let id = source_map.stmt_id(stmt).unwrap();
+                let node = source_map.stmt_syntax(id).unwrap().to_node(root.syntax());
+                assert_eq!(source_map.node_stmt(&node), Some(id));
+                assert!(std::ptr::eq(self::stmt(&file, id).unwrap(), stmt));
+                &input[source_map.stmt_range(id).unwrap()]
+            })
+            .collect();
+        assert_eq!(
+            stmts,
+            ["if a\n  x = 1;\nelse\n  disp(2)\nend", "x = 1;", "disp(2)"]
+        );
+    }
+}
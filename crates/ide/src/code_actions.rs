@@ -0,0 +1,519 @@
+//! Quick fixes for the errors of `ast::validation` and the lints of `hir`, found by their code,
+//! and rewrites offered wherever the cursor is.
+
+use ast::TypedSyntaxNode;
+use ast::validation::{NegationStyle, ValidationError, validate_negation_style};
+use hir::diagnostics::Diagnostic;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::{TextRange, TextSize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeAction {
+    pub title: String,
+    pub kind: CodeActionKind,
+    pub edits: Vec<Edit>,
+    /// A file the action creates, which `edits` do not apply to.
+    pub new_file: Option<NewFile>,
+    /// Whether this is the fix to apply when several are offered for the same problem.
+    pub is_preferred: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeActionKind {
+    QuickFix,
+    Rewrite,
+    Extract,
+    Inline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: TextRange,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+impl CodeAction {
+    fn replace(title: String, kind: CodeActionKind, range: TextRange, text: &str) -> Self {
+        Self {
+            title,
+            kind,
+            edits: vec![Edit {
+                range,
+                text: text.to_string(),
+            }],
+            new_file: None,
+            is_preferred: false,
+        }
+    }
+
+    fn preferred(mut self) -> Self {
+        self.is_preferred = true;
+        self
+    }
+}
+
+/// Returns the fixes for an error found by `ast::validation`.
+#[must_use]
+pub fn validation_fixes(root: &ast::Root, error: &ValidationError) -> Vec<CodeAction> {
+    let expected = error.expected().map(token_text);
+    match (error.code(), expected) {
+        ("missing-terminator", Some(expected)) => insert_terminator(root, error, expected)
+            .into_iter()
+            .collect(),
+        ("mismatched-terminator", Some(expected)) => vec![
+            replace_with(error.range(), expected).preferred(),
+            replace_with(error.range(), "end"),
+        ],
+        ("terminator-style" | "negation-style", Some(expected)) => {
+            vec![replace_with(error.range(), expected).preferred()]
+        }
+        ("output-not-suppressed", _) => vec![suppress_output(root, error.range())],
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the fixes for a lint of `hir`. `path` is the file the lint is in, next to which new
+/// functions are created.
+#[must_use]
+pub fn lint_fixes(
+    root: &ast::Root,
+    diagnostic: &Diagnostic,
+    path: Option<&Path>,
+) -> Vec<CodeAction> {
+    let Some(identifier) = identifier_at(root, diagnostic.range) else {
+        return Vec::new();
+    };
+    match diagnostic.kind.code() {
+        "unused-variable" if is_in_output_list(&identifier) => vec![
+            CodeAction::replace(
+                format!("Replace '{}' with '~'", identifier.text()),
+                CodeActionKind::QuickFix,
+                identifier.text_range(),
+                "~",
+            )
+            .preferred(),
+        ],
+        "undefined-name" => path
+            .and_then(|path| create_function(&identifier, path))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the rewrites of the tokens in `range`: switching a negation between `!` and `~`.
+#[must_use]
+pub fn rewrites(root: &ast::Root, range: TextRange) -> Vec<CodeAction> {
+    [NegationStyle::Tilde, NegationStyle::Exclamation]
+        .into_iter()
+        .flat_map(|style| validate_negation_style(root.syntax(), style))
+        .filter(|error| error.range().intersect(range).is_some())
+        .filter_map(|error| {
+            let found = root.syntax().covering_element(error.range());
+            let expected = token_text(error.expected()?);
+            Some(CodeAction::replace(
+                format!("Replace '{found}' with '{expected}'"),
+                CodeActionKind::Rewrite,
+                error.range(),
+                expected,
+            ))
+        })
+        .collect()
+}
+
+fn replace_with(range: TextRange, text: &str) -> CodeAction {
+    CodeAction::replace(
+        format!("Replace with '{text}'"),
+        CodeActionKind::QuickFix,
+        range,
+        text,
+    )
+}
+
+/// Closes a block on a new line after its last statement, in the style of the rest of the file.
+fn insert_terminator(
+    root: &ast::Root,
+    error: &ValidationError,
+    expected: &'static str,
+) -> Option<CodeAction> {
+    let opener_range = error.opener_range()?;
+    let block = root
+        .syntax()
+        .descendants()
+        .filter_map(ast::BlockStmt::cast)
+        .find(|block| {
+            block
+                .opener()
+                .is_some_and(|opener| opener.text_range() == opener_range)
+        })?;
+    let last = block
+        .syntax()
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| !is_trivia(token))
+        .last()?;
+    let keyword = if root.uses_specific_terminators() {
+        expected
+    } else {
+        "end"
+    };
+    let indent = line_indent(root, opener_range.start());
+    Some(
+        CodeAction::replace(
+            format!("Insert '{keyword}'"),
+            CodeActionKind::QuickFix,
+            TextRange::empty(last.text_range().end()),
+            &format!("\n{indent}{keyword}"),
+        )
+        .preferred(),
+    )
+}
+
+/// Ends an assignment with `;`, replacing the `,` that may separate it from the next statement.
+fn suppress_output(root: &ast::Root, range: TextRange) -> CodeAction {
+    let mut next = root.syntax().token_at_offset(range.end()).right_biased();
+    while let Some(token) = next
+        .as_ref()
+        .filter(|token| token.kind() == TokenKind::Whitespace.into())
+    {
+        next = token.next_token();
+    }
+    let edit_range = match next {
+        Some(comma) if comma.kind() == TokenKind::Comma.into() => comma.text_range(),
+        _ => TextRange::empty(range.end()),
+    };
+    CodeAction::replace(
+        "Add ';' to suppress the output".to_string(),
+        CodeActionKind::QuickFix,
+        edit_range,
+        ";",
+    )
+    .preferred()
+}
+
+/// Whether a variable is one of the outputs of a multiple assignment, as `i` in
+/// `[m, i] = max(x)`, where `~` can take its place.
+fn is_in_output_list(identifier: &SyntaxToken) -> bool {
+    let Some(matrix) = identifier
+        .parent()
+        .filter(|parent| parent.kind() == SyntaxConstruct::VariableRef.into())
+        .and_then(|var| var.parent())
+        .filter(|parent| parent.kind() == SyntaxConstruct::MatrixExpr.into())
+    else {
+        return false;
+    };
+    matrix
+        .parent()
+        .and_then(ast::VariableDef::cast)
+        .and_then(|def| def.lhs())
+        .is_some_and(|lhs| lhs.syntax() == &matrix)
+}
+
+/// Creates `name.m` next to `path` with a function taking the arguments of the call.
+fn create_function(identifier: &SyntaxToken, path: &Path) -> Option<CodeAction> {
+    let name = identifier.text();
+    crate::check_name(name).ok()?;
+    let var = identifier.parent()?;
+
+    let mut params: Vec<String> = Vec::new();
+    let call = var
+        .parent()
+        .filter(|parent| parent.kind() == SyntaxConstruct::CallExpr.into())
+        .and_then(ast::Expr::cast)
+        .and_then(|expr| match expr {
+            ast::Expr::CallExpr(call) => Some(call),
+            _ => None,
+        })
+        .filter(|call| call.func().is_some_and(|func| func.syntax() == &var));
+    if let Some(call) = &call {
+        for (i, arg) in call.args().enumerate() {
+            let param = match &arg {
+                ast::Expr::VariableRef(arg) => arg.name().map(|name| name.text().to_string()),
+                _ => None,
+            };
+            let param = param
+                .filter(|param| !params.contains(param))
+                .unwrap_or_else(|| format!("arg{}", i + 1));
+            params.push(param);
+        }
+    }
+
+    // A value assigned to a variable names the output after it.
+    let used = call
+        .as_ref()
+        .map_or(var.clone(), |call| call.syntax().clone());
+    let output = used
+        .parent()
+        .and_then(ast::VariableDef::cast)
+        .filter(|def| def.value().is_some_and(|value| value.syntax() == &used))
+        .and_then(|def| match def.lhs()? {
+            ast::Expr::VariableRef(output) => Some(output.name()?.text().to_string()),
+            _ => None,
+        })
+        .filter(|output| !params.contains(output));
+
+    let signature = match (output, call) {
+        (Some(output), Some(_)) => format!("{output} = {name}({})", params.join(", ")),
+        (Some(output), None) => format!("{output} = {name}"),
+        (None, Some(_)) => format!("{name}({})", params.join(", ")),
+        (None, None) => name.to_string(),
+    };
+    let file_name = format!("{name}.m");
+    Some(CodeAction {
+        title: format!("Create function file '{file_name}'"),
+        kind: CodeActionKind::QuickFix,
+        edits: Vec::new(),
+        new_file: Some(NewFile {
+            path: path.with_file_name(file_name),
+            text: format!("function {signature}\nend\n"),
+        }),
+        is_preferred: false,
+    })
+}
+
+fn identifier_at(root: &ast::Root, range: TextRange) -> Option<SyntaxToken> {
+    root.syntax()
+        .token_at_offset(range.start())
+        .find(|token| token.kind() == TokenKind::Identifier.into() && token.text_range() == range)
+}
+
+/// The whitespace that starts the line `offset` is on.
+pub(crate) fn line_indent(root: &ast::Root, offset: TextSize) -> String {
+    let mut indent = String::new();
+    let mut token = root.syntax().token_at_offset(offset).right_biased();
+    while let Some(previous) = token.and_then(|token| token.prev_token()) {
+        match previous.kind() {
+            SyntaxKind::LexToken(TokenKind::Whitespace) => indent.insert_str(0, previous.text()),
+            SyntaxKind::LexToken(TokenKind::Newline) => break,
+            _ => indent.clear(),
+        }
+        token = Some(previous);
+    }
+    indent
+}
+
+/// The range of a node without the trivia it absorbs.
+pub(crate) fn trimmed(node: &SyntaxNode) -> Option<TextRange> {
+    let mut tokens = node
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| !is_trivia(token));
+    let first = tokens.next()?.text_range();
+    let last = tokens.last().map_or(first, |token| token.text_range());
+    Some(first.cover(last))
+}
+
+/// `base`, or `base2` and so on if the file already uses that name.
+pub(crate) fn unused_name(root: &ast::Root, base: &str) -> String {
+    let used: HashSet<String> = root
+        .syntax()
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| token.kind() == TokenKind::Identifier.into())
+        .map(|token| token.text().to_string())
+        .collect();
+    let mut name = base.to_string();
+    let mut i = 1;
+    while used.contains(&name) {
+        i += 1;
+        name = format!("{base}{i}");
+    }
+    name
+}
+
+pub(crate) fn is_trivia(token: &SyntaxToken) -> bool {
+    matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia())
+}
+
+fn token_text(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Not => "!",
+        TokenKind::Tilde => "~",
+        TokenKind::NotEquals => "!=",
+        TokenKind::TildeEquals => "~=",
+        _ => kind.keyword_text().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    /// Shows each action with the text it leads to.
+    fn render(input: &str, actions: Vec<CodeAction>) -> String {
+        let mut actual = String::new();
+        for action in actions {
+            let mut text = input.to_string();
+            for edit in action.edits.iter().rev() {
+                text.replace_range(std::ops::Range::<usize>::from(edit.range), &edit.text);
+            }
+            let preferred = if action.is_preferred {
+                " (preferred)"
+            } else {
+                ""
+            };
+            let _ = writeln!(actual, "{:?} {}{preferred}", action.kind, action.title);
+            match action.new_file {
+                Some(file) => {
+                    let _ = writeln!(actual, "{}:\n{}", file.path.display(), file.text);
+                }
+                None => {
+                    let _ = writeln!(actual, "{text}");
+                }
+            }
+        }
+        actual
+    }
+
+    fn parse(input: &str) -> ast::Root {
+        ast::Root::cast(parser::parse(input).syntax()).unwrap()
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check_validation(input: &str, expected: Expect) {
+        let root = parse(input);
+        let actions = ast::validation::validate(root.syntax())
+            .iter()
+            .flat_map(|error| validation_fixes(&root, error))
+            .collect();
+        expected.assert_eq(&render(input, actions));
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check_lints(input: &str, expected: Expect) {
+        let root = parse(input);
+        let actions = hir::diagnostics::diagnostics(&hir::lower(&root), &|name| name == "max")
+            .iter()
+            .flat_map(|diagnostic| lint_fixes(&root, diagnostic, Some(Path::new("/ws/main.m"))))
+            .collect();
+        expected.assert_eq(&render(input, actions));
+    }
+
+    #[test]
+    fn inserts_a_missing_terminator() {
+        check_validation(
+            "if a\n  while b\n    b = b - 1;\n  end\n",
+            expect![[r"
+                QuickFix Insert 'end' (preferred)
+                if a
+                  while b
+                    b = b - 1;
+                  end
+                end
+
+            "]],
+        );
+        check_validation(
+            "while x\n  if x\n    x = 0;\n  endif\n",
+            expect![[r"
+                QuickFix Insert 'endwhile' (preferred)
+                while x
+                  if x
+                    x = 0;
+                  endif
+                endwhile
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn replaces_a_mismatched_terminator() {
+        check_validation(
+            "for i = 1:3\nendif\n",
+            expect![[r"
+                QuickFix Replace with 'endfor' (preferred)
+                for i = 1:3
+                endfor
+
+                QuickFix Replace with 'end'
+                for i = 1:3
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn suppresses_output() {
+        check_validation(
+            "function f\n  x = 1 % one\n  y = 2, z = 3;\nend\n",
+            expect![[r"
+                QuickFix Add ';' to suppress the output (preferred)
+                function f
+                  x = 1; % one
+                  y = 2, z = 3;
+                end
+
+                QuickFix Add ';' to suppress the output (preferred)
+                function f
+                  x = 1 % one
+                  y = 2; z = 3;
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn ignores_unused_outputs() {
+        check_lints(
+            "function m = f(x)\n  [m, i] = max(x);\n  j = 1;\nend\n",
+            expect![[r"
+                QuickFix Replace 'i' with '~' (preferred)
+                function m = f(x)
+                  [m, ~] = max(x);
+                  j = 1;
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn creates_undefined_functions() {
+        check_lints(
+            "function f(a)\n  r = helper(a, 2, a);\n  other;\nend\n",
+            expect![[r"
+                QuickFix Create function file 'helper.m'
+                /ws/helper.m:
+                function r = helper(a, arg2, arg3)
+                end
+
+                QuickFix Create function file 'other.m'
+                /ws/other.m:
+                function other
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn switches_negations() {
+        let input = "if !a && b ~= c\nend\n";
+        let root = parse(input);
+        let actions = rewrites(&root, TextRange::new(0.into(), TextSize::of(input)));
+        expect![[r"
+            Rewrite Replace '!' with '~'
+            if ~a && b ~= c
+            end
+
+            Rewrite Replace '~=' with '!='
+            if !a && b != c
+            end
+
+        "]]
+        .assert_eq(&render(input, actions));
+    }
+}
@@ -0,0 +1,538 @@
+//! Completion of the name being typed.
+//!
+//! Everything is worked out from the tokens around the cursor and the scopes of the file, both of
+//! which hold up well in the half-written code completion is asked for.
+
+use crate::references::{binds, innermost_scope};
+use crate::workspace_symbols::fuzzy_score;
+use ast::TypedSyntaxNode;
+use catalogue::Catalogue;
+use project::{DefinitionKind, Project};
+use std::collections::HashSet;
+use std::path::Path;
+use syntax::{SyntaxConstruct, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::{TextRange, TextSize};
+
+/// Block constructs offered as snippets, with the placeholders of the LSP snippet syntax.
+const SNIPPETS: [(&str, &str); 8] = [
+    ("for", "for ${1:i} = ${2:1:n}\n\t$0\nendfor"),
+    ("while", "while ${1:condition}\n\t$0\nendwhile"),
+    ("do", "do\n\t$0\nuntil ${1:condition}"),
+    ("if", "if ${1:condition}\n\t$0\nendif"),
+    (
+        "switch",
+        "switch ${1:value}\n\tcase ${2:label}\n\t\t$0\n\totherwise\nendswitch",
+    ),
+    (
+        "function",
+        "function ${1:retval} = ${2:name} (${3:args})\n\t$0\nendfunction",
+    ),
+    ("try", "try\n\t$0\ncatch ${1:err}\nend_try_catch"),
+    (
+        "unwind_protect",
+        "unwind_protect\n\t$0\nunwind_protect_cleanup\nend_unwind_protect",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completions {
+    /// The part of the name typed so far, which the completion replaces.
+    pub range: TextRange,
+    /// The most relevant first.
+    pub items: Vec<CompletionItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Shown next to the label, such as the signature of a function.
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+    /// What to insert instead of the label, in the LSP snippet syntax.
+    pub snippet: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Variable,
+    Field,
+    Function,
+    Keyword,
+    Snippet,
+}
+
+impl CompletionItem {
+    fn new(label: impl Into<String>, kind: CompletionKind) -> Self {
+        Self {
+            label: label.into(),
+            kind,
+            detail: None,
+            documentation: None,
+            snippet: None,
+        }
+    }
+
+    fn with_detail(mut self, detail: Option<String>) -> Self {
+        self.detail = detail;
+        self
+    }
+}
+
+/// Returns what could be typed at `offset`. Functions of other files are those of `project` that
+/// are visible from `path`.
+///
+/// There is nothing to complete in comments and strings.
+#[must_use]
+pub fn completions(
+    root: &ast::Root,
+    offset: TextSize,
+    project: &Project,
+    catalogue: &Catalogue,
+    path: Option<&Path>,
+) -> Option<Completions> {
+    let token = root.syntax().token_at_offset(offset).left_biased();
+    if let Some(token) = &token
+        && (token.kind() == TokenKind::Comment.into()
+            || token.parent().and_then(ast::StringLiteral::cast).is_some())
+    {
+        return None;
+    }
+
+    // Keywords are completed too, as in `for` on the way to `format`.
+    let typed = token.filter(|token| {
+        matches!(token.kind(), SyntaxKind::LexToken(kind)
+            if kind == TokenKind::Identifier || kind.is_keyword_statement())
+    });
+    let range = typed.as_ref().map_or(TextRange::empty(offset), |token| {
+        TextRange::new(token.text_range().start(), offset)
+    });
+    let before = match &typed {
+        Some(token) => previous_token(token),
+        None => root
+            .syntax()
+            .token_at_offset(offset)
+            .left_biased()
+            .and_then(|token| skip_whitespace(Some(token))),
+    };
+
+    let items = match before.as_ref().and_then(token_kind) {
+        Some(TokenKind::Dot) => fields(root, before.as_ref()?, offset),
+        Some(TokenKind::At) => names(root, offset, project, catalogue, path, false)
+            .into_iter()
+            .filter(|item| item.kind == CompletionKind::Function)
+            .collect(),
+        _ => names(
+            root,
+            offset,
+            project,
+            catalogue,
+            path,
+            starts_statement(before.as_ref()),
+        ),
+    };
+
+    let prefix = typed.as_ref().map_or("", |token| {
+        &token.text()[..usize::from(offset - token.text_range().start())]
+    });
+    let mut items: Vec<_> = items
+        .into_iter()
+        .filter_map(|item| Some((fuzzy_score(prefix, &item.label)?, item)))
+        .collect();
+    // A stable sort, so ties stay in order of relevance.
+    items.sort_by_key(|(score, _)| *score);
+    Some(Completions {
+        range,
+        items: items.into_iter().map(|(_, item)| item).collect(),
+    })
+}
+
+/// Variables, functions and, at the start of a statement, keywords and snippets, in that order.
+fn names(
+    root: &ast::Root,
+    offset: TextSize,
+    project: &Project,
+    catalogue: &Catalogue,
+    path: Option<&Path>,
+    at_statement_start: bool,
+) -> Vec<CompletionItem> {
+    let mut items = Vec::new();
+
+    let scopes = hir::scope::scopes(root);
+    let scope = innermost_scope(&scopes, offset);
+    let params = scope
+        .params
+        .iter()
+        .map(|binding| (&binding.name, "parameter"));
+    let outputs = scope
+        .outputs
+        .iter()
+        .map(|binding| (&binding.name, "output"));
+    // The name being typed is no variable yet.
+    let assigned = scope
+        .accesses
+        .iter()
+        .filter(|access| !access.range.contains_inclusive(offset))
+        .filter(|access| binds(scope, &access.name))
+        .map(|access| (&access.name, "variable"));
+    for (name, detail) in params.chain(outputs).chain(assigned) {
+        items.push(
+            CompletionItem::new(name.as_str(), CompletionKind::Variable)
+                .with_detail(Some(detail.to_string())),
+        );
+    }
+
+    for fn_def in root.syntax().descendants().filter_map(ast::FnDef::cast) {
+        let Some(name) = fn_def.name() else {
+            continue;
+        };
+        let header = fn_def.header().map(|header| {
+            let text = header.syntax().text().to_string();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        });
+        items.push(CompletionItem::new(name.text(), CompletionKind::Function).with_detail(header));
+    }
+
+    // A script can call itself, but hardly ever means to.
+    let others = project
+        .visible_functions(path)
+        .into_iter()
+        .filter(|(_, definition)| Some(definition.path.as_path()) != path);
+    for (name, definition) in others {
+        let file = definition
+            .path
+            .file_name()
+            .map(|file| file.to_string_lossy().into_owned());
+        let detail = match definition.kind {
+            DefinitionKind::Script => file.map(|file| format!("script {file}")),
+            _ => file,
+        };
+        items.push(CompletionItem::new(name, CompletionKind::Function).with_detail(detail));
+    }
+
+    for function in catalogue.functions() {
+        let mut item = CompletionItem::new(&function.name, CompletionKind::Function)
+            .with_detail(function.usages.first().cloned());
+        item.documentation = Some(function.documentation());
+        items.push(item);
+    }
+
+    if at_statement_start {
+        for keyword in TokenKind::keywords() {
+            items.extend(
+                keyword
+                    .keyword_text()
+                    .map(|text| CompletionItem::new(text, CompletionKind::Keyword)),
+            );
+        }
+        for (label, body) in SNIPPETS {
+            let mut item = CompletionItem::new(label, CompletionKind::Snippet);
+            item.detail = body.lines().last().map(|last| format!("{label} … {last}"));
+            item.snippet = Some(body.to_string());
+            items.push(item);
+        }
+    }
+
+    // Variables shadow functions, and local functions those of other files.
+    let mut seen = HashSet::new();
+    items.retain(|item| {
+        matches!(item.kind, CompletionKind::Keyword | CompletionKind::Snippet)
+            || seen.insert(item.label.clone())
+    });
+    items
+}
+
+/// The fields assigned to the struct before the `dot` anywhere in its scope, as in `s.name = 1` or
+/// `s = struct('name', 1)`.
+fn fields(root: &ast::Root, dot: &SyntaxToken, offset: TextSize) -> Vec<CompletionItem> {
+    let Some(base) = dot
+        .parent()
+        .and_then(ast::FieldExpr::cast)
+        .and_then(|field| field.expr())
+        .and_then(|base| path_of(&base))
+    else {
+        return Vec::new();
+    };
+    let scopes = hir::scope::scopes(root);
+    let scope = innermost_scope(&scopes, offset).range;
+    let in_scope =
+        |node: &SyntaxNode| innermost_scope(&scopes, node.text_range().start()).range == scope;
+
+    let mut names = Vec::new();
+    for node in root.syntax().descendants().filter(in_scope) {
+        if let Some(field) = ast::FieldExpr::cast(node.clone())
+            && let Some(name) = field.field()
+            && !name.text_range().contains_inclusive(offset)
+            && is_assigned(field.syntax())
+            && field.expr().and_then(|expr| path_of(&expr)).as_ref() == Some(&base)
+        {
+            names.push(name.text().to_string());
+        } else if let Some(var_def) = ast::VariableDef::cast(node)
+            && var_def.lhs().and_then(|lhs| path_of(&lhs)).as_ref() == Some(&base)
+            && let Some(ast::Expr::CallExpr(call)) = var_def.value()
+            && let Some(ast::Expr::VariableRef(callee)) = call.func()
+            && callee.name().is_some_and(|name| name.text() == "struct")
+        {
+            names.extend(call.args().step_by(2).filter_map(|arg| match arg {
+                ast::Expr::StringLiteral(string) => Some(string.value()),
+                _ => None,
+            }));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    names
+        .into_iter()
+        .filter(|name| seen.insert(name.clone()))
+        .map(|name| CompletionItem::new(name, CompletionKind::Field))
+        .collect()
+}
+
+/// The names leading to a struct, such as `s`, `a`, `b` for `s.a(2).b`.
+fn path_of(expr: &ast::Expr) -> Option<Vec<String>> {
+    match expr {
+        ast::Expr::VariableRef(var) => Some(vec![var.name()?.text().to_string()]),
+        ast::Expr::FieldExpr(field) => {
+            let mut path = path_of(&field.expr()?)?;
+            path.push(field.field()?.text().to_string());
+            Some(path)
+        }
+        ast::Expr::CallExpr(call) => path_of(&call.func()?),
+        _ => None,
+    }
+}
+
+/// Whether `node` is written to by an assignment, as the field in `s.a(1) = 2` is.
+fn is_assigned(node: &SyntaxNode) -> bool {
+    node.ancestors()
+        .find_map(ast::VariableDef::cast)
+        .and_then(|var_def| var_def.lhs())
+        .is_some_and(|lhs| lhs.syntax().text_range().contains_range(node.text_range()))
+}
+
+/// Whether a statement can start after `before`: at the start of the file or a line, or after a
+/// `;` or `,` that does not separate the elements of a matrix or the arguments of a call.
+fn starts_statement(before: Option<&SyntaxToken>) -> bool {
+    let Some(before) = before else {
+        return true;
+    };
+    let is_separator = matches!(
+        token_kind(before),
+        Some(TokenKind::Newline | TokenKind::Semicolon | TokenKind::Comma | TokenKind::Comment)
+    ) || token_kind(before).is_some_and(|kind| kind.is_keyword_statement());
+    let in_expression = before.parent_ancestors().any(|node| {
+        matches!(
+            node.kind(),
+            SyntaxKind::SyntaxConstruct(
+                SyntaxConstruct::MatrixExpr
+                    | SyntaxConstruct::CallExpr
+                    | SyntaxConstruct::ParenExpr
+            )
+        )
+    });
+    is_separator && !in_expression
+}
+
+fn previous_token(token: &SyntaxToken) -> Option<SyntaxToken> {
+    skip_whitespace(token.prev_token())
+}
+
+fn skip_whitespace(mut token: Option<SyntaxToken>) -> Option<SyntaxToken> {
+    while let Some(current) = &token
+        && current.kind() == TokenKind::Whitespace.into()
+    {
+        token = current.prev_token();
+    }
+    token
+}
+
+fn token_kind(token: &SyntaxToken) -> Option<TokenKind> {
+    match token.kind() {
+        SyntaxKind::LexToken(kind) => Some(kind),
+        SyntaxKind::SyntaxConstruct(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+    use std::path::PathBuf;
+
+    /// Lists the completions at the `$0` marker, leaving out the builtins unless `builtins` is
+    /// set, as there are so many of them.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check_with(input: &str, project: &Project, builtins: bool, expected: Expect) {
+        let offset = input.find("$0").unwrap();
+        let input = input.replace("$0", "");
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+        let path = PathBuf::from("/ws/main.m");
+        let Some(completions) = completions(
+            &root,
+            TextSize::try_from(offset).unwrap(),
+            project,
+            &Catalogue::bundled(),
+            Some(&path),
+        ) else {
+            expected.assert_eq("None");
+            return;
+        };
+
+        let mut actual = format!("{:?}\n", completions.range);
+        for item in completions.items {
+            let is_builtin = Catalogue::bundled().contains(&item.label)
+                && item.kind == CompletionKind::Function
+                && item.documentation.is_some();
+            if is_builtin && !builtins {
+                continue;
+            }
+            let _ = write!(actual, "{:?} {}", item.kind, item.label);
+            if let Some(detail) = &item.detail {
+                let _ = write!(actual, " ({detail})");
+            }
+            actual.push('\n');
+        }
+        expected.assert_eq(&actual);
+    }
+
+    fn check(input: &str, expected: Expect) {
+        check_with(input, &Project::default(), false, expected);
+    }
+
+    #[test]
+    fn variables_of_the_scope() {
+        check(
+            "total = 1;\nfunction r = f(x)\n  y = 2;\n  r = $0\nend",
+            expect![[r"
+                44..44
+                Variable x (parameter)
+                Variable r (output)
+                Variable y (variable)
+                Function f (r = f(x))
+            "]],
+        );
+    }
+
+    #[test]
+    fn keywords_and_snippets_start_statements() {
+        check(
+            "x = 1;\nfo$0",
+            expect![[r"
+                7..9
+                Keyword for
+                Snippet for (for … endfor)
+                Keyword endfor
+                Keyword function
+                Keyword endfunction
+                Snippet function (function … endfunction)
+            "]],
+        );
+        check(
+            "x = fo$0",
+            expect![[r"
+                4..6
+            "]],
+        );
+    }
+
+    #[test]
+    fn names_are_filtered_by_what_was_typed() {
+        check_with(
+            "counter = 1;\ndisp(co$0)",
+            &Project::default(),
+            true,
+            expect![[r"
+                18..20
+                Variable counter (variable)
+                Function colon (r = colon (base, limit))
+                Function colorbar (colorbar)
+                Function colormap (cmap = colormap ())
+                Function columns (nc = columns (A))
+                Function conj (z = conj (x))
+                Function containers.Map (m = containers.Map ())
+                Function conv (y = conv (a, b))
+                Function cos (y = cos (x))
+                Function cosh (y = cosh (x))
+                Function acos (y = acos (x))
+                Function deconv ([b, r] = deconv (y, a))
+                Function iscolumn (tf = iscolumn (x))
+                Function MException (ME = MException (id, template, ...))
+                Function chol (R = chol (A))
+                Function clock (c = clock ())
+                Function close (close)
+                Function cross (z = cross (x, y))
+                Function cumprod (y = cumprod (x))
+                Function factorial (f = factorial (n))
+                Function fclose (status = fclose (fid))
+                Function is_function_handle (tf = is_function_handle (x))
+                Function isvector (tf = isvector (x))
+                Function nchoosek (c = nchoosek (n, k))
+            "]],
+        );
+    }
+
+    #[test]
+    fn workspace_functions() {
+        let mut project = Project::new(vec!["/ws".into()], Vec::new());
+        project.insert("/ws/plot_all.m".into(), "function plot_all\nend");
+        project.insert("/ws/setup.m".into(), "x = 1;");
+        project.insert("/ws/private/helper.m".into(), "function helper\nend");
+        project.insert("/ws/main.m".into(), "");
+        check_with(
+            "h = @$0",
+            &project,
+            false,
+            expect![[r"
+                5..5
+                Function helper (helper.m)
+                Function plot_all (plot_all.m)
+                Function setup (script setup.m)
+            "]],
+        );
+    }
+
+    #[test]
+    fn fields_assigned_in_the_scope() {
+        check(
+            "s.name = 'x';\ns.size(2) = 3;\nt.other = 1;\nfunction f\n  s.hidden = 1;\nend\ndisp(s.$0",
+            expect![[r"
+                80..80
+                Field name
+                Field size
+            "]],
+        );
+        check(
+            "p = struct('x', 1, 'y', 2);\np.inner.z = 3;\nq = p.inner.$0;",
+            expect![[r"
+                55..55
+                Field z
+            "]],
+        );
+        check(
+            "p = struct('x', 1, 'y', 2);\nq = p.$0",
+            expect![[r"
+                34..34
+                Field x
+                Field y
+            "]],
+        );
+    }
+
+    #[test]
+    fn nothing_in_comments_and_strings() {
+        check("% fo$0", expect!["None"]);
+        check("disp('fo$0')", expect!["None"]);
+    }
+
+    #[test]
+    fn unfinished_blocks() {
+        check(
+            "for i = 1:3\n  value = i;\n  va$0",
+            expect![[r"
+                27..29
+                Variable value (variable)
+            "]],
+        );
+    }
+}
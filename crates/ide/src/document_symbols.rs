@@ -0,0 +1,320 @@
+//! The outline of a file: its functions, its cell sections and, optionally, its script variables.
+
+use ast::TypedSyntaxNode;
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::TextRange;
+
+/// Comments starting with this mark the beginning of a cell section.
+///
+/// Octave also accepts `##`, but that is used for plain comments too often, such as in license
+/// headers, to be worth showing.
+const SECTION_MARKER: &str = "%%";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Extra information shown next to the name, such as the signature of a function.
+    pub detail: Option<String>,
+    pub kind: SymbolKind,
+    /// The whole extent of the symbol, such as a function with its body.
+    pub range: TextRange,
+    /// The part to select when jumping to the symbol, such as the name of a function.
+    pub selection_range: TextRange,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Section,
+    /// A file of script code, named after the file.
+    Script,
+    Class,
+    Method,
+}
+
+/// Returns the outline of a file, in source order.
+///
+/// Functions nest their nested functions, and `%%` sections hold whatever is defined in them.
+/// Variables of the script code are only listed if `include_variables` is set.
+#[must_use]
+pub fn document_symbols(root: &ast::Root, include_variables: bool) -> Vec<Symbol> {
+    let mut symbols = functions(root.syntax());
+    if include_variables {
+        symbols.extend(script_variables(root));
+        symbols.sort_by_key(|symbol| symbol.range.start());
+    }
+    in_sections(sections(root.syntax()), symbols)
+}
+
+fn functions(node: &SyntaxNode) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for fn_def in node.children().filter_map(ast::FnDef::cast) {
+        // Without a terminator, the functions that follow are parsed as part of the body, but
+        // they are really subfunctions.
+        let is_terminated = ast::BlockStmt::cast(fn_def.syntax().clone())
+            .and_then(|block| block.terminator())
+            .is_some();
+        let nested = functions(fn_def.syntax());
+        let (children, siblings) = if is_terminated {
+            (nested, Vec::new())
+        } else {
+            (Vec::new(), nested)
+        };
+
+        if let Some(name) = fn_def.name() {
+            let detail = fn_def.header().map(|header| {
+                let text = header.syntax().text().to_string();
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            });
+            symbols.push(Symbol {
+                name: name.text().to_string(),
+                detail,
+                kind: SymbolKind::Function,
+                range: function_range(&fn_def, is_terminated),
+                selection_range: name.text_range(),
+                children,
+            });
+        } else {
+            symbols.extend(children);
+        }
+        symbols.extend(siblings);
+    }
+    symbols
+}
+
+/// The range of a function, leaving out the subfunctions that follow an unterminated one.
+fn function_range(fn_def: &ast::FnDef, is_terminated: bool) -> TextRange {
+    let node = fn_def.syntax();
+    let own_tokens = node
+        .children_with_tokens()
+        .take_while(|element| {
+            is_terminated || element.kind() != SyntaxKind::SyntaxConstruct(SyntaxConstruct::FnDef)
+        })
+        .flat_map(|element| match element {
+            SyntaxElement::Node(node) => node
+                .descendants_with_tokens()
+                .filter_map(SyntaxElement::into_token)
+                .collect(),
+            SyntaxElement::Token(token) => vec![token],
+        });
+    trimmed_range(own_tokens).unwrap_or_else(|| node.text_range())
+}
+
+/// Returns the first assignment of each variable of the script code.
+fn script_variables(root: &ast::Root) -> Vec<Symbol> {
+    let scopes = hir::scope::scopes(root);
+    let mut symbols: Vec<Symbol> = Vec::new();
+
+    for write in scopes[0]
+        .accesses
+        .iter()
+        .filter(|access| access.kind == hir::scope::AccessKind::Write)
+    {
+        if symbols.iter().all(|symbol| symbol.name != write.name) {
+            symbols.push(Symbol {
+                name: write.name.to_string(),
+                detail: None,
+                kind: SymbolKind::Variable,
+                range: write.range,
+                selection_range: write.range,
+                children: Vec::new(),
+            });
+        }
+    }
+    symbols
+}
+
+/// Finds the `%%` sections of the script code. Each lasts until the next one.
+pub(crate) fn sections(root: &SyntaxNode) -> Vec<Symbol> {
+    let markers: Vec<SyntaxToken> = root
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| {
+            token.kind() == TokenKind::Comment.into()
+                && token.text().starts_with(SECTION_MARKER)
+                && starts_line(token)
+                && !token
+                    .parent_ancestors()
+                    .any(|node| ast::FnDef::cast(node).is_some())
+        })
+        .collect();
+
+    let mut sections = Vec::new();
+    for (idx, marker) in markers.iter().enumerate() {
+        let next = markers.get(idx + 1).map(|next| next.text_range().start());
+        let tokens = root
+            .descendants_with_tokens()
+            .filter_map(SyntaxElement::into_token)
+            .skip_while(|token| token != marker)
+            .take_while(|token| next.is_none_or(|next| token.text_range().start() < next));
+        let title = marker.text()[SECTION_MARKER.len()..].trim();
+
+        sections.push(Symbol {
+            name: if title.is_empty() {
+                "(untitled)".to_string()
+            } else {
+                title.to_string()
+            },
+            detail: None,
+            kind: SymbolKind::Section,
+            range: trimmed_range(tokens).map_or(marker.text_range(), |range| {
+                marker.text_range().cover(range)
+            }),
+            selection_range: marker.text_range(),
+            children: Vec::new(),
+        });
+    }
+    sections
+}
+
+/// Moves the symbols into the sections they are defined in.
+fn in_sections(mut sections: Vec<Symbol>, symbols: Vec<Symbol>) -> Vec<Symbol> {
+    let mut outline = Vec::new();
+    for symbol in symbols {
+        let section = sections
+            .iter_mut()
+            .find(|section| section.range.contains_range(symbol.range));
+        match section {
+            Some(section) => section.children.push(symbol),
+            None => outline.push(symbol),
+        }
+    }
+    outline.extend(sections);
+    outline.sort_by_key(|symbol| symbol.range.start());
+    outline
+}
+
+pub(crate) fn starts_line(token: &SyntaxToken) -> bool {
+    let mut previous = token.prev_token();
+    while let Some(token) = &previous {
+        match token.kind() {
+            SyntaxKind::LexToken(TokenKind::Whitespace) => previous = token.prev_token(),
+            SyntaxKind::LexToken(TokenKind::Newline) => return true,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// The range from the first to the last token that is not trivia.
+fn trimmed_range(tokens: impl Iterator<Item = SyntaxToken>) -> Option<TextRange> {
+    let mut tokens = tokens
+        .filter(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()));
+    let first = tokens.next()?.text_range();
+    let last = tokens.last().map_or(first, |token| token.text_range());
+    Some(first.cover(last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, include_variables: bool, expected: Expect) {
+        fn render(symbols: &[Symbol], depth: usize, output: &mut String) {
+            for symbol in symbols {
+                let _ = write!(
+                    output,
+                    "{}{:?} {} {:?} {:?}",
+                    "  ".repeat(depth),
+                    symbol.kind,
+                    symbol.name,
+                    symbol.range,
+                    symbol.selection_range,
+                );
+                if let Some(detail) = &symbol.detail {
+                    let _ = write!(output, " ({detail})");
+                }
+                output.push('\n');
+                render(&symbol.children, depth + 1, output);
+            }
+        }
+
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let mut actual = String::new();
+        render(&document_symbols(&root, include_variables), 0, &mut actual);
+
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn functions_with_signatures() {
+        check(
+            "function [a, b] = f(x, y)\n  a = x;\n  b = y;\nend\nfunction g\nend",
+            false,
+            expect![[r"
+                Function f 0..47 18..19 ([a, b] = f(x, y))
+                Function g 48..62 57..58 (g)
+            "]],
+        );
+    }
+
+    #[test]
+    fn nested_functions() {
+        check(
+            "function f\n  function g\n  end\nend",
+            false,
+            expect![[r"
+                Function f 0..33 9..10 (f)
+                  Function g 13..29 22..23 (g)
+            "]],
+        );
+    }
+
+    #[test]
+    fn unterminated_functions_are_siblings() {
+        check(
+            "function f\n  disp(1)\n\nfunction g\n  disp(2)\n",
+            false,
+            expect![[r"
+                Function f 0..20 9..10 (f)
+                Function g 22..42 31..32 (g)
+            "]],
+        );
+    }
+
+    #[test]
+    fn script_variables_are_optional() {
+        let input = "a = 1;\nb = a;\na = 2;";
+        check(input, false, expect![""]);
+        check(
+            input,
+            true,
+            expect![[r"
+            Variable a 0..1 0..1
+            Variable b 7..8 7..8
+        "]],
+        );
+    }
+
+    #[test]
+    fn sections_hold_their_symbols() {
+        check(
+            "x = 1;\n%% Load data\ny = 2;\n\n%%\nz = 3;\n",
+            true,
+            expect![[r"
+                Variable x 0..1 0..1
+                Section Load data 7..26 7..19
+                  Variable y 20..21 20..21
+                Section (untitled) 28..37 28..30
+                  Variable z 31..32 31..32
+            "]],
+        );
+    }
+
+    #[test]
+    fn section_markers_must_start_a_line() {
+        check(
+            "x = 1; %% not a section\n%%not%% either",
+            true,
+            expect![[r"
+            Variable x 0..1 0..1
+            Section not%% either 24..38 24..38
+        "]],
+        );
+    }
+}
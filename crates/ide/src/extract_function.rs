@@ -0,0 +1,463 @@
+//! Moving a run of statements into a function of their own, in the same file or a new one.
+//!
+//! The variables the statements read become the parameters of the new function and the ones
+//! they leave for later become its outputs, as computed by `hir::region`.
+
+use crate::code_actions::{
+    CodeAction, CodeActionKind, Edit, NewFile, line_indent, trimmed, unused_name,
+};
+use ast::TypedSyntaxNode;
+use hir::region::Region;
+use std::path::Path;
+use syntax::{SyntaxConstruct, SyntaxNode};
+use text_size::{TextRange, TextSize};
+
+/// Returns the ways to extract the statements `range` covers into a new function: as a
+/// subfunction, and as a function file next to `path`.
+#[must_use]
+pub fn extract_function(
+    root: &ast::Root,
+    range: TextRange,
+    path: Option<&Path>,
+) -> Vec<CodeAction> {
+    if range.is_empty() {
+        return Vec::new();
+    }
+    let lists = vec![root.stmts().collect()];
+    let Some(stmts) = selected(lists, range).filter(|stmts| can_extract(stmts)) else {
+        return Vec::new();
+    };
+    let (Some(first), Some(last)) = (
+        stmts.first().and_then(|stmt| trimmed(stmt.syntax())),
+        stmts.last().and_then(|stmt| trimmed(stmt.syntax())),
+    ) else {
+        return Vec::new();
+    };
+    // Parts of statements are left to extracting a variable.
+    if !range.contains_range(first.cover(last)) {
+        return Vec::new();
+    }
+    let range = first.cover(last);
+
+    let region = hir::region::region(root, range);
+    let name = unused_name(root, "extracted");
+    let call = Edit {
+        range,
+        text: format!(
+            "{}{name}({});",
+            assignment(&region),
+            region.inputs.join(", ")
+        ),
+    };
+    let keyword = if root.uses_specific_terminators() {
+        "endfunction"
+    } else {
+        "end"
+    };
+    let text = root.syntax().text().to_string();
+    let body = |indent: &str| {
+        reindent(
+            &text[std::ops::Range::<usize>::from(range)],
+            &line_indent(root, range.start()),
+            indent,
+        )
+    };
+    let header = header(&name, &region);
+
+    let mut actions = Vec::new();
+    if let Some((offset, function)) = subfunction_position(root, range) {
+        let (indent, terminator) = match &function {
+            Some(function) => (
+                body_indent(root, function),
+                ast::BlockStmt::cast(function.clone())
+                    .and_then(|function| function.terminator())
+                    .is_some()
+                    .then_some(keyword),
+            ),
+            None => ("  ".to_string(), Some(keyword)),
+        };
+        let mut definition = format!("function {header}\n{}\n", body(&indent));
+        if let Some(terminator) = terminator {
+            definition.push_str(terminator);
+        }
+        let definition = if function.is_some() {
+            Edit {
+                range: TextRange::empty(offset),
+                text: format!("\n\n{}", definition.trim_end()),
+            }
+        } else {
+            Edit {
+                range: TextRange::empty(offset),
+                text: format!("{definition}\n\n"),
+            }
+        };
+        let mut edits = vec![call.clone(), definition];
+        // A definition inserted where the call goes comes before it.
+        edits.sort_by_key(|edit| (edit.range.start(), !edit.range.is_empty()));
+        actions.push(CodeAction {
+            title: format!("Extract into subfunction '{name}'"),
+            kind: CodeActionKind::Extract,
+            edits,
+            new_file: None,
+            is_preferred: false,
+        });
+    }
+    if let Some(path) = path {
+        let file_name = format!("{name}.m");
+        actions.push(CodeAction {
+            title: format!("Extract into function file '{file_name}'"),
+            kind: CodeActionKind::Extract,
+            edits: vec![call],
+            new_file: Some(NewFile {
+                path: path.with_file_name(file_name),
+                text: format!("function {header}\n{}\n{keyword}\n", body("  ")),
+            }),
+            is_preferred: false,
+        });
+    }
+    actions
+}
+
+/// Finds the statements touched by `range` in the innermost list of statements holding them all.
+/// A block is only entered if the range starts after its opening keyword.
+fn selected(lists: Vec<Vec<ast::Stmt>>, range: TextRange) -> Option<Vec<ast::Stmt>> {
+    for list in lists {
+        let touched: Vec<ast::Stmt> = list
+            .into_iter()
+            .filter(|stmt| {
+                trimmed(stmt.syntax())
+                    .is_some_and(|stmt| stmt.start() < range.end() && range.start() < stmt.end())
+            })
+            .collect();
+        match touched.as_slice() {
+            [] => {}
+            [stmt] if !range.contains_range(trimmed(stmt.syntax())?) => {
+                let opener =
+                    ast::BlockStmt::cast(stmt.syntax().clone()).and_then(|block| block.opener());
+                return match opener {
+                    Some(opener) if range.start() > opener.text_range().start() => {
+                        selected(bodies(stmt), range)
+                    }
+                    _ => Some(touched),
+                };
+            }
+            _ => return Some(touched),
+        }
+    }
+    None
+}
+
+fn bodies(stmt: &ast::Stmt) -> Vec<Vec<ast::Stmt>> {
+    let branches = |branches: Vec<ast::Branch>| {
+        branches
+            .iter()
+            .map(|branch| branch.body().to_vec())
+            .collect()
+    };
+    match stmt {
+        ast::Stmt::FnDef(fn_def) => vec![fn_def.body().collect()],
+        ast::Stmt::IfStmt(if_stmt) => branches(if_stmt.branches()),
+        ast::Stmt::SwitchStmt(switch) => branches(switch.cases()),
+        ast::Stmt::ForLoop(for_loop) => vec![for_loop.body().collect()],
+        ast::Stmt::WhileLoop(while_loop) => vec![while_loop.body().collect()],
+        ast::Stmt::TryStmt(try_stmt) => vec![try_stmt.try_body(), try_stmt.catch_body()],
+        ast::Stmt::UnwindProtectStmt(unwind) => {
+            vec![unwind.protected_body(), unwind.cleanup_body()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Statements that define functions, declare variables or leave the code around them cannot
+/// move into a function.
+fn can_extract(stmts: &[ast::Stmt]) -> bool {
+    stmts.iter().all(|stmt| {
+        stmt.syntax().descendants().all(|node| {
+            let kind = node.kind();
+            if kind == SyntaxConstruct::FnDef.into()
+                || kind == SyntaxConstruct::Declaration.into()
+                || kind == SyntaxConstruct::ReturnStmt.into()
+            {
+                return false;
+            }
+            if kind == SyntaxConstruct::BreakStmt.into()
+                || kind == SyntaxConstruct::ContinueStmt.into()
+            {
+                return node
+                    .ancestors()
+                    .take_while(|ancestor| ancestor != stmt.syntax())
+                    .chain(std::iter::once(stmt.syntax().clone()))
+                    .any(|ancestor| {
+                        ancestor.kind() == SyntaxConstruct::ForLoop.into()
+                            || ancestor.kind() == SyntaxConstruct::WhileLoop.into()
+                    });
+            }
+            true
+        })
+    })
+}
+
+/// Where the subfunction goes, and the top-level function it follows. Outside functions it goes
+/// before the statement holding the range, as scripts define functions before using them, but
+/// not at the start of the file, which would make it a function file.
+fn subfunction_position(
+    root: &ast::Root,
+    range: TextRange,
+) -> Option<(TextSize, Option<SyntaxNode>)> {
+    let top = root
+        .syntax()
+        .children()
+        .find(|child| child.text_range().contains_range(range))?;
+    if top.kind() == SyntaxConstruct::FnDef.into() {
+        return Some((trimmed(&top)?.end(), Some(top)));
+    }
+    let is_script_code = root
+        .stmts()
+        .take_while(|stmt| stmt.syntax() != &top)
+        .any(|stmt| !matches!(stmt, ast::Stmt::FnDef(_)));
+    is_script_code.then(|| {
+        (
+            trimmed(&top).map_or(top.text_range().start(), TextRange::start),
+            None,
+        )
+    })
+}
+
+/// The indentation of the statements of a function, or two spaces if it has none.
+fn body_indent(root: &ast::Root, function: &SyntaxNode) -> String {
+    ast::FnDef::cast(function.clone())
+        .and_then(|fn_def| fn_def.body().next())
+        .and_then(|stmt| trimmed(stmt.syntax()))
+        .map_or_else(|| "  ".to_string(), |stmt| line_indent(root, stmt.start()))
+}
+
+fn assignment(region: &Region) -> String {
+    match region.outputs.as_slice() {
+        [] => String::new(),
+        [output] => format!("{output} = "),
+        outputs => format!("[{}] = ", outputs.join(", ")),
+    }
+}
+
+fn header(name: &str, region: &Region) -> String {
+    if region.inputs.is_empty() {
+        format!("{}{name}", assignment(region))
+    } else {
+        format!("{}{name}({})", assignment(region), region.inputs.join(", "))
+    }
+}
+
+/// Moves lines indented by `from` (the first one having lost its indentation) to `to`.
+fn reindent(text: &str, from: &str, to: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = if i == 0 {
+                line
+            } else {
+                line.strip_prefix(from).unwrap_or_else(|| line.trim_start())
+            };
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{to}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    /// Extracts the statements between the two `$0` markers and shows each way of doing it.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let start = input.find("$0").unwrap();
+        let input = input.replacen("$0", "", 1);
+        let end = input.find("$0").unwrap();
+        let input = input.replacen("$0", "", 1);
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+        let range = TextRange::new(
+            u32::try_from(start).unwrap().into(),
+            u32::try_from(end).unwrap().into(),
+        );
+
+        let mut actual = String::new();
+        for action in extract_function(&root, range, Some(Path::new("/ws/main.m"))) {
+            let mut text = input.clone();
+            for edit in action.edits.iter().rev() {
+                text.replace_range(std::ops::Range::<usize>::from(edit.range), &edit.text);
+            }
+            let _ = writeln!(actual, "{}\n{text}", action.title);
+            if let Some(file) = action.new_file {
+                let _ = writeln!(actual, "{}:\n{}", file.path.display(), file.text);
+            }
+        }
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn extracts_statements_of_a_function() {
+        check(
+            "function y = f(a)\n  $0b = a + 1;\n  c = b * 2;$0\n  y = c;\nend\n",
+            expect![[r"
+                Extract into subfunction 'extracted'
+                function y = f(a)
+                  c = extracted(a);
+                  y = c;
+                end
+
+                function c = extracted(a)
+                  b = a + 1;
+                  c = b * 2;
+                end
+
+                Extract into function file 'extracted.m'
+                function y = f(a)
+                  c = extracted(a);
+                  y = c;
+                end
+
+                /ws/extracted.m:
+                function c = extracted(a)
+                  b = a + 1;
+                  c = b * 2;
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn extracts_a_loop_body() {
+        check(
+            "total = 0;\nfor i = 1:3\n  $0x = i ^ 2;\n  total = total + x;$0\nend\ndisp(total)\n",
+            expect![[r"
+                Extract into subfunction 'extracted'
+                total = 0;
+                function total = extracted(i, total)
+                  x = i ^ 2;
+                  total = total + x;
+                end
+
+                for i = 1:3
+                  total = extracted(i, total);
+                end
+                disp(total)
+
+                Extract into function file 'extracted.m'
+                total = 0;
+                for i = 1:3
+                  total = extracted(i, total);
+                end
+                disp(total)
+
+                /ws/extracted.m:
+                function total = extracted(i, total)
+                  x = i ^ 2;
+                  total = total + x;
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn extracts_whole_blocks() {
+        check(
+            "x = 1;\n$0if x > 0\n  y = 2;\nelse\n  y = 3;\nend\n$0disp(y)\n",
+            expect![[r"
+                Extract into subfunction 'extracted'
+                x = 1;
+                function y = extracted(x)
+                  if x > 0
+                    y = 2;
+                  else
+                    y = 3;
+                  end
+                end
+
+                y = extracted(x);
+                disp(y)
+
+                Extract into function file 'extracted.m'
+                x = 1;
+                y = extracted(x);
+                disp(y)
+
+                /ws/extracted.m:
+                function y = extracted(x)
+                  if x > 0
+                    y = 2;
+                  else
+                    y = 3;
+                  end
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn follows_files_without_end() {
+        check(
+            "function f(x)\n    $0disp(x)$0\n\nfunction g\n",
+            expect![[r"
+                Extract into subfunction 'extracted'
+                function f(x)
+                    extracted(x);
+
+                function g
+
+                function extracted(x)
+                    disp(x)
+
+                Extract into function file 'extracted.m'
+                function f(x)
+                    extracted(x);
+
+                function g
+
+                /ws/extracted.m:
+                function extracted(x)
+                  disp(x)
+                end
+
+            "]],
+        );
+    }
+
+    #[test]
+    fn does_not_extract_what_leaves_the_selection() {
+        check(
+            "function f(x)\n  for i = 1:x\n    $0if i > 2\n      break\n    end$0\n  end\nend\n",
+            expect![""],
+        );
+        check(
+            "function f(x)\n  $0if x\n    return\n  end$0\nend\n",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn keeps_scripts_from_starting_with_a_function() {
+        check(
+            "$0x = 1;$0\ndisp(x)\n",
+            expect![[r"
+                Extract into function file 'extracted.m'
+                x = extracted();
+                disp(x)
+
+                /ws/extracted.m:
+                function x = extracted
+                  x = 1;
+                end
+
+            "]],
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Moving an expression into a variable assigned right before the statement using it.
+//!
+//! Expressions are found in the arena of `hir` through its source map, so only whole
+//! subexpressions can be extracted.
+
+use crate::code_actions::{CodeAction, CodeActionKind, Edit, line_indent, trimmed, unused_name};
+use ast::TypedSyntaxNode;
+use hir::{Database, Expr, Stmt};
+use syntax::{SyntaxConstruct, SyntaxElement, TokenKind};
+use text_size::{TextRange, TextSize};
+
+/// Returns the action extracting the expression `range` covers, ignoring whitespace around it.
+#[must_use]
+pub fn extract_variable(root: &ast::Root, range: TextRange) -> Option<CodeAction> {
+    let text = root.syntax().text().to_string();
+    let selected = text.get(std::ops::Range::<usize>::from(range))?;
+    if selected.trim().is_empty() {
+        return None;
+    }
+    let leading = TextSize::of(&selected[..selected.len() - selected.trim_start().len()]);
+    let trailing = TextSize::of(&selected[selected.trim_end().len()..]);
+    let range = TextRange::new(range.start() + leading, range.end() - trailing);
+
+    let hir::Body { db, stmts, .. } = hir::lower(root);
+    let source_map = db.source_map();
+    // Nodes hold the trivia after them, so the expression may be any node around the range.
+    let node = match root.syntax().covering_element(range) {
+        SyntaxElement::Node(node) => node,
+        SyntaxElement::Token(token) => token.parent()?,
+    };
+    let idx = node
+        .ancestors()
+        .filter_map(|node| source_map.node_expr(&node))
+        .find(|idx| source_map.expr_range(*idx) == Some(range))?;
+    let expr = &db[idx];
+    // Names are already variables or calls, and what is called cannot be taken apart from its
+    // arguments.
+    let is_callee = db
+        .exprs()
+        .any(|(_, other)| matches!(other, Expr::Call { func, .. } if *func == idx));
+    if matches!(expr, Expr::Missing | Expr::VariableRef { .. })
+        || is_callee
+        || has_end_index(&db, expr)
+        || is_assigned(root, range)
+    {
+        return None;
+    }
+
+    let stmt = hir::preorder(&stmts)
+        .filter(|stmt| {
+            db.stmt_range(stmt)
+                .is_some_and(|stmt| stmt.contains_range(range))
+        })
+        .last()?;
+    // The condition of an `if` or `switch` is evaluated once, before everything else in it.
+    let condition = match stmt {
+        // A whole statement, or the value of an assignment, is as good as a variable already.
+        Stmt::VariableDef { value, .. } | Stmt::Expr(value) if *value == idx => return None,
+        Stmt::If { condition, .. } | Stmt::Switch { condition, .. } => Some(*condition),
+        Stmt::VariableDef { .. } | Stmt::Expr(_) => None,
+        _ => return None,
+    };
+    if condition.is_some_and(|condition| {
+        !source_map
+            .expr_range(condition)
+            .is_some_and(|condition| condition.contains_range(range))
+    }) {
+        return None;
+    }
+
+    let stmt_start = db.stmt_range(stmt)?.start();
+    let name = unused_name(root, "value");
+    let indent = line_indent(root, stmt_start);
+    // Parentheses around the whole expression are not needed once it stands alone.
+    let value_range = match root.syntax().covering_element(range) {
+        SyntaxElement::Node(node) if node.kind() == SyntaxConstruct::ParenExpr.into() => node
+            .children()
+            .next()
+            .and_then(|inner| trimmed(&inner))
+            .unwrap_or(range),
+        _ => range,
+    };
+    let expr_text = &text[std::ops::Range::<usize>::from(value_range)];
+    Some(CodeAction {
+        title: format!("Extract expression into variable '{name}'"),
+        kind: CodeActionKind::Extract,
+        edits: vec![
+            Edit {
+                range: TextRange::empty(stmt_start),
+                text: format!("{name} = {expr_text};\n{indent}"),
+            },
+            Edit { range, text: name },
+        ],
+        new_file: None,
+        is_preferred: false,
+    })
+}
+
+/// Whether the expression uses `end` for the last index of something around it.
+fn has_end_index(db: &Database, expr: &Expr) -> bool {
+    matches!(expr, Expr::EndIndex)
+        || expr
+            .children()
+            .into_iter()
+            .any(|child| has_end_index(db, &db[child]))
+}
+
+/// Whether `range` is in the target of an assignment, as `i` in `x(i) = 1`.
+fn is_assigned(root: &ast::Root, range: TextRange) -> bool {
+    let element = root.syntax().covering_element(range);
+    let mut node = match element {
+        SyntaxElement::Node(node) => node,
+        SyntaxElement::Token(token) => match token.parent() {
+            Some(parent) => parent,
+            None => return false,
+        },
+    };
+    while let Some(parent) = node.parent() {
+        if parent.kind() == SyntaxConstruct::InfixExpr.into()
+            && parent.first_child().as_ref() == Some(&node)
+            && parent
+                .children_with_tokens()
+                .filter_map(SyntaxElement::into_token)
+                .any(|token| token.kind() == TokenKind::Equals.into())
+        {
+            return true;
+        }
+        node = parent;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+
+    /// Extracts the expression between the two `$0` markers.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let start = input.find("$0").unwrap();
+        let input = input.replacen("$0", "", 1);
+        let end = input.find("$0").unwrap();
+        let input = input.replacen("$0", "", 1);
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+        let range = TextRange::new(
+            u32::try_from(start).unwrap().into(),
+            u32::try_from(end).unwrap().into(),
+        );
+
+        let actual = extract_variable(&root, range).map_or_else(String::new, |action| {
+            let mut text = input.clone();
+            for edit in action.edits.iter().rev() {
+                text.replace_range(std::ops::Range::<usize>::from(edit.range), &edit.text);
+            }
+            format!("{}\n{text}", action.title)
+        });
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn extracts_a_subexpression() {
+        check(
+            "function y = f(a, b)\n  y = $0(a + b)$0 * 2;\nend\n",
+            expect![[r"
+                Extract expression into variable 'value'
+                function y = f(a, b)
+                  value = a + b;
+                  y = value * 2;
+                end
+            "]],
+        );
+        check(
+            "x = 1;\ndisp(x * $0 3.5 $0)\n",
+            expect![[r"
+                Extract expression into variable 'value'
+                x = 1;
+                value = 3.5;
+                disp(x *  value )
+            "]],
+        );
+    }
+
+    #[test]
+    fn extracts_before_an_if() {
+        check(
+            "if $0numel(x) > 3$0\n  y = 1;\nend\n",
+            expect![[r"
+                Extract expression into variable 'value'
+                value = numel(x) > 3;
+                if value
+                  y = 1;
+                end
+            "]],
+        );
+        check(
+            "if a\n  y = 1;\nelseif $0b + 1$0\n  y = 2;\nend\n",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn does_not_extract_what_cannot_be_evaluated_earlier() {
+        check("while $0k + 1$0 < 3\n  k = k + 1;\nend\n", expect![""]);
+        check("x = a($0end - 1$0);\n", expect![""]);
+        check("x($0i + 1$0) = 2;\n", expect![""]);
+        check("x = $0f$0(2);\n", expect![""]);
+        check("x = $0a + $0b;\n", expect![""]);
+    }
+
+    #[test]
+    fn picks_an_unused_name() {
+        check(
+            "value = 2;\ny = $0value * 2$0 + 1;\n",
+            expect![[r"
+                Extract expression into variable 'value2'
+                value = 2;
+                value2 = value * 2;
+                y = value2 + 1;
+            "]],
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! The parts of a file that can be collapsed: blocks, comment blocks and cell sections.
+
+use crate::document_symbols::{sections, starts_line};
+use ast::TypedSyntaxNode;
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::TextRange;
+
+/// Keywords that start a new part of a block, which folds on its own.
+const PART_KEYWORDS: [TokenKind; 5] = [
+    TokenKind::ElseIfKw,
+    TokenKind::ElseKw,
+    TokenKind::CaseKw,
+    TokenKind::OtherwiseKw,
+    TokenKind::CatchKw,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fold {
+    pub range: TextRange,
+    pub kind: FoldKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldKind {
+    /// A block or one of its branches, from its keyword to the end of its body. The terminator
+    /// is left out so that it stays visible.
+    Block,
+    /// Comments on consecutive lines, or a `%{ ... %}` block comment.
+    Comment,
+    /// A `%%` cell section.
+    Region,
+}
+
+/// Returns the folds of a file, sorted by their start.
+#[must_use]
+pub fn folding_ranges(root: &ast::Root) -> Vec<Fold> {
+    let mut folds: Vec<Fold> = Vec::new();
+    for block in root.syntax().descendants().filter_map(ast::BlockStmt::cast) {
+        block_folds(&block, &mut folds);
+    }
+    comment_folds(root.syntax(), &mut folds);
+    folds.extend(sections(root.syntax()).into_iter().map(|section| Fold {
+        range: section.range,
+        kind: FoldKind::Region,
+    }));
+    folds.sort_by_key(|fold| (fold.range.start(), std::cmp::Reverse(fold.range.end())));
+    folds
+}
+
+/// Folds a block as a whole and, if it has any, each of its `else`, `case` or `catch` parts.
+/// A `switch` folds both as a whole and by case; the others only by part.
+fn block_folds(block: &ast::BlockStmt, folds: &mut Vec<Fold>) {
+    let node = block.syntax();
+    let terminator = block.terminator();
+    // Without a terminator, the functions that follow an unterminated one are parsed into its
+    // body, but they are really subfunctions.
+    let elements: Vec<SyntaxElement> = node
+        .children_with_tokens()
+        .take_while(|element| {
+            terminator.is_some()
+                || node.kind() != SyntaxConstruct::FnDef.into()
+                || element.kind() != SyntaxConstruct::FnDef.into()
+        })
+        .filter(|element| {
+            terminator
+                .as_ref()
+                .is_none_or(|terminator| element.as_token() != Some(terminator))
+        })
+        .collect();
+
+    let is_part = |element: &SyntaxElement| match element.kind() {
+        SyntaxKind::LexToken(kind) => PART_KEYWORDS.contains(&kind),
+        SyntaxKind::SyntaxConstruct(_) => false,
+    };
+    let is_switch = node.kind() == SyntaxConstruct::SwitchStmt.into();
+    let has_parts = elements.iter().any(is_part);
+
+    if (is_switch || !has_parts)
+        && let Some(range) = trimmed_range(&elements)
+    {
+        folds.push(Fold {
+            range,
+            kind: FoldKind::Block,
+        });
+    }
+    if !has_parts {
+        return;
+    }
+
+    let mut starts: Vec<usize> = elements
+        .iter()
+        .enumerate()
+        .filter(|(_, element)| is_part(element))
+        .map(|(idx, _)| idx)
+        .collect();
+    if !is_switch {
+        starts.insert(0, 0);
+    }
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(elements.len());
+        if let Some(range) = trimmed_range(&elements[start..end]) {
+            folds.push(Fold {
+                range,
+                kind: FoldKind::Block,
+            });
+        }
+    }
+}
+
+/// The comments on lines of their own.
+fn own_line_comments(root: &SyntaxNode) -> Vec<SyntaxToken> {
+    root.descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| token.kind() == TokenKind::Comment.into() && starts_line(token))
+        .collect()
+}
+
+/// The `%{ ... %}` block comments, from their opening line to their closing one.
+pub(crate) fn block_comments(root: &SyntaxNode) -> Vec<TextRange> {
+    let mut block_comments = Vec::new();
+    let mut open: Option<SyntaxToken> = None;
+    for comment in own_line_comments(root) {
+        match comment.text().trim_end() {
+            "%{" | "#{" if open.is_none() => open = Some(comment),
+            "%}" | "#}" => {
+                if let Some(start) = open.take() {
+                    block_comments.push(start.text_range().cover(comment.text_range()));
+                }
+            }
+            _ => {}
+        }
+    }
+    block_comments
+}
+
+/// Folds comments on consecutive lines of their own, and `%{ ... %}` block comments.
+fn comment_folds(root: &SyntaxNode, folds: &mut Vec<Fold>) {
+    let comments = own_line_comments(root);
+    let block_comments = block_comments(root);
+
+    let mut run: Vec<&SyntaxToken> = Vec::new();
+    let mut flush = |run: &mut Vec<&SyntaxToken>| {
+        if let [first, .., last] = run.as_slice() {
+            folds.push(Fold {
+                range: first.text_range().cover(last.text_range()),
+                kind: FoldKind::Comment,
+            });
+        }
+        run.clear();
+    };
+    for comment in &comments {
+        let range = comment.text_range();
+        let in_block = block_comments
+            .iter()
+            .any(|block| block.contains_range(range));
+        // Section markers start a region of their own rather than continuing a comment.
+        let is_marker = comment.text().starts_with("%%");
+        let continues = run.last().is_some_and(|last| on_next_line(last, comment));
+        if !continues || in_block || is_marker {
+            flush(&mut run);
+        }
+        if !in_block && !is_marker {
+            run.push(comment);
+        }
+    }
+    flush(&mut run);
+
+    folds.extend(block_comments.into_iter().map(|range| Fold {
+        range,
+        kind: FoldKind::Comment,
+    }));
+}
+
+/// Whether `next` is on the line right after `comment`, with only whitespace between them.
+fn on_next_line(comment: &SyntaxToken, next: &SyntaxToken) -> bool {
+    let mut newlines = 0;
+    let mut token = comment.next_token();
+    while let Some(current) = token {
+        if current == *next {
+            return newlines == 1;
+        }
+        match current.kind() {
+            SyntaxKind::LexToken(TokenKind::Newline) => newlines += 1,
+            SyntaxKind::LexToken(TokenKind::Whitespace) => {}
+            _ => return false,
+        }
+        token = current.next_token();
+    }
+    false
+}
+
+/// The range from the first to the last token of `elements` that is not trivia.
+fn trimmed_range(elements: &[SyntaxElement]) -> Option<TextRange> {
+    let mut tokens = elements
+        .iter()
+        .flat_map(|element| match element {
+            SyntaxElement::Node(node) => node
+                .descendants_with_tokens()
+                .filter_map(SyntaxElement::into_token)
+                .collect(),
+            SyntaxElement::Token(token) => vec![token.clone()],
+        })
+        .filter(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()));
+    let first = tokens.next()?.text_range();
+    let last = tokens.last().map_or(first, |token| token.text_range());
+    Some(first.cover(last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    /// Shows the first and last line of each fold.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let mut actual = String::new();
+        for fold in folding_ranges(&root) {
+            let text = &input[fold.range];
+            let first = text.lines().next().unwrap_or_default();
+            let last = text.lines().last().unwrap_or_default();
+            let _ = writeln!(actual, "{:?} {first:?} .. {last:?}", fold.kind);
+        }
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn blocks() {
+        check(
+            "function f(x)\n  for i = 1:x\n    disp(i);\n  end\n  while x\n    x = x - 1;\n  endwhile\nend",
+            expect![[r#"
+                Block "function f(x)" .. "  endwhile"
+                Block "for i = 1:x" .. "    disp(i);"
+                Block "while x" .. "    x = x - 1;"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn branches_fold_on_their_own() {
+        check(
+            "if a\n  b = 1;\nelseif c\n  b = 2;\nelse\n  b = 3;\nend\n\
+             try\n  x = 1;\ncatch err\n  x = 2;\nend",
+            expect![[r#"
+                Block "if a" .. "  b = 1;"
+                Block "elseif c" .. "  b = 2;"
+                Block "else" .. "  b = 3;"
+                Block "try" .. "  x = 1;"
+                Block "catch err" .. "  x = 2;"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn switch_folds_as_a_whole_and_by_case() {
+        check(
+            "switch x\n  case 1\n    y = 1;\n  otherwise\n    y = 2;\nend",
+            expect![[r#"
+                Block "switch x" .. "    y = 2;"
+                Block "case 1" .. "    y = 1;"
+                Block "otherwise" .. "    y = 2;"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn unterminated_functions_leave_out_subfunctions() {
+        check(
+            "function f\n  disp(1)\n\nfunction g\n  disp(2)\n",
+            expect![[r#"
+                Block "function f" .. "  disp(1)"
+                Block "function g" .. "  disp(2)"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn comments() {
+        check(
+            "% one\n% two\nx = 1; % trailing\n% alone\n\n% three\n  % four\n%{\nblock\n%}\n",
+            expect![[r#"
+                Comment "% one" .. "% two"
+                Comment "% three" .. "  % four"
+                Comment "%{" .. "%}"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn sections() {
+        check(
+            "%% Load\n% about loading\nx = 1;\n\n%% Plot\nplot(x);\n",
+            expect![[r#"
+                Region "%% Load" .. "x = 1;"
+                Region "%% Plot" .. "plot(x);"
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! What a function is, shown when hovering its name.
+
+use crate::references::{Target, name_at, target_at};
+use crate::signature_help::{builtin_signatures, user_signature};
+use ast::TypedSyntaxNode;
+use catalogue::Catalogue;
+use text_size::{TextRange, TextSize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hover {
+    /// The name hovered.
+    pub range: TextRange,
+    /// The ways of calling the function and its documentation, in Markdown.
+    pub markdown: String,
+}
+
+/// Describes the function named at `offset`.
+///
+/// Functions are looked for as for signature help: in the file itself, then in the file that
+/// `definition` returns for a name, and finally in the `catalogue` of builtins.
+pub fn hover(
+    root: &ast::Root,
+    offset: TextSize,
+    catalogue: &Catalogue,
+    definition: impl FnOnce(&str) -> Option<ast::Root>,
+) -> Option<Hover> {
+    let token = name_at(root.syntax(), offset)?;
+    let Target::Function { name } = target_at(root, offset)? else {
+        return None;
+    };
+
+    let signatures = match user_signature(root, &name) {
+        Some(signature) => vec![signature],
+        None => match definition(&name).and_then(|other| user_signature(&other, &name)) {
+            Some(signature) => vec![signature],
+            None => builtin_signatures(catalogue, &name),
+        },
+    };
+    let first = signatures.first()?;
+
+    let labels: Vec<_> = signatures
+        .iter()
+        .map(|signature| signature.label.as_str())
+        .collect();
+    let mut markdown = format!("```octave\n{}\n```", labels.join("\n"));
+    if let Some(documentation) = &first.documentation {
+        markdown.push_str("\n\n");
+        markdown.push_str(documentation);
+    }
+    Some(Hover {
+        range: token.text_range(),
+        markdown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let offset = input.find("$0").unwrap();
+        let input = input.replace("$0", "");
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+        let actual = hover(
+            &root,
+            TextSize::try_from(offset).unwrap(),
+            &Catalogue::bundled(),
+            |_| None,
+        )
+        .map_or_else(|| "None".to_string(), |hover| hover.markdown);
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn builtins() {
+        check(
+            "x = ze$0ros(2);",
+            expect![[r#"
+                ```octave
+                val = zeros (n)
+                val = zeros (m, n)
+                val = zeros (m, n, k, ...)
+                val = zeros ([m n ...])
+                val = zeros (..., "like", var)
+                val = zeros (..., class)
+                ```
+
+                Return a matrix or N-dimensional array whose elements are all 0."#]],
+        );
+    }
+
+    #[test]
+    fn package_functions() {
+        check(
+            "p = normpdf$0(0);",
+            expect![[r"
+                ```octave
+                y = normpdf (x)
+                y = normpdf (x, mu)
+                y = normpdf (x, mu, sigma)
+                ```
+
+                Normal probability density function (PDF).
+
+                From the statistics package: `pkg load statistics`."]],
+        );
+    }
+
+    #[test]
+    fn user_functions() {
+        check(
+            "y = twice(3);\nfunction r = tw$0ice(x)\n  % Doubles x.\n  r = 2 * x;\nend",
+            expect![[r"
+                ```octave
+                r = twice(x)
+                ```
+
+                Doubles x."]],
+        );
+    }
+
+    #[test]
+    fn variables_and_unknown_names() {
+        check("zeros = 1;\ndisp(zer$0os);", expect!["None"]);
+        check("unknown$0_function(1);", expect!["None"]);
+    }
+}
@@ -0,0 +1,438 @@
+//! Replacing a variable by the expression assigned to it.
+//!
+//! Only variables assigned exactly once can be inlined, and only at uses that the assignment
+//! comes before, with none of the variables it reads assigned again in between or in a loop
+//! around the use that the assignment is outside of. The operands are taken from the `hir`
+//! lowering of the assignment, and the value is parenthesised wherever the binding powers of the
+//! parser, or Octave's reading of `-a^2`, would otherwise split it.
+
+use crate::code_actions::{CodeAction, CodeActionKind, Edit, trimmed};
+use crate::references::{innermost_scope, name_at};
+use ast::TypedSyntaxNode;
+use hir::scope::Scope;
+use hir::{Database, Expr, Stmt};
+use parser::{BinaryOp, UnaryOp};
+use smol_str::SmolStr;
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, TokenKind};
+use text_size::{TextRange, TextSize};
+
+const BRANCH_KEYWORDS: [TokenKind; 6] = [
+    TokenKind::ElseIfKw,
+    TokenKind::ElseKw,
+    TokenKind::CaseKw,
+    TokenKind::OtherwiseKw,
+    TokenKind::CatchKw,
+    TokenKind::UnwindProtectCleanupKw,
+];
+
+/// Returns the action inlining the variable at `offset`: at every use if `offset` is on its
+/// assignment, or else at the use `offset` is on. The assignment goes once nothing uses it.
+#[must_use]
+pub fn inline_variable(root: &ast::Root, offset: TextSize) -> Option<CodeAction> {
+    let token = name_at(root.syntax(), offset)?;
+    let name = token.text();
+    let scopes = hir::scope::scopes(root);
+    let scope = innermost_scope(&scopes, offset);
+    if scope
+        .params
+        .iter()
+        .chain(&scope.outputs)
+        .any(|binding| binding.name == name)
+    {
+        return None;
+    }
+    let [write] = scope.writes(name).collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let def = root
+        .syntax()
+        .token_at_offset(write.range.start())
+        .right_biased()
+        .and_then(|token| token.parent()?.parent())
+        .and_then(ast::VariableDef::cast)?;
+    if !matches!(def.lhs()?, ast::Expr::VariableRef(_)) {
+        return None;
+    }
+    let value = def.value()?;
+    let def_range = trimmed(def.syntax())?;
+    let value_range = expr_range(value.syntax())?;
+
+    let reads: Vec<_> = scope.reads(name).collect();
+    let uses: Vec<TextRange> = if token.text_range() == write.range {
+        reads.iter().map(|read| read.range).collect()
+    } else {
+        vec![
+            reads
+                .iter()
+                .find(|read| read.range == token.text_range())?
+                .range,
+        ]
+    };
+    let operands = operands(root, &def)?;
+    // Uses outside the statements the assignment is among may run without it.
+    let block = rest_of_block(def.syntax())?;
+    let is_inlinable = |range: &TextRange| {
+        range.start() >= def_range.end()
+            && block.contains_range(*range)
+            && operands.iter().all(|operand| {
+                operand != name
+                    && !scope.writes(operand).any(|write| {
+                        write.range.start() > def_range.start()
+                            && write.range.start() < range.start()
+                    })
+            })
+    };
+    if uses.is_empty()
+        || !uses.iter().all(|range| {
+            is_inlinable(range) && !rerun_after_write(root, scope, &operands, def_range, *range)
+        })
+    {
+        return None;
+    }
+
+    let text = root.syntax().text().to_string();
+    let value_text = &text[std::ops::Range::<usize>::from(value_range)];
+    let mut edits = Vec::new();
+    if uses.len() == reads.len() {
+        edits.push(Edit {
+            range: removal_range(&text, def_range),
+            text: String::new(),
+        });
+    }
+    for range in &uses {
+        let var = root
+            .syntax()
+            .token_at_offset(range.start())
+            .right_biased()
+            .and_then(|token| token.parent())?;
+        let text = if needs_parens(&value, &var) {
+            format!("({value_text})")
+        } else {
+            value_text.to_string()
+        };
+        edits.push(Edit {
+            range: *range,
+            text,
+        });
+    }
+    Some(CodeAction {
+        title: format!("Inline variable '{name}'"),
+        kind: CodeActionKind::Inline,
+        edits,
+        new_file: None,
+        is_preferred: false,
+    })
+}
+
+/// Whether a loop around the use at `range` but not the assignment at `def_range` writes one of
+/// its operands, so that the use runs again after the write.
+fn rerun_after_write(
+    root: &ast::Root,
+    scope: &Scope,
+    operands: &[SmolStr],
+    def_range: TextRange,
+    range: TextRange,
+) -> bool {
+    root.syntax()
+        .token_at_offset(range.start())
+        .right_biased()
+        .into_iter()
+        .flat_map(|token| token.parent_ancestors())
+        .filter(|node| {
+            matches!(
+                node.kind(),
+                SyntaxKind::SyntaxConstruct(SyntaxConstruct::ForLoop | SyntaxConstruct::WhileLoop)
+            ) && !node.text_range().contains_range(def_range)
+        })
+        .any(|node| {
+            operands.iter().any(|operand| {
+                scope
+                    .writes(operand)
+                    .any(|write| node.text_range().contains_range(write.range))
+            })
+        })
+}
+
+/// The variables read by the value of the assignment `def`, from its lowering.
+fn operands(root: &ast::Root, def: &ast::VariableDef) -> Option<Vec<SmolStr>> {
+    fn collect(db: &Database, expr: &Expr, operands: &mut Vec<SmolStr>) {
+        if let Expr::VariableRef { var } = expr {
+            operands.push(var.clone());
+        }
+        for child in expr.children() {
+            collect(db, &db[child], operands);
+        }
+    }
+
+    let hir::Body { db, stmts, .. } = hir::lower(root);
+    let id = db.source_map().node_stmt(def.syntax())?;
+    let Stmt::VariableDef { value, .. } = hir::source_map::stmt(&stmts, id)? else {
+        return None;
+    };
+    let mut operands = Vec::new();
+    collect(&db, &db[*value], &mut operands);
+    Some(operands)
+}
+
+/// Whether the value needs parentheses in place of the variable `var`.
+fn needs_parens(value: &ast::Expr, var: &SyntaxNode) -> bool {
+    let Some(parent) = var.parent() else {
+        return false;
+    };
+    let is_first = parent.first_child().as_ref() == Some(var);
+    let value_power = match value {
+        ast::Expr::BinaryExpr(value) => value.op().and_then(|op| binary_op(op.kind())),
+        _ => None,
+    }
+    .map(BinaryOp::binding_power);
+    let is_prefix = matches!(value, ast::Expr::UnaryExpr(_));
+    let is_atom = !is_prefix && value_power.is_none();
+
+    match parent.kind() {
+        SyntaxKind::SyntaxConstruct(SyntaxConstruct::InfixExpr) => {
+            let Some(op) = parent
+                .children_with_tokens()
+                .filter_map(SyntaxElement::into_token)
+                .find_map(|token| binary_op(token.kind()))
+            else {
+                return false;
+            };
+            if op == BinaryOp::Assign {
+                return false;
+            }
+            let (left, right) = op.binding_power();
+            let ((), prefix) = UnaryOp::Neg.binding_power();
+            match (value_power, is_first) {
+                (Some((_, value_right)), true) => left >= value_right,
+                (Some((value_left, _)), false) => value_left < right,
+                // Octave applies `^` before a sign on its base, as in `-a^2`.
+                (None, true) => {
+                    is_prefix && (left >= prefix || matches!(op, BinaryOp::Pow | BinaryOp::ElmtPow))
+                }
+                (None, false) => false,
+            }
+        }
+        // Prefix operators bind tighter than any binary one, a sign next to another could read
+        // as `--` or `++`, and spaces separate the elements of a matrix.
+        SyntaxKind::SyntaxConstruct(SyntaxConstruct::PrefixExpr | SyntaxConstruct::MatrixExpr) => {
+            !is_atom
+        }
+        SyntaxKind::SyntaxConstruct(
+            SyntaxConstruct::PostfixExpr | SyntaxConstruct::CallExpr | SyntaxConstruct::FieldExpr,
+        ) => {
+            is_first
+                && !matches!(
+                    value,
+                    ast::Expr::VariableRef(_)
+                        | ast::Expr::CallExpr(_)
+                        | ast::Expr::ParenExpr(_)
+                        | ast::Expr::MatrixExpr(_)
+                        | ast::Expr::FieldExpr(_)
+                        | ast::Expr::PostfixExpr(_)
+                )
+        }
+        _ => false,
+    }
+}
+
+/// The range from the statement `def` to the end of the statements it is among, which is the
+/// next branch of its block or the end of it.
+fn rest_of_block(def: &SyntaxNode) -> Option<TextRange> {
+    let parent = def.parent()?;
+    let end = parent
+        .children_with_tokens()
+        .skip_while(|element| element.as_node() != Some(def))
+        .filter_map(SyntaxElement::into_token)
+        .find(|token| {
+            matches!(token.kind(), SyntaxKind::LexToken(kind)
+                if kind.is_block_terminator() || BRANCH_KEYWORDS.contains(&kind))
+        })
+        .map_or(parent.text_range().end(), |token| {
+            token.text_range().start()
+        });
+    Some(TextRange::new(def.text_range().start(), end))
+}
+
+fn binary_op(kind: SyntaxKind) -> Option<BinaryOp> {
+    match kind {
+        SyntaxKind::LexToken(kind) => BinaryOp::from_token(kind),
+        SyntaxKind::SyntaxConstruct(_) => None,
+    }
+}
+
+/// The range of an expression without its trivia and the `;` ending its statement.
+fn expr_range(node: &SyntaxNode) -> Option<TextRange> {
+    let tokens: Vec<_> = node
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()))
+        .collect();
+    let last = tokens
+        .iter()
+        .rposition(|token| token.kind() != TokenKind::Semicolon.into())?;
+    Some(tokens[0].text_range().cover(tokens[last].text_range()))
+}
+
+/// The range to delete with the statement at `range`: its whole line if nothing else is on it.
+fn removal_range(text: &str, range: TextRange) -> TextRange {
+    let start = usize::from(range.start());
+    let end = usize::from(range.end());
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let rest = &text[end..];
+    let line_end = rest.find('\n').map_or(text.len(), |i| end + i + 1);
+    let to_size = |offset: usize| TextSize::try_from(offset).unwrap_or_default();
+    if text[line_start..start].trim().is_empty() && rest[..line_end - end].trim().is_empty() {
+        TextRange::new(to_size(line_start), to_size(line_end))
+    } else {
+        let spaces = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        TextRange::new(range.start(), to_size(end + spaces))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+
+    /// Inlines the variable at the `$0` marker.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let offset = input.find("$0").unwrap();
+        let input = input.replace("$0", "");
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+
+        let offset = TextSize::try_from(offset).unwrap();
+        let actual = inline_variable(&root, offset).map_or_else(String::new, |action| {
+            let mut text = input.clone();
+            for edit in action.edits.iter().rev() {
+                text.replace_range(std::ops::Range::<usize>::from(edit.range), &edit.text);
+            }
+            format!("{}\n{text}", action.title)
+        });
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn inlines_every_use() {
+        check(
+            "function y = f(a)\n  $0t = a + 1;\n  y = t * t;\nend\n",
+            expect![[r"
+                Inline variable 't'
+                function y = f(a)
+                  y = (a + 1) * (a + 1);
+                end
+            "]],
+        );
+    }
+
+    #[test]
+    fn inlines_one_use() {
+        check(
+            "t = g(1);\ndisp($0t)\ndisp(t')\n",
+            expect![[r"
+                Inline variable 't'
+                t = g(1);
+                disp(g(1))
+                disp(t')
+            "]],
+        );
+        check(
+            "t = g(1);\ndisp($0t)\n",
+            expect![[r"
+                Inline variable 't'
+                disp(g(1))
+            "]],
+        );
+    }
+
+    #[test]
+    fn parenthesises_by_binding_power() {
+        check(
+            "t = a - b;\ny = c - $0t;\n",
+            expect![[r"
+                Inline variable 't'
+                y = c - (a - b);
+            "]],
+        );
+        check(
+            "t = a - b;\ny = $0t - c;\n",
+            expect![[r"
+                Inline variable 't'
+                y = a - b - c;
+            "]],
+        );
+        check(
+            "t = a * b;\ny = c - $0t;\n",
+            expect![[r"
+                Inline variable 't'
+                y = c - a * b;
+            "]],
+        );
+        check(
+            "t = a ^ b;\ny = $0t ^ c;\n",
+            expect![[r"
+                Inline variable 't'
+                y = (a ^ b) ^ c;
+            "]],
+        );
+        check(
+            "t = 1:n;\ny = $0t + 1;\n",
+            expect![[r"
+                Inline variable 't'
+                y = (1:n) + 1;
+            "]],
+        );
+        check(
+            "t = -a;\ny = -$0t;\n",
+            expect![[r"
+                Inline variable 't'
+                y = -(-a);
+            "]],
+        );
+        check(
+            "t = -a;\ny = $0t^2;\n",
+            expect![[r"
+                Inline variable 't'
+                y = (-a)^2;
+            "]],
+        );
+        check(
+            "t = -a;\ny = $0t.^2;\n",
+            expect![[r"
+                Inline variable 't'
+                y = (-a).^2;
+            "]],
+        );
+        check(
+            "t = a + b;\ny = $0t';\n",
+            expect![[r"
+                Inline variable 't'
+                y = (a + b)';
+            "]],
+        );
+        check(
+            "t = a + b;\ny = [$0t c];\n",
+            expect![[r"
+                Inline variable 't'
+                y = [(a + b) c];
+            "]],
+        );
+    }
+
+    #[test]
+    fn does_not_inline_what_may_change() {
+        check("t = 1;\nt = 2;\ndisp($0t)\n", expect![""]);
+        check("t = a + 1;\na = 3;\ndisp($0t)\n", expect![""]);
+        check("if c\n  t = 1;\nelse\n  disp($0t)\nend\n", expect![""]);
+        check("function f(t)\n  disp($0t)\nend\n", expect![""]);
+        check("[t, u] = size(x);\ndisp($0t)\n", expect![""]);
+        check(
+            "t = a + 1;\nfor i = 1:3\n  disp($0t)\n  a = i;\nend\n",
+            expect![""],
+        );
+        check(
+            "t = a + 1;\nwhile a < 3\n  disp($0t)\n  a = a + 1;\nend\n",
+            expect![""],
+        );
+    }
+}
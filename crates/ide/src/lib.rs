@@ -0,0 +1,39 @@
+//! Editor features, computed on top of the syntax tree and the HIR.
+//!
+//! Everything here works with plain text ranges; turning the results into LSP types is left to the
+//! server.
+#![warn(clippy::pedantic)]
+
+mod code_actions;
+mod completion;
+mod document_symbols;
+mod extract_function;
+mod extract_variable;
+mod folding_ranges;
+mod hover;
+mod inline_variable;
+mod line_index;
+mod references;
+mod rename;
+mod semantic_tokens;
+mod signature_help;
+mod workspace_symbols;
+
+pub use code_actions::{
+    CodeAction, CodeActionKind, Edit, NewFile, lint_fixes, rewrites, validation_fixes,
+};
+pub use completion::{CompletionItem, CompletionKind, Completions, completions};
+pub use document_symbols::{Symbol, SymbolKind, document_symbols};
+pub use extract_function::extract_function;
+pub use extract_variable::extract_variable;
+pub use folding_ranges::{Fold, FoldKind, folding_ranges};
+pub use hover::{Hover, hover};
+pub use inline_variable::inline_variable;
+pub use line_index::{LineCol, LineIndex, PositionEncoding};
+pub use references::{Reference, ReferenceKind, Target, references, target_at};
+pub use rename::{ConflictKind, RenameError, check_conflicts, check_name, prepare_rename};
+pub use semantic_tokens::{
+    SemanticToken, SemanticTokenKind, SemanticTokenModifier, semantic_tokens,
+};
+pub use signature_help::{Parameter, Signature, SignatureHelp, signature_help};
+pub use workspace_symbols::{SearchResult, SymbolIndex, WorkspaceSymbol, file_symbols};
@@ -0,0 +1,235 @@
+//! Conversion of text offsets into lines and columns.
+
+use std::collections::HashMap;
+use text_size::TextSize;
+
+/// A position in a text. The column counts the units of a [`PositionEncoding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// What the columns of a [`LineCol`] count. Editors speaking LSP count UTF-16 code units unless
+/// they agree on another encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// Bytes.
+    Utf8,
+    #[default]
+    Utf16,
+    /// Characters.
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// The length of `text` in the units of the encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the text is longer than `u32::MAX` units.
+    #[must_use]
+    pub fn measure(self, text: &str) -> u32 {
+        let len = match self {
+            Self::Utf8 => text.len(),
+            Self::Utf16 => text.chars().map(char::len_utf16).sum(),
+            Self::Utf32 => text.chars().count(),
+        };
+        u32::try_from(len).unwrap()
+    }
+
+    fn char_len(self, ch: char) -> u32 {
+        match self {
+            Self::Utf8 => u32::from(TextSize::of(ch)),
+            Self::Utf16 => u32::try_from(ch.len_utf16()).unwrap(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    line_starts: Vec<TextSize>,
+    /// The characters longer than one byte of each line that has any, with their offset from the
+    /// start of the line.
+    multibyte_chars: HashMap<u32, Vec<(TextSize, char)>>,
+    len: TextSize,
+}
+
+impl LineIndex {
+    /// # Panics
+    ///
+    /// Panics if the text is longer than `u32::MAX` bytes.
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![TextSize::from(0)];
+        let mut multibyte_chars: HashMap<u32, Vec<_>> = HashMap::new();
+
+        for (offset, ch) in text.char_indices() {
+            let offset = TextSize::try_from(offset).unwrap();
+            if ch == '\n' {
+                line_starts.push(offset + TextSize::of(ch));
+            } else if !ch.is_ascii() {
+                let line = u32::try_from(line_starts.len() - 1).unwrap();
+                let line_start = *line_starts.last().unwrap();
+                multibyte_chars
+                    .entry(line)
+                    .or_default()
+                    .push((offset - line_start, ch));
+            }
+        }
+
+        Self {
+            line_starts,
+            multibyte_chars,
+            len: TextSize::of(text),
+        }
+    }
+
+    /// Offsets past the end of the text are clamped to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the text has more than `u32::MAX` lines.
+    #[must_use]
+    pub fn line_col(&self, offset: TextSize, encoding: PositionEncoding) -> LineCol {
+        let offset = offset.min(self.len);
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line = u32::try_from(line).unwrap();
+        let byte_col = offset - self.line_starts[line as usize];
+
+        // Each character counts as one byte so far, which the encoding may count differently.
+        let col = self
+            .multibyte_chars(line)
+            .iter()
+            .filter(|(char_offset, _)| *char_offset < byte_col)
+            .fold(u32::from(byte_col), |col, (_, ch)| {
+                col - u32::from(TextSize::of(*ch)) + encoding.char_len(*ch)
+            });
+
+        LineCol { line, col }
+    }
+
+    /// The inverse of [`LineIndex::line_col`]. Columns past the end of a line are clamped to it,
+    /// and columns within a character to its start. Lines past the end of the text give None.
+    #[must_use]
+    pub fn offset(&self, line_col: LineCol, encoding: PositionEncoding) -> Option<TextSize> {
+        let line_start = *self.line_starts.get(line_col.line as usize)?;
+        let line_end = self
+            .line_starts
+            .get(line_col.line as usize + 1)
+            .map_or(self.len, |next| *next - TextSize::from(1));
+
+        let mut offset = line_start;
+        let mut col = 0;
+        let mut chars = self.multibyte_chars(line_col.line).iter().peekable();
+        while offset < line_end {
+            let (units, len) = match chars.peek() {
+                Some((char_offset, ch)) if line_start + *char_offset == offset => {
+                    (encoding.char_len(*ch), TextSize::of(*ch))
+                }
+                _ => (1, TextSize::from(1)),
+            };
+            if col + units > line_col.col {
+                break;
+            }
+            if len > TextSize::from(1) {
+                chars.next();
+            }
+            col += units;
+            offset += len;
+        }
+        Some(offset)
+    }
+
+    fn multibyte_chars(&self, line: u32) -> &[(TextSize, char)] {
+        self.multibyte_chars.get(&line).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_col(text: &str, offset: u32, encoding: PositionEncoding) -> (u32, u32) {
+        let LineCol { line, col } = LineIndex::new(text).line_col(offset.into(), encoding);
+        (line, col)
+    }
+
+    #[test]
+    fn ascii() {
+        let text = "a = 1;\nb = 2;\n";
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            assert_eq!(line_col(text, 0, encoding), (0, 0));
+            assert_eq!(line_col(text, 6, encoding), (0, 6));
+            assert_eq!(line_col(text, 7, encoding), (1, 0));
+            assert_eq!(line_col(text, 11, encoding), (1, 4));
+            assert_eq!(line_col(text, 14, encoding), (2, 0));
+        }
+    }
+
+    #[test]
+    fn multibyte_chars_count_once() {
+        let text = "x = 'äö';\ny";
+        assert_eq!(line_col(text, 7, PositionEncoding::Utf32), (0, 6));
+        assert_eq!(line_col(text, 9, PositionEncoding::Utf32), (0, 7));
+        assert_eq!(line_col(text, 12, PositionEncoding::Utf32), (1, 0));
+        assert_eq!(line_col(text, 9, PositionEncoding::Utf16), (0, 7));
+        assert_eq!(line_col(text, 9, PositionEncoding::Utf8), (0, 9));
+    }
+
+    #[test]
+    fn astral_chars_are_two_utf16_units() {
+        // The emoji takes four bytes.
+        let text = "% 😀 x\ny";
+        assert_eq!(line_col(text, 7, PositionEncoding::Utf8), (0, 7));
+        assert_eq!(line_col(text, 7, PositionEncoding::Utf16), (0, 5));
+        assert_eq!(line_col(text, 7, PositionEncoding::Utf32), (0, 4));
+
+        let index = LineIndex::new(text);
+        let offset = |col| index.offset(LineCol { line: 0, col }, PositionEncoding::Utf16);
+        assert_eq!(offset(5), Some(TextSize::from(7)));
+        // Within the surrogate pair.
+        assert_eq!(offset(3), Some(TextSize::from(2)));
+    }
+
+    #[test]
+    fn offsets_round_trip() {
+        let text = "x = 'ä😀';\ny = 1;";
+        let index = LineIndex::new(text);
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            for (offset, _) in text.char_indices() {
+                let offset = TextSize::try_from(offset).unwrap();
+                let line_col = index.line_col(offset, encoding);
+                assert_eq!(index.offset(line_col, encoding), Some(offset));
+            }
+        }
+        let past_the_end = LineCol { line: 0, col: 100 };
+        assert_eq!(
+            index.offset(past_the_end, PositionEncoding::Utf16),
+            Some(TextSize::from(13))
+        );
+        let missing_line = LineCol { line: 2, col: 0 };
+        assert_eq!(index.offset(missing_line, PositionEncoding::Utf16), None);
+    }
+
+    #[test]
+    fn offsets_past_the_end_are_clamped() {
+        assert_eq!(line_col("ab", 10, PositionEncoding::Utf16), (0, 2));
+    }
+
+    #[test]
+    fn measure() {
+        assert_eq!(PositionEncoding::Utf8.measure("a😀"), 5);
+        assert_eq!(PositionEncoding::Utf16.measure("a😀"), 3);
+        assert_eq!(PositionEncoding::Utf32.measure("a😀"), 2);
+    }
+}
@@ -0,0 +1,367 @@
+//! Finding the uses of a variable or a function.
+//!
+//! Variables are local to the scope they are used in. Functions are found by name in one file at a
+//! time; deciding which files refer to the same function is up to the project model.
+
+use ast::TypedSyntaxNode;
+use hir::scope::{AccessKind, Scope};
+use smol_str::SmolStr;
+use syntax::{SyntaxNode, SyntaxToken, TokenKind};
+use text_size::{TextRange, TextSize};
+
+/// Functions that take the name of a function as their first argument.
+const FUNCTION_NAME_TAKERS: [&str; 5] = ["arrayfun", "cellfun", "feval", "str2func", "structfun"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A variable of the scope with the given range.
+    Variable {
+        name: SmolStr,
+        scope: TextRange,
+    },
+    Function {
+        name: SmolStr,
+    },
+}
+
+impl Target {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Variable { name, .. } | Self::Function { name } => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub range: TextRange,
+    pub kind: ReferenceKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    /// A parameter or output of a function, or the name of a function definition.
+    Definition,
+    Write,
+    Read,
+    Call,
+    /// A function handle, such as `@f`.
+    Handle,
+    /// The name of a function in a string, as in `feval('f')`. The string may well be meant for
+    /// something else, so these are less certain than the others.
+    String,
+}
+
+impl Reference {
+    #[must_use]
+    pub fn is_certain(&self) -> bool {
+        self.kind != ReferenceKind::String
+    }
+}
+
+/// Returns what the name at `offset` refers to.
+#[must_use]
+pub fn target_at(root: &ast::Root, offset: TextSize) -> Option<Target> {
+    let token = name_at(root.syntax(), offset)?;
+    let parent = token.parent()?;
+    if ast::StringLiteral::cast(parent.clone()).is_some() {
+        return Some(Target::Function {
+            name: string_name(&token)?,
+        });
+    }
+
+    let name: SmolStr = token.text().into();
+    if ast::FnHandle::cast(parent).is_some() || is_fn_def_name(&token) {
+        return Some(Target::Function { name });
+    }
+
+    let scopes = hir::scope::scopes(root);
+    let scope = innermost_scope(&scopes, token.text_range().start());
+    if binds(scope, &name) {
+        Some(Target::Variable {
+            name,
+            scope: scope.range,
+        })
+    } else {
+        Some(Target::Function { name })
+    }
+}
+
+/// Returns every use of `target` in the file, in source order.
+#[must_use]
+pub fn references(root: &ast::Root, target: &Target) -> Vec<Reference> {
+    let scopes = hir::scope::scopes(root);
+    let mut references = match target {
+        Target::Variable { name, scope } => scopes
+            .iter()
+            .find(|candidate| candidate.range == *scope)
+            .map(|scope| variable_references(scope, name))
+            .unwrap_or_default(),
+        Target::Function { name } => function_references(root, &scopes, name),
+    };
+    references.sort_by_key(|reference| reference.range.start());
+    references.dedup();
+    references
+}
+
+fn variable_references(scope: &Scope, name: &str) -> Vec<Reference> {
+    let bindings = scope.params.iter().chain(&scope.outputs);
+    let definitions = bindings
+        .filter(|binding| binding.name == name)
+        .map(|binding| Reference {
+            range: binding.range,
+            kind: ReferenceKind::Definition,
+        });
+    let accesses = scope
+        .accesses
+        .iter()
+        .filter(|access| access.name == name)
+        .map(|access| Reference {
+            range: access.range,
+            kind: match access.kind {
+                AccessKind::Read => ReferenceKind::Read,
+                AccessKind::Write => ReferenceKind::Write,
+            },
+        });
+    definitions.chain(accesses).collect()
+}
+
+fn function_references(root: &ast::Root, scopes: &[Scope], name: &str) -> Vec<Reference> {
+    let headers: Vec<TextRange> = root
+        .syntax()
+        .descendants()
+        .filter_map(ast::FnDef::cast)
+        .filter_map(|fn_def| Some(fn_def.header()?.syntax().text_range()))
+        .collect();
+
+    let mut references = Vec::new();
+    for node in root.syntax().descendants() {
+        if let Some(fn_def) = ast::FnDef::cast(node.clone()) {
+            if let Some(fn_name) = fn_def.name().filter(|fn_name| fn_name.text() == name) {
+                references.push(Reference {
+                    range: fn_name.text_range(),
+                    kind: ReferenceKind::Definition,
+                });
+            }
+        } else if let Some(handle) = ast::FnHandle::cast(node.clone()) {
+            if let Some(handle_name) = handle
+                .name()
+                .filter(|handle_name| handle_name.text() == name)
+            {
+                references.push(Reference {
+                    range: handle_name.text_range(),
+                    kind: ReferenceKind::Handle,
+                });
+            }
+        } else if let Some(var) = ast::VariableRef::cast(node.clone()) {
+            let Some(var_name) = var.name().filter(|var_name| var_name.text() == name) else {
+                continue;
+            };
+            let range = var_name.text_range();
+            let in_header = headers.iter().any(|header| header.contains_range(range));
+            if !in_header && !binds(innermost_scope(scopes, range.start()), name) {
+                references.push(Reference {
+                    range,
+                    kind: ReferenceKind::Call,
+                });
+            }
+        } else if let Some(call) = ast::CallExpr::cast(node)
+            && let Some(ast::Expr::StringLiteral(string)) = function_name_arg(&call)
+            && string.value() == name
+        {
+            references.push(Reference {
+                range: string_contents_range(&string),
+                kind: ReferenceKind::String,
+            });
+        }
+    }
+    references
+}
+
+/// Returns the first argument of calls like `feval('f', x)`.
+fn function_name_arg(call: &ast::CallExpr) -> Option<ast::Expr> {
+    let ast::Expr::VariableRef(callee) = call.func()? else {
+        return None;
+    };
+    if !FUNCTION_NAME_TAKERS.contains(&callee.name()?.text()) {
+        return None;
+    }
+    call.args().next()
+}
+
+/// The range of a string without its quotes.
+fn string_contents_range(string: &ast::StringLiteral) -> TextRange {
+    let range = string.syntax().text_range();
+    let quote = TextSize::of('\'');
+    let end = if string.syntax().text().len() >= quote + quote {
+        range.end() - quote
+    } else {
+        range.end()
+    };
+    TextRange::new(range.start() + quote, end.max(range.start() + quote))
+}
+
+/// The identifier at `offset`, preferring the one before it when `offset` sits right after a name.
+pub(crate) fn name_at(root: &SyntaxNode, offset: TextSize) -> Option<SyntaxToken> {
+    let is_identifier = |token: &SyntaxToken| token.kind() == TokenKind::Identifier.into();
+    let tokens = root.token_at_offset(offset);
+    tokens.clone().find(is_identifier).or_else(|| {
+        // Inside a string given to `feval` and friends, the whole string is the name.
+        let token = tokens.right_biased()?;
+        let string = token.parent().and_then(ast::StringLiteral::cast)?;
+        let call = string.syntax().parent().and_then(ast::CallExpr::cast)?;
+        let arg = function_name_arg(&call)?;
+        (arg.syntax() == string.syntax()).then_some(token)
+    })
+}
+
+/// The function named by a string given to `feval` and friends, if `token` is part of one.
+fn string_name(token: &SyntaxToken) -> Option<SmolStr> {
+    let string = token.parent().and_then(ast::StringLiteral::cast)?;
+    let call = string.syntax().parent().and_then(ast::CallExpr::cast)?;
+    let arg = function_name_arg(&call)?;
+    (arg.syntax() == string.syntax()).then(|| string.value().into())
+}
+
+fn is_fn_def_name(token: &SyntaxToken) -> bool {
+    token
+        .parent_ancestors()
+        .find_map(ast::FnDef::cast)
+        .and_then(|fn_def| fn_def.name())
+        .is_some_and(|name| name == *token)
+}
+
+/// The scope of the innermost function containing `offset`, or the script scope.
+pub(crate) fn innermost_scope(scopes: &[Scope], offset: TextSize) -> &Scope {
+    scopes
+        .iter()
+        .filter(|scope| scope.range.contains_inclusive(offset))
+        .min_by_key(|scope| (scope.range.len(), !scope.is_function()))
+        .unwrap_or(&scopes[0])
+}
+
+/// Whether `name` is a variable in `scope`: a parameter, an output or assigned somewhere.
+pub(crate) fn binds(scope: &Scope, name: &str) -> bool {
+    scope
+        .params
+        .iter()
+        .chain(&scope.outputs)
+        .any(|binding| binding.name == name)
+        || scope.writes(name).next().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    /// Finds the references of whatever is at the `$0` marker.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let offset = input.find("$0").unwrap();
+        let input = input.replace("$0", "");
+        let root = ast::Root::cast(parser::parse(&input).syntax()).unwrap();
+        let target = target_at(&root, TextSize::try_from(offset).unwrap()).unwrap();
+
+        let mut actual = format!("{target:?}\n");
+        for reference in references(&root, &target) {
+            let text = &input[reference.range];
+            let _ = writeln!(
+                actual,
+                "{:?} {text:?} {:?}",
+                reference.kind, reference.range
+            );
+        }
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn variables_stay_in_their_function() {
+        check(
+            "x = 1;\nfunction y = f(x)\n  y = $0x + 1;\nend\nfunction g\n  x = 2;\nend",
+            expect![[r#"
+                Variable { name: "x", scope: 7..42 }
+                Definition "x" 22..23
+                Read "x" 31..32
+            "#]],
+        );
+    }
+
+    #[test]
+    fn script_variables() {
+        check(
+            "a$0 = 1;\nb = a * a;\nfunction f\n  a = 3;\nend",
+            expect![[r#"
+            Variable { name: "a", scope: 0..41 }
+            Write "a" 0..1
+            Read "a" 11..12
+            Read "a" 15..16
+        "#]],
+        );
+    }
+
+    #[test]
+    fn functions_are_found_by_name() {
+        check(
+            "function r = helper(x)\n  r = x;\nend\n\
+             y = help$0er(1);\nh = @helper;\nz = cellfun('helper', c);\nfeval('other');",
+            expect![[r#"
+                Function { name: "helper" }
+                Definition "helper" 13..19
+                Call "helper" 40..46
+                Handle "helper" 56..62
+                String "helper" 77..83
+            "#]],
+        );
+    }
+
+    #[test]
+    fn functions_from_calls() {
+        check(
+            "function r = main\n  r = help$0er(1) + helper;\nend\nfunction r = helper(x)\n  r = x;\nend",
+            expect![[r#"
+                Function { name: "helper" }
+                Call "helper" 24..30
+                Call "helper" 36..42
+                Definition "helper" 61..67
+            "#]],
+        );
+    }
+
+    #[test]
+    fn functions_from_handles_and_strings() {
+        check(
+            "h = @ne$0xt;\nfeval('next');",
+            expect![[r#"
+            Function { name: "next" }
+            Handle "next" 5..9
+            String "next" 18..22
+        "#]],
+        );
+        check(
+            "h = @next;\nfeval('ne$0xt', 1);",
+            expect![[r#"
+            Function { name: "next" }
+            Handle "next" 5..9
+            String "next" 18..22
+        "#]],
+        );
+
+        let root = ast::Root::cast(parser::parse("disp('next')").syntax()).unwrap();
+        assert_eq!(target_at(&root, TextSize::from(7)), None);
+    }
+
+    #[test]
+    fn variables_shadow_functions() {
+        check(
+            "function f\n  disp = 1;\n  disp$0(1);\nend\nfunction g\n  disp(1);\nend",
+            expect![[r#"
+                Variable { name: "disp", scope: 0..38 }
+                Write "disp" 13..17
+                Read "disp" 25..29
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Checks for renaming a variable or a function.
+//!
+//! The edits themselves are the [references](crate::references) of the target. What is left here
+//! is making sure the new name is a valid one and does not change what any name refers to.
+
+use crate::references::{self, Target, innermost_scope};
+use crate::{Reference, ReferenceKind};
+use ast::TypedSyntaxNode;
+use lexer::{Lexer, TokenKind};
+use std::fmt;
+use text_size::{TextRange, TextSize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameError {
+    /// There is no variable or function at the cursor.
+    NothingToRename,
+    InvalidName(String),
+    Keyword(String),
+    /// The new name is already taken by a variable or function that would be shadowed, or would
+    /// shadow the target.
+    Conflict {
+        name: String,
+        kind: ConflictKind,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    Variable,
+    Function,
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NothingToRename => write!(f, "there is nothing to rename here"),
+            Self::InvalidName(name) => write!(f, "`{name}` is not a valid name"),
+            Self::Keyword(name) => write!(f, "`{name}` is a keyword"),
+            Self::Conflict {
+                name,
+                kind: ConflictKind::Variable,
+            } => write!(f, "there is already a variable named `{name}`"),
+            Self::Conflict {
+                name,
+                kind: ConflictKind::Function,
+            } => write!(f, "there is already a function named `{name}`"),
+        }
+    }
+}
+
+/// Returns what would be renamed at `offset`, and the range of the name there.
+///
+/// # Errors
+///
+/// Fails if there is no variable or function at `offset`.
+pub fn prepare_rename(
+    root: &ast::Root,
+    offset: TextSize,
+) -> Result<(Target, TextRange), RenameError> {
+    let target = references::target_at(root, offset).ok_or(RenameError::NothingToRename)?;
+    let range = references::references(root, &target)
+        .into_iter()
+        .map(|reference| reference.range)
+        .find(|range| range.contains_inclusive(offset))
+        .ok_or(RenameError::NothingToRename)?;
+    Ok((target, range))
+}
+
+/// Checks that `name` is an identifier that is not a keyword.
+///
+/// # Errors
+///
+/// Fails with the reason the name cannot be used.
+pub fn check_name(name: &str) -> Result<(), RenameError> {
+    let tokens: Vec<_> = Lexer::new(name).collect();
+    match tokens.as_slice() {
+        [token] if token.text == name && token.kind == TokenKind::Identifier => Ok(()),
+        [token] if token.text == name && token.kind.is_keyword_statement() => {
+            Err(RenameError::Keyword(name.to_string()))
+        }
+        _ => Err(RenameError::InvalidName(name.to_string())),
+    }
+}
+
+/// Checks that renaming `target` to `new_name` does not change what any name in the file refers
+/// to. Whether other files define a function named `new_name` is up to the caller.
+///
+/// # Errors
+///
+/// Fails with the name that would be shadowed.
+pub fn check_conflicts(
+    root: &ast::Root,
+    target: &Target,
+    new_name: &str,
+) -> Result<(), RenameError> {
+    let conflict = |kind| {
+        Err(RenameError::Conflict {
+            name: new_name.to_string(),
+            kind,
+        })
+    };
+    let scopes = hir::scope::scopes(root);
+
+    match target {
+        Target::Variable { scope, .. } => {
+            let Some(scope) = scopes.iter().find(|candidate| candidate.range == *scope) else {
+                return Ok(());
+            };
+            if references::binds(scope, new_name) {
+                return conflict(ConflictKind::Variable);
+            }
+            // A call to a function with the new name would turn into a use of the variable.
+            let new_target = Target::Function {
+                name: new_name.into(),
+            };
+            let calls_in_scope = references::references(root, &new_target)
+                .into_iter()
+                .filter(is_use)
+                .any(|reference| {
+                    innermost_scope(&scopes, reference.range.start()).range == scope.range
+                });
+            if calls_in_scope {
+                return conflict(ConflictKind::Function);
+            }
+        }
+        Target::Function { .. } => {
+            let is_local_function = root
+                .syntax()
+                .descendants()
+                .filter_map(ast::FnDef::cast)
+                .filter_map(|fn_def| fn_def.name())
+                .any(|name| name.text() == new_name);
+            if is_local_function {
+                return conflict(ConflictKind::Function);
+            }
+            // A call to the renamed function would turn into a use of a variable.
+            let shadowed = references::references(root, target)
+                .into_iter()
+                .filter(is_use)
+                .any(|reference| {
+                    references::binds(innermost_scope(&scopes, reference.range.start()), new_name)
+                });
+            if shadowed {
+                return conflict(ConflictKind::Variable);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_use(reference: &Reference) -> bool {
+    matches!(reference.kind, ReferenceKind::Call | ReferenceKind::Handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(text: &str) -> ast::Root {
+        ast::Root::cast(parser::parse(text).syntax()).unwrap()
+    }
+
+    fn rename_check(text: &str, new_name: &str) -> Result<(), RenameError> {
+        let offset = text.find("$0").unwrap();
+        let text = text.replace("$0", "");
+        let root = root(&text);
+        let (target, _) = prepare_rename(&root, TextSize::try_from(offset).unwrap())?;
+        check_name(new_name)?;
+        check_conflicts(&root, &target, new_name)
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(check_name("total_2"), Ok(()));
+        assert_eq!(
+            check_name("end"),
+            Err(RenameError::Keyword("end".to_string()))
+        );
+        assert_eq!(
+            check_name("2x"),
+            Err(RenameError::InvalidName("2x".to_string()))
+        );
+        assert_eq!(
+            check_name("a b"),
+            Err(RenameError::InvalidName("a b".to_string()))
+        );
+        assert_eq!(check_name(""), Err(RenameError::InvalidName(String::new())));
+    }
+
+    #[test]
+    fn prepare_gives_the_range_under_the_cursor() {
+        let root = root("x = 1;\ny = x;");
+        let (target, range) = prepare_rename(&root, TextSize::from(12)).unwrap();
+        assert_eq!(target.name(), "x");
+        assert_eq!(range, TextRange::new(11.into(), 12.into()));
+        assert_eq!(
+            prepare_rename(&root, TextSize::from(8)).map(|(_, range)| range),
+            Ok(TextRange::new(7.into(), 8.into()))
+        );
+        assert_eq!(
+            prepare_rename(&root, TextSize::from(3)),
+            Err(RenameError::NothingToRename)
+        );
+    }
+
+    #[test]
+    fn variable_conflicts() {
+        assert_eq!(rename_check("a$0 = 1;\nb = 2;\nc = a;", "d"), Ok(()));
+        assert_eq!(
+            rename_check("a$0 = 1;\nb = 2;\nc = a;", "b"),
+            Err(RenameError::Conflict {
+                name: "b".to_string(),
+                kind: ConflictKind::Variable
+            })
+        );
+        assert_eq!(
+            rename_check("a$0 = 1;\ndisp(a);", "disp"),
+            Err(RenameError::Conflict {
+                name: "disp".to_string(),
+                kind: ConflictKind::Function
+            })
+        );
+        // Other functions have workspaces of their own.
+        assert_eq!(
+            rename_check("a$0 = 1;\nfunction f\n  b = 1;\nend", "b"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn function_conflicts() {
+        let text = "function main\n  x = 1;\n  hel$0per(x);\nend\nfunction helper(y)\nend\nfunction other\nend";
+        assert_eq!(rename_check(text, "assist"), Ok(()));
+        assert_eq!(
+            rename_check(text, "other"),
+            Err(RenameError::Conflict {
+                name: "other".to_string(),
+                kind: ConflictKind::Function
+            })
+        );
+        assert_eq!(
+            rename_check(text, "x"),
+            Err(RenameError::Conflict {
+                name: "x".to_string(),
+                kind: ConflictKind::Variable
+            })
+        );
+        // The parameter `y` is in a different scope from the call.
+        assert_eq!(rename_check(text, "y"), Ok(()));
+    }
+}
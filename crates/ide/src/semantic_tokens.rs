@@ -0,0 +1,568 @@
+//! What each name is, for editors to color.
+//!
+//! A `TextMate` grammar only sees the text, so it cannot tell `foo(1)` indexing a variable from
+//! `foo(1)` calling a function. The scopes of the HIR can.
+
+use crate::references::binds;
+use ast::TypedSyntaxNode;
+use catalogue::Catalogue;
+use hir::scope::Scope;
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use syntax::{SyntaxConstruct, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::TextRange;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub range: TextRange,
+    pub kind: SemanticTokenKind,
+    pub modifiers: Vec<SemanticTokenModifier>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticTokenKind {
+    Comment,
+    Number,
+    /// A string, or a word of a command such as `on` in `hold on`.
+    String,
+    Keyword,
+    Operator,
+    Variable,
+    Parameter,
+    Function,
+    /// The field of a struct, as `name` in `s.name`.
+    Property,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticTokenModifier {
+    /// Where a function, parameter or output is defined.
+    Declaration,
+    /// `end` inside an index, which cannot be assigned.
+    Readonly,
+    /// A function of Octave or of one of its packages.
+    DefaultLibrary,
+    /// A variable declared `global`.
+    Global,
+    /// A variable declared `persistent`.
+    Static,
+}
+
+impl SemanticTokenKind {
+    pub const ALL: [Self; 9] = [
+        Self::Comment,
+        Self::Number,
+        Self::String,
+        Self::Keyword,
+        Self::Operator,
+        Self::Variable,
+        Self::Parameter,
+        Self::Function,
+        Self::Property,
+    ];
+}
+
+impl SemanticTokenModifier {
+    pub const ALL: [Self; 5] = [
+        Self::Declaration,
+        Self::Readonly,
+        Self::DefaultLibrary,
+        Self::Global,
+        Self::Static,
+    ];
+}
+
+/// Returns the tokens of the file to color, in source order, leaving out whitespace and
+/// punctuation. With a `range`, only the tokens that overlap it are returned. Builtins are those
+/// of `catalogue`.
+#[must_use]
+pub fn semantic_tokens(
+    root: &ast::Root,
+    catalogue: &Catalogue,
+    range: Option<TextRange>,
+) -> Vec<SemanticToken> {
+    let scopes = hir::scope::scopes(root);
+    let highlighter = Highlighter {
+        catalogue,
+        declarations: declarations(root, &scopes),
+        command_words: command_words(root.syntax(), &scopes),
+        block_comments: crate::folding_ranges::block_comments(root.syntax()),
+        scopes,
+    };
+
+    let mut tokens: Vec<SemanticToken> = Vec::new();
+    for token in root
+        .syntax()
+        .descendants_with_tokens()
+        .filter_map(syntax::SyntaxElement::into_token)
+    {
+        let overlaps = |range: TextRange| {
+            range
+                .intersect(token.text_range())
+                .is_some_and(|overlap| !overlap.is_empty())
+        };
+        if range.is_some_and(|range| !overlaps(range)) {
+            continue;
+        }
+        let Some(mut colored) = highlighter.token(&token) else {
+            continue;
+        };
+        if let Some(range) = range {
+            colored.range = colored.range.intersect(range).unwrap_or(colored.range);
+        }
+        // Strings, command words and block comments cover several tokens, but are colored once.
+        if tokens
+            .last()
+            .is_none_or(|last| last.range.end() <= colored.range.start())
+        {
+            tokens.push(colored);
+        }
+    }
+    tokens
+}
+
+struct Highlighter<'a> {
+    catalogue: &'a Catalogue,
+    scopes: Vec<Scope>,
+    /// The variables declared `global` (true) or `persistent` (false), by scope.
+    declarations: HashMap<(TextRange, SmolStr), bool>,
+    /// The words of commands, as `on` in `hold on`.
+    command_words: Vec<TextRange>,
+    /// The `%{ ... %}` block comments, whose lines are not code even if they lex as such.
+    block_comments: Vec<TextRange>,
+}
+
+impl Highlighter<'_> {
+    fn token(&self, token: &SyntaxToken) -> Option<SemanticToken> {
+        let SyntaxKind::LexToken(kind) = token.kind() else {
+            return None;
+        };
+        let parent = token.parent()?;
+        let plain = |kind| {
+            Some(SemanticToken {
+                range: token.text_range(),
+                kind,
+                modifiers: Vec::new(),
+            })
+        };
+
+        if let Some(&block) = self
+            .block_comments
+            .iter()
+            .find(|block| block.contains_range(token.text_range()))
+        {
+            return Some(SemanticToken {
+                range: block,
+                kind: SemanticTokenKind::Comment,
+                modifiers: Vec::new(),
+            });
+        }
+        if kind == TokenKind::Comment {
+            return plain(SemanticTokenKind::Comment);
+        }
+        if kind.is_trivia() {
+            return None;
+        }
+        if let Some(string) = ast::StringLiteral::cast(parent.clone()) {
+            return Some(SemanticToken {
+                range: trimmed_range(string.syntax()),
+                kind: SemanticTokenKind::String,
+                modifiers: Vec::new(),
+            });
+        }
+        if let Some(&word) = self
+            .command_words
+            .iter()
+            .find(|word| word.contains_range(token.text_range()))
+        {
+            return Some(SemanticToken {
+                range: word,
+                kind: SemanticTokenKind::String,
+                modifiers: Vec::new(),
+            });
+        }
+
+        match kind {
+            TokenKind::Number => plain(SemanticTokenKind::Number),
+            TokenKind::EndKw if ast::EndIndex::cast(parent.clone()).is_some() => {
+                Some(SemanticToken {
+                    range: token.text_range(),
+                    kind: SemanticTokenKind::Variable,
+                    modifiers: vec![
+                        SemanticTokenModifier::Readonly,
+                        SemanticTokenModifier::DefaultLibrary,
+                    ],
+                })
+            }
+            _ if kind.is_keyword_statement() => plain(SemanticTokenKind::Keyword),
+            TokenKind::Identifier => Some(self.identifier(token, &parent)),
+            _ if is_operator(kind) => plain(SemanticTokenKind::Operator),
+            _ => None,
+        }
+    }
+
+    fn identifier(&self, token: &SyntaxToken, parent: &SyntaxNode) -> SemanticToken {
+        let name = token.text();
+        let (kind, modifiers) = if ast::FieldExpr::cast(parent.clone()).is_some() {
+            (SemanticTokenKind::Property, Vec::new())
+        } else if ast::FnHandle::cast(parent.clone()).is_some() {
+            (SemanticTokenKind::Function, self.function_modifiers(name))
+        } else if let Some(fn_def) = header_of(token) {
+            let scope = scope_of(&self.scopes, token.text_range());
+            let kind = if fn_def.name().as_ref() == Some(token) {
+                SemanticTokenKind::Function
+            } else if scope.params.iter().any(|param| param.name == name) {
+                SemanticTokenKind::Parameter
+            } else {
+                SemanticTokenKind::Variable
+            };
+            (kind, vec![SemanticTokenModifier::Declaration])
+        } else {
+            let scope = scope_of(&self.scopes, token.text_range());
+            if scope.params.iter().any(|param| param.name == name) {
+                (SemanticTokenKind::Parameter, Vec::new())
+            } else if binds(scope, name) {
+                let modifiers = match self.declarations.get(&(scope.range, name.into())) {
+                    Some(true) => vec![SemanticTokenModifier::Global],
+                    Some(false) => vec![SemanticTokenModifier::Static],
+                    None => Vec::new(),
+                };
+                (SemanticTokenKind::Variable, modifiers)
+            } else {
+                (SemanticTokenKind::Function, self.function_modifiers(name))
+            }
+        };
+        SemanticToken {
+            range: token.text_range(),
+            kind,
+            modifiers,
+        }
+    }
+
+    /// Functions of the catalogue are from the default library, unless the file defines its own.
+    fn function_modifiers(&self, name: &str) -> Vec<SemanticTokenModifier> {
+        let is_local = self
+            .scopes
+            .iter()
+            .any(|scope| scope.name.as_deref() == Some(name));
+        if !is_local && self.catalogue.contains(name) {
+            vec![SemanticTokenModifier::DefaultLibrary]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// The scope of the innermost function containing `range`, or the script scope.
+fn scope_of(scopes: &[Scope], range: TextRange) -> &Scope {
+    scopes
+        .iter()
+        .filter(|scope| scope.range.contains_range(range))
+        .min_by_key(|scope| (scope.range.len(), !scope.is_function()))
+        .unwrap_or(&scopes[0])
+}
+
+/// The function whose header `token` is part of, if any.
+fn header_of(token: &SyntaxToken) -> Option<ast::FnDef> {
+    let fn_def = token.parent_ancestors().find_map(ast::FnDef::cast)?;
+    let header = fn_def.header()?;
+    header
+        .syntax()
+        .text_range()
+        .contains_range(token.text_range())
+        .then_some(fn_def)
+}
+
+/// The variables declared by `global` and `persistent`, by the range of their scope.
+fn declarations(root: &ast::Root, scopes: &[Scope]) -> HashMap<(TextRange, SmolStr), bool> {
+    let mut declarations = HashMap::new();
+    for declaration in root
+        .syntax()
+        .descendants()
+        .filter_map(ast::Declaration::cast)
+    {
+        let scope = scope_of(scopes, declaration.syntax().text_range());
+        for name in declaration
+            .variables()
+            .iter()
+            .filter_map(ast::VariableRef::name)
+        {
+            declarations.insert((scope.range, name.text().into()), declaration.is_global());
+        }
+    }
+    declarations
+}
+
+/// The words of command syntax, as `on` in `hold on`: the statements on the same line as a bare
+/// name that is not a variable.
+fn command_words(root: &SyntaxNode, scopes: &[Scope]) -> Vec<TextRange> {
+    let mut words = Vec::new();
+    for var in root.descendants().filter_map(ast::VariableRef::cast) {
+        let in_expr = var.syntax().parent().is_some_and(|parent| {
+            parent.kind() != SyntaxConstruct::Root.into() && ast::Expr::cast(parent).is_some()
+        });
+        let is_stmt = !in_expr && starts_statement(var.syntax());
+        let Some(name) = var.name().filter(|_| is_stmt) else {
+            continue;
+        };
+        if binds(scope_of(scopes, name.text_range()), name.text()) {
+            continue;
+        }
+        let mut previous = var.syntax().clone();
+        while !ends_statement(&previous) {
+            let Some(next) = previous.next_sibling() else {
+                break;
+            };
+            words.push(trimmed_range(&next));
+            previous = next;
+        }
+    }
+    words
+}
+
+/// Whether the node comes first on a line, or after a `;` or `,`.
+fn starts_statement(node: &SyntaxNode) -> bool {
+    let mut token = node.first_token().and_then(|token| token.prev_token());
+    while let Some(previous) = &token {
+        match previous.kind() {
+            SyntaxKind::LexToken(TokenKind::Whitespace | TokenKind::Comment) => {
+                token = previous.prev_token();
+            }
+            SyntaxKind::LexToken(TokenKind::Newline | TokenKind::Semicolon | TokenKind::Comma) => {
+                return true;
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Whether a newline, `;` or `,` ends the node, so that what follows is another statement. The
+/// separator may also come right after the node, outside of it.
+fn ends_statement(node: &SyntaxNode) -> bool {
+    let following = std::iter::successors(node.next_sibling_or_token(), |element| {
+        element.next_sibling_or_token()
+    })
+    .map_while(syntax::SyntaxElement::into_token);
+    let last = node
+        .descendants_with_tokens()
+        .filter_map(syntax::SyntaxElement::into_token)
+        .chain(following)
+        .filter(|token| {
+            !matches!(
+                token.kind(),
+                SyntaxKind::LexToken(TokenKind::Whitespace | TokenKind::Comment)
+            )
+        })
+        .last();
+    last.is_none_or(|token| {
+        matches!(
+            token.kind(),
+            SyntaxKind::LexToken(TokenKind::Newline | TokenKind::Semicolon | TokenKind::Comma)
+        )
+    })
+}
+
+/// The range of a node without its trailing trivia and separators.
+fn trimmed_range(node: &SyntaxNode) -> TextRange {
+    let end = node
+        .descendants_with_tokens()
+        .filter_map(syntax::SyntaxElement::into_token)
+        .filter(|token| {
+            !matches!(token.kind(), SyntaxKind::LexToken(kind)
+                if kind.is_trivia() || kind == TokenKind::Semicolon || kind == TokenKind::Comma)
+        })
+        .last()
+        .map_or(node.text_range().start(), |token| token.text_range().end());
+    TextRange::new(node.text_range().start(), end)
+}
+
+fn is_operator(kind: TokenKind) -> bool {
+    (TokenKind::Plus..=TokenKind::Colon).contains(&kind) && kind != TokenKind::At
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(input: &str, expected: Expect) {
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let mut actual = String::new();
+        for token in semantic_tokens(&root, &Catalogue::bundled(), None) {
+            let _ = write!(actual, "{:?} {:?}", &input[token.range], token.kind);
+            for modifier in &token.modifiers {
+                let _ = write!(actual, " {modifier:?}");
+            }
+            actual.push('\n');
+        }
+        expected.assert_eq(&actual);
+    }
+
+    #[test]
+    fn variables_and_calls() {
+        check(
+            "x = 1;\ny = x(2) + f(3);",
+            expect![[r#"
+            "x" Variable
+            "=" Operator
+            "1" Number
+            "y" Variable
+            "=" Operator
+            "x" Variable
+            "2" Number
+            "+" Operator
+            "f" Function
+            "3" Number
+        "#]],
+        );
+    }
+
+    #[test]
+    fn block_comments_are_one_comment() {
+        check(
+            "%{\n  kept x\n%}\ny = 1;\n",
+            expect![[r#"
+                "%{\n  kept x\n%}" Comment
+                "y" Variable
+                "=" Operator
+                "1" Number
+            "#]],
+        );
+    }
+
+    #[test]
+    fn functions_and_parameters() {
+        check(
+            "function r = f(a)\n  % doubles\n  r = a * 2;\nend\nb = f(zeros(1));",
+            expect![[r#"
+                "function" Keyword
+                "r" Variable Declaration
+                "=" Operator
+                "f" Function Declaration
+                "a" Parameter Declaration
+                "% doubles" Comment
+                "r" Variable
+                "=" Operator
+                "a" Parameter
+                "*" Operator
+                "2" Number
+                "end" Keyword
+                "b" Variable
+                "=" Operator
+                "f" Function
+                "zeros" Function DefaultLibrary
+                "1" Number
+            "#]],
+        );
+    }
+
+    #[test]
+    fn globals_and_persistents() {
+        check(
+            "function r = count\n  global total\n  persistent n\n  r = total + n;\nend",
+            expect![[r#"
+                "function" Keyword
+                "r" Variable Declaration
+                "=" Operator
+                "count" Function Declaration
+                "global" Keyword
+                "total" Variable Global
+                "persistent" Keyword
+                "n" Variable Static
+                "r" Variable
+                "=" Operator
+                "total" Variable Global
+                "+" Operator
+                "n" Variable Static
+                "end" Keyword
+            "#]],
+        );
+    }
+
+    #[test]
+    fn transposes_and_strings() {
+        check(
+            "x = 1;\ny = x';\ns = 'it''s';",
+            expect![[r#"
+            "x" Variable
+            "=" Operator
+            "1" Number
+            "y" Variable
+            "=" Operator
+            "x" Variable
+            "'" Operator
+            "s" Variable
+            "=" Operator
+            "'it''s'" String
+        "#]],
+        );
+    }
+
+    #[test]
+    fn end_in_index() {
+        check(
+            "x = 1;\ny = x(end - 1);",
+            expect![[r#"
+            "x" Variable
+            "=" Operator
+            "1" Number
+            "y" Variable
+            "=" Operator
+            "x" Variable
+            "end" Variable Readonly DefaultLibrary
+            "-" Operator
+            "1" Number
+        "#]],
+        );
+    }
+
+    #[test]
+    fn command_words() {
+        check(
+            "hold on\nformat long; x = 1;\nx y",
+            expect![[r#"
+            "hold" Function DefaultLibrary
+            "on" String
+            "format" Function DefaultLibrary
+            "long" String
+            "x" Variable
+            "=" Operator
+            "1" Number
+            "x" Variable
+            "y" Function
+        "#]],
+        );
+    }
+
+    #[test]
+    fn fields_and_handles() {
+        check(
+            "s.name = 1;\nh = @sin;",
+            expect![[r#"
+            "s" Variable
+            "name" Property
+            "=" Operator
+            "1" Number
+            "h" Variable
+            "=" Operator
+            "sin" Function DefaultLibrary
+        "#]],
+        );
+    }
+
+    #[test]
+    fn range() {
+        let input = "x = 1;\ny = x + 2;";
+        let root = ast::Root::cast(parser::parse(input).syntax()).unwrap();
+        let tokens = semantic_tokens(
+            &root,
+            &Catalogue::bundled(),
+            Some(TextRange::new(7.into(), 11.into())),
+        );
+        let texts: Vec<_> = tokens.iter().map(|token| &input[token.range]).collect();
+        assert_eq!(texts, ["y", "="]);
+    }
+}
@@ -0,0 +1,507 @@
+//! The parameters of the function being called, while its arguments are typed.
+
+use crate::references::{binds, innermost_scope};
+use ast::TypedSyntaxNode;
+use catalogue::Catalogue;
+use syntax::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, TokenKind};
+use text_size::{TextRange, TextSize};
+
+/// The parameter that takes any number of arguments, in user functions and in the catalogue.
+const VARIADIC: [&str; 2] = ["varargin", "..."];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHelp {
+    /// The ways the function can be called. Builtins often have several.
+    pub signatures: Vec<Signature>,
+    pub active_signature: usize,
+    /// The parameter the argument at the cursor goes to, if there is one.
+    pub active_parameter: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Such as `r = f(x, y)`.
+    pub label: String,
+    pub documentation: Option<String>,
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    /// Where the parameter is in the label of its signature.
+    pub range: TextRange,
+    pub documentation: Option<String>,
+}
+
+impl Signature {
+    /// The parameter that the argument at `index` goes to. A variadic parameter comes last and
+    /// takes all the remaining arguments.
+    fn parameter_for(&self, index: usize) -> Option<usize> {
+        let last = self.parameters.len().checked_sub(1)?;
+        let variadic = VARIADIC.contains(&&self.label[self.parameters[last].range]);
+        if variadic && index >= last {
+            Some(last)
+        } else {
+            (index <= last).then_some(index)
+        }
+    }
+}
+
+/// Returns the signatures of the function called around `offset`.
+///
+/// Functions are looked for in the file itself first, then in the file that `definition` returns
+/// for a name, if any, and finally in the `catalogue` of builtins.
+pub fn signature_help(
+    root: &ast::Root,
+    offset: TextSize,
+    catalogue: &Catalogue,
+    definition: impl FnOnce(&str) -> Option<ast::Root>,
+) -> Option<SignatureHelp> {
+    let (call, name) = call_at(root, offset)?;
+    let argument = call
+        .syntax()
+        .children_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .filter(|token| token.kind() == TokenKind::Comma.into())
+        .take_while(|comma| comma.text_range().end() <= offset)
+        .count();
+
+    let signatures = match user_signature(root, &name) {
+        Some(signature) => vec![signature],
+        None => match definition(&name).and_then(|other| user_signature(&other, &name)) {
+            Some(signature) => vec![signature],
+            None => builtin_signatures(catalogue, &name),
+        },
+    };
+    if signatures.is_empty() {
+        return None;
+    }
+
+    // The first signature that takes this many arguments.
+    let active_signature = signatures
+        .iter()
+        .position(|signature| signature.parameter_for(argument).is_some())
+        .unwrap_or(0);
+    let active_parameter = signatures[active_signature].parameter_for(argument);
+    Some(SignatureHelp {
+        signatures,
+        active_signature,
+        active_parameter,
+    })
+}
+
+/// The innermost call whose argument list holds `offset`, and the name of the function called.
+/// Indexing into a variable is no call.
+fn call_at(root: &ast::Root, offset: TextSize) -> Option<(ast::CallExpr, String)> {
+    if !root.syntax().text_range().contains_inclusive(offset) {
+        return None;
+    }
+    let mut token = root.syntax().token_at_offset(offset).left_biased()?;
+    while is_trivia(&token) {
+        token = token.prev_token()?;
+    }
+
+    let call = token
+        .parent_ancestors()
+        .filter_map(ast::CallExpr::cast)
+        .find(|call| {
+            let paren = |kind: TokenKind| child_token(call.syntax(), kind);
+            let opened =
+                paren(TokenKind::LParen).is_some_and(|open| open.text_range().end() <= offset);
+            let closed =
+                paren(TokenKind::RParen).is_some_and(|close| close.text_range().start() < offset);
+            opened && !closed
+        })?;
+
+    let ast::Expr::VariableRef(callee) = call.func()? else {
+        return None;
+    };
+    let name = callee.name()?;
+    let scopes = hir::scope::scopes(root);
+    if binds(
+        innermost_scope(&scopes, name.text_range().start()),
+        name.text(),
+    ) {
+        return None;
+    }
+    Some((call, name.text().to_string()))
+}
+
+fn is_trivia(token: &SyntaxToken) -> bool {
+    matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia())
+}
+
+fn child_token(node: &SyntaxNode, kind: TokenKind) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .find(|token| token.kind() == kind.into())
+}
+
+/// The signature of the function `name` defined in a file, from its header and help text.
+pub(crate) fn user_signature(root: &ast::Root, name: &str) -> Option<Signature> {
+    let fn_def = root
+        .syntax()
+        .descendants()
+        .filter_map(ast::FnDef::cast)
+        .find(|fn_def| fn_def.name().is_some_and(|fn_name| fn_name.text() == name))?;
+
+    let collapse = |node: &SyntaxNode| {
+        let text = node.text().to_string();
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    let mut label = String::new();
+    let call = match fn_def.header()? {
+        ast::Expr::BinaryExpr(header) => {
+            if let Some(outputs) = header.lhs() {
+                label.push_str(&collapse(outputs.syntax()));
+                label.push_str(" = ");
+            }
+            header.rhs()
+        }
+        header => Some(header),
+    };
+    label.push_str(name);
+    label.push('(');
+
+    let help = help_text(&fn_def);
+    let mut parameters = Vec::new();
+    let args = match call {
+        Some(ast::Expr::CallExpr(call)) => call.args().collect(),
+        _ => Vec::new(),
+    };
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+        let text = collapse(arg.syntax());
+        let start = TextSize::of(label.as_str());
+        label.push_str(&text);
+        parameters.push(Parameter {
+            range: TextRange::at(start, TextSize::of(text.as_str())),
+            documentation: help.as_deref().and_then(|help| parameter_help(help, &text)),
+        });
+    }
+    label.push(')');
+
+    Some(Signature {
+        label,
+        documentation: help.map(|help| first_paragraph(&help)),
+        parameters,
+    })
+}
+
+/// The signatures of a builtin, one for each way of calling it listed in the catalogue.
+pub(crate) fn builtin_signatures(catalogue: &Catalogue, name: &str) -> Vec<Signature> {
+    let Some(function) = catalogue.get(name) else {
+        return Vec::new();
+    };
+    function
+        .usages
+        .iter()
+        .map(|usage| Signature {
+            label: usage.clone(),
+            documentation: Some(function.documentation()),
+            parameters: usage_parameters(usage, name),
+        })
+        .collect()
+}
+
+/// Finds the parameters in a usage such as `[s, i] = sort (x, dim)`.
+fn usage_parameters(usage: &str, name: &str) -> Vec<Parameter> {
+    let Some(open) = usage
+        .match_indices(name)
+        .map(|(start, _)| start + name.len())
+        .find_map(|end| {
+            let rest = &usage[end..];
+            let skipped = rest.len() - rest.trim_start().len();
+            rest.trim_start()
+                .starts_with('(')
+                .then_some(end + skipped + 1)
+        })
+    else {
+        return Vec::new();
+    };
+    let Some(close) = usage.rfind(')').filter(|&close| close >= open) else {
+        return Vec::new();
+    };
+
+    let mut parameters = Vec::new();
+    let mut depth = 0usize;
+    let mut start = open;
+    for (i, ch) in usage[open..close]
+        .char_indices()
+        .map(|(i, ch)| (open + i, ch))
+    {
+        match ch {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parameters.extend(parameter_in(usage, start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parameters.extend(parameter_in(usage, start, close));
+    parameters
+}
+
+/// The parameter between `start` and `end` of a usage, without the spaces around it.
+fn parameter_in(usage: &str, start: usize, end: usize) -> Option<Parameter> {
+    let text = &usage[start..end];
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let start = start + (text.len() - text.trim_start().len());
+    Some(Parameter {
+        range: TextRange::at(TextSize::try_from(start).ok()?, TextSize::of(trimmed)),
+        documentation: None,
+    })
+}
+
+/// The help text of a function: the comments right after its header or, failing that, right
+/// before the function.
+fn help_text(fn_def: &ast::FnDef) -> Option<String> {
+    let after = fn_def
+        .header()
+        .and_then(|header| {
+            // The header holds the trivia after it, help text included.
+            let mut last = header.syntax().last_token()?;
+            while is_trivia(&last) {
+                last = last.prev_token()?;
+            }
+            Some(last)
+        })
+        .map(|last| comment_block(&last, SyntaxToken::next_token))
+        .filter(|lines| !lines.is_empty());
+    let lines = after.or_else(|| {
+        let first = fn_def.syntax().first_token()?;
+        let mut lines = comment_block(&first, SyntaxToken::prev_token);
+        lines.reverse();
+        Some(lines)
+    })?;
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// The text of the comments next to `from`, going one way with `step`, up to the first blank
+/// line or code.
+fn comment_block(from: &SyntaxToken, step: fn(&SyntaxToken) -> Option<SyntaxToken>) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut newlines = 0;
+    let mut token = step(from);
+    while let Some(current) = token {
+        match current.kind() {
+            SyntaxKind::LexToken(TokenKind::Comment) => {
+                newlines = 0;
+                let text = current.text().trim_start_matches(['%', '#']);
+                lines.push(
+                    text.strip_prefix(' ')
+                        .unwrap_or(text)
+                        .trim_end()
+                        .to_string(),
+                );
+            }
+            SyntaxKind::LexToken(TokenKind::Newline) => {
+                newlines += 1;
+                if newlines > 1 {
+                    break;
+                }
+            }
+            SyntaxKind::LexToken(TokenKind::Whitespace) => {}
+            _ => break,
+        }
+        token = step(&current);
+    }
+    lines
+}
+
+/// The text up to the first blank line.
+fn first_paragraph(help: &str) -> String {
+    help.lines()
+        .take_while(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What the help text says about a parameter, in a line such as `x - the input` or `x: the
+/// input`, along with the more indented lines that follow it.
+fn parameter_help(help: &str, name: &str) -> Option<String> {
+    let mut lines = help.lines();
+    let (indent, first) = lines.find_map(|line| {
+        let trimmed = line.trim_start();
+        let rest = trimmed.strip_prefix(name)?.trim_start();
+        let rest = rest.strip_prefix(['-', ':'])?;
+        Some((line.len() - trimmed.len(), rest.trim().to_string()))
+    })?;
+
+    let mut documentation = vec![first];
+    for line in lines {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || line.len() - trimmed.len() <= indent {
+            break;
+        }
+        documentation.push(trimmed.to_string());
+    }
+    Some(documentation.join(" ").trim().to_string()).filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+    use std::fmt::Write;
+
+    /// Shows the signatures for the call at the `$0` marker, marking the active ones with `>`
+    /// and the active parameter with brackets.
+    #[allow(clippy::needless_pass_by_value)]
+    fn check_with(input: &str, other_file: Option<&str>, expected: Expect) {
+        let offset = input.find("$0").unwrap();
+        let input = input.replace("$0", "");
+        let parse = |text: &str| ast::Root::cast(parser::parse(text).syntax()).unwrap();
+        let root = parse(&input);
+        let help = signature_help(
+            &root,
+            TextSize::try_from(offset).unwrap(),
+            &Catalogue::bundled(),
+            |_| other_file.map(parse),
+        );
+        let Some(help) = help else {
+            expected.assert_eq("None");
+            return;
+        };
+
+        let mut actual = String::new();
+        for (i, signature) in help.signatures.iter().enumerate() {
+            let is_active = i == help.active_signature;
+            let mut label = signature.label.clone();
+            if is_active && let Some(parameter) = help.active_parameter {
+                let range = signature.parameters[parameter].range;
+                label.insert(usize::from(range.end()), ']');
+                label.insert(usize::from(range.start()), '[');
+            }
+            let marker = if is_active { ">" } else { " " };
+            let _ = writeln!(actual, "{marker} {label}");
+            if is_active {
+                if let Some(documentation) = &signature.documentation {
+                    let _ = writeln!(actual, "  {documentation:?}");
+                }
+                for parameter in &signature.parameters {
+                    if let Some(documentation) = &parameter.documentation {
+                        let name = &signature.label[parameter.range];
+                        let _ = writeln!(actual, "  {name}: {documentation:?}");
+                    }
+                }
+            }
+        }
+        expected.assert_eq(&actual);
+    }
+
+    fn check(input: &str, expected: Expect) {
+        check_with(input, None, expected);
+    }
+
+    #[test]
+    fn user_functions() {
+        check(
+            "y = scale(1, $0);\nfunction r = scale(x, factor)\n  % Scales x.\n  %\n  % x - the values\n  % factor: how much, which\n  %   may be negative\n  r = x * factor;\nend",
+            expect![[r#"
+                > r = scale(x, [factor])
+                  "Scales x."
+                  x: "the values"
+                  factor: "how much, which may be negative"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn help_before_the_function() {
+        check(
+            "% Greets.\nfunction greet(name)\nend\ngreet($0)",
+            expect![[r#"
+                > greet([name])
+                  "Greets."
+            "#]],
+        );
+    }
+
+    #[test]
+    fn varargin_takes_the_rest() {
+        check(
+            "function out = f(a, varargin)\nend\nf(1, 2, 3$0)",
+            expect![[r"
+                > out = f(a, [varargin])
+            "]],
+        );
+        check(
+            "function f(a)\nend\nf(1, 2$0)",
+            expect![[r"
+                > f(a)
+            "]],
+        );
+    }
+
+    #[test]
+    fn functions_of_other_files() {
+        check_with(
+            "plot_all(x, $0)",
+            Some("function plot_all(data, style)\nend"),
+            expect![[r"
+                > plot_all(data, [style])
+            "]],
+        );
+    }
+
+    #[test]
+    fn builtins() {
+        check(
+            "z = zeros(3, $0",
+            expect![[r#"
+                  val = zeros (n)
+                > val = zeros (m, [n])
+                  "Return a matrix or N-dimensional array whose elements are all 0."
+                  val = zeros (m, n, k, ...)
+                  val = zeros ([m n ...])
+                  val = zeros (..., "like", var)
+                  val = zeros (..., class)
+            "#]],
+        );
+        check(
+            "printf(template, 1, 2$0)",
+            expect![[r#"
+                > printf (template, [...])
+                  "Print optional arguments under the control of the template string template to the stream stdout and return the number of characters printed."
+            "#]],
+        );
+    }
+
+    #[test]
+    fn innermost_call() {
+        check(
+            "disp(zeros($0))",
+            expect![[r#"
+                > val = zeros ([n])
+                  "Return a matrix or N-dimensional array whose elements are all 0."
+                  val = zeros (m, n)
+                  val = zeros (m, n, k, ...)
+                  val = zeros ([m n ...])
+                  val = zeros (..., "like", var)
+                  val = zeros (..., class)
+            "#]],
+        );
+        check(
+            "disp(zeros(2), $0)",
+            expect![[r#"
+                > disp (x)
+                  "Display the value of x."
+                  str = disp (x)
+            "#]],
+        );
+    }
+
+    #[test]
+    fn indexing_is_no_call() {
+        check("x = 1:3;\ny = x($0", expect!["None"]);
+        check("y = unknown_function($0", expect!["None"]);
+    }
+}
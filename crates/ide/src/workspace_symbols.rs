@@ -0,0 +1,282 @@
+//! Search by name for the functions, scripts and classes of a whole workspace.
+//!
+//! Every `.m` file defines a function or is a script, named after the file. Function files may hold
+//! subfunctions, files in `@class` directories are methods of that class and files in `+package`
+//! directories belong to the package.
+
+use crate::{LineIndex, SymbolKind};
+use ast::TypedSyntaxNode;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use text_size::TextRange;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The class or package the symbol belongs to, or the function or script holding it.
+    pub container: Option<String>,
+    pub range: TextRange,
+}
+
+pub struct SearchResult<'a> {
+    pub path: &'a Path,
+    pub symbol: &'a WorkspaceSymbol,
+    pub line_index: &'a LineIndex,
+}
+
+/// The symbols of every file of the workspace, kept up to date as files change. Clones share the
+/// symbols of each file.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    files: HashMap<PathBuf, Arc<(Vec<WorkspaceSymbol>, LineIndex)>>,
+}
+
+impl SymbolIndex {
+    /// Adds a file, or replaces what was known about it.
+    pub fn insert(&mut self, path: PathBuf, text: &str) {
+        let symbols = file_symbols(&path, text);
+        self.files
+            .insert(path, Arc::new((symbols, LineIndex::new(text))));
+    }
+
+    /// Adds the files of another index, which replace those known here.
+    pub fn extend(&mut self, other: SymbolIndex) {
+        self.files.extend(other.files);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    #[must_use]
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Returns the symbols whose name contains the characters of `query` in order, ignoring
+    /// case, best matches first. An empty query matches everything.
+    #[must_use]
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult<'_>> {
+        let mut results: Vec<_> = self
+            .files
+            .iter()
+            .flat_map(|(path, file)| {
+                let (symbols, line_index) = &**file;
+                symbols.iter().filter_map(move |symbol| {
+                    let score = fuzzy_score(query, &symbol.name)?;
+                    Some((
+                        score,
+                        SearchResult {
+                            path,
+                            symbol,
+                            line_index,
+                        },
+                    ))
+                })
+            })
+            .collect();
+
+        results.sort_by(|(a_score, a), (b_score, b)| {
+            a_score
+                .cmp(b_score)
+                .then_with(|| a.symbol.name.len().cmp(&b.symbol.name.len()))
+                .then_with(|| a.symbol.name.cmp(&b.symbol.name))
+                .then_with(|| a.path.cmp(b.path))
+        });
+        results.truncate(limit);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+/// Returns the symbols a file defines.
+#[must_use]
+pub fn file_symbols(path: &Path, text: &str) -> Vec<WorkspaceSymbol> {
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return Vec::new();
+    };
+    let Some(root) = ast::Root::cast(parser::parse(text).syntax()) else {
+        return Vec::new();
+    };
+    let project::Placement { package, class, .. } = project::Placement::of(path);
+    let kind = project::FileKind::of(&root);
+    let start = TextRange::empty(0.into());
+
+    if kind == project::FileKind::Classdef {
+        return vec![WorkspaceSymbol {
+            name: stem.to_string(),
+            kind: SymbolKind::Class,
+            container: package,
+            range: start,
+        }];
+    }
+
+    let functions: Vec<_> = crate::document_symbols(&root, false)
+        .into_iter()
+        .flat_map(flatten)
+        .filter(|symbol| symbol.kind == SymbolKind::Function)
+        .collect();
+    let is_function_file = kind == project::FileKind::Function;
+
+    let mut symbols = Vec::new();
+    if !is_function_file {
+        symbols.push(WorkspaceSymbol {
+            name: stem.to_string(),
+            kind: SymbolKind::Script,
+            container: package.clone(),
+            range: start,
+        });
+    }
+    for (idx, function) in functions.into_iter().enumerate() {
+        let is_primary = is_function_file && idx == 0;
+        let (kind, container) = match &class {
+            Some(class) if is_primary && function.name == *class => {
+                (SymbolKind::Class, package.clone())
+            }
+            Some(class) if is_primary => (SymbolKind::Method, Some(class.clone())),
+            _ if is_primary => (SymbolKind::Function, package.clone()),
+            _ => (SymbolKind::Function, Some(stem.to_string())),
+        };
+        symbols.push(WorkspaceSymbol {
+            name: function.name,
+            kind,
+            container,
+            range: function.selection_range,
+        });
+    }
+    symbols
+}
+
+fn flatten(symbol: crate::Symbol) -> Vec<crate::Symbol> {
+    let mut children = Vec::new();
+    let mut symbol = symbol;
+    for child in std::mem::take(&mut symbol.children) {
+        children.extend(flatten(child));
+    }
+    std::iter::once(symbol).chain(children).collect()
+}
+
+/// Scores how well `name` matches `query`, lower being better: exact matches first, then prefixes,
+/// substrings and finally names that merely contain the characters of the query in order.
+pub(crate) fn fuzzy_score(query: &str, name: &str) -> Option<u8> {
+    let query = query.to_lowercase();
+    let name = name.to_lowercase();
+
+    if name == query {
+        Some(0)
+    } else if name.starts_with(&query) {
+        Some(1)
+    } else if name.contains(&query) {
+        Some(2)
+    } else {
+        let mut chars = name.chars();
+        query
+            .chars()
+            .all(|wanted| chars.any(|ch| ch == wanted))
+            .then_some(3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::{Expect, expect};
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn check(path: &str, text: &str, expected: Expect) {
+        let actual: Vec<_> = file_symbols(Path::new(path), text)
+            .into_iter()
+            .map(|symbol| {
+                format!(
+                    "{:?} {} in {:?} {:?}",
+                    symbol.kind, symbol.name, symbol.container, symbol.range
+                )
+            })
+            .collect();
+        expected.assert_eq(&actual.join("\n"));
+    }
+
+    #[test]
+    fn function_file_with_subfunctions() {
+        check(
+            "/ws/f.m",
+            "function y = f(x)\n  y = g(x);\nend\nfunction y = g(x)\n  y = x;\nend",
+            expect![[r#"
+                Function f in None 13..14
+                Function g in Some("f") 47..48"#]],
+        );
+    }
+
+    #[test]
+    fn script_file() {
+        check(
+            "/ws/run_all.m",
+            "1;\nfunction h\nend\nh();",
+            expect![[r#"
+            Script run_all in None 0..0
+            Function h in Some("run_all") 12..13"#]],
+        );
+    }
+
+    #[test]
+    fn class_directory() {
+        check(
+            "/ws/@point/point.m",
+            "function p = point(x)\n  p = x;\nend",
+            expect!["Class point in None 13..18"],
+        );
+        check(
+            "/ws/@point/norm.m",
+            "function n = norm(p)\n  n = p;\nend",
+            expect![[r#"Method norm in Some("point") 13..17"#]],
+        );
+    }
+
+    #[test]
+    fn package_directories() {
+        check(
+            "/ws/+geo/+shapes/area.m",
+            "function a = area(s)\n  a = s;\nend",
+            expect![[r#"Function area in Some("geo.shapes") 13..17"#]],
+        );
+        check(
+            "/ws/+geo/@circle/circle.m",
+            "function c = circle(r)\n  c = r;\nend",
+            expect![[r#"Class circle in Some("geo") 13..19"#]],
+        );
+    }
+
+    #[test]
+    fn classdef_file() {
+        check(
+            "/ws/Shape.m",
+            "classdef Shape < handle\nend",
+            expect!["Class Shape in None 0..0"],
+        );
+    }
+
+    #[test]
+    fn search_ranks_better_matches_first() {
+        let mut index = SymbolIndex::default();
+        index.insert("/ws/plot_results.m".into(), "function plot_results\nend");
+        index.insert("/ws/plot.m".into(), "function plot\nend");
+        index.insert("/ws/load_data.m".into(), "function load_data\nend");
+        index.insert("/ws/polyfit_all.m".into(), "function polyfit_all\nend");
+
+        let names = |index: &SymbolIndex, query| -> Vec<String> {
+            index
+                .search(query, 10)
+                .iter()
+                .map(|result| result.symbol.name.clone())
+                .collect()
+        };
+        assert_eq!(names(&index, "plot"), ["plot", "plot_results"]);
+        assert_eq!(names(&index, "pl"), ["plot", "plot_results", "polyfit_all"]);
+        assert_eq!(names(&index, "DATA"), ["load_data"]);
+        assert_eq!(names(&index, "").len(), 4);
+
+        index.remove(Path::new("/ws/plot.m"));
+        assert_eq!(names(&index, "plot"), ["plot_results"]);
+    }
+}
@@ -80,6 +80,8 @@ pub enum TokenKind {
     BreakKw,
     #[token("continue")]
     ContinueKw,
+    #[token("return")]
+    ReturnKw,
     #[token("unwind_protect")]
     UnwindProtectKw,
     #[token("unwind_protect_cleanup")]
@@ -92,6 +94,10 @@ pub enum TokenKind {
     CatchKw,
     #[token("end_try_catch")]
     EndTryKw,
+    #[token("global")]
+    GlobalKw,
+    #[token("persistent")]
+    PersistentKw,
     #[token("end")]
     EndKw,
 
@@ -169,6 +175,15 @@ pub enum TokenKind {
     #[token(",")]
     Comma,
 
+    /// A line continuation: `...` and whatever follows it up to the line break, which it joins
+    /// to the next line.
+    #[regex(r"\.\.\.[^\n]*")]
+    Continuation,
+
+    /// Field access, as in `s.name`.
+    #[token(".")]
+    Dot,
+
     #[token("{")]
     LBrace,
     #[token("}")]
@@ -196,12 +211,71 @@ pub enum TokenKind {
 impl TokenKind {
     #[must_use]
     pub fn is_trivia(self) -> bool {
-        matches!(self, Self::Whitespace | Self::Newline | Self::Comment)
+        matches!(
+            self,
+            Self::Whitespace | Self::Newline | Self::Comment | Self::Continuation
+        )
     }
     #[must_use]
     pub fn is_keyword_statement(&self) -> bool {
         (TokenKind::FnKw..=TokenKind::EndKw).contains(self)
     }
+    /// Every keyword, in the order they are declared.
+    pub fn keywords() -> impl Iterator<Item = TokenKind> {
+        (u8::from(TokenKind::FnKw)..=u8::from(TokenKind::EndKw))
+            .filter_map(|kind| TokenKind::try_from(kind).ok())
+    }
+    /// The text of a keyword, or None for other tokens.
+    #[must_use]
+    pub fn keyword_text(self) -> Option<&'static str> {
+        Some(match self {
+            Self::FnKw => "function",
+            Self::EndFnKw => "endfunction",
+            Self::IfKw => "if",
+            Self::ElseIfKw => "elseif",
+            Self::ElseKw => "else",
+            Self::EndIfKw => "endif",
+            Self::SwitchKw => "switch",
+            Self::CaseKw => "case",
+            Self::OtherwiseKw => "otherwise",
+            Self::EndSwitchKw => "endswitch",
+            Self::WhileKw => "while",
+            Self::EndWhileKw => "endwhile",
+            Self::DoKw => "do",
+            Self::UntilKw => "until",
+            Self::ForKw => "for",
+            Self::EndForKw => "endfor",
+            Self::BreakKw => "break",
+            Self::ContinueKw => "continue",
+            Self::ReturnKw => "return",
+            Self::UnwindProtectKw => "unwind_protect",
+            Self::UnwindProtectCleanupKw => "unwind_protect_cleanup",
+            Self::EndUnwindProtectKw => "end_unwind_protect",
+            Self::TryKw => "try",
+            Self::CatchKw => "catch",
+            Self::EndTryKw => "end_try_catch",
+            Self::GlobalKw => "global",
+            Self::PersistentKw => "persistent",
+            Self::EndKw => "end",
+            _ => return None,
+        })
+    }
+    /// Whether the token closes a block: either the generic `end` or one of the specific
+    /// `endXXX` keywords.
+    #[must_use]
+    pub fn is_block_terminator(&self) -> bool {
+        matches!(
+            *self,
+            Self::EndKw
+                | Self::EndFnKw
+                | Self::EndIfKw
+                | Self::EndSwitchKw
+                | Self::EndWhileKw
+                | Self::EndForKw
+                | Self::EndUnwindProtectKw
+                | Self::EndTryKw
+        )
+    }
 }
 
 impl fmt::Display for TokenKind {
@@ -224,6 +298,8 @@ impl fmt::Display for TokenKind {
             Self::Semicolon => "';'",
             Self::Colon => "':'",
             Self::Comma => "','",
+            Self::Continuation => "'...'",
+            Self::Dot => "'.'",
             Self::Tilde => "'~'",
             Self::TildeEquals => "'~='",
             Self::At => "'@'",
@@ -271,12 +347,15 @@ impl fmt::Display for TokenKind {
             | Self::EndForKw
             | Self::BreakKw
             | Self::ContinueKw
+            | Self::ReturnKw
             | Self::UnwindProtectKw
             | Self::UnwindProtectCleanupKw
             | Self::EndUnwindProtectKw
             | Self::TryKw
             | Self::CatchKw
             | Self::EndTryKw
+            | Self::GlobalKw
+            | Self::PersistentKw
             | Self::EndKw => "keyword",
         })
     }
@@ -361,11 +440,7 @@ mod tests {
 
     #[test]
     fn bare_dot_not_a_number() {
-        let mut lexer = Lexer::new(".");
-        assert!(
-            lexer.next().is_none(),
-            "bare dot should not be a number token"
-        );
+        check(".", TokenKind::Dot);
     }
 
     #[test]
@@ -532,110 +607,125 @@ mod tests {
 
     #[test]
     fn lex_kw_if() {
-        check("if", TokenKind::IfKw)
+        check("if", TokenKind::IfKw);
     }
 
     #[test]
     fn lex_kw_elseif() {
-        check("elseif", TokenKind::ElseIfKw)
+        check("elseif", TokenKind::ElseIfKw);
     }
 
     #[test]
     fn lex_kw_else() {
-        check("else", TokenKind::ElseKw)
+        check("else", TokenKind::ElseKw);
     }
 
     #[test]
     fn lex_kw_endif() {
-        check("endif", TokenKind::EndIfKw)
+        check("endif", TokenKind::EndIfKw);
     }
     #[test]
     fn lex_kw_switchkw() {
-        check("switch", TokenKind::SwitchKw)
+        check("switch", TokenKind::SwitchKw);
     }
     #[test]
     fn lex_kw_casekw() {
-        check("case", TokenKind::CaseKw)
+        check("case", TokenKind::CaseKw);
     }
     #[test]
     fn lex_kw_otherwisekw() {
-        check("otherwise", TokenKind::OtherwiseKw)
+        check("otherwise", TokenKind::OtherwiseKw);
     }
     #[test]
     fn lex_kw_endswitch() {
-        check("endswitch", TokenKind::EndSwitchKw)
+        check("endswitch", TokenKind::EndSwitchKw);
     }
     #[test]
     fn lex_kw_whilekw() {
-        check("while", TokenKind::WhileKw)
+        check("while", TokenKind::WhileKw);
     }
     #[test]
     fn lex_kw_endwhilekw() {
-        check("endwhile", TokenKind::EndWhileKw)
+        check("endwhile", TokenKind::EndWhileKw);
     }
     #[test]
     fn lex_kw_dokw() {
-        check("do", TokenKind::DoKw)
+        check("do", TokenKind::DoKw);
     }
     #[test]
     fn lex_kw_untilkw() {
-        check("until", TokenKind::UntilKw)
+        check("until", TokenKind::UntilKw);
     }
     #[test]
     fn lex_kw_forkw() {
-        check("for", TokenKind::ForKw)
+        check("for", TokenKind::ForKw);
     }
     #[test]
     fn lex_kw_endforkw() {
-        check("endfor", TokenKind::EndForKw)
+        check("endfor", TokenKind::EndForKw);
     }
     #[test]
     fn lex_kw_breakkw() {
-        check("break", TokenKind::BreakKw)
+        check("break", TokenKind::BreakKw);
     }
 
     #[test]
     fn lex_kw_continuekw() {
-        check("continue", TokenKind::ContinueKw)
+        check("continue", TokenKind::ContinueKw);
+    }
+
+    #[test]
+    fn lex_kw_returnkw() {
+        check("return", TokenKind::ReturnKw);
     }
 
     #[test]
     fn lex_kw_unwindprotectkw() {
-        check("unwind_protect", TokenKind::UnwindProtectKw)
+        check("unwind_protect", TokenKind::UnwindProtectKw);
     }
 
     #[test]
     fn lex_kw_unwindprotectcleanupkw() {
-        check("unwind_protect_cleanup", TokenKind::UnwindProtectCleanupKw)
+        check("unwind_protect_cleanup", TokenKind::UnwindProtectCleanupKw);
     }
 
     #[test]
     fn lex_kw_endunwindprotectkw() {
-        check("end_unwind_protect", TokenKind::EndUnwindProtectKw)
+        check("end_unwind_protect", TokenKind::EndUnwindProtectKw);
     }
 
     #[test]
     fn lex_kw_trykw() {
-        check("try", TokenKind::TryKw)
+        check("try", TokenKind::TryKw);
     }
 
     #[test]
     fn lex_kw_catchkw() {
-        check("catch", TokenKind::CatchKw)
+        check("catch", TokenKind::CatchKw);
     }
 
     #[test]
     fn lex_kw_endtrykw() {
-        check("end_try_catch", TokenKind::EndTryKw)
+        check("end_try_catch", TokenKind::EndTryKw);
+    }
+
+    #[test]
+    fn lex_kw_globalkw() {
+        check("global", TokenKind::GlobalKw);
+    }
+
+    #[test]
+    fn lex_kw_persistentkw() {
+        check("persistent", TokenKind::PersistentKw);
     }
 
     #[test]
     fn lex_kw_endkw() {
-        check("end", TokenKind::EndKw)
+        check("end", TokenKind::EndKw);
     }
 
     // Test with full coverage. An error here would be hard to debug
-    fn to_kw_or_not_to_kw() -> ([TokenKind; 25], [TokenKind; 46]) {
+    fn to_kw_or_not_to_kw() -> ([TokenKind; 28], [TokenKind; 48]) {
         use crate::TokenKind::*;
         let keywords = [
             FnKw,
@@ -656,12 +746,15 @@ mod tests {
             EndForKw,
             BreakKw,
             ContinueKw,
+            ReturnKw,
             UnwindProtectKw,
             UnwindProtectCleanupKw,
             EndUnwindProtectKw,
             TryKw,
             CatchKw,
             EndTryKw,
+            GlobalKw,
+            PersistentKw,
             EndKw,
         ];
 
@@ -705,6 +798,8 @@ mod tests {
             ElmtPowEquals,
             Colon,
             Comma,
+            Continuation,
+            Dot,
             LParen,
             RParen,
             LBracket,
@@ -714,7 +809,7 @@ mod tests {
             Comment,
         ];
 
-        let u: HashSet<TokenKind> = keywords.into_iter().chain(non_kw.into_iter()).collect();
+        let u: HashSet<TokenKind> = keywords.into_iter().chain(non_kw).collect();
         let v: HashSet<TokenKind> = TokenKind::iter().collect();
 
         let dif: Vec<_> = u.symmetric_difference(&v).collect();
@@ -724,18 +819,18 @@ mod tests {
     }
 
     fn check_trivia(input: TokenKind, expected: bool) {
-        assert_eq!(TokenKind::is_trivia(input), expected)
+        assert_eq!(TokenKind::is_trivia(input), expected);
     }
 
     fn check_is_keyword(input: TokenKind, expected: bool) {
-        assert_eq!(TokenKind::is_keyword_statement(&input), expected)
+        assert_eq!(TokenKind::is_keyword_statement(&input), expected);
     }
 
     #[test]
     fn full_coverage() {
         use strum::IntoEnumIterator;
         let (kws, non_kws) = to_kw_or_not_to_kw();
-        assert_eq!(kws.len() + non_kws.len(), TokenKind::iter().count())
+        assert_eq!(kws.len() + non_kws.len(), TokenKind::iter().count());
     }
 
     #[test]
@@ -748,12 +843,35 @@ mod tests {
                     TokenKind::Whitespace,
                     TokenKind::Newline,
                     TokenKind::Comment,
+                    TokenKind::Continuation,
                 ]
                 .contains(&val),
             );
         }
     }
 
+    #[test]
+    fn check_block_terminators() {
+        use strum::IntoEnumIterator;
+        for val in TokenKind::iter() {
+            assert_eq!(
+                val.is_block_terminator(),
+                [
+                    TokenKind::EndKw,
+                    TokenKind::EndFnKw,
+                    TokenKind::EndIfKw,
+                    TokenKind::EndSwitchKw,
+                    TokenKind::EndWhileKw,
+                    TokenKind::EndForKw,
+                    TokenKind::EndUnwindProtectKw,
+                    TokenKind::EndTryKw,
+                ]
+                .contains(&val),
+                "{val:?}"
+            );
+        }
+    }
+
     #[test]
     fn check_yes_keywords() {
         let (keywords, _) = to_kw_or_not_to_kw();
@@ -762,6 +880,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn keyword_texts_lex_back() {
+        let (keywords, non_kws) = to_kw_or_not_to_kw();
+        assert_eq!(TokenKind::keywords().collect::<Vec<_>>(), keywords);
+        for val in keywords {
+            check(val.keyword_text().unwrap(), val);
+        }
+        for val in non_kws {
+            assert_eq!(val.keyword_text(), None);
+        }
+    }
+
+    #[test]
+    fn lex_field_access() {
+        let kinds: Vec<_> = Lexer::new("s.a .5").map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Identifier,
+                TokenKind::Dot,
+                TokenKind::Identifier,
+                TokenKind::Whitespace,
+                TokenKind::Number,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_continuations() {
+        check("...", TokenKind::Continuation);
+        check("... the rest", TokenKind::Continuation);
+        let kinds: Vec<_> = Lexer::new("[1, ... x\n2]")
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::LBracket,
+                TokenKind::Number,
+                TokenKind::Comma,
+                TokenKind::Whitespace,
+                TokenKind::Continuation,
+                TokenKind::Newline,
+                TokenKind::Number,
+                TokenKind::RBracket,
+            ]
+        );
+    }
+
     #[test]
     fn check_no_keyword() {
         let (_, non_kws) = to_kw_or_not_to_kw();
@@ -0,0 +1,54 @@
+//! `octave-lsp catalogue [OCTAVE_HOME] [--output FILE]`: rebuilds the catalogue of builtins from
+//! the help texts of a local Octave installation.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const USAGE: &str = "usage: octave-lsp catalogue [OCTAVE_HOME] [--output FILE]";
+
+/// Writes the catalogue to the output file, or to stdout.
+pub fn run(args: &[String]) -> Result<()> {
+    let mut octave_home = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().context(USAGE)?),
+            _ if arg.starts_with('-') || octave_home.is_some() => bail!(USAGE),
+            _ => octave_home = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let octave_home = match octave_home {
+        Some(octave_home) => octave_home,
+        None => find_octave_home()
+            .context("no Octave installation found; pass the directory it is installed in")?,
+    };
+    let catalogue = catalogue::generate(&octave_home)
+        .with_context(|| format!("reading the Octave in {}", octave_home.display()))?;
+
+    let json = catalogue.to_json();
+    match output {
+        Some(output) => fs::write(output, json).with_context(|| format!("writing {output}"))?,
+        None => print!("{json}"),
+    }
+    eprintln!(
+        "{} functions of Octave {}",
+        catalogue.functions().count(),
+        catalogue.version
+    );
+    Ok(())
+}
+
+/// Asks the Octave on the `PATH` where it is installed.
+fn find_octave_home() -> Option<PathBuf> {
+    let output = Command::new("octave-config")
+        .args(["--print", "OCTAVE_HOME"])
+        .output()
+        .ok()?;
+    let home = String::from_utf8(output.stdout).ok()?;
+    let home = home.trim();
+    (output.status.success() && !home.is_empty()).then(|| PathBuf::from(home))
+}
@@ -0,0 +1,354 @@
+//! The settings of the server: the `octave-lsp` section of the editor's settings, which clients
+//! send as `initializationOptions`, answer `workspace/configuration` with and push with
+//! `workspace/didChangeConfiguration`, and those of the [project file](crate::project_file).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer};
+
+/// The settings, with the defaults for those that are missing or `null`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    /// Directories searched for functions after the workspace folders. Relative ones start from
+    /// the first workspace folder.
+    #[serde(deserialize_with = "null_as_default")]
+    pub load_path: Vec<PathBuf>,
+    /// A script whose `addpath` calls extend the load path, such as `startup.m`.
+    pub startup_script: Option<PathBuf>,
+    /// Levels for the problems with these codes, which replace their own severity.
+    #[serde(deserialize_with = "null_as_default")]
+    pub lints: HashMap<String, LintLevel>,
+    #[serde(deserialize_with = "null_as_default")]
+    pub format: FormatConfig,
+    /// Whether the code has to run in MATLAB too. Octave-only syntax is then reported, and the
+    /// formatter closes blocks with `end`.
+    #[serde(deserialize_with = "null_as_default")]
+    pub matlab_compatible: bool,
+    #[serde(deserialize_with = "null_as_default")]
+    pub catalogue: CatalogueConfig,
+    #[serde(deserialize_with = "null_as_default")]
+    pub outline: OutlineConfig,
+    /// Directories whose files are left out, from the project file.
+    #[serde(skip)]
+    pub exclude: Vec<PathBuf>,
+    /// Settings for the files under some directories, from the project file. Later ones take
+    /// precedence.
+    #[serde(skip)]
+    pub overrides: Vec<Override>,
+}
+
+/// Settings that replace those of the project for the files under a directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub dir: PathBuf,
+    pub matlab_compatible: Option<bool>,
+    pub lints: HashMap<String, LintLevel>,
+    pub format: FormatConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Off,
+    Hint,
+    Information,
+    Warning,
+    Error,
+}
+
+/// The formatting style. The indentation the editor asks for is used unless it is set here.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormatConfig {
+    pub indent_width: Option<usize>,
+    pub use_tabs: Option<bool>,
+    pub indent_function_bodies: Option<bool>,
+    pub end_keywords: Option<EndKeywords>,
+}
+
+impl FormatConfig {
+    /// Takes the options `other` sets.
+    pub fn extend(&mut self, other: &Self) {
+        self.indent_width = other.indent_width.or(self.indent_width);
+        self.use_tabs = other.use_tabs.or(self.use_tabs);
+        self.indent_function_bodies = other.indent_function_bodies.or(self.indent_function_bodies);
+        self.end_keywords = other.end_keywords.or(self.end_keywords);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndKeywords {
+    Keep,
+    Generic,
+    Specific,
+}
+
+/// Which functions come with Octave.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CatalogueConfig {
+    /// A catalogue written by `octave-lsp catalogue`, used instead of the bundled one.
+    pub path: Option<PathBuf>,
+    /// The version of Octave the catalogue has to describe.
+    pub version: Option<String>,
+}
+
+/// What the document outline shows.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OutlineConfig {
+    /// Whether the variables of scripts are listed along with the functions.
+    pub script_variables: bool,
+}
+
+impl Default for OutlineConfig {
+    fn default() -> Self {
+        Self {
+            script_variables: true,
+        }
+    }
+}
+
+/// Reads a setting that editors may leave `null`, which `#[serde(default)]` only does for missing
+/// ones. The default is that of the type, so it is only used where the container has the same.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl Config {
+    /// Reads the settings. `null` stands for the defaults.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a message for the user if a setting has the wrong type or an unknown value.
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, String> {
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+        Self::deserialize(value).map_err(|error| format!("invalid octave-lsp settings: {error}"))
+    }
+
+    /// The settings for the file at `path`, with the overrides for its directory.
+    pub fn for_file(&self, path: &Path) -> Cow<'_, Self> {
+        let mut overrides = self
+            .overrides
+            .iter()
+            .filter(|rule| path.starts_with(&rule.dir))
+            .peekable();
+        if overrides.peek().is_none() {
+            return Cow::Borrowed(self);
+        }
+        let mut config = self.clone();
+        for rule in overrides {
+            config.matlab_compatible = rule.matlab_compatible.unwrap_or(config.matlab_compatible);
+            config.lints.extend(rule.lints.clone());
+            config.format.extend(&rule.format);
+        }
+        Cow::Owned(config)
+    }
+
+    /// The codes in `lints` that no problem has, which are probably misspelt.
+    pub fn unknown_lints(&self) -> impl Iterator<Item = &str> {
+        self.lints
+            .keys()
+            .chain(self.overrides.iter().flat_map(|rule| rule.lints.keys()))
+            .map(String::as_str)
+            .filter(|code| {
+                *code != "syntax-error"
+                    && !ast::validation::ValidationError::CODES.contains(code)
+                    && !hir::diagnostics::DiagnosticKind::CODES.contains(code)
+            })
+    }
+
+    /// The level set for problems with `code`, if any.
+    pub fn lint_level(&self, code: &str) -> Option<LintLevel> {
+        self.lints.get(code).copied()
+    }
+
+    pub fn is_enabled(&self, code: &str) -> bool {
+        self.lint_level(code) != Some(LintLevel::Off)
+    }
+
+    /// The formatter options, with the indentation given here unless the settings set it.
+    pub fn format_options(&self, indent_width: usize, use_tabs: bool) -> formatter::FormatOptions {
+        let format = &self.format;
+        let end_keywords = match format.end_keywords {
+            Some(EndKeywords::Keep) => formatter::EndKeywords::Keep,
+            Some(EndKeywords::Generic) => formatter::EndKeywords::Generic,
+            Some(EndKeywords::Specific) => formatter::EndKeywords::Specific,
+            None if self.matlab_compatible => formatter::EndKeywords::Generic,
+            None => formatter::EndKeywords::default(),
+        };
+        formatter::FormatOptions {
+            indent_width: format.indent_width.unwrap_or(indent_width),
+            use_tabs: format.use_tabs.unwrap_or(use_tabs),
+            indent_function_bodies: format
+                .indent_function_bodies
+                .unwrap_or(formatter::FormatOptions::default().indent_function_bodies),
+            end_keywords,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_the_settings_of_the_client() {
+        let config = Config::from_json(&json!({
+            "loadPath": ["lib", "/opt/toolbox"],
+            "startupScript": "startup.m",
+            "lints": {"unused-variable": "off", "undefined-name": "warning"},
+            "format": {"indentWidth": 2, "endKeywords": "specific"},
+            "matlabCompatible": true,
+            "catalogue": {"path": "octave.json", "version": "9.2.0"},
+            "outline": {"scriptVariables": false},
+        }))
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                load_path: vec!["lib".into(), "/opt/toolbox".into()],
+                startup_script: Some("startup.m".into()),
+                lints: HashMap::from([
+                    ("unused-variable".to_string(), LintLevel::Off),
+                    ("undefined-name".to_string(), LintLevel::Warning),
+                ]),
+                format: FormatConfig {
+                    indent_width: Some(2),
+                    end_keywords: Some(EndKeywords::Specific),
+                    ..FormatConfig::default()
+                },
+                matlab_compatible: true,
+                catalogue: CatalogueConfig {
+                    path: Some("octave.json".into()),
+                    version: Some("9.2.0".to_string()),
+                },
+                outline: OutlineConfig {
+                    script_variables: false,
+                },
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn missing_settings_take_their_defaults() {
+        for value in [
+            json!(null),
+            json!({}),
+            json!({"loadPath": null, "format": null, "outline": {}}),
+        ] {
+            let config = Config::from_json(&value).unwrap();
+            assert_eq!(config, Config::default(), "{value}");
+        }
+        let config = Config::default();
+        assert!(config.outline.script_variables);
+        assert!(!config.matlab_compatible);
+        assert_eq!(config.lint_level("unused-variable"), None);
+        assert!(config.is_enabled("unused-variable"));
+    }
+
+    #[test]
+    fn unknown_settings_are_ignored() {
+        let config = Config::from_json(&json!({
+            "matlabCompatible": true,
+            "trace": {"server": "verbose"},
+            "format": {"indentWidth": 3, "lineWidth": 100},
+        }))
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                matlab_compatible: true,
+                format: FormatConfig {
+                    indent_width: Some(3),
+                    ..FormatConfig::default()
+                },
+                ..Config::default()
+            }
+        );
+        // Settings only the project file has cannot come from the client.
+        let config = Config::from_json(&json!({"exclude": ["build"]})).unwrap();
+        assert_eq!(config.exclude, Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        for (value, expected) in [
+            (
+                json!({"loadPath": "lib"}),
+                "invalid type: string \"lib\", expected a sequence",
+            ),
+            (
+                json!({"lints": {"unused-variable": "loud"}}),
+                "unknown variant `loud`",
+            ),
+            (
+                json!({"format": {"indentWidth": -1}}),
+                "invalid value: integer `-1`",
+            ),
+            (
+                json!("settings"),
+                "invalid type: string \"settings\", expected struct Config",
+            ),
+        ] {
+            let error = Config::from_json(&value).unwrap_err();
+            assert!(
+                error.starts_with("invalid octave-lsp settings: "),
+                "{error}"
+            );
+            assert!(error.contains(expected), "{error}");
+        }
+    }
+
+    #[test]
+    fn lint_levels_are_overridden_by_directory() {
+        let mut config = Config::from_json(&json!({
+            "lints": {"unused-variable": "error", "unreachable-code": "hint", "no-such-lint": "off"},
+        }))
+        .unwrap();
+        config.overrides = vec![
+            Override {
+                dir: "/ws/tests".into(),
+                matlab_compatible: Some(true),
+                lints: HashMap::from([("unused-variable".to_string(), LintLevel::Off)]),
+                format: FormatConfig::default(),
+            },
+            Override {
+                dir: "/ws/tests/legacy".into(),
+                matlab_compatible: None,
+                lints: HashMap::from([("unused-variable".to_string(), LintLevel::Warning)]),
+                format: FormatConfig::default(),
+            },
+        ];
+
+        let main = config.for_file(Path::new("/ws/main.m"));
+        assert!(matches!(main, Cow::Borrowed(_)));
+        assert_eq!(main.lint_level("unused-variable"), Some(LintLevel::Error));
+
+        let test = config.for_file(Path::new("/ws/tests/test_main.m"));
+        assert!(!test.is_enabled("unused-variable"));
+        assert_eq!(test.lint_level("unreachable-code"), Some(LintLevel::Hint));
+        assert!(test.matlab_compatible);
+
+        let legacy = config.for_file(Path::new("/ws/tests/legacy/old.m"));
+        assert_eq!(
+            legacy.lint_level("unused-variable"),
+            Some(LintLevel::Warning)
+        );
+        assert!(legacy.matlab_compatible);
+
+        assert_eq!(config.unknown_lints().collect::<Vec<_>>(), ["no-such-lint"]);
+    }
+}
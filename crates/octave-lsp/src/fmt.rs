@@ -0,0 +1,87 @@
+//! `octave-lsp fmt [--check] [FILE...]`: formats files in place, or reports the ones that are not
+//! formatted. Without files, formats stdin to stdout.
+//!
+//! The style is that of the project file in the current directory or the closest of its
+//! ancestors, as in the editor. Files in the directories it excludes are left alone.
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+
+use crate::config::Config;
+use crate::project_file::ProjectFile;
+
+const USAGE: &str = "usage: octave-lsp fmt [--check] [FILE...]";
+
+/// Formats the files, or with `--check` fails if any of them would change.
+pub fn run(args: &[String]) -> Result<()> {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with('-') => bail!(USAGE),
+            _ => files.push(arg),
+        }
+    }
+    let cwd = env::current_dir().context("reading the current directory")?;
+    let mut config = Config::default();
+    if let Some((root, project_file)) = ProjectFile::find(&cwd).map_err(|error| anyhow!(error))? {
+        project_file.apply(&mut config, &root);
+    }
+    let defaults = formatter::FormatOptions::default();
+    let options = |config: &Config| config.format_options(defaults.indent_width, defaults.use_tabs);
+
+    if files.is_empty() {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("reading stdin")?;
+        let formatted = formatter::format(&text, &options(&config)).context("formatting stdin")?;
+        if check {
+            if formatted != text {
+                bail!("stdin is not formatted");
+            }
+        } else {
+            print!("{formatted}");
+        }
+        return Ok(());
+    }
+
+    let mut unformatted = 0;
+    let mut failed = 0;
+    for file in files {
+        // Resolves `..`, as paths are compared with the directories of the project file by prefix.
+        let path = fs::canonicalize(file).unwrap_or_else(|_| cwd.join(file));
+        if config.exclude.iter().any(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        let text = fs::read_to_string(file).with_context(|| format!("reading {file}"))?;
+        let formatted = match formatter::format(&text, &options(&config.for_file(&path))) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                eprintln!("{file}: {error}");
+                failed += 1;
+                continue;
+            }
+        };
+        if formatted == text {
+            continue;
+        }
+        if check {
+            println!("{file}");
+            unformatted += 1;
+        } else {
+            fs::write(file, formatted).with_context(|| format!("writing {file}"))?;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} files could not be formatted");
+    }
+    if unformatted > 0 {
+        bail!("{unformatted} files are not formatted");
+    }
+    Ok(())
+}
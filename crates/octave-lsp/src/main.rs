@@ -6,13 +6,24 @@
 #![warn(missing_docs)]
 #![warn(clippy::pedantic)]
 
+mod catalogue;
+mod config;
+mod fmt;
+mod project_file;
 mod repl;
 mod server;
+mod thread_pool;
+mod workspace;
 
 fn main() -> anyhow::Result<()> {
-    let is_repl = std::env::args().any(|a| a == "--repl");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let is_repl = args.iter().any(|a| a == "--repl");
 
-    if is_repl {
+    if args.first().is_some_and(|command| command == "catalogue") {
+        catalogue::run(&args[1..])?;
+    } else if args.first().is_some_and(|command| command == "fmt") {
+        fmt::run(&args[1..])?;
+    } else if is_repl {
         repl::run()?;
     } else {
         server::run()?;
@@ -0,0 +1,297 @@
+//! The project file, `.octave-lsp.toml` at the root of the workspace. It holds the settings a
+//! project shares, so that the editor and `octave-lsp fmt` in CI agree:
+//!
+//! ```toml
+//! dialect = "matlab"
+//! load-path = ["lib"]
+//! exclude = ["build"]
+//!
+//! [lints]
+//! unused-variable = "hint"
+//!
+//! [format]
+//! indent-width = 2
+//!
+//! [[overrides]]
+//! path = "tests"
+//! lints = { undefined-name = "off" }
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::{Config, EndKeywords, FormatConfig, LintLevel, Override};
+
+pub const FILE_NAME: &str = ".octave-lsp.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectFile {
+    dialect: Option<Dialect>,
+    /// Directories searched for functions after the workspace folders.
+    load_path: Vec<PathBuf>,
+    /// Directories whose files are left out.
+    exclude: Vec<PathBuf>,
+    lints: HashMap<String, LintLevel>,
+    format: FormatSection,
+    /// Settings for the files under some directories. Later ones take precedence.
+    overrides: Vec<OverrideSection>,
+}
+
+/// The language the code is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Dialect {
+    Octave,
+    /// Code that has to run in MATLAB too.
+    Matlab,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct FormatSection {
+    indent_width: Option<usize>,
+    use_tabs: Option<bool>,
+    indent_function_bodies: Option<bool>,
+    end_keywords: Option<EndKeywords>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct OverrideSection {
+    path: PathBuf,
+    dialect: Option<Dialect>,
+    #[serde(default)]
+    lints: HashMap<String, LintLevel>,
+    #[serde(default)]
+    format: FormatSection,
+}
+
+impl ProjectFile {
+    /// Reads the project file in `dir`, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a message for the user if the file cannot be read or is not valid.
+    pub fn read(dir: &Path) -> Result<Option<Self>, String> {
+        let path = dir.join(FILE_NAME);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(format!("cannot read {}: {error}", path.display())),
+        };
+        toml::from_str(&text)
+            .map(Some)
+            .map_err(|error| format!("invalid {}: {error}", path.display()))
+    }
+
+    /// Finds the project file in `dir` or the closest of its ancestors, and returns the
+    /// directory it is in along with it.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a message for the user if the file cannot be read or is not valid.
+    pub fn find(dir: &Path) -> Result<Option<(PathBuf, Self)>, String> {
+        for dir in dir.ancestors() {
+            if let Some(file) = Self::read(dir)? {
+                return Ok(Some((dir.to_path_buf(), file)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Adds the project's settings to those of the editor, taking precedence over them. Relative
+    /// paths start from `root`, the directory of the file.
+    pub fn apply(&self, config: &mut Config, root: &Path) {
+        if let Some(dialect) = self.dialect {
+            config.matlab_compatible = dialect == Dialect::Matlab;
+        }
+        config
+            .load_path
+            .splice(0..0, self.load_path.iter().map(|path| root.join(path)));
+        config
+            .exclude
+            .extend(self.exclude.iter().map(|path| root.join(path)));
+        config.lints.extend(self.lints.clone());
+        config.format.extend(&self.format.to_config());
+        config
+            .overrides
+            .extend(self.overrides.iter().map(|section| Override {
+                dir: root.join(&section.path),
+                matlab_compatible: section.dialect.map(|dialect| dialect == Dialect::Matlab),
+                lints: section.lints.clone(),
+                format: section.format.to_config(),
+            }));
+    }
+}
+
+impl FormatSection {
+    fn to_config(&self) -> FormatConfig {
+        FormatConfig {
+            indent_width: self.indent_width,
+            use_tabs: self.use_tabs,
+            indent_function_bodies: self.indent_function_bodies,
+            end_keywords: self.end_keywords,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(text: &str) -> Result<ProjectFile, String> {
+        toml::from_str(text).map_err(|error| error.message().to_string())
+    }
+
+    /// Reads `text` as the project file of `/ws`, and applies it to the settings of the client.
+    fn apply(text: &str, client: &serde_json::Value) -> Config {
+        let mut config = Config::from_json(client).unwrap();
+        parse(text).unwrap().apply(&mut config, Path::new("/ws"));
+        config
+    }
+
+    #[test]
+    fn reads_every_setting() {
+        let config = apply(
+            r#"
+            dialect = "matlab"
+            load-path = ["lib", "/opt/toolbox"]
+            exclude = ["build"]
+
+            [lints]
+            unused-variable = "hint"
+
+            [format]
+            indent-width = 2
+            use-tabs = false
+            indent-function-bodies = true
+            end-keywords = "generic"
+
+            [[overrides]]
+            path = "tests"
+            dialect = "octave"
+            lints = { undefined-name = "off" }
+            format = { indent-width = 4 }
+            "#,
+            &json!(null),
+        );
+        assert_eq!(
+            config,
+            Config {
+                load_path: vec!["/ws/lib".into(), "/opt/toolbox".into()],
+                lints: HashMap::from([("unused-variable".to_string(), LintLevel::Hint)]),
+                format: FormatConfig {
+                    indent_width: Some(2),
+                    use_tabs: Some(false),
+                    indent_function_bodies: Some(true),
+                    end_keywords: Some(EndKeywords::Generic),
+                },
+                matlab_compatible: true,
+                exclude: vec!["/ws/build".into()],
+                overrides: vec![Override {
+                    dir: "/ws/tests".into(),
+                    matlab_compatible: Some(false),
+                    lints: HashMap::from([("undefined-name".to_string(), LintLevel::Off)]),
+                    format: FormatConfig {
+                        indent_width: Some(4),
+                        ..FormatConfig::default()
+                    },
+                }],
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn an_empty_file_changes_nothing() {
+        let client = json!({"loadPath": ["lib"], "matlabCompatible": true});
+        assert_eq!(apply("", &client), Config::from_json(&client).unwrap());
+    }
+
+    #[test]
+    fn invalid_files_are_reported() {
+        for (text, expected) in [
+            ("dialect = \"fortran\"", "unknown variant `fortran`"),
+            (
+                "load-path = \"lib\"",
+                "invalid type: string \"lib\", expected a sequence",
+            ),
+            ("loadPath = [\"lib\"]", "unknown field `loadPath`"),
+            ("[format]\nline-width = 80", "unknown field `line-width`"),
+            (
+                "[lints]\nunused-variable = \"loud\"",
+                "unknown variant `loud`",
+            ),
+            (
+                "[[overrides]]\ndialect = \"matlab\"",
+                "missing field `path`",
+            ),
+            ("dialect = ", "string values must be quoted"),
+        ] {
+            let error = parse(text).map(|_| ()).unwrap_err();
+            assert!(error.contains(expected), "{text}: {error}");
+        }
+    }
+
+    #[test]
+    fn the_project_file_takes_precedence_over_the_client() {
+        let config = apply(
+            r#"
+            dialect = "octave"
+            load-path = ["lib"]
+
+            [lints]
+            unused-variable = "error"
+
+            [format]
+            indent-width = 2
+            "#,
+            &json!({
+                "loadPath": ["/home/user/octave"],
+                "matlabCompatible": true,
+                "lints": {"unused-variable": "off", "unreachable-code": "hint"},
+                "format": {"indentWidth": 8, "useTabs": true},
+            }),
+        );
+        assert!(!config.matlab_compatible);
+        // The project's directories are searched first.
+        assert_eq!(
+            config.load_path,
+            [PathBuf::from("/ws/lib"), PathBuf::from("/home/user/octave")]
+        );
+        assert_eq!(config.lint_level("unused-variable"), Some(LintLevel::Error));
+        assert_eq!(config.lint_level("unreachable-code"), Some(LintLevel::Hint));
+        assert_eq!(config.format.indent_width, Some(2));
+        assert_eq!(config.format.use_tabs, Some(true));
+
+        // Without a dialect, the client decides.
+        let config = apply("", &json!({"matlabCompatible": true}));
+        assert!(config.matlab_compatible);
+    }
+
+    #[test]
+    fn the_closest_file_is_found() {
+        let root = std::env::temp_dir().join(format!("project-file-{}", std::process::id()));
+        let nested = root.join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(FILE_NAME), "dialect = \"matlab\"\n").unwrap();
+
+        let (dir, file) = ProjectFile::find(&nested).unwrap().unwrap();
+        assert_eq!(dir, root);
+        assert_eq!(file.dialect, Some(Dialect::Matlab));
+        assert!(ProjectFile::read(&nested).unwrap().is_none());
+
+        fs::write(root.join("src").join(FILE_NAME), "dialect = [1]\n").unwrap();
+        let error = ProjectFile::find(&nested).unwrap_err();
+        assert!(error.starts_with("invalid "), "{error}");
+        assert!(error.contains(FILE_NAME), "{error}");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -38,7 +38,16 @@ pub fn run() -> io::Result<()> {
                 .collect::<Vec<_>>()
         );
 
-        dbg!(hir::lower(&root));
+        // Only the functions of the file itself are known here.
+        let body = hir::lower(&root);
+        for diagnostic in hir::diagnostics::diagnostics(&body, &|_| false) {
+            println!("{diagnostic}");
+        }
+
+        for stmts in hir::cfg::bodies(&body.stmts) {
+            println!("{}", hir::cfg::Cfg::build(&body.db, stmts));
+        }
+        dbg!(body);
 
         input.clear();
     }
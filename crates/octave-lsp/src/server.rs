@@ -1,134 +1,2049 @@
 //! Octave LSP server: handles LSP protocol over stdio.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use lsp_server::{Connection, Message, Notification};
-use lsp_types::notification::{DidChangeTextDocument, DidOpenTextDocument, Notification as _};
+use catalogue::Catalogue;
+use crossbeam_channel::{Receiver, Sender, select};
+use hir::db::{AnalysisDatabase, Cancelled};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    Cancel, DidChangeConfiguration, DidChangeTextDocument, DidChangeWatchedFiles,
+    DidCloseTextDocument, DidOpenTextDocument, Notification as _, ShowMessage,
+};
+use lsp_types::request::{
+    CodeActionRequest, Completion, DocumentSymbolRequest, FoldingRangeRequest, Formatting,
+    HoverRequest, OnTypeFormatting, PrepareRenameRequest, RangeFormatting, References,
+    RegisterCapability, Rename, Request as _, SemanticTokensFullDeltaRequest,
+    SemanticTokensFullRequest, SemanticTokensRangeRequest, SignatureHelpRequest,
+    WorkspaceConfiguration, WorkspaceSymbolRequest,
+};
 use lsp_types::{
-    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
-    InitializeParams, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
-    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+    AnnotatedTextEdit, CancelParams, ChangeAnnotation, ClientCapabilities, CodeAction,
+    CodeActionKind, CodeActionOptions, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CompletionItem, CompletionItemKind, CompletionOptions,
+    CompletionResponse, CompletionTextEdit, ConfigurationItem, ConfigurationParams, CreateFile,
+    CreateFileOptions, Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag,
+    DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+    DidChangeWatchedFilesRegistrationOptions, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentChangeOperation, DocumentChanges, DocumentFormattingParams,
+    DocumentOnTypeFormattingOptions, DocumentOnTypeFormattingParams, DocumentSymbol,
+    DocumentSymbolParams, DocumentSymbolResponse, Documentation, FileChangeType, FileSystemWatcher,
+    FoldingRange, FoldingRangeKind, FoldingRangeParams, FoldingRangeProviderCapability,
+    FormattingOptions, GlobPattern, Hover, HoverContents, HoverProviderCapability,
+    InitializeParams, InsertTextFormat, Location, MarkupContent, MarkupKind, MessageType,
+    NumberOrString, OneOf, OptionalVersionedTextDocumentIdentifier, ParameterInformation,
+    ParameterLabel, Position, PositionEncodingKind, PrepareRenameResponse,
+    PublishDiagnosticsParams, Range, ReferenceParams, Registration, RegistrationParams, RenameFile,
+    RenameOptions, RenameParams, ResourceOp, ResourceOperationKind, SemanticToken,
+    SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensDelta,
+    SemanticTokensDeltaParams, SemanticTokensEdit, SemanticTokensFullDeltaResult,
+    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensRangeParams, SemanticTokensRangeResult, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, ShowMessageParams, SignatureHelp,
+    SignatureHelpOptions, SignatureInformation, SymbolKind, TextDocumentEdit,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Uri,
+    WorkDoneProgressOptions, WorkspaceEdit, WorkspaceSymbol, WorkspaceSymbolParams,
+    WorkspaceSymbolResponse,
 };
 use parser::parse;
+use text_size::TextSize;
+
+use crate::config::{CatalogueConfig, Config, LintLevel};
+use crate::project_file::{self, ProjectFile};
+use crate::thread_pool::ThreadPool;
+use crate::workspace::{self, Scan, Workspace, WorkspaceSnapshot, path_to_uri, uri_to_path};
+
+/// Workspace symbol searches return at most this many results.
+const MAX_WORKSPACE_SYMBOLS: usize = 256;
+
+/// Groups the renames inside strings, which the user should look at before they are applied.
+const STRING_REFERENCES_ANNOTATION: &str = "string-references";
+
+/// How long a document has to stay the same before its diagnostics are published.
+const DIAGNOSTICS_DELAY: Duration = Duration::from_millis(200);
+
+/// What the main loop knows. Notifications change it there, and requests are answered on the
+/// worker threads, from a [`Snapshot`].
+struct State {
+    capabilities: Arc<ClientCapabilities>,
+    /// The settings of the client and the project file together.
+    config: Arc<Config>,
+    client_config: Config,
+    /// The project file at the root of the workspace, if there is one.
+    project_file: Option<ProjectFile>,
+    /// How the columns of positions are counted, as agreed on with the client.
+    encoding: ide::PositionEncoding,
+    /// The open documents, by URI.
+    documents: Arc<HashMap<String, Arc<Document>>>,
+    workspace: Workspace,
+    /// The semantic tokens last sent for each document, which deltas are computed against.
+    semantic_tokens: HashMap<String, SemanticTokens>,
+    /// The result ID given to the next semantic tokens sent.
+    next_result_id: u64,
+    sender: Sender<Message>,
+    pool: ThreadPool,
+    /// Where the worker threads send what they are done with.
+    tasks: Sender<Task>,
+    /// The requests on the worker threads, and whether they were cancelled before they started.
+    in_flight: HashMap<RequestId, Arc<AtomicBool>>,
+    /// The documents whose diagnostics are published once `diagnostics_due` has passed.
+    pending_diagnostics: HashSet<Uri>,
+    diagnostics_due: Option<Instant>,
+    /// The last `workspace/configuration` request sent, whose answer is still to come.
+    configuration_request: Option<RequestId>,
+    /// The number of `workspace/configuration` requests sent, for their IDs.
+    configuration_requests: u64,
+}
+
+/// The state as a request sees it, which stays the same while the main loop goes on. Its
+/// analyses are cancelled once the workspace changes.
+struct Snapshot {
+    capabilities: Arc<ClientCapabilities>,
+    config: Arc<Config>,
+    encoding: ide::PositionEncoding,
+    documents: Arc<HashMap<String, Arc<Document>>>,
+    workspace: WorkspaceSnapshot,
+}
+
+/// The text of an open document, and what its positions are converted with.
+struct Document {
+    text: String,
+    line_index: ide::LineIndex,
+    encoding: ide::PositionEncoding,
+}
+
+/// What the worker threads send back to the main loop.
+enum Task {
+    Response(Response),
+    /// The answer to a semantic tokens request, along with the tokens to compute later deltas
+    /// against.
+    SemanticTokens {
+        response: Response,
+        uri: Uri,
+        tokens: Option<SemanticTokens>,
+    },
+    /// The diagnostics of a document, for the text it had then.
+    Diagnostics {
+        uri: Uri,
+        document: Arc<Document>,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// The diagnostics of a document were cancelled by a change, and are due again.
+    DiagnosticsCancelled(Uri),
+    /// The files found under the workspace folders and the load path, once it is set or changes.
+    Scanned(Scan),
+}
 
 /// Runs the LSP server on stdin/stdout.
 pub fn run() -> Result<()> {
     let (connection, io_threads) = Connection::stdio();
 
-    let capabilities = ServerCapabilities {
-        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
-        ..ServerCapabilities::default()
+    let (id, init_params) = connection.initialize_start()?;
+    let init_params: InitializeParams = serde_json::from_value(init_params)?;
+    let encoding = position_encoding(&init_params.capabilities);
+    let initialize_result = serde_json::json!({
+        "capabilities": server_capabilities(encoding),
+    });
+    connection.initialize_finish(id, initialize_result)?;
+
+    let mut warnings = Vec::new();
+    let (client_config, project_file) = initial_config(&init_params, &mut warnings);
+    let root = workspace::folders(&init_params).into_iter().next();
+    let config = merge_config(&client_config, project_file.as_ref(), root.as_deref());
+
+    let (tasks, task_receiver) = crossbeam_channel::unbounded();
+    let mut state = State {
+        capabilities: Arc::new(init_params.capabilities.clone()),
+        config: Arc::default(),
+        client_config,
+        project_file,
+        encoding,
+        documents: Arc::default(),
+        workspace: Workspace::new(&init_params, &config),
+        semantic_tokens: HashMap::new(),
+        next_result_id: 0,
+        sender: connection.sender.clone(),
+        pool: ThreadPool::new(),
+        tasks,
+        in_flight: HashMap::new(),
+        pending_diagnostics: HashSet::new(),
+        diagnostics_due: None,
+        configuration_request: None,
+        configuration_requests: 0,
     };
+    for message in warnings {
+        show_warning(&state, message)?;
+    }
+    set_config(&mut state, config)?;
+    spawn_scan(&mut state);
+    register_file_watcher(&connection, &init_params)?;
+    request_config(&mut state)?;
 
-    let init_params: InitializeParams =
-        serde_json::from_value(connection.initialize(serde_json::to_value(capabilities)?)?)?;
+    main_loop(&connection, &task_receiver, &mut state)?;
 
-    tracing_init(&init_params);
+    // The writer thread only stops once every sender is gone.
+    drop(state);
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
 
-    let mut documents: HashMap<String, String> = HashMap::new();
+/// Counts columns in UTF-8 or UTF-32 if the client can, as those are closer to how text is
+/// stored, and in the UTF-16 every client understands otherwise.
+fn position_encoding(capabilities: &ClientCapabilities) -> ide::PositionEncoding {
+    let offered = capabilities
+        .general
+        .as_ref()
+        .and_then(|general| general.position_encodings.as_deref())
+        .unwrap_or_default();
+    if offered.contains(&PositionEncodingKind::UTF8) {
+        ide::PositionEncoding::Utf8
+    } else if offered.contains(&PositionEncodingKind::UTF32) {
+        ide::PositionEncoding::Utf32
+    } else {
+        ide::PositionEncoding::Utf16
+    }
+}
+
+fn server_capabilities(encoding: ide::PositionEncoding) -> ServerCapabilities {
+    ServerCapabilities {
+        position_encoding: Some(match encoding {
+            ide::PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            ide::PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            ide::PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }),
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(
+            TextDocumentSyncKind::INCREMENTAL,
+        )),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::REFACTOR_REWRITE,
+                CodeActionKind::REFACTOR_EXTRACT,
+                CodeActionKind::REFACTOR_INLINE,
+            ]),
+            ..CodeActionOptions::default()
+        })),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "\n".to_string(),
+            more_trigger_character: None,
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string(), "@".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+            ..SignatureHelpOptions::default()
+        }),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        })),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: semantic_tokens_legend(),
+                range: Some(true),
+                full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                work_done_progress_options: WorkDoneProgressOptions::default(),
+            },
+        )),
+        ..ServerCapabilities::default()
+    }
+}
 
-    for msg in &connection.receiver {
-        match msg {
-            Message::Request(req) => {
-                if connection.handle_shutdown(&req)? {
-                    break;
+fn main_loop(connection: &Connection, tasks: &Receiver<Task>, state: &mut State) -> Result<()> {
+    loop {
+        let diagnostics_due = state
+            .diagnostics_due
+            .map_or_else(crossbeam_channel::never, crossbeam_channel::at);
+        select! {
+            recv(connection.receiver) -> msg => {
+                let Ok(msg) = msg else {
+                    return Ok(());
+                };
+                match msg {
+                    Message::Request(req) => {
+                        if connection.handle_shutdown(&req)? {
+                            return Ok(());
+                        }
+                        handle_request(req, state)?;
+                    }
+                    Message::Notification(not) => handle_notification(&not, state)?,
+                    Message::Response(response) => handle_response(&response, state)?,
                 }
             }
-            Message::Notification(not) => {
-                handle_notification(&connection, &not, &mut documents)?;
+            recv(tasks) -> task => {
+                if let Ok(task) = task {
+                    handle_task(task, state)?;
+                }
             }
-            Message::Response(_) => {}
+            recv(diagnostics_due) -> _ => spawn_diagnostics(state),
         }
+        spawn_scan(state);
     }
+}
 
-    io_threads.join()?;
+/// Reads the files of the load path on a worker thread if it changed. Requests are answered with
+/// what is known so far while they are read.
+fn spawn_scan(state: &mut State) {
+    let Some(load_path_config) = state.workspace.take_scan() else {
+        return;
+    };
+    let tasks = state.tasks.clone();
+    state.pool.spawn(move || {
+        let _ = tasks.send(Task::Scanned(Scan::read(&load_path_config)));
+    });
+}
+
+/// Reads the settings the client starts with and the project file, so that the workspace starts
+/// with their load path. Those that are invalid are left out with a warning.
+fn initial_config(
+    init_params: &InitializeParams,
+    warnings: &mut Vec<String>,
+) -> (Config, Option<ProjectFile>) {
+    let options = init_params.initialization_options.as_ref();
+    let client_config = Config::from_json(options.unwrap_or(&serde_json::Value::Null))
+        .unwrap_or_else(|message| {
+            warnings.push(message);
+            Config::default()
+        });
+    let root = workspace::folders(init_params).into_iter().next();
+    let project_file = root
+        .as_deref()
+        .map(ProjectFile::read)
+        .transpose()
+        .unwrap_or_else(|message| {
+            warnings.push(message);
+            None
+        })
+        .flatten();
+    (client_config, project_file)
+}
+
+/// Asks the client for the settings, if it can be asked. The answer replaces those it sent
+/// before.
+fn request_config(state: &mut State) -> Result<()> {
+    if !can_request_config(state) {
+        return Ok(());
+    }
+    state.configuration_requests += 1;
+    let id = RequestId::from(format!("configuration-{}", state.configuration_requests));
+    let params = ConfigurationParams {
+        items: vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some("octave-lsp".to_string()),
+        }],
+    };
+    let request = Request::new(
+        id.clone(),
+        WorkspaceConfiguration::METHOD.to_string(),
+        params,
+    );
+    state.configuration_request = Some(id);
+    state.sender.send(Message::Request(request))?;
+    Ok(())
+}
+
+fn can_request_config(state: &State) -> bool {
+    state
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.configuration)
+        .unwrap_or(false)
+}
+
+fn handle_response(response: &Response, state: &mut State) -> Result<()> {
+    if state.configuration_request.as_ref() != Some(&response.id) {
+        return Ok(());
+    }
+    state.configuration_request = None;
+    let settings = response
+        .result
+        .as_ref()
+        .and_then(|result| result.get(0))
+        .filter(|settings| !settings.is_null());
+    if let Some(settings) = settings {
+        read_config(state, settings)?;
+    }
+    Ok(())
+}
+
+/// Takes new settings from the client, or keeps the current ones if they are invalid.
+fn read_config(state: &mut State, settings: &serde_json::Value) -> Result<()> {
+    match Config::from_json(settings) {
+        Ok(config) => {
+            state.client_config = config;
+            update_config(state)
+        }
+        Err(message) => show_warning(state, message),
+    }
+}
+
+/// Reads the project file again, or keeps the last one read if it is invalid.
+fn read_project_file(state: &mut State) -> Result<()> {
+    let Some(root) = state.workspace.root() else {
+        return Ok(());
+    };
+    match ProjectFile::read(root) {
+        Ok(project_file) => {
+            state.project_file = project_file;
+            update_config(state)
+        }
+        Err(message) => show_warning(state, message),
+    }
+}
+
+fn update_config(state: &mut State) -> Result<()> {
+    let config = merge_config(
+        &state.client_config,
+        state.project_file.as_ref(),
+        state.workspace.root(),
+    );
+    set_config(state, config)
+}
+
+/// The settings of the client, with those of the project file taking precedence.
+fn merge_config(
+    client_config: &Config,
+    project_file: Option<&ProjectFile>,
+    root: Option<&Path>,
+) -> Config {
+    let mut config = client_config.clone();
+    if let Some((project_file, root)) = project_file.zip(root) {
+        project_file.apply(&mut config, root);
+    }
+    config
+}
+
+fn set_config(state: &mut State, config: Config) -> Result<()> {
+    if config == *state.config {
+        return Ok(());
+    }
+    for code in config.unknown_lints() {
+        show_warning(
+            state,
+            format!(
+                "unknown lint '{code}' in the octave-lsp settings or {}",
+                project_file::FILE_NAME
+            ),
+        )?;
+    }
+    if config.catalogue != state.config.catalogue {
+        set_catalogue(state, &config.catalogue)?;
+    }
+    state.workspace.set_config(&config);
+    state.config = Arc::new(config);
+    let open: Vec<Uri> = state
+        .documents
+        .keys()
+        .filter_map(|uri| uri.parse().ok())
+        .collect();
+    for uri in open {
+        schedule_diagnostics(state, uri);
+    }
+    Ok(())
+}
+
+/// Looks builtins up in the catalogue of the settings, or in the bundled one if there is none or
+/// it cannot be read.
+fn set_catalogue(state: &mut State, config: &CatalogueConfig) -> Result<()> {
+    let catalogue = match &config.path {
+        Some(path) => {
+            let path = state.workspace.absolute(path);
+            let catalogue = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|json| Catalogue::parse(&json).map_err(|error| error.to_string()));
+            match catalogue {
+                Ok(catalogue) => Some(catalogue),
+                Err(error) => {
+                    let message = format!("cannot read the catalogue {}: {error}", path.display());
+                    show_warning(state, message)?;
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let catalogue = catalogue.map_or_else(Catalogue::bundled, Arc::new);
+    let version = catalogue.version.clone();
+    if let Some(expected) = config
+        .version
+        .as_ref()
+        .filter(|expected| **expected != version)
+    {
+        let message = format!(
+            "the catalogue of builtins describes Octave {version}, not {expected}; \
+             write one with `octave-lsp catalogue` and set `octave-lsp.catalogue.path`"
+        );
+        show_warning(state, message)?;
+    }
+    state.workspace.db.set_catalogue(catalogue);
+    Ok(())
+}
+
+fn show_warning(state: &State, message: String) -> Result<()> {
+    let params = ShowMessageParams {
+        typ: MessageType::WARNING,
+        message,
+    };
+    state.sender.send(Message::Notification(Notification::new(
+        ShowMessage::METHOD.to_string(),
+        params,
+    )))?;
+    Ok(())
+}
+
+/// Asks the client to tell us about changes to `.m` files, if it lets us register for them.
+fn register_file_watcher(connection: &Connection, init_params: &InitializeParams) -> Result<()> {
+    let can_register = init_params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .and_then(|capability| capability.dynamic_registration)
+        .unwrap_or(false);
+    if !can_register {
+        return Ok(());
+    }
+
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.m".to_string()),
+                kind: None,
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/{}", project_file::FILE_NAME)),
+                kind: None,
+            },
+        ],
+    };
+    let params = RegistrationParams {
+        registrations: vec![Registration {
+            id: "watch-files".to_string(),
+            method: DidChangeWatchedFiles::METHOD.to_string(),
+            register_options: Some(serde_json::to_value(options)?),
+        }],
+    };
+    let request = Request::new(
+        RequestId::from("watch-files".to_string()),
+        RegisterCapability::METHOD.to_string(),
+        params,
+    );
+    connection.sender.send(Message::Request(request))?;
+    Ok(())
+}
+
+/// Answers a request on a worker thread. Semantic tokens are numbered here, and the tokens that
+/// deltas are computed against are looked up here, as only the main loop keeps them.
+fn handle_request(req: Request, state: &mut State) -> Result<()> {
+    match req.method.as_str() {
+        SemanticTokensFullRequest::METHOD => {
+            let Some(params) = params_or_reject::<SemanticTokensParams>(&req, state)? else {
+                return Ok(());
+            };
+            let result_id = next_result_id(state);
+            spawn_with(req, state, move |req, state| {
+                let uri = params.text_document.uri;
+                let tokens = full_semantic_tokens(state, &uri, result_id);
+                let result = tokens.clone().map(SemanticTokensResult::Tokens);
+                let response = Response::new_ok(req.id.clone(), serde_json::to_value(result)?);
+                Ok(Task::SemanticTokens {
+                    response,
+                    uri,
+                    tokens,
+                })
+            });
+        }
+        SemanticTokensFullDeltaRequest::METHOD => {
+            let Some(params) = params_or_reject::<SemanticTokensDeltaParams>(&req, state)? else {
+                return Ok(());
+            };
+            let previous = state
+                .semantic_tokens
+                .get(&params.text_document.uri.to_string())
+                .filter(|previous| previous.result_id.as_ref() == Some(&params.previous_result_id))
+                .map(|previous| previous.data.clone());
+            let result_id = next_result_id(state);
+            spawn_with(req, state, move |req, state| {
+                let uri = params.text_document.uri;
+                let tokens = full_semantic_tokens(state, &uri, result_id);
+                let result = tokens
+                    .clone()
+                    .map(|tokens| semantic_tokens_delta(tokens, previous));
+                let response = Response::new_ok(req.id.clone(), serde_json::to_value(result)?);
+                Ok(Task::SemanticTokens {
+                    response,
+                    uri,
+                    tokens,
+                })
+            });
+        }
+        _ => spawn_with(req, state, |req, state| {
+            answer(req, state).map(Task::Response)
+        }),
+    }
     Ok(())
 }
 
-fn tracing_init(_init_params: &InitializeParams) {}
+/// Runs `answer` on a worker thread, unless the request is cancelled before it starts. Requests
+/// whose analyses are cancelled by a change are answered with an error, which clients ask again
+/// after.
+fn spawn_with(
+    req: Request,
+    state: &mut State,
+    answer: impl FnOnce(&Request, &Snapshot) -> Result<Task> + Send + 'static,
+) {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    state.in_flight.insert(req.id.clone(), cancelled.clone());
+    let snapshot = state.snapshot();
+    let tasks = state.tasks.clone();
+    state.pool.spawn(move || {
+        if cancelled.load(Ordering::Acquire) {
+            return;
+        }
+        let task = panic::catch_unwind(AssertUnwindSafe(|| {
+            snapshot.workspace.db.unwind_if_cancelled();
+            answer(&req, &snapshot)
+        }));
+        let task = match task {
+            Ok(Ok(task)) => task,
+            Ok(Err(error)) => Task::Response(error_response(
+                &req,
+                ErrorCode::InvalidParams,
+                error.to_string(),
+            )),
+            Err(payload) if payload.is::<Cancelled>() => Task::Response(error_response(
+                &req,
+                ErrorCode::ContentModified,
+                Cancelled.to_string(),
+            )),
+            Err(_) => {
+                let message = format!("{} panicked", req.method);
+                Task::Response(error_response(&req, ErrorCode::InternalError, message))
+            }
+        };
+        let _ = tasks.send(task);
+    });
+}
+
+fn next_result_id(state: &mut State) -> String {
+    let result_id = state.next_result_id.to_string();
+    state.next_result_id += 1;
+    result_id
+}
+
+/// Answers a request that only reads the state.
+fn answer(req: &Request, state: &Snapshot) -> Result<Response> {
+    let result = match req.method.as_str() {
+        DocumentSymbolRequest::METHOD => {
+            let params: DocumentSymbolParams = serde_json::from_value(req.params.clone())?;
+            let symbols = state
+                .documents
+                .get(&params.text_document.uri.to_string())
+                .map(|document| document_symbols(document, state.config.outline.script_variables));
+            serde_json::to_value(symbols.map(DocumentSymbolResponse::Nested))?
+        }
+        FoldingRangeRequest::METHOD => {
+            let params: FoldingRangeParams = serde_json::from_value(req.params.clone())?;
+            let ranges = state
+                .documents
+                .get(&params.text_document.uri.to_string())
+                .map(|document| folding_ranges(document));
+            serde_json::to_value(ranges)?
+        }
+        Formatting::METHOD | RangeFormatting::METHOD => {
+            // Range formatting has the same parameters, plus the range.
+            let params: DocumentFormattingParams = serde_json::from_value(req.params.clone())?;
+            let range: Option<Range> = req
+                .params
+                .get("range")
+                .map(|range| serde_json::from_value(range.clone()))
+                .transpose()?;
+            match formatting(state, &params.text_document.uri, range, &params.options) {
+                Ok(edits) => serde_json::to_value(edits)?,
+                Err(message) => {
+                    return Ok(error_response(req, ErrorCode::RequestFailed, message));
+                }
+            }
+        }
+        CodeActionRequest::METHOD => {
+            respond::<CodeActionRequest>(req, |params| code_actions(state, &params))?
+        }
+        OnTypeFormatting::METHOD => {
+            respond::<OnTypeFormatting>(req, |params| on_type_formatting(state, &params))?
+        }
+        WorkspaceSymbolRequest::METHOD => {
+            let params: WorkspaceSymbolParams = serde_json::from_value(req.params.clone())?;
+            let symbols = workspace_symbols(&state.workspace, &params.query, state.encoding);
+            serde_json::to_value(WorkspaceSymbolResponse::Nested(symbols))?
+        }
+        Completion::METHOD => respond::<Completion>(req, |params| {
+            completions(state, &params.text_document_position)
+        })?,
+        HoverRequest::METHOD => respond::<HoverRequest>(req, |params| {
+            hover(state, &params.text_document_position_params)
+        })?,
+        SignatureHelpRequest::METHOD => respond::<SignatureHelpRequest>(req, |params| {
+            signature_help(state, &params.text_document_position_params)
+        })?,
+        References::METHOD => respond::<References>(req, |params| references(state, &params))?,
+        PrepareRenameRequest::METHOD => {
+            let params: TextDocumentPositionParams = serde_json::from_value(req.params.clone())?;
+            match prepare_rename(state, &params) {
+                Ok(response) => serde_json::to_value(response)?,
+                Err(message) => {
+                    return Ok(error_response(req, ErrorCode::RequestFailed, message));
+                }
+            }
+        }
+        SemanticTokensRangeRequest::METHOD => {
+            let params: SemanticTokensRangeParams = serde_json::from_value(req.params.clone())?;
+            let tokens = range_semantic_tokens(state, &params);
+            serde_json::to_value(tokens.map(SemanticTokensRangeResult::Tokens))?
+        }
+        Rename::METHOD => {
+            let params: RenameParams = serde_json::from_value(req.params.clone())?;
+            match rename(state, &params) {
+                Ok(edit) => serde_json::to_value(edit)?,
+                Err(message) => {
+                    return Ok(error_response(req, ErrorCode::RequestFailed, message));
+                }
+            }
+        }
+        _ => {
+            let message = format!("unhandled method: {}", req.method);
+            return Ok(error_response(req, ErrorCode::MethodNotFound, message));
+        }
+    };
+
+    Ok(Response::new_ok(req.id.clone(), result))
+}
+
+/// Answers a request whose handler cannot fail.
+fn respond<R: lsp_types::request::Request>(
+    req: &Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Result<serde_json::Value> {
+    let params = serde_json::from_value(req.params.clone())?;
+    Ok(serde_json::to_value(handler(params))?)
+}
+
+/// The params of a request read on the main loop, where malformed ones are answered with an
+/// error rather than stopping the loop.
+fn params_or_reject<P: serde::de::DeserializeOwned>(
+    req: &Request,
+    state: &State,
+) -> Result<Option<P>> {
+    match serde_json::from_value(req.params.clone()) {
+        Ok(params) => Ok(Some(params)),
+        Err(error) => {
+            let response = error_response(req, ErrorCode::InvalidParams, error.to_string());
+            state.sender.send(Message::Response(response))?;
+            Ok(None)
+        }
+    }
+}
+
+fn error_response(req: &Request, code: ErrorCode, message: String) -> Response {
+    Response::new_err(req.id.clone(), code as i32, message)
+}
+
+fn handle_task(task: Task, state: &mut State) -> Result<()> {
+    match task {
+        Task::Response(response) => {
+            // Cancelled requests were answered already.
+            if state.in_flight.remove(&response.id).is_some() {
+                state.sender.send(Message::Response(response))?;
+            }
+        }
+        Task::SemanticTokens {
+            response,
+            uri,
+            tokens,
+        } => {
+            if state.in_flight.remove(&response.id).is_none() {
+                return Ok(());
+            }
+            // Closed documents need no deltas.
+            if let Some(tokens) = tokens
+                && state.documents.contains_key(&uri.to_string())
+            {
+                state.semantic_tokens.insert(uri.to_string(), tokens);
+            }
+            state.sender.send(Message::Response(response))?;
+        }
+        Task::Diagnostics {
+            uri,
+            document,
+            diagnostics,
+        } => {
+            let current = state.documents.get(&uri.to_string());
+            if current.is_some_and(|current| current.text == document.text) {
+                publish_diagnostics(state, uri, diagnostics)?;
+            }
+        }
+        Task::DiagnosticsCancelled(uri) => schedule_diagnostics(state, uri),
+        Task::Scanned(scan) => {
+            state.workspace.add_scan(scan);
+            // Names may be defined by the files just found.
+            let open: Vec<Uri> = state
+                .documents
+                .keys()
+                .filter_map(|uri| uri.parse().ok())
+                .collect();
+            for uri in open {
+                schedule_diagnostics(state, uri);
+            }
+        }
+    }
+    Ok(())
+}
 
-fn handle_notification(
-    connection: &Connection,
-    not: &Notification,
-    documents: &mut HashMap<String, String>,
-) -> Result<()> {
+fn handle_notification(not: &Notification, state: &mut State) -> Result<()> {
     match not.method.as_str() {
+        Cancel::METHOD => {
+            let params: CancelParams = serde_json::from_value(not.params.clone())?;
+            let id = match params.id {
+                NumberOrString::Number(id) => RequestId::from(id),
+                NumberOrString::String(id) => RequestId::from(id),
+            };
+            if let Some(cancelled) = state.in_flight.remove(&id) {
+                cancelled.store(true, Ordering::Release);
+                let message = "the request was cancelled".to_string();
+                let response = Response::new_err(id, ErrorCode::RequestCanceled as i32, message);
+                state.sender.send(Message::Response(response))?;
+            }
+        }
+        DidChangeConfiguration::METHOD => {
+            let params: DidChangeConfigurationParams = serde_json::from_value(not.params.clone())?;
+            // Clients that can be asked only say that something changed.
+            if can_request_config(state) {
+                request_config(state)?;
+            } else {
+                let settings = params.settings.get("octave-lsp");
+                read_config(state, settings.unwrap_or(&params.settings))?;
+            }
+        }
         DidOpenTextDocument::METHOD => {
             let params: DidOpenTextDocumentParams = serde_json::from_value(not.params.clone())?;
             let uri = params.text_document.uri;
-            let text = params.text_document.text;
-            let key = uri.to_string();
-            documents.insert(key, text.clone());
-            publish_diagnostics(connection, &uri, &text);
+            update_document(state, &uri, params.text_document.text);
+            schedule_diagnostics(state, uri);
         }
         DidChangeTextDocument::METHOD => {
             let params: DidChangeTextDocumentParams = serde_json::from_value(not.params.clone())?;
+            change_document(state, params);
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(not.params.clone())?;
             let uri = params.text_document.uri;
-            let key = uri.to_string();
-            let mut text = documents.remove(&key).unwrap_or_default();
-            for change in &params.content_changes {
-                text.clone_from(&change.text);
+            Arc::make_mut(&mut state.documents).remove(&uri.to_string());
+            state.semantic_tokens.remove(&uri.to_string());
+            state.pending_diagnostics.remove(&uri);
+            // Unsaved changes are gone, so the file on disk is what counts again.
+            let path = document_path(&uri);
+            if state.workspace.contains(&path) {
+                state.workspace.reload(path);
+            } else {
+                state.workspace.remove(&path);
+            }
+        }
+        DidChangeWatchedFiles::METHOD => {
+            let params: DidChangeWatchedFilesParams = serde_json::from_value(not.params.clone())?;
+            for change in params.changes {
+                let Some(path) = uri_to_path(&change.uri) else {
+                    continue;
+                };
+                if state
+                    .workspace
+                    .root()
+                    .map(|root| root.join(project_file::FILE_NAME))
+                    == Some(path.clone())
+                {
+                    read_project_file(state)?;
+                    continue;
+                }
+                // The editor has the latest text of open documents, but the load path follows
+                // the startup script as saved.
+                if state.documents.contains_key(&change.uri.to_string())
+                    && !state.workspace.is_startup_script(&path)
+                {
+                    continue;
+                }
+                if change.typ == FileChangeType::DELETED {
+                    state.workspace.remove(&path);
+                } else if state.workspace.contains(&path) {
+                    state.workspace.reload(path);
+                }
             }
-            documents.insert(key, text.clone());
-            publish_diagnostics(connection, &uri, &text);
         }
         _ => {}
     }
     Ok(())
 }
 
-fn publish_diagnostics(connection: &Connection, uri: &Uri, text: &str) {
-    let parse = parse(text);
-    let syntax = parse.syntax();
-    let validation_errors = ast::validation::validate(&syntax);
+/// Applies the changes of the editor to a document, reparsing only what they touch when the
+/// database has the text they apply to.
+fn change_document(state: &mut State, params: DidChangeTextDocumentParams) {
+    let uri = params.text_document.uri;
+    let key = uri.to_string();
+    let mut text = Arc::make_mut(&mut state.documents)
+        .remove(&key)
+        .map(|document| {
+            Arc::try_unwrap(document).map_or_else(|shared| shared.text.clone(), |own| own.text)
+        })
+        .unwrap_or_default();
+    let path = document_path(&uri);
+    for change in params.content_changes {
+        let Some(range) = change.range else {
+            text = change.text;
+            continue;
+        };
+        let line_index = ide::LineIndex::new(&text);
+        let offset = |position: Position| {
+            let line_col = ide::LineCol {
+                line: position.line,
+                col: position.character,
+            };
+            line_index.offset(line_col, state.encoding)
+        };
+        let (Some(start), Some(end)) = (offset(range.start), offset(range.end)) else {
+            continue;
+        };
+        let edit = parser::TextEdit {
+            range: text_size::TextRange::new(start, end.max(start)),
+            insert: change.text,
+        };
+        // The database keeps the parse of the document, which the edit is applied to.
+        let db = &mut state.workspace.db;
+        if let Some(file) = db
+            .file_id(&path)
+            .filter(|&file| *db.file_text(file) == *text)
+        {
+            db.edit_file(file, &edit);
+        }
+        edit.apply(&mut text);
+    }
+    update_document(state, &uri, text);
+    schedule_diagnostics(state, uri);
+}
 
-    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+/// Records the text of an open document, which takes precedence over the file on disk.
+fn update_document(state: &mut State, uri: &Uri, text: String) {
+    state.workspace.update(document_path(uri), &text);
+    let document = Document::new(text, state.encoding);
+    Arc::make_mut(&mut state.documents).insert(uri.to_string(), Arc::new(document));
+}
+
+impl State {
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            capabilities: self.capabilities.clone(),
+            config: self.config.clone(),
+            encoding: self.encoding,
+            documents: self.documents.clone(),
+            workspace: self.workspace.snapshot(),
+        }
+    }
+}
+
+impl Document {
+    fn new(text: String, encoding: ide::PositionEncoding) -> Self {
+        Self {
+            line_index: ide::LineIndex::new(&text),
+            text,
+            encoding,
+        }
+    }
+
+    fn offset(&self, position: Position) -> Option<TextSize> {
+        let line_col = ide::LineCol {
+            line: position.line,
+            col: position.character,
+        };
+        self.line_index.offset(line_col, self.encoding)
+    }
+
+    /// The text between two positions, or none if they are the wrong way round.
+    fn text_range(&self, range: Range) -> Option<text_size::TextRange> {
+        let start = self.offset(range.start)?;
+        Some(text_size::TextRange::new(
+            start,
+            self.offset(range.end)?.max(start),
+        ))
+    }
+
+    fn position(&self, offset: TextSize) -> Position {
+        let line_col = self.line_index.line_col(offset, self.encoding);
+        Position::new(line_col.line, line_col.col)
+    }
+
+    fn range(&self, range: text_size::TextRange) -> Range {
+        Range::new(self.position(range.start()), self.position(range.end()))
+    }
+}
+
+/// Where the analyses of an open document are kept. Documents that are not files, such as new
+/// ones, are kept under their URI.
+fn document_path(uri: &Uri) -> PathBuf {
+    uri_to_path(uri).unwrap_or_else(|| PathBuf::from(uri.as_str()))
+}
+
+/// What is wrong with a document: its syntax errors, the errors of `ast::validation` and the
+/// lints of `hir`, without those the settings turn off.
+struct Problems {
+    parse: Arc<parser::Parse>,
+    validation: Vec<ast::validation::ValidationError>,
+    lints: Arc<Vec<hir::diagnostics::Diagnostic>>,
+}
+
+fn problems(uri: &Uri, text: &str, state: &Snapshot) -> Problems {
+    let db = &state.workspace.db;
+    // Open documents are always in the database, unless they were just taken out of the
+    // workspace.
+    let (parse, lints) = match db.file_id(&document_path(uri)) {
+        Some(file) if *db.file_text(file) == *text => (db.parse(file), db.diagnostics(file)),
+        _ => (Arc::new(parse(text)), Arc::default()),
+    };
+    let config = state.config.for_file(&document_path(uri));
+    let root = parse.syntax();
+    let mut validation = ast::validation::validate(&root);
+    if config.matlab_compatible {
+        use ast::validation::{NegationStyle, TerminatorStyle};
+        validation.extend(ast::validation::validate_negation_style(
+            &root,
+            NegationStyle::Tilde,
+        ));
+        validation.extend(ast::validation::validate_terminator_style(
+            &root,
+            TerminatorStyle::Plain,
+        ));
+    }
+    validation.retain(|error| config.is_enabled(error.code()));
+    let lints = if lints.iter().all(|lint| config.is_enabled(lint.kind.code())) {
+        lints
+    } else {
+        Arc::new(
+            lints
+                .iter()
+                .filter(|lint| config.is_enabled(lint.kind.code()))
+                .cloned()
+                .collect(),
+        )
+    };
+    Problems {
+        parse,
+        validation,
+        lints,
+    }
+}
 
-    for error in parse.errors() {
-        diagnostics.push(parse_error_to_diagnostic(error, text));
+/// Publishes the diagnostics of a document once it has stayed the same for a while.
+fn schedule_diagnostics(state: &mut State, uri: Uri) {
+    state.pending_diagnostics.insert(uri);
+    state.diagnostics_due = Some(Instant::now() + DIAGNOSTICS_DELAY);
+}
+
+/// Computes the diagnostics that are due on the worker threads.
+fn spawn_diagnostics(state: &mut State) {
+    state.diagnostics_due = None;
+    let snapshot = Arc::new(state.snapshot());
+    for uri in state.pending_diagnostics.drain() {
+        let Some(document) = snapshot.documents.get(&uri.to_string()).cloned() else {
+            continue;
+        };
+        let snapshot = snapshot.clone();
+        let tasks = state.tasks.clone();
+        state.pool.spawn(move || {
+            let diagnostics =
+                panic::catch_unwind(AssertUnwindSafe(|| diagnostics(&uri, &document, &snapshot)));
+            let task = match diagnostics {
+                Ok(diagnostics) => Task::Diagnostics {
+                    uri,
+                    document,
+                    diagnostics,
+                },
+                Err(payload) if payload.is::<Cancelled>() => Task::DiagnosticsCancelled(uri),
+                Err(_) => return,
+            };
+            let _ = tasks.send(task);
+        });
     }
+}
+
+fn diagnostics(uri: &Uri, document: &Document, state: &Snapshot) -> Vec<Diagnostic> {
+    let problems = problems(uri, &document.text, state);
 
-    for error in &validation_errors {
-        diagnostics.push(validation_error_to_diagnostic(error, text));
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for error in problems.parse.errors() {
+        diagnostics.push(parse_error_to_diagnostic(error, document));
+    }
+    for error in &problems.validation {
+        diagnostics.push(validation_error_to_diagnostic(error, uri, document));
     }
+    for diagnostic in problems.lints.iter() {
+        diagnostics.push(hir_diagnostic_to_diagnostic(diagnostic, document));
+    }
+    // Syntax errors are not `Problems` of their own, so they are turned off here.
+    let config = state.config.for_file(&document_path(uri));
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        diagnostic.severity = match config.lint_level(code) {
+            Some(LintLevel::Off) => return false,
+            Some(LintLevel::Hint) => Some(DiagnosticSeverity::HINT),
+            Some(LintLevel::Information) => Some(DiagnosticSeverity::INFORMATION),
+            Some(LintLevel::Warning) => Some(DiagnosticSeverity::WARNING),
+            Some(LintLevel::Error) => Some(DiagnosticSeverity::ERROR),
+            None => diagnostic.severity,
+        };
+        true
+    });
+    diagnostics
+}
 
+fn publish_diagnostics(state: &State, uri: Uri, diagnostics: Vec<Diagnostic>) -> Result<()> {
     let params = PublishDiagnosticsParams {
-        uri: uri.clone(),
+        uri,
         diagnostics,
         version: None,
     };
-
-    let _ = connection.sender.send(Message::Notification(Notification {
+    state.sender.send(Message::Notification(Notification {
         method: "textDocument/publishDiagnostics".to_string(),
-        params: serde_json::to_value(params).unwrap(),
-    }));
+        params: serde_json::to_value(params)?,
+    }))?;
+    Ok(())
 }
 
-fn offset_to_position(text: &str, offset: u32) -> Position {
-    let offset = offset as usize;
-    let mut line = 0u32;
-    let mut character = 0u32;
+/// The quick fixes for the problems in the range, and the refactorings of what it covers.
+fn code_actions(state: &Snapshot, params: &CodeActionParams) -> Option<Vec<CodeActionOrCommand>> {
+    let uri = &params.text_document.uri;
+    let document = state.documents.get(&uri.to_string())?;
+    let range = document.text_range(params.range)?;
+    let in_range = |problem: text_size::TextRange| problem.intersect(range).is_some();
 
-    for ch in text[..offset.min(text.len())].chars() {
-        if ch == '\n' {
-            line += 1;
-            character = 0;
-        } else {
-            character += 1;
+    let problems = problems(uri, &document.text, state);
+    let root: ast::Root = ast::TypedSyntaxNode::cast(problems.parse.syntax()).unwrap();
+    let path = uri_to_path(uri);
+    let mut actions = Vec::new();
+    for error in problems
+        .validation
+        .iter()
+        .filter(|error| in_range(error.range()))
+    {
+        let diagnostic = validation_error_to_diagnostic(error, uri, document);
+        for fix in ide::validation_fixes(&root, error) {
+            actions.extend(code_action(state, uri, document, fix, Some(&diagnostic)));
+        }
+    }
+    for lint in problems.lints.iter().filter(|lint| in_range(lint.range)) {
+        let diagnostic = hir_diagnostic_to_diagnostic(lint, document);
+        for fix in ide::lint_fixes(&root, lint, path.as_deref()) {
+            actions.extend(code_action(state, uri, document, fix, Some(&diagnostic)));
         }
     }
+    for refactor in ide::rewrites(&root, range)
+        .into_iter()
+        .chain(ide::extract_function(&root, range, path.as_deref()))
+        .chain(ide::extract_variable(&root, range))
+        .chain(ide::inline_variable(&root, range.start()))
+    {
+        actions.extend(code_action(state, uri, document, refactor, None));
+    }
 
-    Position { line, character }
+    if let Some(only) = &params.context.only {
+        actions.retain(|action| {
+            let kind = action.kind.as_ref().map_or("", CodeActionKind::as_str);
+            only.iter().any(|only| {
+                kind == only.as_str() || kind.starts_with(&format!("{}.", only.as_str()))
+            })
+        });
+    }
+    Some(
+        actions
+            .into_iter()
+            .map(CodeActionOrCommand::CodeAction)
+            .collect(),
+    )
 }
 
-fn parse_error_to_diagnostic(error: &parser::ParseError, text: &str) -> Diagnostic {
-    Diagnostic {
-        range: Range {
-            start: offset_to_position(text, u32::from(error.range.start())),
-            end: offset_to_position(text, u32::from(error.range.end())),
+/// Turns an action into its LSP form. Actions that create a file are left out if the client
+/// cannot create files or the file already exists.
+fn code_action(
+    state: &Snapshot,
+    uri: &Uri,
+    document: &Document,
+    action: ide::CodeAction,
+    diagnostic: Option<&Diagnostic>,
+) -> Option<CodeAction> {
+    let kind = match action.kind {
+        ide::CodeActionKind::QuickFix => CodeActionKind::QUICKFIX,
+        ide::CodeActionKind::Rewrite => CodeActionKind::REFACTOR_REWRITE,
+        ide::CodeActionKind::Extract => CodeActionKind::REFACTOR_EXTRACT,
+        ide::CodeActionKind::Inline => CodeActionKind::REFACTOR_INLINE,
+    };
+    let mut operations = Vec::new();
+    if let Some(new_file) = action.new_file {
+        let can_create = state
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref())
+            .and_then(|workspace_edit| workspace_edit.resource_operations.as_ref())
+            .is_some_and(|operations| operations.contains(&ResourceOperationKind::Create));
+        if !can_create || new_file.path.exists() {
+            return None;
+        }
+        let new_uri = path_to_uri(&new_file.path)?;
+        operations.push(DocumentChangeOperation::Op(ResourceOp::Create(
+            CreateFile {
+                uri: new_uri.clone(),
+                options: Some(CreateFileOptions {
+                    overwrite: Some(false),
+                    ignore_if_exists: Some(true),
+                }),
+                annotation_id: None,
+            },
+        )));
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: new_uri,
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit {
+                range: Range::default(),
+                new_text: new_file.text,
+            })],
+        }));
+    }
+    if !action.edits.is_empty() {
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: None,
+            },
+            edits: action
+                .edits
+                .into_iter()
+                .map(|edit| {
+                    OneOf::Left(TextEdit {
+                        range: document.range(edit.range),
+                        new_text: edit.text,
+                    })
+                })
+                .collect(),
+        }));
+    }
+    Some(CodeAction {
+        title: action.title,
+        kind: Some(kind),
+        diagnostics: diagnostic.map(|diagnostic| vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..WorkspaceEdit::default()
+        }),
+        is_preferred: action.is_preferred.then_some(true),
+        ..CodeAction::default()
+    })
+}
+
+fn document_symbols(document: &Document, include_variables: bool) -> Vec<DocumentSymbol> {
+    #[allow(deprecated)]
+    fn convert(symbol: ide::Symbol, document: &Document) -> DocumentSymbol {
+        DocumentSymbol {
+            name: symbol.name,
+            detail: symbol.detail,
+            kind: symbol_kind(symbol.kind),
+            tags: None,
+            deprecated: None,
+            range: document.range(symbol.range),
+            selection_range: document.range(symbol.selection_range),
+            children: Some(
+                symbol
+                    .children
+                    .into_iter()
+                    .map(|child| convert(child, document))
+                    .collect(),
+            ),
+        }
+    }
+
+    ide::document_symbols(&parse_root(&document.text), include_variables)
+        .into_iter()
+        .map(|symbol| convert(symbol, document))
+        .collect()
+}
+
+/// Folds whole lines, so folds within a single line are left out.
+fn folding_ranges(document: &Document) -> Vec<FoldingRange> {
+    ide::folding_ranges(&parse_root(&document.text))
+        .into_iter()
+        .filter_map(|fold| {
+            let start_line = document.position(fold.range.start()).line;
+            let end_line = document.position(fold.range.end()).line;
+            (end_line > start_line).then(|| FoldingRange {
+                start_line,
+                end_line,
+                kind: match fold.kind {
+                    ide::FoldKind::Block => None,
+                    ide::FoldKind::Comment => Some(FoldingRangeKind::Comment),
+                    ide::FoldKind::Region => Some(FoldingRangeKind::Region),
+                },
+                ..FoldingRange::default()
+            })
+        })
+        .collect()
+}
+
+/// Formats a document, or the lines a range touches, in the style of the settings. The
+/// indentation is the one the editor asks for unless they set it.
+fn formatting(
+    state: &Snapshot,
+    uri: &Uri,
+    range: Option<Range>,
+    options: &FormattingOptions,
+) -> Result<Option<Vec<TextEdit>>, String> {
+    let Some(document) = state.documents.get(&uri.to_string()) else {
+        return Ok(None);
+    };
+    let range = match range {
+        Some(range) => match document.text_range(range) {
+            Some(range) => Some(range),
+            None => return Ok(None),
         },
+        None => None,
+    };
+    let options = state
+        .config
+        .for_file(&document_path(uri))
+        .format_options(options.tab_size as usize, !options.insert_spaces);
+    let edits =
+        formatter::edits(&document.text, range, &options).map_err(|error| error.to_string())?;
+    Ok(Some(
+        edits
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: document.range(edit.range),
+                new_text: edit.text,
+            })
+            .collect(),
+    ))
+}
+
+/// Indents the new line after a line break, and closes the block it opens.
+fn on_type_formatting(
+    state: &Snapshot,
+    params: &DocumentOnTypeFormattingParams,
+) -> Option<Vec<TextEdit>> {
+    if params.ch != "\n" {
+        return None;
+    }
+    let (document, offset) = document_offset(state, &params.text_document_position)?;
+    let options = state
+        .config
+        .for_file(&document_path(
+            &params.text_document_position.text_document.uri,
+        ))
+        .format_options(
+            params.options.tab_size as usize,
+            !params.options.insert_spaces,
+        );
+    Some(
+        formatter::on_newline(&document.text, offset, &options)
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: document.range(edit.range),
+                new_text: edit.text,
+            })
+            .collect(),
+    )
+}
+
+fn workspace_symbols(
+    workspace: &WorkspaceSnapshot,
+    query: &str,
+    encoding: ide::PositionEncoding,
+) -> Vec<WorkspaceSymbol> {
+    workspace
+        .index
+        .search(query, MAX_WORKSPACE_SYMBOLS)
+        .into_iter()
+        .filter_map(|result| {
+            let start = result
+                .line_index
+                .line_col(result.symbol.range.start(), encoding);
+            let end = result
+                .line_index
+                .line_col(result.symbol.range.end(), encoding);
+            let range = Range {
+                start: Position::new(start.line, start.col),
+                end: Position::new(end.line, end.col),
+            };
+            Some(WorkspaceSymbol {
+                name: result.symbol.name.clone(),
+                kind: symbol_kind(result.symbol.kind),
+                tags: None,
+                container_name: result.symbol.container.clone(),
+                location: OneOf::Left(Location {
+                    uri: path_to_uri(result.path)?,
+                    range,
+                }),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+fn completions(
+    state: &Snapshot,
+    position: &TextDocumentPositionParams,
+) -> Option<CompletionResponse> {
+    let (document, offset) = document_offset(state, position)?;
+    let path = uri_to_path(&position.text_document.uri);
+    let completions = ide::completions(
+        &document_root(&state.workspace.db, &position.text_document.uri, document),
+        offset,
+        &state.workspace.project(),
+        &state.workspace.db.catalogue(),
+        path.as_deref(),
+    )?;
+
+    let snippet_support = state
+        .capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.completion.as_ref())
+        .and_then(|completion| completion.completion_item.as_ref())
+        .and_then(|item| item.snippet_support)
+        .unwrap_or(false);
+    let range = document.range(completions.range);
+    let items = completions
+        .items
+        .into_iter()
+        .filter(|item| snippet_support || item.snippet.is_none())
+        .enumerate()
+        .map(|(index, item)| CompletionItem {
+            kind: Some(match item.kind {
+                ide::CompletionKind::Variable => CompletionItemKind::VARIABLE,
+                ide::CompletionKind::Field => CompletionItemKind::FIELD,
+                ide::CompletionKind::Function => CompletionItemKind::FUNCTION,
+                ide::CompletionKind::Keyword => CompletionItemKind::KEYWORD,
+                ide::CompletionKind::Snippet => CompletionItemKind::SNIPPET,
+            }),
+            detail: item.detail,
+            documentation: item.documentation.map(Documentation::String),
+            // The client sorts by this once it has filtered the items by what was typed.
+            sort_text: Some(format!("{index:05}")),
+            insert_text_format: item.snippet.is_some().then_some(InsertTextFormat::SNIPPET),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: item.snippet.unwrap_or_else(|| item.label.clone()),
+            })),
+            label: item.label,
+            ..CompletionItem::default()
+        })
+        .collect();
+    Some(CompletionResponse::Array(items))
+}
+
+/// Describes the function at the cursor.
+fn hover(state: &Snapshot, position: &TextDocumentPositionParams) -> Option<Hover> {
+    let (document, offset) = document_offset(state, position)?;
+    let definition = |name: &str| definition_root(state, &position.text_document.uri, name);
+    let root = document_root(&state.workspace.db, &position.text_document.uri, document);
+    let hover = ide::hover(&root, offset, &state.workspace.db.catalogue(), definition)?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: hover.markdown,
+        }),
+        range: Some(document.range(hover.range)),
+    })
+}
+
+/// Shows the parameters of the function called at the cursor.
+fn signature_help(
+    state: &Snapshot,
+    position: &TextDocumentPositionParams,
+) -> Option<SignatureHelp> {
+    let (document, offset) = document_offset(state, position)?;
+    let definition = |name: &str| definition_root(state, &position.text_document.uri, name);
+    let root = document_root(&state.workspace.db, &position.text_document.uri, document);
+    let catalogue = state.workspace.db.catalogue();
+    let help = ide::signature_help(&root, offset, &catalogue, definition)?;
+
+    // Parameters are given as offsets into the label, counted like the columns of positions.
+    let len = |text: &str| state.encoding.measure(text);
+    let signatures = help
+        .signatures
+        .into_iter()
+        .map(|signature| {
+            let parameters = signature
+                .parameters
+                .iter()
+                .map(|parameter| {
+                    let start = len(&signature.label[..usize::from(parameter.range.start())]);
+                    let end = start + len(&signature.label[parameter.range]);
+                    ParameterInformation {
+                        label: ParameterLabel::LabelOffsets([start, end]),
+                        documentation: parameter.documentation.clone().map(Documentation::String),
+                    }
+                })
+                .collect();
+            SignatureInformation {
+                label: signature.label,
+                documentation: signature.documentation.map(Documentation::String),
+                parameters: Some(parameters),
+                active_parameter: None,
+            }
+        })
+        .collect();
+    Some(SignatureHelp {
+        signatures,
+        active_signature: u32::try_from(help.active_signature).ok(),
+        active_parameter: help
+            .active_parameter
+            .and_then(|parameter| u32::try_from(parameter).ok()),
+    })
+}
+
+/// Finds the uses of the variable or function at the cursor. Functions are looked for in every
+/// file where the name refers to the same definition; their uses in strings come last, as they are
+/// the least certain.
+fn references(state: &Snapshot, params: &ReferenceParams) -> Option<Vec<Location>> {
+    let position = &params.text_document_position;
+    let (document, offset) = document_offset(state, position)?;
+    let root = document_root(&state.workspace.db, &position.text_document.uri, document);
+    let target = ide::target_at(&root, offset)?;
+
+    let mut certain = Vec::new();
+    let mut uncertain = Vec::new();
+    for (uri, document, root) in target_files(state, &position.text_document.uri, document, &target)
+    {
+        for reference in ide::references(&root, &target) {
+            if reference.kind == ide::ReferenceKind::Definition
+                && !params.context.include_declaration
+            {
+                continue;
+            }
+            let location = Location {
+                uri: uri.clone(),
+                range: document.range(reference.range),
+            };
+            if reference.is_certain() {
+                certain.push(location);
+            } else {
+                uncertain.push(location);
+            }
+        }
+    }
+    certain.extend(uncertain);
+    Some(certain)
+}
+
+/// Checks that the name at the cursor can be renamed, giving its range.
+fn prepare_rename(
+    state: &Snapshot,
+    position: &TextDocumentPositionParams,
+) -> Result<PrepareRenameResponse, String> {
+    let (document, offset) = document_offset(state, position).ok_or("the document is not open")?;
+    let root = document_root(&state.workspace.db, &position.text_document.uri, document);
+    let (target, range) = ide::prepare_rename(&root, offset).map_err(|error| error.to_string())?;
+    renamed_definition(state, &position.text_document.uri, &root, &target)?;
+    Ok(PrepareRenameResponse::RangeWithPlaceholder {
+        range: document.range(range),
+        placeholder: target.name().to_string(),
+    })
+}
+
+/// Renames a variable within its scope, or a function everywhere it is used. A function named by
+/// its file takes the file along.
+fn rename(state: &Snapshot, params: &RenameParams) -> Result<WorkspaceEdit, String> {
+    let position = &params.text_document_position;
+    let uri = &position.text_document.uri;
+    let (document, offset) = document_offset(state, position).ok_or("the document is not open")?;
+    let root = document_root(&state.workspace.db, uri, document);
+    let (target, _) = ide::prepare_rename(&root, offset).map_err(|error| error.to_string())?;
+    let new_name = params.new_name.as_str();
+    ide::check_name(new_name).map_err(|error| error.to_string())?;
+    let definition = renamed_definition(state, uri, &root, &target)?;
+
+    let files = target_files(state, uri, document, &target);
+    let project = state.workspace.project();
+    for (file, _, root) in &files {
+        ide::check_conflicts(root, &target, new_name).map_err(|error| error.to_string())?;
+        let is_taken = matches!(target, ide::Target::Function { .. })
+            && uri_to_path(file)
+                .is_some_and(|path| project.resolve(new_name, Some(&path)).is_some());
+        if is_taken {
+            let error = ide::RenameError::Conflict {
+                name: new_name.to_string(),
+                kind: ide::ConflictKind::Function,
+            };
+            return Err(error.to_string());
+        }
+    }
+
+    let file_rename = match &definition {
+        Some(definition)
+            if definition.path.file_stem().and_then(|stem| stem.to_str())
+                == Some(target.name()) =>
+        {
+            Some(file_rename(state, &definition.path, new_name)?)
+        }
+        _ => None,
+    };
+
+    let workspace_edit = state
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.workspace_edit.as_ref());
+    let can_annotate = workspace_edit
+        .is_some_and(|workspace_edit| workspace_edit.change_annotation_support.is_some());
+    let annotation = || STRING_REFERENCES_ANNOTATION.to_string();
+
+    let mut operations = Vec::new();
+    let mut has_string_references = false;
+    for (uri, document, root) in files {
+        let edits: Vec<_> = ide::references(&root, &target)
+            .into_iter()
+            .map(|reference| {
+                let text_edit = TextEdit {
+                    range: document.range(reference.range),
+                    new_text: new_name.to_string(),
+                };
+                if reference.is_certain() || !can_annotate {
+                    OneOf::Left(text_edit)
+                } else {
+                    has_string_references = true;
+                    OneOf::Right(AnnotatedTextEdit {
+                        text_edit,
+                        annotation_id: annotation(),
+                    })
+                }
+            })
+            .collect();
+        if edits.is_empty() {
+            continue;
+        }
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits,
+        }));
+    }
+    // The edits refer to the file by its old name, so it is renamed last.
+    operations
+        .extend(file_rename.map(|rename| DocumentChangeOperation::Op(ResourceOp::Rename(rename))));
+
+    let change_annotations = has_string_references.then(|| {
+        HashMap::from([(
+            annotation(),
+            ChangeAnnotation {
+                label: "Rename in strings".to_string(),
+                needs_confirmation: Some(true),
+                description: Some(
+                    "The name also appears in strings given to functions such as `feval`."
+                        .to_string(),
+                ),
+            },
+        )])
+    });
+    Ok(WorkspaceEdit {
+        changes: None,
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        change_annotations,
+    })
+}
+
+/// Where the function being renamed is defined, if it is defined in the workspace. Builtins and
+/// functions we cannot find are not renamed, and neither are classes, whose names are also those
+/// of their directories.
+fn renamed_definition(
+    state: &Snapshot,
+    uri: &Uri,
+    root: &ast::Root,
+    target: &ide::Target,
+) -> Result<Option<project::Definition>, String> {
+    let ide::Target::Function { name } = target else {
+        return Ok(None);
+    };
+    let project = state.workspace.project();
+    let definition = uri_to_path(uri)
+        .filter(|path| project.file(path).is_some())
+        .and_then(|path| project.resolve(name, Some(&path)));
+    match definition {
+        Some(definition)
+            if matches!(
+                definition.kind,
+                project::DefinitionKind::Constructor | project::DefinitionKind::Method
+            ) =>
+        {
+            Err(format!(
+                "`{name}` belongs to a class, which cannot be renamed yet"
+            ))
+        }
+        Some(definition) => Ok(Some(definition)),
+        // Documents outside the workspace can still rename their own functions.
+        None if ide::references(root, target)
+            .iter()
+            .any(|reference| reference.kind == ide::ReferenceKind::Definition) =>
+        {
+            Ok(None)
+        }
+        None => Err(format!("`{name}` is not defined in the workspace")),
+    }
+}
+
+/// Renames the file of a function along with it, if the editor can do that.
+fn file_rename(state: &Snapshot, path: &Path, new_name: &str) -> Result<RenameFile, String> {
+    let can_rename_files = state
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.workspace_edit.as_ref())
+        .and_then(|workspace_edit| workspace_edit.resource_operations.as_ref())
+        .is_some_and(|operations| operations.contains(&ResourceOperationKind::Rename));
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    if !can_rename_files {
+        return Err(format!("the editor cannot rename `{file_name}`"));
+    }
+    let new_path = path.with_file_name(format!("{new_name}.m"));
+    if new_path.exists() {
+        return Err(format!("`{new_name}.m` already exists"));
+    }
+    let (Some(old_uri), Some(new_uri)) = (path_to_uri(path), path_to_uri(&new_path)) else {
+        return Err(format!("`{file_name}` cannot be renamed"));
+    };
+    Ok(RenameFile {
+        old_uri,
+        new_uri,
+        options: None,
+        annotation_id: None,
+    })
+}
+
+fn semantic_tokens_legend() -> SemanticTokensLegend {
+    let token_types = ide::SemanticTokenKind::ALL
+        .iter()
+        .map(|kind| match kind {
+            ide::SemanticTokenKind::Comment => SemanticTokenType::COMMENT,
+            ide::SemanticTokenKind::Number => SemanticTokenType::NUMBER,
+            ide::SemanticTokenKind::String => SemanticTokenType::STRING,
+            ide::SemanticTokenKind::Keyword => SemanticTokenType::KEYWORD,
+            ide::SemanticTokenKind::Operator => SemanticTokenType::OPERATOR,
+            ide::SemanticTokenKind::Variable => SemanticTokenType::VARIABLE,
+            ide::SemanticTokenKind::Parameter => SemanticTokenType::PARAMETER,
+            ide::SemanticTokenKind::Function => SemanticTokenType::FUNCTION,
+            ide::SemanticTokenKind::Property => SemanticTokenType::PROPERTY,
+        })
+        .collect();
+    let token_modifiers = ide::SemanticTokenModifier::ALL
+        .iter()
+        .map(|modifier| match modifier {
+            ide::SemanticTokenModifier::Declaration => SemanticTokenModifier::DECLARATION,
+            ide::SemanticTokenModifier::Readonly => SemanticTokenModifier::READONLY,
+            ide::SemanticTokenModifier::DefaultLibrary => SemanticTokenModifier::DEFAULT_LIBRARY,
+            // Not one of the standard modifiers, so themes only color it if they know it.
+            ide::SemanticTokenModifier::Global => SemanticTokenModifier::new("global"),
+            ide::SemanticTokenModifier::Static => SemanticTokenModifier::STATIC,
+        })
+        .collect();
+    SemanticTokensLegend {
+        token_types,
+        token_modifiers,
+    }
+}
+
+/// Colors the whole document.
+fn full_semantic_tokens(state: &Snapshot, uri: &Uri, result_id: String) -> Option<SemanticTokens> {
+    let document = state.documents.get(&uri.to_string())?;
+    let db = &state.workspace.db;
+    let root = document_root(db, uri, document);
+    Some(SemanticTokens {
+        result_id: Some(result_id),
+        data: encode_semantic_tokens(
+            &root,
+            &db.catalogue(),
+            document,
+            None,
+            multiline_tokens(&state.capabilities),
+        ),
+    })
+}
+
+/// Sends only the tokens that changed since the `previous` ones, as a single edit replacing the
+/// run between the common start and end. If those tokens are gone, sends them all again.
+fn semantic_tokens_delta(
+    tokens: SemanticTokens,
+    previous: Option<Vec<SemanticToken>>,
+) -> SemanticTokensFullDeltaResult {
+    let Some(previous) = previous else {
+        return SemanticTokensFullDeltaResult::Tokens(tokens);
+    };
+
+    let prefix = previous
+        .iter()
+        .zip(&tokens.data)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(tokens.data[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let deleted = previous.len() - prefix - suffix;
+    let inserted = &tokens.data[prefix..tokens.data.len() - suffix];
+    let edits = if deleted == 0 && inserted.is_empty() {
+        Vec::new()
+    } else {
+        // Edits count the integers of the encoding, five for each token.
+        let integers = |tokens: usize| u32::try_from(tokens * 5).unwrap_or(u32::MAX);
+        vec![SemanticTokensEdit {
+            start: integers(prefix),
+            delete_count: integers(deleted),
+            data: Some(inserted.to_vec()),
+        }]
+    };
+    SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+        result_id: tokens.result_id,
+        edits,
+    })
+}
+
+fn range_semantic_tokens(
+    state: &Snapshot,
+    params: &SemanticTokensRangeParams,
+) -> Option<SemanticTokens> {
+    let uri = &params.text_document.uri;
+    let document = state.documents.get(&uri.to_string())?;
+    let range = document.text_range(params.range)?;
+    let db = &state.workspace.db;
+    let root = document_root(db, uri, document);
+    Some(SemanticTokens {
+        result_id: None,
+        data: encode_semantic_tokens(
+            &root,
+            &db.catalogue(),
+            document,
+            Some(range),
+            multiline_tokens(&state.capabilities),
+        ),
+    })
+}
+
+/// Encodes the tokens of a document relative to each other, as the protocol wants them.
+/// Tokens spanning several lines, as block comments do, are split at the line breaks unless the
+/// client takes them whole.
+fn encode_semantic_tokens(
+    root: &ast::Root,
+    catalogue: &Catalogue,
+    document: &Document,
+    range: Option<text_size::TextRange>,
+    multiline: bool,
+) -> Vec<SemanticToken> {
+    let mut previous = Position::new(0, 0);
+    let mut data = Vec::new();
+    for token in ide::semantic_tokens(root, catalogue, range) {
+        let token_type = ide::SemanticTokenKind::ALL
+            .iter()
+            .position(|kind| *kind == token.kind)
+            .unwrap_or_default();
+        let token_modifiers_bitset = token
+            .modifiers
+            .iter()
+            .filter_map(|modifier| {
+                ide::SemanticTokenModifier::ALL
+                    .iter()
+                    .position(|other| other == modifier)
+            })
+            .fold(0, |bits, index| bits | (1 << index));
+        for range in token_lines(&document.text, token.range, multiline) {
+            let start = document.position(range.start());
+            let length = document.encoding.measure(&document.text[range]);
+            data.push(SemanticToken {
+                delta_line: start.line - previous.line,
+                delta_start: if start.line == previous.line {
+                    start.character - previous.character
+                } else {
+                    start.character
+                },
+                length,
+                token_type: u32::try_from(token_type).unwrap_or_default(),
+                token_modifiers_bitset,
+            });
+            previous = start;
+        }
+    }
+    data
+}
+
+/// The ranges a token is sent as: the whole token, or else the text of each of its lines.
+fn token_lines(
+    text: &str,
+    range: text_size::TextRange,
+    multiline: bool,
+) -> Vec<text_size::TextRange> {
+    if multiline {
+        return vec![range];
+    }
+    let mut start = range.start();
+    let mut lines = Vec::new();
+    for line in text[range].split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        if !content.is_empty() {
+            lines.push(text_size::TextRange::at(start, TextSize::of(content)));
+        }
+        start += TextSize::of(line);
+    }
+    lines
+}
+
+/// Whether the client takes semantic tokens that span several lines.
+fn multiline_tokens(capabilities: &ClientCapabilities) -> bool {
+    capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.semantic_tokens.as_ref())
+        .and_then(|semantic_tokens| semantic_tokens.multiline_token_support)
+        .unwrap_or(false)
+}
+
+/// The document a request is about, and the offset of its position.
+fn document_offset<'a>(
+    state: &'a Snapshot,
+    position: &TextDocumentPositionParams,
+) -> Option<(&'a Arc<Document>, TextSize)> {
+    let document = state
+        .documents
+        .get(&position.text_document.uri.to_string())?;
+    let offset = document.offset(position.position)?;
+    Some((document, offset))
+}
+
+/// The files that may refer to `target`, with their text and syntax tree. That is the document
+/// itself and, for a function of the project, every other file where its name refers to the same
+/// definition. Files are taken from the database, which holds the unsaved text of open documents.
+fn target_files(
+    state: &Snapshot,
+    uri: &Uri,
+    document: &Arc<Document>,
+    target: &ide::Target,
+) -> Vec<(Uri, Arc<Document>, ast::Root)> {
+    let db = &state.workspace.db;
+    let root = document_root(db, uri, document);
+    let mut files = vec![(uri.clone(), document.clone(), root)];
+    let project = state.workspace.project();
+    let ide::Target::Function { name } = target else {
+        return files;
+    };
+    let Some(path) = uri_to_path(uri).filter(|path| project.file(path).is_some()) else {
+        return files;
+    };
+
+    let open_documents: HashMap<_, _> = state
+        .documents
+        .iter()
+        .filter_map(|(uri, document)| Some((uri_to_path(&uri.parse().ok()?)?, document)))
+        .collect();
+    let definition = |from: &Path| project.resolve(name, Some(from)).map(|def| def.path);
+    let expected = definition(&path);
+
+    for other in project.files().filter(|other| *other != path) {
+        if definition(other) != expected {
+            continue;
+        }
+        let Some(file) = db.file_id(other) else {
+            continue;
+        };
+        let text = db.file_text(file);
+        if !text.contains(name.as_str()) {
+            continue;
+        }
+        let Some(uri) = path_to_uri(other) else {
+            continue;
+        };
+        let document = match open_documents.get(other) {
+            Some(document) if *document.text == *text => Arc::clone(document),
+            _ => Arc::new(Document::new(text.to_string(), state.encoding)),
+        };
+        let root = ast::TypedSyntaxNode::cast(db.parse(file).syntax()).unwrap();
+        files.push((uri, document, root));
+    }
+    files
+}
+
+/// The file that defines the function `name` as called from `uri`. It is read from the open
+/// document, or else from disk.
+fn definition_root(state: &Snapshot, uri: &Uri, name: &str) -> Option<ast::Root> {
+    let path = uri_to_path(uri);
+    let definition = state.workspace.project().resolve(name, path.as_deref())?;
+    let db = &state.workspace.db;
+    match db.file_id(&definition.path) {
+        Some(file) => ast::TypedSyntaxNode::cast(db.parse(file).syntax()),
+        None => Some(parse_root(&file_text(state, &definition.path)?)),
+    }
+}
+
+/// The text of a file of the project: that of its open document, or else what is on disk.
+fn file_text(state: &Snapshot, path: &Path) -> Option<String> {
+    let open = state.documents.iter().find_map(|(uri, document)| {
+        let uri = uri.parse().ok()?;
+        (uri_to_path(&uri)? == path).then(|| document.text.clone())
+    });
+    open.or_else(|| fs::read_to_string(path).ok())
+}
+
+/// The syntax tree of an open document. It is the parse of the database, which is only computed
+/// again when the text changes, unless the document was just taken out of the workspace.
+fn document_root(db: &AnalysisDatabase, uri: &Uri, document: &Document) -> ast::Root {
+    match db.file_id(&document_path(uri)) {
+        Some(file) if *db.file_text(file) == *document.text => {
+            ast::TypedSyntaxNode::cast(db.parse(file).syntax()).unwrap()
+        }
+        _ => parse_root(&document.text),
+    }
+}
+
+fn parse_root(text: &str) -> ast::Root {
+    ast::TypedSyntaxNode::cast(parse(text).syntax()).unwrap()
+}
+
+fn symbol_kind(kind: ide::SymbolKind) -> SymbolKind {
+    match kind {
+        ide::SymbolKind::Function => SymbolKind::FUNCTION,
+        ide::SymbolKind::Variable => SymbolKind::VARIABLE,
+        ide::SymbolKind::Section => SymbolKind::NAMESPACE,
+        ide::SymbolKind::Script => SymbolKind::FILE,
+        ide::SymbolKind::Class => SymbolKind::CLASS,
+        ide::SymbolKind::Method => SymbolKind::METHOD,
+    }
+}
+
+fn parse_error_to_diagnostic(error: &parser::ParseError, document: &Document) -> Diagnostic {
+    Diagnostic {
+        range: document.range(error.range),
         severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String("syntax-error".to_string())),
         message: error.to_string(),
         ..Diagnostic::default()
     }
@@ -136,16 +2051,269 @@ fn parse_error_to_diagnostic(error: &parser::ParseError, text: &str) -> Diagnost
 
 fn validation_error_to_diagnostic(
     error: &ast::validation::ValidationError,
-    text: &str,
+    uri: &Uri,
+    document: &Document,
 ) -> Diagnostic {
     let range = error.range();
+    let severity = match error.severity() {
+        ast::validation::Severity::Error => DiagnosticSeverity::ERROR,
+        ast::validation::Severity::Warning => DiagnosticSeverity::WARNING,
+    };
+    let related_information = error.opener_range().map(|opener| {
+        vec![DiagnosticRelatedInformation {
+            location: Location {
+                uri: uri.clone(),
+                range: document.range(opener),
+            },
+            message: "block opened here".to_string(),
+        }]
+    });
     Diagnostic {
-        range: Range {
-            start: offset_to_position(text, u32::from(range.start())),
-            end: offset_to_position(text, u32::from(range.end())),
-        },
-        severity: Some(DiagnosticSeverity::ERROR),
+        range: document.range(range),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(error.code().to_string())),
         message: error.to_string(),
+        related_information,
+        ..Diagnostic::default()
+    }
+}
+
+fn hir_diagnostic_to_diagnostic(
+    diagnostic: &hir::diagnostics::Diagnostic,
+    document: &Document,
+) -> Diagnostic {
+    let severity = match diagnostic.severity() {
+        hir::diagnostics::Severity::Error => DiagnosticSeverity::ERROR,
+        hir::diagnostics::Severity::Warning => DiagnosticSeverity::WARNING,
+    };
+    let tags = diagnostic
+        .is_unnecessary()
+        .then(|| vec![DiagnosticTag::UNNECESSARY]);
+    Diagnostic {
+        range: document.range(diagnostic.range),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(diagnostic.kind.code().to_string())),
+        message: diagnostic.kind.to_string(),
+        tags,
         ..Diagnostic::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{TextDocumentContentChangeEvent, VersionedTextDocumentIdentifier};
+
+    fn state(encoding: ide::PositionEncoding) -> State {
+        state_with_tasks(encoding).0
+    }
+
+    /// A state along with what its worker threads send back.
+    fn state_with_tasks(encoding: ide::PositionEncoding) -> (State, Receiver<Task>) {
+        let (sender, _) = crossbeam_channel::unbounded();
+        let (tasks, task_receiver) = crossbeam_channel::unbounded();
+        let state = State {
+            capabilities: Arc::default(),
+            config: Arc::default(),
+            client_config: Config::default(),
+            project_file: None,
+            encoding,
+            documents: Arc::default(),
+            workspace: Workspace::new(&InitializeParams::default(), &Config::default()),
+            semantic_tokens: HashMap::new(),
+            next_result_id: 0,
+            sender,
+            pool: ThreadPool::new(),
+            tasks,
+            in_flight: HashMap::new(),
+            pending_diagnostics: HashSet::new(),
+            diagnostics_due: None,
+            configuration_request: None,
+            configuration_requests: 0,
+        };
+        (state, task_receiver)
+    }
+
+    /// Replaces the text between two columns of a line.
+    fn change(state: &mut State, uri: &Uri, line: u32, cols: (u32, u32), text: &str) {
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    Position::new(line, cols.0),
+                    Position::new(line, cols.1),
+                )),
+                range_length: None,
+                text: text.to_string(),
+            }],
+        };
+        change_document(state, params);
+    }
+
+    #[test]
+    fn malformed_semantic_token_params_are_rejected() {
+        let mut state = state(ide::PositionEncoding::Utf16);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        state.sender = sender;
+        let req = Request::new(
+            RequestId::from(1),
+            SemanticTokensFullRequest::METHOD.to_string(),
+            serde_json::json!({ "textDocument": 1 }),
+        );
+        handle_request(req, &mut state).unwrap();
+        let Ok(Message::Response(response)) = receiver.try_recv() else {
+            panic!("expected a response");
+        };
+        assert_eq!(
+            response.error.map(|error| error.code),
+            Some(ErrorCode::InvalidParams as i32)
+        );
+        assert!(state.in_flight.is_empty());
+    }
+
+    #[test]
+    fn change_after_astral_char() {
+        let uri: Uri = "file:///project/script.m".parse().unwrap();
+        // The emoji is two UTF-16 code units, four bytes and one character.
+        let text = "x = 1; % 😀 y\nz = x;\n";
+        for (encoding, col) in [
+            (ide::PositionEncoding::Utf16, 12),
+            (ide::PositionEncoding::Utf8, 14),
+            (ide::PositionEncoding::Utf32, 11),
+        ] {
+            let mut state = state(encoding);
+            update_document(&mut state, &uri, text.to_string());
+            change(&mut state, &uri, 0, (col, col + 1), "w");
+
+            let expected = "x = 1; % 😀 w\nz = x;\n";
+            assert_eq!(state.documents[&uri.to_string()].text, expected);
+            let db = &state.workspace.db;
+            let file = db.file_id(&document_path(&uri)).unwrap();
+            assert_eq!(*db.file_text(file), *expected);
+            assert_eq!(
+                db.parse(file).debug_tree(),
+                parse(expected).debug_tree(),
+                "{encoding:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn edits_cancel_the_queries_in_flight() {
+        let (mut state, tasks) = state_with_tasks(ide::PositionEncoding::Utf16);
+        let uri: Uri = "file:///project/script.m".parse().unwrap();
+        update_document(&mut state, &uri, "x = 1;\n".to_string());
+
+        let (started, wait_for_start) = crossbeam_channel::bounded(0);
+        let (edited, wait_for_edit) = crossbeam_channel::bounded(0);
+        let req = Request::new(
+            RequestId::from(1),
+            "test".to_string(),
+            serde_json::Value::Null,
+        );
+        spawn_with(req, &mut state, move |req, state| {
+            started.send(()).unwrap();
+            let _: () = wait_for_edit.recv().unwrap();
+            let db = &state.workspace.db;
+            let file = db.file_id(Path::new("/project/script.m")).unwrap();
+            let lints = db.diagnostics(file).len();
+            Ok(Task::Response(Response::new_ok(req.id.clone(), lints)))
+        });
+        // The query starts before the edit, and asks the database after it.
+        let _: () = wait_for_start.recv().unwrap();
+        change(&mut state, &uri, 0, (4, 5), "2");
+        edited.send(()).unwrap();
+
+        let Ok(Task::Response(response)) = tasks.recv() else {
+            panic!("expected a response");
+        };
+        assert_eq!(response.id, RequestId::from(1));
+        assert_eq!(
+            response.error.map(|error| error.code),
+            Some(ErrorCode::ContentModified as i32)
+        );
+    }
+
+    #[test]
+    fn references_are_found_in_the_text_of_the_database() {
+        let mut state = state(ide::PositionEncoding::Utf16);
+        let init_params = InitializeParams {
+            workspace_folders: Some(vec![lsp_types::WorkspaceFolder {
+                uri: "file:///project".parse().unwrap(),
+                name: "project".to_string(),
+            }]),
+            ..InitializeParams::default()
+        };
+        state.workspace = Workspace::new(&init_params, &Config::default());
+        let uri: Uri = "file:///project/helper.m".parse().unwrap();
+        update_document(&mut state, &uri, "function helper\nend\n".to_string());
+        // Not open, and not on disk either.
+        state
+            .workspace
+            .update("/project/main.m".into(), "x = 1;\nhelper();\n");
+
+        let params = ReferenceParams {
+            text_document_position: TextDocumentPositionParams::new(
+                lsp_types::TextDocumentIdentifier::new(uri),
+                Position::new(0, 10),
+            ),
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+            partial_result_params: lsp_types::PartialResultParams::default(),
+            context: lsp_types::ReferenceContext {
+                include_declaration: false,
+            },
+        };
+        let locations = references(&state.snapshot(), &params).unwrap();
+        let locations: Vec<_> = locations
+            .iter()
+            .map(|location| (location.uri.as_str(), location.range))
+            .collect();
+        assert_eq!(
+            locations,
+            [(
+                "file:///project/main.m",
+                Range::new(Position::new(1, 0), Position::new(1, 6))
+            )]
+        );
+    }
+
+    #[test]
+    fn semantic_tokens_are_measured_in_the_encoding() {
+        let text = "% 😀\nx = 1;\n";
+        for (encoding, length) in [
+            (ide::PositionEncoding::Utf8, 6),
+            (ide::PositionEncoding::Utf16, 4),
+            (ide::PositionEncoding::Utf32, 3),
+        ] {
+            let document = Document::new(text.to_string(), encoding);
+            let tokens = encode_semantic_tokens(
+                &parse_root(text),
+                &Catalogue::bundled(),
+                &document,
+                None,
+                false,
+            );
+            assert_eq!(tokens[0].length, length, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn block_comments_are_split_into_lines_unless_the_client_takes_them_whole() {
+        let text = "%{\n  kept\r\n\n%}\nx = 1;\n";
+        let document = Document::new(text.to_string(), ide::PositionEncoding::Utf16);
+        let encode = |multiline| {
+            encode_semantic_tokens(
+                &parse_root(text),
+                &Catalogue::bundled(),
+                &document,
+                None,
+                multiline,
+            )
+            .iter()
+            .map(|token| (token.delta_line, token.delta_start, token.length))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(encode(false)[..3], [(0, 0, 2), (1, 0, 6), (2, 0, 2)]);
+        assert_eq!(encode(true)[..2], [(0, 0, 14), (4, 0, 1)]);
+    }
+}
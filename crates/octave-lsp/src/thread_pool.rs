@@ -0,0 +1,38 @@
+//! The worker threads that answer requests away from the main loop.
+
+use std::num::NonZero;
+use std::thread;
+
+use crossbeam_channel::Sender;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs jobs in the order they are spawned, on as many threads as there are cores. The threads
+/// stop once the pool is dropped and the jobs left are done.
+pub struct ThreadPool {
+    sender: Sender<Job>,
+}
+
+impl ThreadPool {
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(4, NonZero::get);
+        let (sender, receiver) = crossbeam_channel::unbounded::<Job>();
+        for index in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("worker-{index}"))
+                .spawn(move || {
+                    for job in receiver {
+                        job();
+                    }
+                })
+                .expect("failed to spawn a worker thread");
+        }
+        Self { sender }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        // The workers only stop once the sender is gone.
+        let _ = self.sender.send(Box::new(job));
+    }
+}
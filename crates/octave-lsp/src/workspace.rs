@@ -0,0 +1,322 @@
+//! The `.m` files of the workspace, including those that are not open in the editor.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use hir::db::{self, AnalysisDatabase};
+use lsp_types::{InitializeParams, Uri};
+use project::{LoadPathConfig, LoadPathEntry, Project};
+
+use crate::config::Config;
+
+/// The directories we look for `.m` files in, and what we found in them.
+#[derive(Debug, Default)]
+pub struct Workspace {
+    /// The files of the workspace and the open documents, and what we know about them.
+    pub db: AnalysisDatabase,
+    /// Shared with the snapshots, and copied when it changes while they are around.
+    pub index: Arc<ide::SymbolIndex>,
+    roots: Vec<PathBuf>,
+    /// Where relative paths start from: the first workspace folder, as Octave is usually started
+    /// there.
+    cwd: Option<PathBuf>,
+    /// The directories of the `loadPath` setting.
+    configured_load_path: Vec<PathBuf>,
+    /// A script whose `addpath` calls extend the load path, from the `startupScript` setting.
+    startup_script: Option<PathBuf>,
+    /// The directories whose files are left out, from the project file.
+    excluded: Vec<PathBuf>,
+    /// Set when the load path changed, until a [`Scan`] is started to read the files it adds.
+    scan_due: bool,
+}
+
+impl Workspace {
+    /// Uses the workspace folders, or the root of older clients, and the `loadPath` and
+    /// `startupScript` settings. Relative paths are resolved against the first workspace folder.
+    pub fn new(init_params: &InitializeParams, config: &Config) -> Self {
+        let roots = folders(init_params);
+        let mut workspace = Self {
+            db: AnalysisDatabase::default(),
+            index: Arc::default(),
+            cwd: roots.first().cloned(),
+            roots,
+            configured_load_path: Vec::new(),
+            startup_script: None,
+            excluded: Vec::new(),
+            scan_due: false,
+        };
+        workspace.configure(config);
+        workspace.load_path_changed();
+        workspace
+    }
+
+    /// Takes the load path, the startup script and the excluded directories from new settings.
+    /// If they changed, the files they add are left to a [`Scan`].
+    pub fn set_config(&mut self, config: &Config) {
+        let old = (
+            self.configured_load_path.clone(),
+            self.startup_script.clone(),
+            self.excluded.clone(),
+        );
+        self.configure(config);
+        let new = (
+            &self.configured_load_path,
+            &self.startup_script,
+            &self.excluded,
+        );
+        if new != (&old.0, &old.1, &old.2) {
+            self.load_path_changed();
+        }
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.configured_load_path = config
+            .load_path
+            .iter()
+            .map(|path| self.absolute(path))
+            .collect();
+        self.startup_script = config
+            .startup_script
+            .as_ref()
+            .map(|path| self.absolute(path));
+        self.excluded = config
+            .exclude
+            .iter()
+            .map(|path| self.absolute(path))
+            .collect();
+    }
+
+    /// The first workspace folder, where the project file is.
+    pub fn root(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    /// Resolves a path of the settings against the first workspace folder.
+    pub fn absolute(&self, path: &Path) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Returns what requests need of the workspace, to answer them on another thread.
+    pub fn snapshot(&self) -> WorkspaceSnapshot {
+        WorkspaceSnapshot {
+            db: self.db.snapshot(),
+            index: self.index.clone(),
+        }
+    }
+
+    /// The load path after the workspace folders: whatever the startup script prepends, the
+    /// configured directories and whatever the startup script appends.
+    fn load_path(&self) -> Vec<LoadPathEntry> {
+        let changes = self
+            .startup_script
+            .as_ref()
+            .and_then(|script| fs::read_to_string(script).ok())
+            .zip(self.cwd.as_ref())
+            .map(|(text, cwd)| project::startup::load_path_changes(&text, cwd))
+            .unwrap_or_default();
+
+        changes
+            .prepended
+            .into_iter()
+            .chain(
+                self.configured_load_path
+                    .iter()
+                    .cloned()
+                    .map(LoadPathEntry::Dir),
+            )
+            .chain(changes.appended)
+            .collect()
+    }
+
+    /// The load path whose files are to be read, if it changed since the last [`Scan`] started.
+    pub fn take_scan(&mut self) -> Option<LoadPathConfig> {
+        std::mem::take(&mut self.scan_due).then(|| self.db.load_path_config().clone())
+    }
+
+    /// Adds the files of a scan that are still under the workspace folders or the load path.
+    /// Files that are known already keep their text, which may be that of an open document.
+    pub fn add_scan(&mut self, scan: Scan) {
+        let mut index = scan.index;
+        for (path, text) in scan.files {
+            if !self.db.load_path_config().contains(&path) {
+                index.remove(&path);
+                continue;
+            }
+            match self.db.file_id(&path) {
+                Some(file) => index.insert(path, &self.db.file_text(file)),
+                None => {
+                    self.db.set_file_text(path, &text);
+                }
+            }
+        }
+        Arc::make_mut(&mut self.index).extend(index);
+    }
+
+    /// Reads a file from disk again, forgetting it if it is gone.
+    pub fn reload(&mut self, path: PathBuf) {
+        match fs::read_to_string(&path) {
+            Ok(text) => self.update(path, &text),
+            Err(_) => self.remove(&path),
+        }
+    }
+
+    /// Records the text of a file. Open documents outside of the workspace are only analysed on
+    /// their own.
+    pub fn update(&mut self, path: PathBuf, text: &str) {
+        let is_startup_script = self.is_startup_script(&path);
+        if self.db.load_path_config().contains(&path) {
+            Arc::make_mut(&mut self.index).insert(path.clone(), text);
+        }
+        self.db.set_file_text(path, text);
+        if is_startup_script {
+            self.load_path_changed();
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if self.index.contains(path) {
+            Arc::make_mut(&mut self.index).remove(path);
+        }
+        self.db.remove_file(path);
+        if self.is_startup_script(path) {
+            self.load_path_changed();
+        }
+    }
+
+    pub fn is_startup_script(&self, path: &Path) -> bool {
+        self.startup_script.as_deref() == Some(path)
+    }
+
+    /// Forgets the files that are no longer under the workspace folders or the load path, and
+    /// leaves those it adds to a [`Scan`].
+    fn load_path_changed(&mut self) {
+        let config = LoadPathConfig {
+            roots: self.roots.clone(),
+            load_path: self.load_path(),
+            excluded: self.excluded.clone(),
+        };
+        if *self.db.load_path_config() == config {
+            return;
+        }
+        let gone: Vec<PathBuf> = self
+            .db
+            .files()
+            .map(|file| self.db.path(file))
+            .filter(|path| self.index.contains(path) && !config.contains(path))
+            .map(Path::to_path_buf)
+            .collect();
+        self.db.set_load_path_config(config);
+        for path in gone {
+            self.remove(&path);
+        }
+        self.scan_due = true;
+    }
+
+    /// Whether changes to `path` concern us.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.db.load_path_config().contains(path) || self.is_startup_script(path)
+    }
+}
+
+/// The workspace as a request sees it, which stays the same while the workspace changes.
+pub struct WorkspaceSnapshot {
+    pub db: db::Snapshot,
+    pub index: Arc<ide::SymbolIndex>,
+}
+
+impl WorkspaceSnapshot {
+    /// The project model of the files under the workspace folders and the load path.
+    pub fn project(&self) -> Arc<Project> {
+        self.db.project()
+    }
+}
+
+/// The `.m` files of the project, read and indexed away from the main loop.
+pub struct Scan {
+    files: Vec<(PathBuf, String)>,
+    index: ide::SymbolIndex,
+}
+
+impl Scan {
+    pub fn read(config: &LoadPathConfig) -> Self {
+        let mut scan = Self {
+            files: Vec::new(),
+            index: ide::SymbolIndex::default(),
+        };
+        for path in config.discover() {
+            if let Ok(text) = fs::read_to_string(&path) {
+                scan.index.insert(path.clone(), &text);
+                scan.files.push((path, text));
+            }
+        }
+        scan
+    }
+}
+
+/// The workspace folders, or the root of older clients.
+pub fn folders(init_params: &InitializeParams) -> Vec<PathBuf> {
+    #[allow(deprecated)]
+    match &init_params.workspace_folders {
+        Some(folders) => folders
+            .iter()
+            .filter_map(|folder| uri_to_path(&folder.uri))
+            .collect(),
+        None => init_params
+            .root_uri
+            .iter()
+            .filter_map(uri_to_path)
+            .collect(),
+    }
+}
+
+/// Converts a `file:` URI into a path. Other schemes have no path.
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    let uri = uri.as_str();
+    let path = uri.strip_prefix("file://")?;
+    // Skip the authority, which is usually empty.
+    let path = &path[path.find('/')?..];
+    let path = percent_decode(path)?;
+    // Windows paths look like `/c:/Users`.
+    if cfg!(windows) && path.as_bytes().get(2) == Some(&b':') {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
+
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.to_str()?.replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(char::from(byte));
+        } else {
+            let _ = write!(uri, "%{byte:02X}");
+        }
+    }
+    Uri::from_str(&uri).ok()
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -1,4 +1,4 @@
-mod expr;
+pub(crate) mod expr;
 mod stmt;
 
 use crate::parser::Parser;
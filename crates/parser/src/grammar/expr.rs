@@ -5,7 +5,9 @@ use crate::parser::marker::CompletedMarker;
 use lexer::TokenKind;
 use syntax::SyntaxConstruct;
 
-enum BinaryOp {
+/// The binary operators, with the binding powers the parser gives them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
     Assign,
     Add,
     Sub,
@@ -29,7 +31,41 @@ enum BinaryOp {
 }
 
 impl BinaryOp {
-    fn binding_power(&self) -> (u8, u8) {
+    /// Returns the operator a token stands for, if it is a binary one.
+    #[must_use]
+    pub fn from_token(kind: TokenKind) -> Option<Self> {
+        let op = match kind {
+            TokenKind::Equals => Self::Assign,
+            TokenKind::Plus => Self::Add,
+            TokenKind::Minus => Self::Sub,
+            TokenKind::Asterisk => Self::Mul,
+            TokenKind::Slash => Self::Div,
+            TokenKind::ElmtMult => Self::ElmtMul,
+            TokenKind::ElmtDiv => Self::ElmtDiv,
+            TokenKind::LeftDiv => Self::LeftDiv,
+            TokenKind::ElmtLeftDiv => Self::ElmtLeftDiv,
+            TokenKind::Caret => Self::Pow,
+            TokenKind::ElmtPow => Self::ElmtPow,
+            TokenKind::EqualsEquals => Self::Eq,
+            TokenKind::NotEquals | TokenKind::TildeEquals => Self::Neq,
+            TokenKind::LessThan => Self::Lt,
+            TokenKind::GreaterThan => Self::Gt,
+            TokenKind::LessThanEquals => Self::Le,
+            TokenKind::GreaterThanEquals => Self::Ge,
+            TokenKind::And => Self::And,
+            TokenKind::Or => Self::Or,
+            TokenKind::Colon => Self::Colon,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Returns how tightly the operator binds the operands on its left and right. An operand
+    /// that is itself a binary expression needs parentheses on the left if its right binding
+    /// power is at most this left one, and on the right if its left binding power is below this
+    /// right one.
+    #[must_use]
+    pub fn binding_power(self) -> (u8, u8) {
         match self {
             Self::Assign => (1, 0),
             Self::Or => (1, 2),
@@ -48,14 +84,29 @@ impl BinaryOp {
     }
 }
 
-enum UnaryOp {
+/// The prefix operators, with the binding powers the parser gives them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
     Neg,
     Pos,
     Not,
 }
 
 impl UnaryOp {
-    fn binding_power(&self) -> ((), u8) {
+    /// Returns the operator a token stands for, if it is a prefix one.
+    #[must_use]
+    pub fn from_token(kind: TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::Minus => Some(Self::Neg),
+            TokenKind::Plus => Some(Self::Pos),
+            TokenKind::Not | TokenKind::Tilde => Some(Self::Not),
+            _ => None,
+        }
+    }
+
+    /// Returns how tightly the operator binds its operand.
+    #[must_use]
+    pub fn binding_power(self) -> ((), u8) {
         match self {
             Self::Neg | Self::Pos | Self::Not => ((), 15),
         }
@@ -170,6 +221,34 @@ fn string_literal(p: &mut Parser) -> CompletedMarker {
     m.complete(p, SyntaxConstruct::StringLiteral.into())
 }
 
+/// `end` inside an index, as in `x(end)`.
+fn end_index(p: &mut Parser) -> CompletedMarker {
+    let m = p.start();
+    p.bump();
+    m.complete(p, SyntaxConstruct::EndIndex.into())
+}
+
+/// A variable declared by `global` or `persistent`, with its initial value if it has one.
+pub(super) fn declared_variable(p: &mut Parser) -> CompletedMarker {
+    let var = variable_ref(p);
+    if !p.at(TokenKind::Equals) {
+        return var;
+    }
+    let m = var.precede(p);
+    p.bump();
+    expr_binding_power(p, BinaryOp::Assign.binding_power().1);
+    m.complete(p, SyntaxConstruct::InfixExpr.into())
+}
+
+fn fn_handle(p: &mut Parser) -> CompletedMarker {
+    assert!(p.at(TokenKind::At));
+
+    let m = p.start();
+    p.bump();
+    p.expect(TokenKind::Identifier);
+    m.complete(p, SyntaxConstruct::FnHandle.into())
+}
+
 fn lhs(p: &mut Parser) -> Option<CompletedMarker> {
     let cm = if p.at(TokenKind::Number) {
         literal(p)
@@ -187,6 +266,10 @@ fn lhs(p: &mut Parser) -> Option<CompletedMarker> {
         matrix_expr(p)
     } else if p.at(TokenKind::Transpose) {
         string_literal(p)
+    } else if p.at(TokenKind::At) {
+        fn_handle(p)
+    } else if p.in_index() && p.peek() == Some(TokenKind::EndKw) {
+        end_index(p)
     } else if let Some(TokenKind::Semicolon) = p.peek() {
         // Finished expression succesfully
         p.bump();
@@ -199,6 +282,47 @@ fn lhs(p: &mut Parser) -> Option<CompletedMarker> {
     Some(cm)
 }
 
+/// Field access: expr.name. The name may still be missing while it is being typed.
+fn field_expr(p: &mut Parser, lhs: CompletedMarker) -> CompletedMarker {
+    assert!(p.at(TokenKind::Dot));
+    let m = lhs.precede(p);
+    p.bump();
+    p.expect(TokenKind::Identifier);
+    m.complete(p, SyntaxConstruct::FieldExpr.into())
+}
+
+/// Function call / indexing: expr(args)
+fn call_expr(p: &mut Parser, lhs: CompletedMarker) -> CompletedMarker {
+    assert!(p.at(TokenKind::LParen));
+    let m = lhs.precede(p);
+    p.bump();
+    p.enter_index();
+    loop {
+        if p.at(TokenKind::RParen) {
+            p.bump();
+            break;
+        }
+        if p.at_end() {
+            break;
+        }
+        // Parse argument expression
+        expr(p);
+        if p.at(TokenKind::Comma) {
+            p.bump();
+            continue;
+        }
+        if p.at(TokenKind::RParen) {
+            p.bump();
+            break;
+        }
+        // Unexpected token — error recovery
+        p.error();
+        break;
+    }
+    p.leave_index();
+    m.complete(p, SyntaxConstruct::CallExpr.into())
+}
+
 fn expr_binding_power(p: &mut Parser, minimum_binding_power: u8) -> Option<CompletedMarker> {
     let mut lhs = lhs(p)?;
 
@@ -211,33 +335,13 @@ fn expr_binding_power(p: &mut Parser, minimum_binding_power: u8) -> Option<Compl
             continue;
         }
 
-        // Function call / indexing: expr(args)
+        if p.at(TokenKind::Dot) {
+            lhs = field_expr(p, lhs);
+            continue;
+        }
+
         if p.at(TokenKind::LParen) {
-            let m = lhs.precede(p);
-            p.bump();
-            loop {
-                if p.at(TokenKind::RParen) {
-                    p.bump();
-                    break;
-                }
-                if p.at_end() {
-                    break;
-                }
-                // Parse argument expression
-                expr(p);
-                if p.at(TokenKind::Comma) {
-                    p.bump();
-                    continue;
-                }
-                if p.at(TokenKind::RParen) {
-                    p.bump();
-                    break;
-                }
-                // Unexpected token — error recovery
-                p.error();
-                break;
-            }
-            lhs = m.complete(p, SyntaxConstruct::CallExpr.into());
+            lhs = call_expr(p, lhs);
             continue;
         }
 
@@ -580,7 +684,7 @@ Root@0..19
                     LParen@0..1 "("
                     VariableRef@1..4
                       Identifier@1..4 "foo"
-                error at 1..4: expected '', '.'', '(', '+', '-', '*', '/', '.*', './', '\', '.\', '^', '.^', '==', '!=', '<', '>', '<=', '>=', '~=', '&&', '||', ':', '=' or ')'"#]],
+                error at 1..4: expected '', '.'', '.', '(', '+', '-', '*', '/', '.*', './', '\', '.\', '^', '.^', '==', '!=', '<', '>', '<=', '>=', '~=', '&&', '||', ':', '=' or ')'"#]],
         );
     }
 
@@ -608,7 +712,7 @@ Root@0..19
                       Literal@1..2
                         Number@1..2 "1"
                       Plus@2..3 "+"
-                error at 2..3: expected number, identifier, '-', '+', '!', '~', '(', '[' or ''
+                error at 2..3: expected number, identifier, '-', '+', '!', '~', '(', '[', '' or '@'
                 error at 2..3: expected ')'"#]],
         );
     }
@@ -1004,6 +1108,91 @@ Root@0..7
         );
     }
 
+    #[test]
+    fn parse_fn_handle() {
+        check(
+            "cellfun(@numel, c)",
+            expect![[r#"
+            Root@0..18
+              CallExpr@0..18
+                VariableRef@0..7
+                  Identifier@0..7 "cellfun"
+                LParen@7..8 "("
+                FnHandle@8..14
+                  At@8..9 "@"
+                  Identifier@9..14 "numel"
+                Comma@14..15 ","
+                Whitespace@15..16 " "
+                VariableRef@16..17
+                  Identifier@16..17 "c"
+                RParen@17..18 ")""#]],
+        );
+    }
+
+    #[test]
+    fn parse_field_access() {
+        check(
+            "s.a.b(2)",
+            expect![[r#"
+            Root@0..8
+              CallExpr@0..8
+                FieldExpr@0..5
+                  FieldExpr@0..3
+                    VariableRef@0..1
+                      Identifier@0..1 "s"
+                    Dot@1..2 "."
+                    Identifier@2..3 "a"
+                  Dot@3..4 "."
+                  Identifier@4..5 "b"
+                LParen@5..6 "("
+                Literal@6..7
+                  Number@6..7 "2"
+                RParen@7..8 ")""#]],
+        );
+    }
+
+    #[test]
+    fn parse_incomplete_field_access() {
+        check(
+            "x = s.",
+            expect![[r#"
+            Root@0..6
+              InfixExpr@0..6
+                VariableRef@0..2
+                  Identifier@0..1 "x"
+                  Whitespace@1..2 " "
+                Equals@2..3 "="
+                Whitespace@3..4 " "
+                FieldExpr@4..6
+                  VariableRef@4..5
+                    Identifier@4..5 "s"
+                  Dot@5..6 "."
+            error at 5..6: expected identifier"#]],
+        );
+    }
+
+    #[test]
+    fn parse_anonymous_fn_is_an_error() {
+        check(
+            "@(x) x",
+            expect![[r#"
+                Root@0..6
+                  FnHandle@0..2
+                    At@0..1 "@"
+                    Error@1..2
+                      LParen@1..2 "("
+                  VariableRef@2..3
+                    Identifier@2..3 "x"
+                  Error@3..5
+                    RParen@3..4 ")"
+                    Whitespace@4..5 " "
+                  VariableRef@5..6
+                    Identifier@5..6 "x"
+                error at 1..2: expected identifier, but found '('
+                error at 3..4: expected '', '.'', '.', '(', '+', '-', '*', '/', '.*', './', '\', '.\', '^', '.^', '==', '!=', '<', '>', '<=', '>=', '~=', '&&', '||', ':', '=', 'fn', keyword, keyword, keyword, keyword, keyword, keyword, keyword, keyword, keyword, keyword, keyword, number, identifier, '-', '+', '!', '~', '(', '[', '' or '@', but found ')'"#]],
+        );
+    }
+
     #[test]
     fn parse_string_literal() {
         check(
@@ -1078,4 +1267,149 @@ Root@0..10
     RBracket@9..10 "]""#]],
         );
     }
+
+    #[test]
+    fn parse_end_in_index() {
+        check(
+            "x(end - 1, y(end))",
+            expect![[r#"
+            Root@0..18
+              CallExpr@0..18
+                VariableRef@0..1
+                  Identifier@0..1 "x"
+                LParen@1..2 "("
+                InfixExpr@2..9
+                  EndIndex@2..6
+                    EndKw@2..5 "end"
+                    Whitespace@5..6 " "
+                  Minus@6..7 "-"
+                  Whitespace@7..8 " "
+                  Literal@8..9
+                    Number@8..9 "1"
+                Comma@9..10 ","
+                Whitespace@10..11 " "
+                CallExpr@11..17
+                  VariableRef@11..12
+                    Identifier@11..12 "y"
+                  LParen@12..13 "("
+                  EndIndex@13..16
+                    EndKw@13..16 "end"
+                  RParen@16..17 ")"
+                RParen@17..18 ")""#]],
+        );
+    }
+
+    #[test]
+    fn parse_end_outside_index() {
+        check(
+            "[end]",
+            expect![[r#"
+            Root@0..5
+              MatrixExpr@0..5
+                LBracket@0..1 "["
+                Error@1..4
+                  EndKw@1..4 "end"
+                RBracket@4..5 "]"
+            error at 1..4: expected ']', ',', ';', number, identifier, '-', '+', '!', '~', '(', '[', '' or '@', but found keyword"#]],
+        );
+    }
+
+    #[test]
+    fn parse_continuation() {
+        check(
+            "x = 1 + ...\n    2;",
+            expect![[r#"
+                Root@0..18
+                  InfixExpr@0..18
+                    VariableRef@0..2
+                      Identifier@0..1 "x"
+                      Whitespace@1..2 " "
+                    Equals@2..3 "="
+                    Whitespace@3..4 " "
+                    InfixExpr@4..18
+                      Literal@4..6
+                        Number@4..5 "1"
+                        Whitespace@5..6 " "
+                      Plus@6..7 "+"
+                      Whitespace@7..8 " "
+                      Continuation@8..11 "..."
+                      Newline@11..12 "\n"
+                      Whitespace@12..16 "    "
+                      Literal@16..17
+                        Number@16..17 "2"
+                      Semicolon@17..18 ";""#]],
+        );
+    }
+
+    #[test]
+    fn parse_continuation_with_text_after_it() {
+        check(
+            "x = 1 ... comment\n + 2",
+            expect![[r#"
+                Root@0..22
+                  InfixExpr@0..22
+                    VariableRef@0..2
+                      Identifier@0..1 "x"
+                      Whitespace@1..2 " "
+                    Equals@2..3 "="
+                    Whitespace@3..4 " "
+                    InfixExpr@4..22
+                      Literal@4..19
+                        Number@4..5 "1"
+                        Whitespace@5..6 " "
+                        Continuation@6..17 "... comment"
+                        Newline@17..18 "\n"
+                        Whitespace@18..19 " "
+                      Plus@19..20 "+"
+                      Whitespace@20..21 " "
+                      Literal@21..22
+                        Number@21..22 "2""#]],
+        );
+    }
+
+    #[test]
+    fn parse_continuation_in_matrix() {
+        check(
+            "[1, 2, ...\n 3]",
+            expect![[r#"
+                Root@0..14
+                  MatrixExpr@0..14
+                    LBracket@0..1 "["
+                    Literal@1..2
+                      Number@1..2 "1"
+                    Comma@2..3 ","
+                    Whitespace@3..4 " "
+                    Literal@4..5
+                      Number@4..5 "2"
+                    Comma@5..6 ","
+                    Whitespace@6..7 " "
+                    Continuation@7..10 "..."
+                    Newline@10..11 "\n"
+                    Whitespace@11..12 " "
+                    Literal@12..13
+                      Number@12..13 "3"
+                    RBracket@13..14 "]""#]],
+        );
+    }
+
+    #[test]
+    fn parse_continuation_with_text_after_it_in_matrix() {
+        check(
+            "[1, ... more\n 2]",
+            expect![[r#"
+                Root@0..16
+                  MatrixExpr@0..16
+                    LBracket@0..1 "["
+                    Literal@1..2
+                      Number@1..2 "1"
+                    Comma@2..3 ","
+                    Whitespace@3..4 " "
+                    Continuation@4..12 "... more"
+                    Newline@12..13 "\n"
+                    Whitespace@13..14 " "
+                    Literal@14..15
+                      Number@14..15 "2"
+                    RBracket@15..16 "]""#]],
+        );
+    }
 }
@@ -20,6 +20,10 @@ pub(super) fn stmt(p: &mut Parser) -> Option<CompletedMarker> {
         Some(break_stmt(p))
     } else if p.at(TokenKind::ContinueKw) {
         Some(continue_stmt(p))
+    } else if p.at(TokenKind::ReturnKw) {
+        Some(return_stmt(p))
+    } else if p.at(TokenKind::GlobalKw) || p.at(TokenKind::PersistentKw) {
+        Some(declaration(p))
     } else {
         expr::expr(p)
     }
@@ -35,8 +39,7 @@ fn fn_def(p: &mut Parser) -> CompletedMarker {
 
     // Parse body statements until endfunction/endfn/end
     loop {
-        if p.at(TokenKind::EndFnKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -66,8 +69,7 @@ fn if_stmt(p: &mut Parser) -> CompletedMarker {
             p.bump();
             continue;
         }
-        if p.at(TokenKind::EndIfKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -89,8 +91,7 @@ fn for_loop(p: &mut Parser) -> CompletedMarker {
 
     // Parse body until endfor/end
     loop {
-        if p.at(TokenKind::EndForKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -111,8 +112,7 @@ fn while_loop(p: &mut Parser) -> CompletedMarker {
 
     // Parse body until endwhile/end
     loop {
-        if p.at(TokenKind::EndWhileKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -142,8 +142,7 @@ fn switch_stmt(p: &mut Parser) -> CompletedMarker {
             p.bump();
             continue;
         }
-        if p.at(TokenKind::EndSwitchKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -165,8 +164,7 @@ fn try_catch_stmt(p: &mut Parser) -> CompletedMarker {
             p.bump();
             continue;
         }
-        if p.at(TokenKind::EndTryKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -188,8 +186,7 @@ fn unwind_protect_stmt(p: &mut Parser) -> CompletedMarker {
             p.bump();
             continue;
         }
-        if p.at(TokenKind::EndUnwindProtectKw) || p.at(TokenKind::EndKw) {
-            p.bump();
+        if eat_block_terminator(p) {
             break;
         }
         if p.at_end() {
@@ -201,6 +198,20 @@ fn unwind_protect_stmt(p: &mut Parser) -> CompletedMarker {
     m.complete(p, SyntaxConstruct::UnwindProtectStmt.into())
 }
 
+/// Eats the token closing a block, if the parser is at one.
+///
+/// Any block terminator is accepted, not only `end` and the block's own `endXXX` keyword, so that
+/// e.g. an `endwhile` closing an `if` still ends the innermost block instead of leaking into the
+/// enclosing one. Reporting the mismatch is left to `ast::validation`.
+fn eat_block_terminator(p: &mut Parser) -> bool {
+    if p.peek().is_some_and(|kind| kind.is_block_terminator()) {
+        p.bump();
+        true
+    } else {
+        false
+    }
+}
+
 fn break_stmt(p: &mut Parser) -> CompletedMarker {
     let m = p.start();
     p.bump();
@@ -213,6 +224,26 @@ fn continue_stmt(p: &mut Parser) -> CompletedMarker {
     m.complete(p, SyntaxConstruct::ContinueStmt.into())
 }
 
+fn return_stmt(p: &mut Parser) -> CompletedMarker {
+    let m = p.start();
+    p.bump();
+    m.complete(p, SyntaxConstruct::ReturnStmt.into())
+}
+
+/// `global a b` or `persistent n = 0`: the variables declared go up to the end of the statement,
+/// whose `;` or `,` belongs to the declaration.
+fn declaration(p: &mut Parser) -> CompletedMarker {
+    let m = p.start();
+    p.bump();
+    while !p.at_statement_end() && p.at(TokenKind::Identifier) {
+        expr::declared_variable(p);
+    }
+    if matches!(p.peek(), Some(TokenKind::Semicolon | TokenKind::Comma)) {
+        p.bump();
+    }
+    m.complete(p, SyntaxConstruct::Declaration.into())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::check;
@@ -250,7 +281,7 @@ mod tests {
                     Literal@4..6
                       Number@4..6 "43"
                     Semicolon@6..7 ";""#]],
-        )
+        );
     }
 
     #[test]
@@ -271,7 +302,7 @@ mod tests {
                     Whitespace@7..8 " "
                   Literal@8..9
                     Number@8..9 "7""#]],
-        )
+        );
     }
 
     #[test]
@@ -293,7 +324,7 @@ mod tests {
                       Plus@7..8 "+"
                       Whitespace@8..9 " "
                       Semicolon@9..10 ";""#]],
-        )
+        );
     }
 
     #[test]
@@ -323,7 +354,7 @@ mod tests {
                     Whitespace@13..14 " "
                     VariableRef@14..15
                       Identifier@14..15 "a""#]],
-        )
+        );
     }
 
     #[test]
@@ -363,7 +394,7 @@ Root@0..3
     Plus@1..2 "+"
     VariableRef@2..3
       Identifier@2..3 "a""#]],
-        )
+        );
     }
 
     #[test]
@@ -378,7 +409,7 @@ Root@0..3
       Plus@1..2 "+"
       Literal@2..3
         Number@2..3 "1""#]],
-        )
+        );
     }
 
     #[test]
@@ -421,7 +452,7 @@ Root@0..3
                     Whitespace@7..8 " "
                     Literal@8..9
                       Number@8..9 "1""#]],
-        )
+        );
     }
 
     #[test]
@@ -446,6 +477,80 @@ Root@0..8
         );
     }
 
+    #[test]
+    fn parse_return() {
+        check(
+            "return",
+            expect![[r#"
+Root@0..6
+  ReturnStmt@0..6
+    ReturnKw@0..6 "return""#]],
+        );
+    }
+
+    #[test]
+    fn parse_global_declaration() {
+        check(
+            "global a b;\nc\npersistent n\nm = 1",
+            expect![[r#"
+            Root@0..32
+              Declaration@0..12
+                GlobalKw@0..6 "global"
+                Whitespace@6..7 " "
+                VariableRef@7..9
+                  Identifier@7..8 "a"
+                  Whitespace@8..9 " "
+                VariableRef@9..10
+                  Identifier@9..10 "b"
+                Semicolon@10..11 ";"
+                Newline@11..12 "\n"
+              VariableRef@12..14
+                Identifier@12..13 "c"
+                Newline@13..14 "\n"
+              Declaration@14..27
+                PersistentKw@14..24 "persistent"
+                Whitespace@24..25 " "
+                VariableRef@25..27
+                  Identifier@25..26 "n"
+                  Newline@26..27 "\n"
+              InfixExpr@27..32
+                VariableRef@27..29
+                  Identifier@27..28 "m"
+                  Whitespace@28..29 " "
+                Equals@29..30 "="
+                Whitespace@30..31 " "
+                Literal@31..32
+                  Number@31..32 "1""#]],
+        );
+    }
+
+    #[test]
+    fn parse_persistent_with_value() {
+        check(
+            "persistent n = 0, m\nn",
+            expect![[r#"
+            Root@0..21
+              Declaration@0..18
+                PersistentKw@0..10 "persistent"
+                Whitespace@10..11 " "
+                InfixExpr@11..16
+                  VariableRef@11..13
+                    Identifier@11..12 "n"
+                    Whitespace@12..13 " "
+                  Equals@13..14 "="
+                  Whitespace@14..15 " "
+                  Literal@15..16
+                    Number@15..16 "0"
+                Comma@16..17 ","
+                Whitespace@17..18 " "
+              VariableRef@18..20
+                Identifier@18..19 "m"
+                Newline@19..20 "\n"
+              VariableRef@20..21
+                Identifier@20..21 "n""#]],
+        );
+    }
+
     #[test]
     fn parse_simple_if() {
         check(
@@ -519,6 +624,42 @@ Root@0..30
         );
     }
 
+    #[test]
+    fn parse_if_with_mismatched_terminator() {
+        check(
+            "if x y endwhile",
+            expect![[r#"
+            Root@0..15
+              IfStmt@0..15
+                IfKw@0..2 "if"
+                Whitespace@2..3 " "
+                VariableRef@3..5
+                  Identifier@3..4 "x"
+                  Whitespace@4..5 " "
+                VariableRef@5..7
+                  Identifier@5..6 "y"
+                  Whitespace@6..7 " "
+                EndWhileKw@7..15 "endwhile""#]],
+        );
+    }
+
+    #[test]
+    fn parse_unterminated_if() {
+        check(
+            "if x y",
+            expect![[r#"
+            Root@0..6
+              IfStmt@0..6
+                IfKw@0..2 "if"
+                Whitespace@2..3 " "
+                VariableRef@3..5
+                  Identifier@3..4 "x"
+                  Whitespace@4..5 " "
+                VariableRef@5..6
+                  Identifier@5..6 "y""#]],
+        );
+    }
+
     #[test]
     fn parse_for_loop() {
         check(
@@ -717,4 +858,27 @@ Root@0..44
                     EndUnwindProtectKw@54..72 "end_unwind_protect""#]],
         );
     }
+
+    #[test]
+    fn parse_declaration_across_continuation() {
+        check(
+            "global a ...\n  b\nc",
+            expect![[r#"
+                Root@0..18
+                  Declaration@0..17
+                    GlobalKw@0..6 "global"
+                    Whitespace@6..7 " "
+                    VariableRef@7..15
+                      Identifier@7..8 "a"
+                      Whitespace@8..9 " "
+                      Continuation@9..12 "..."
+                      Newline@12..13 "\n"
+                      Whitespace@13..15 "  "
+                    VariableRef@15..17
+                      Identifier@15..16 "b"
+                      Newline@16..17 "\n"
+                  VariableRef@17..18
+                    Identifier@17..18 "c""#]],
+        );
+    }
 }
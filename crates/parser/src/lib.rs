@@ -3,11 +3,14 @@
 mod event;
 mod grammar;
 mod parser;
+mod reparsing;
 mod sink;
 mod source;
 
+pub use crate::grammar::expr::{BinaryOp, UnaryOp};
 pub use crate::parser::ParseError;
 use crate::parser::Parser;
+pub use crate::reparsing::TextEdit;
 use lexer::Lexer;
 use rowan::GreenNode;
 use sink::Sink;
@@ -26,6 +29,7 @@ pub fn parse(input: &str) -> Parse {
     sink.finish()
 }
 
+#[derive(Debug, PartialEq)]
 pub struct Parse {
     green_node: GreenNode,
     errors: Vec<ParseError>,
@@ -60,6 +64,7 @@ impl Parse {
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_pass_by_value)]
 fn check(input: &str, expected_tree: expect_test::Expect) {
     let parse = parse(input);
     expected_tree.assert_eq(&parse.debug_tree());
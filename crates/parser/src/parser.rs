@@ -17,6 +17,8 @@ pub(crate) struct Parser<'t, 'input> {
     source: Source<'t, 'input>,
     events: Vec<Event>,
     expected_kinds: Vec<TokenKind>,
+    /// How many index (or call) argument lists are being parsed, where `end` is an expression.
+    index_depth: usize,
 }
 
 impl<'t, 'input> Parser<'t, 'input> {
@@ -25,6 +27,7 @@ impl<'t, 'input> Parser<'t, 'input> {
             source,
             events: Vec::new(),
             expected_kinds: Vec::new(),
+            index_depth: 0,
         }
     }
 
@@ -95,6 +98,22 @@ impl<'t, 'input> Parser<'t, 'input> {
     pub(crate) fn at_end(&mut self) -> bool {
         self.peek().is_none()
     }
+
+    pub(crate) fn at_statement_end(&self) -> bool {
+        self.source.at_statement_end()
+    }
+
+    pub(crate) fn enter_index(&mut self) {
+        self.index_depth += 1;
+    }
+
+    pub(crate) fn leave_index(&mut self) {
+        self.index_depth -= 1;
+    }
+
+    pub(crate) fn in_index(&self) -> bool {
+        self.index_depth > 0
+    }
 }
 
 #[cfg(test)]
@@ -104,7 +123,7 @@ mod tests {
 
     #[test]
     fn parse_nothing() {
-        check("", expect![[r#"Root@0..0"#]]);
+        check("", expect![[r"Root@0..0"]]);
     }
 
     #[test]
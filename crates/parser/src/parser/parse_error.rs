@@ -2,10 +2,10 @@ use lexer::TokenKind;
 use std::fmt;
 use text_size::TextRange;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParseError {
     pub(super) expected: Vec<TokenKind>,
-    pub(super) found: Option<TokenKind>,
+    pub(crate) found: Option<TokenKind>,
     pub range: TextRange,
 }
 
@@ -46,6 +46,7 @@ mod tests {
     use expect_test::expect;
     use std::ops::Range as StdRange;
 
+    #[allow(clippy::needless_pass_by_value)]
     fn check(
         expected: Vec<TokenKind>,
         found: Option<TokenKind>,
@@ -97,7 +98,7 @@ mod tests {
             ],
             Some(TokenKind::Semicolon),
             100..105,
-            expect![r#"error at 100..105: expected number, identifier, '-' or '(', but found ';'"#],
+            expect![r"error at 100..105: expected number, identifier, '-' or '(', but found ';'"],
         );
     }
 
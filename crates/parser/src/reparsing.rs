@@ -0,0 +1,279 @@
+//! Reparsing only the block an edit falls in, instead of the whole file.
+//!
+//! The innermost function, loop, `if`, `switch`, `try` or `unwind_protect` around the edit whose
+//! first and last tokens it leaves alone is lexed and parsed again on its own, and its new green
+//! node replaces the old one. Blocks start with their keyword and end with their terminator and
+//! the trivia after it, so they parse the same wherever they are, as long as the edit leaves a
+//! single block of the same kind, still closed by its terminator. Otherwise the whole file is
+//! parsed again.
+
+use crate::{Parse, ParseError, parse};
+use syntax::{SyntaxConstruct, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use text_size::{TextRange, TextSize};
+
+const BLOCKS: [SyntaxConstruct; 7] = [
+    SyntaxConstruct::FnDef,
+    SyntaxConstruct::IfStmt,
+    SyntaxConstruct::ForLoop,
+    SyntaxConstruct::WhileLoop,
+    SyntaxConstruct::SwitchStmt,
+    SyntaxConstruct::TryStmt,
+    SyntaxConstruct::UnwindProtectStmt,
+];
+
+/// A change to a text: `range` of the old text replaced by `insert`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: TextRange,
+    pub insert: String,
+}
+
+impl TextEdit {
+    /// Applies the edit to `text`, which must be the text it was made for.
+    pub fn apply(&self, text: &mut String) {
+        text.replace_range(std::ops::Range::<usize>::from(self.range), &self.insert);
+    }
+}
+
+impl Parse {
+    /// Returns the parse of `new_text`, which is the text of this parse after `edit`.
+    #[must_use]
+    pub fn reparse(&self, edit: &TextEdit, new_text: &str) -> Parse {
+        reparse_block(self, edit).unwrap_or_else(|| parse(new_text))
+    }
+}
+
+fn reparse_block(old: &Parse, edit: &TextEdit) -> Option<Parse> {
+    let root = old.syntax();
+    if !root.text_range().contains_range(edit.range) {
+        return None;
+    }
+    let node = match root.covering_element(edit.range) {
+        SyntaxElement::Node(node) => node,
+        SyntaxElement::Token(token) => token.parent()?,
+    }
+    .ancestors()
+    .find(|node| {
+        let (Some(first), Some(last)) = (node.first_token(), terminator(node)) else {
+            return false;
+        };
+        is_block(node)
+            && first.text_range().end() < edit.range.start()
+            && edit.range.end() < last.text_range().start()
+    })?;
+
+    let node_range = node.text_range();
+    let mut text = node.text().to_string();
+    TextEdit {
+        range: edit.range - node_range.start(),
+        insert: edit.insert.clone(),
+    }
+    .apply(&mut text);
+    let fragment = parse(&text);
+    let fragment_root = fragment.syntax();
+    let mut elements = fragment_root.children_with_tokens();
+    let Some(SyntaxElement::Node(block)) = elements.next() else {
+        return None;
+    };
+    // A block of another kind, or one left open or closed early, changes how what follows it
+    // is parsed. An error at the end of the fragment would be at another token in the file.
+    if elements.next().is_some()
+        || block.kind() != node.kind()
+        || terminator(&block).is_none()
+        || fragment_root.text_range().len() != TextSize::of(&text)
+        || fragment.errors.iter().any(|error| error.found.is_none())
+    {
+        return None;
+    }
+
+    let green_node = node.replace_with(block.green().into_owned());
+    // Errors at the end of the file are reported by the blocks left open around this one, so
+    // they are kept even when they are at the trivia ending the block.
+    let (before, after): (Vec<_>, Vec<_>) = old
+        .errors
+        .iter()
+        .filter(|error| error.found.is_none() || !node_range.contains_range(error.range))
+        .partition(|error| error.range.start() < node_range.start());
+    let shifted = |error: &ParseError, range: TextRange| {
+        let mut error = error.clone();
+        error.range = range;
+        error
+    };
+    let (old_len, new_len) = (edit.range.len(), TextSize::of(&edit.insert));
+    let errors = before
+        .into_iter()
+        .cloned()
+        .chain(
+            fragment
+                .errors
+                .iter()
+                .map(|error| shifted(error, error.range + node_range.start())),
+        )
+        .chain(
+            after
+                .into_iter()
+                .map(|error| shifted(error, error.range + new_len - old_len)),
+        )
+        .collect();
+    Some(Parse { green_node, errors })
+}
+
+fn is_block(node: &SyntaxNode) -> bool {
+    matches!(node.kind(), SyntaxKind::SyntaxConstruct(construct) if BLOCKS.contains(&construct))
+}
+
+/// The keyword closing the block `node`, which is its last token apart from trivia.
+fn terminator(node: &SyntaxNode) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter(|element| {
+            !matches!(element.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia())
+        })
+        .last()?
+        .into_token()
+        .filter(|token| {
+            matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_block_terminator())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: [&str; 5] = [
+        "function y = f(x)\n  if x > 0\n    y = x;\n  else\n    y = -x;\n  end\nend\n\nfunction g\n  for i = 1:3\n    disp(i)\n  endfor\nend\n",
+        "x = 1;\nwhile x < 10\n  x = x * 2; % double\n  if x == 4, continue; end\nend\ndisp(x')\n",
+        "switch s\n  case 'a'\n    try\n      f(s);\n    catch err\n      disp(err.message)\n    end_try_catch\n  otherwise\n    unwind_protect\n      g()\n    unwind_protect_cleanup\n      h()\n    end_unwind_protect\nend\n",
+        "function r = outer(a)\n  r = inner(a) + [1, 2; 3 4];\n\nfunction s = inner(b)\n  s = b(end);\n  while true\n    break\n  endwhile\n",
+        "if a\n  if b\n    c = {1, 'x'};\n  elseif d\n    e = @(t) t.^2;\n  end\nend\nz = 1:\n",
+    ];
+
+    const INSERTIONS: [&str; 20] = [
+        "x",
+        " ",
+        "\n",
+        ";",
+        "(",
+        ")",
+        "'",
+        "%",
+        "end",
+        "if a\n",
+        "for i = 1:3\n",
+        "function h\n",
+        "endwhile",
+        "1 + ",
+        "[",
+        "else\n",
+        "case 2\n",
+        "y = 2;\n",
+        "#{\n",
+        ",",
+    ];
+
+    /// A xorshift generator, so that failures can be replayed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            usize::try_from(self.0 % n as u64).unwrap()
+        }
+    }
+
+    fn random_edit(rng: &mut Rng, text: &str) -> TextEdit {
+        let start = rng.below(text.len() + 1);
+        let end = (start + rng.below(6)).min(text.len());
+        let insert = if rng.below(3) == 0 {
+            String::new()
+        } else {
+            INSERTIONS[rng.below(INSERTIONS.len())].to_string()
+        };
+        TextEdit {
+            range: TextRange::new(
+                u32::try_from(start).unwrap().into(),
+                u32::try_from(end).unwrap().into(),
+            ),
+            insert,
+        }
+    }
+
+    #[test]
+    fn reparses_the_edited_block() {
+        let text = "function f\n  x = 1;\nend\n\nfunction g\n  y = 2;\nend\n";
+        let old = parse(text);
+        let edit = TextEdit {
+            range: TextRange::new(17.into(), 18.into()),
+            insert: "42".to_string(),
+        };
+        let mut new_text = text.to_string();
+        edit.apply(&mut new_text);
+
+        let reparsed = reparse_block(&old, &edit).unwrap();
+        assert_eq!(reparsed.debug_tree(), parse(&new_text).debug_tree());
+        // The other function is still the same node.
+        let function = |parse: &Parse| {
+            parse
+                .syntax()
+                .children()
+                .nth(1)
+                .unwrap()
+                .green()
+                .into_owned()
+        };
+        let (old, new) = (function(&old), function(&reparsed));
+        assert!(std::ptr::eq(&raw const *old, &raw const *new));
+    }
+
+    #[test]
+    fn parses_everything_when_blocks_change() {
+        let text = "if a\n  b = 1;\nend\nc = 2;\n";
+        let old = parse(text);
+        for (range, insert) in [((6, 6), "while c\n"), ((0, 2), "while"), ((14, 17), "")] {
+            let edit = TextEdit {
+                range: TextRange::new(range.0.into(), range.1.into()),
+                insert: insert.to_string(),
+            };
+            assert!(reparse_block(&old, &edit).is_none(), "{edit:?}");
+        }
+    }
+
+    /// A smoke test of what `fuzz/fuzz_targets/reparse.rs` checks on arbitrary edits.
+    #[test]
+    fn fuzz_against_full_parses() {
+        let mut rng = Rng(0x5eed_cafe);
+        let mut reparsed = 0;
+        for round in 0..1000 {
+            let mut text = CORPUS[round % CORPUS.len()].to_string();
+            let mut old = parse(&text);
+            // A few edits in a row, so that errors and odd trees are edited too.
+            for _ in 0..4 {
+                let edit = random_edit(&mut rng, &text);
+                if !text.is_char_boundary(edit.range.start().into())
+                    || !text.is_char_boundary(edit.range.end().into())
+                {
+                    continue;
+                }
+                let before = text.clone();
+                edit.apply(&mut text);
+                if reparse_block(&old, &edit).is_some() {
+                    reparsed += 1;
+                }
+                let new = old.reparse(&edit, &text);
+                let full = parse(&text);
+                assert_eq!(
+                    new.debug_tree(),
+                    full.debug_tree(),
+                    "{edit:?} on {before:?}"
+                );
+                assert_eq!(new.errors(), full.errors(), "{edit:?} on {before:?}");
+                old = new;
+            }
+        }
+        assert!(
+            reparsed > 250,
+            "only {reparsed} edits were reparsed in place"
+        );
+    }
+}
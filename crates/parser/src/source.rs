@@ -50,6 +50,34 @@ impl<'t, 'input> Source<'t, 'input> {
         self.tokens.get(self.cursor)
     }
 
+    /// Whether the statement being parsed has ended by the next token: a newline, `;` or `,`
+    /// separates it from the last one, or there is no next token. The newline after a `...`
+    /// continuation does not count.
+    pub(crate) fn at_statement_end(&self) -> bool {
+        let last = self.tokens[..self.cursor]
+            .iter()
+            .rposition(|token| !token.kind.is_trivia());
+        let after_separator = last.is_some_and(|last| {
+            matches!(
+                self.tokens[last].kind,
+                TokenKind::Semicolon | TokenKind::Comma
+            )
+        });
+        // Trivia may already have been eaten, so it is looked for on both sides of the cursor.
+        let trivia: Vec<_> = self.tokens[last.map_or(0, |last| last + 1)..]
+            .iter()
+            .take_while(|token| token.kind.is_trivia())
+            .map(|token| token.kind)
+            .collect();
+        let before_newline = trivia.iter().enumerate().any(|(i, kind)| {
+            *kind == TokenKind::Newline && (i == 0 || trivia[i - 1] != TokenKind::Continuation)
+        });
+        let at_end = self.tokens[self.cursor..]
+            .iter()
+            .all(|token| token.kind.is_trivia());
+        after_separator || before_newline || at_end
+    }
+
     pub(crate) fn last_token_range(&self) -> Option<TextRange> {
         self.tokens.last().map(|Token { range, .. }| *range)
     }
@@ -0,0 +1,596 @@
+//! The project model: which `.m` files there are and which function a name refers to.
+//!
+//! Octave looks a function name up in this order:
+//!
+//! 1. functions of the calling file, i.e. subfunctions and nested functions,
+//! 2. private functions, in the `private` directory next to the calling file,
+//! 3. class constructors, in `@class` directories or `classdef` files,
+//! 4. methods of the class the calling file belongs to,
+//! 5. functions and scripts in the directories of the load path, in order.
+//!
+//! Octave picks class methods by the class of the arguments, which we do not know, so we only
+//! resolve methods called from other methods of the same class. Names that Octave would not find
+//! may still be defined somewhere in the workspace, in a directory that is added to the load path
+//! in a way we cannot see, so those are found last.
+#![warn(clippy::pedantic)]
+
+mod source_file;
+pub mod startup;
+
+pub use source_file::{FileKind, Placement, SourceFile};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// A directory on the load path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadPathEntry {
+    Dir(PathBuf),
+    /// A directory and all its subdirectories, as added with `addpath(genpath(dir))`.
+    Tree(PathBuf),
+}
+
+impl LoadPathEntry {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Dir(path) | Self::Tree(path) => path,
+        }
+    }
+}
+
+/// Where the files of a project are: the workspace folders and the load path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadPathConfig {
+    /// The directories the workspace is made of. Octave looks in the current directory first,
+    /// which we take to be any of these.
+    pub roots: Vec<PathBuf>,
+    pub load_path: Vec<LoadPathEntry>,
+    /// Directories under the roots or the load path whose files are left out, such as build
+    /// output or vendored code.
+    pub excluded: Vec<PathBuf>,
+}
+
+impl LoadPathConfig {
+    /// The directories whose files belong to the project.
+    pub fn search_roots(&self) -> impl Iterator<Item = &Path> {
+        self.roots
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(self.load_path.iter().map(LoadPathEntry::path))
+    }
+
+    /// Whether `path` is an `.m` file that belongs to the project.
+    #[must_use]
+    pub fn contains(&self, path: &Path) -> bool {
+        is_m_file(path)
+            && self.search_roots().any(|root| path.starts_with(root))
+            && !self.is_excluded(path)
+    }
+
+    #[must_use]
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.excluded.iter().any(|dir| path.starts_with(dir))
+    }
+
+    /// Finds the `.m` files of the project, skipping hidden and excluded directories.
+    #[must_use]
+    pub fn discover(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for root in self.search_roots() {
+            if !self.is_excluded(root) {
+                discover_into(root, &self.excluded, &mut files);
+            }
+        }
+        files
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub path: PathBuf,
+    pub kind: DefinitionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    /// A function of the calling file.
+    Local,
+    Private,
+    Constructor,
+    Method,
+    Function,
+    Script,
+    /// A function file that is not on the load path, as far as we can tell.
+    Unlisted,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Project {
+    config: LoadPathConfig,
+    files: BTreeMap<PathBuf, SourceFile>,
+    /// The files by name without the extension.
+    stems: HashMap<String, BTreeSet<PathBuf>>,
+    /// The directories searched for functions, in order, with trees expanded.
+    dirs: Vec<PathBuf>,
+}
+
+impl Project {
+    #[must_use]
+    pub fn new(roots: Vec<PathBuf>, load_path: Vec<LoadPathEntry>) -> Self {
+        Self::with_config(LoadPathConfig {
+            roots,
+            load_path,
+            excluded: Vec::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn with_config(config: LoadPathConfig) -> Self {
+        let mut project = Self {
+            config,
+            ..Self::default()
+        };
+        project.expand_load_path();
+        project
+    }
+
+    pub fn set_load_path(&mut self, load_path: Vec<LoadPathEntry>) {
+        self.config.load_path = load_path;
+        self.expand_load_path();
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &LoadPathConfig {
+        &self.config
+    }
+
+    /// The directories whose files belong to the project.
+    pub fn search_roots(&self) -> impl Iterator<Item = &Path> {
+        self.config.search_roots()
+    }
+
+    /// The directories searched for functions, in order.
+    #[must_use]
+    pub fn load_path(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Whether `path` is an `.m` file that belongs to the project.
+    #[must_use]
+    pub fn contains(&self, path: &Path) -> bool {
+        self.config.contains(path)
+    }
+
+    /// Adds a file, or replaces what was known about it.
+    pub fn insert(&mut self, path: PathBuf, text: &str) {
+        self.insert_files([(path, SourceFile::new(text))]);
+    }
+
+    /// Adds files whose contents are already known, or replaces what was known about them.
+    pub fn insert_files(&mut self, files: impl IntoIterator<Item = (PathBuf, SourceFile)>) {
+        let mut is_new = false;
+        for (path, file) in files {
+            if let Some(stem) = file_stem(&path) {
+                self.stems
+                    .entry(stem.to_string())
+                    .or_default()
+                    .insert(path.clone());
+            }
+            is_new |= self.files.insert(path, file).is_none();
+        }
+        if is_new {
+            self.expand_load_path();
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if self.files.remove(path).is_none() {
+            return;
+        }
+        if let Some(paths) = file_stem(path).and_then(|stem| self.stems.get_mut(stem)) {
+            paths.remove(path);
+        }
+        self.expand_load_path();
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    #[must_use]
+    pub fn file(&self, path: &Path) -> Option<&SourceFile> {
+        self.files.get(path)
+    }
+
+    /// Finds the function or script that `name` refers to when used in the file at `from`.
+    ///
+    /// Names of package functions are qualified with dots, such as `pkg.f`.
+    #[must_use]
+    pub fn resolve(&self, name: &str, from: Option<&Path>) -> Option<Definition> {
+        if let Some((package, name)) = name.rsplit_once('.') {
+            let package_dir: PathBuf = package.split('.').map(|part| format!("+{part}")).collect();
+            return self.resolve_in_load_path(&package_dir, name);
+        }
+
+        let from_file = from.and_then(|from| Some((from, self.files.get(from)?)));
+        if let Some((from, file)) = from_file
+            && file.functions.iter().any(|function| function == name)
+        {
+            return Some(self.definition(from.to_path_buf(), DefinitionKind::Local));
+        }
+
+        if let Some(from) = from
+            && let Some(dir) = from.parent()
+        {
+            let private_dir = if Placement::of(from).is_private {
+                dir.to_path_buf()
+            } else {
+                dir.join("private")
+            };
+            let path = private_dir.join(m_file(name));
+            if self.files.contains_key(&path) {
+                return Some(self.definition(path, DefinitionKind::Private));
+            }
+        }
+
+        let constructor = self.dirs.iter().find_map(|dir| {
+            let path = dir.join(format!("@{name}")).join(m_file(name));
+            self.files.contains_key(&path).then_some(path)
+        });
+        if let Some(path) = constructor {
+            return Some(self.definition(path, DefinitionKind::Constructor));
+        }
+
+        if let Some(from) = from
+            && let Some(class) = Placement::of(from).class
+        {
+            let class_dir = PathBuf::from(format!("@{class}"));
+            let method = from
+                .parent()
+                .map(Path::to_path_buf)
+                .into_iter()
+                .chain(self.dirs.iter().map(|dir| dir.join(&class_dir)))
+                .map(|dir| dir.join(m_file(name)))
+                .find(|path| self.files.contains_key(path));
+            if let Some(path) = method {
+                return Some(self.definition(path, DefinitionKind::Method));
+            }
+        }
+
+        self.resolve_in_load_path(Path::new(""), name).or_else(|| {
+            let path = self.stems.get(name)?.iter().find(|path| {
+                Placement::of(path) == Placement::default()
+                    && self.files[*path].kind == FileKind::Function
+            })?;
+            Some(self.definition(path.clone(), DefinitionKind::Unlisted))
+        })
+    }
+
+    /// The functions and scripts that can be called by their plain name from the file at `from`,
+    /// by name. Package functions are left out, and so are the subfunctions of `from` itself.
+    #[must_use]
+    pub fn visible_functions(&self, from: Option<&Path>) -> Vec<(&str, Definition)> {
+        let mut functions: Vec<_> = self
+            .stems
+            .iter()
+            .filter(|(_, paths)| !paths.is_empty())
+            .filter_map(|(name, _)| {
+                let definition = self.resolve(name, from)?;
+                (definition.kind != DefinitionKind::Local).then_some((name.as_str(), definition))
+            })
+            .collect();
+        functions.sort_by_key(|(name, _)| *name);
+        functions
+    }
+
+    /// Looks for `name` in the subdirectory `subdir` of every directory of the load path.
+    fn resolve_in_load_path(&self, subdir: &Path, name: &str) -> Option<Definition> {
+        self.dirs.iter().find_map(|dir| {
+            let dir = dir.join(subdir);
+            let path = dir.join(m_file(name));
+            if let Some(file) = self.files.get(&path) {
+                let kind = match file.kind {
+                    FileKind::Function => DefinitionKind::Function,
+                    FileKind::Script => DefinitionKind::Script,
+                    FileKind::Classdef => DefinitionKind::Constructor,
+                };
+                return Some(Definition { path, kind });
+            }
+            let path = dir.join(format!("@{name}")).join(m_file(name));
+            self.files.contains_key(&path).then_some(Definition {
+                path,
+                kind: DefinitionKind::Constructor,
+            })
+        })
+    }
+
+    fn definition(&self, path: PathBuf, kind: DefinitionKind) -> Definition {
+        // Scripts in `private` directories are still scripts.
+        let kind = match self.files.get(&path) {
+            Some(file) if file.kind == FileKind::Script && kind != DefinitionKind::Local => {
+                DefinitionKind::Script
+            }
+            _ => kind,
+        };
+        Definition { path, kind }
+    }
+
+    fn expand_load_path(&mut self) {
+        let mut dirs: Vec<PathBuf> = self.config.roots.clone();
+        for entry in &self.config.load_path {
+            let subdirs: Vec<PathBuf> = match entry {
+                LoadPathEntry::Dir(dir) => vec![dir.clone()],
+                LoadPathEntry::Tree(root) => {
+                    let mut subdirs = BTreeSet::from([root.clone()]);
+                    for path in self.files.keys().filter(|path| path.starts_with(root)) {
+                        for dir in path.ancestors().skip(1) {
+                            if !dir.starts_with(root) {
+                                break;
+                            }
+                            if is_plain_dir(dir.strip_prefix(root).unwrap_or(dir)) {
+                                subdirs.insert(dir.to_path_buf());
+                            }
+                        }
+                    }
+                    subdirs.into_iter().collect()
+                }
+            };
+            for dir in subdirs {
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        self.dirs = dirs;
+    }
+}
+
+/// `genpath` leaves out directories that Octave treats specially, and hidden ones.
+fn is_plain_dir(relative: &Path) -> bool {
+    relative.components().all(|component| match component {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            !(name.starts_with(['@', '+', '.']) || name == "private")
+        }
+        _ => false,
+    })
+}
+
+/// Finds the `.m` files under `dir`, skipping hidden directories.
+#[must_use]
+pub fn discover(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    discover_into(dir, &[], &mut files);
+    files
+}
+
+fn discover_into(dir: &Path, excluded: &[PathBuf], files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // Symbolic links are not followed, so that links back up the tree cannot trap us.
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !is_hidden && !excluded.contains(&path) {
+                discover_into(&path, excluded, files);
+            }
+        } else if file_type.is_file() && is_m_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn is_m_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "m")
+}
+
+fn file_stem(path: &Path) -> Option<&str> {
+    path.file_stem()?.to_str()
+}
+
+fn m_file(name: &str) -> String {
+    format!("{name}.m")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUNCTION: &str = "function f\nend";
+
+    fn project(load_path: Vec<LoadPathEntry>, files: &[(&str, &str)]) -> Project {
+        let mut project = Project::new(vec!["/ws".into()], load_path);
+        for (path, text) in files {
+            project.insert(PathBuf::from(path), text);
+        }
+        project
+    }
+
+    fn resolve(project: &Project, name: &str, from: &str) -> Option<(String, DefinitionKind)> {
+        let definition = project.resolve(name, Some(Path::new(from)))?;
+        Some((
+            definition.path.to_string_lossy().into_owned(),
+            definition.kind,
+        ))
+    }
+
+    #[test]
+    fn visible_functions() {
+        let project = project(
+            Vec::new(),
+            &[
+                ("/ws/main.m", "function main\nend\nfunction g\nend"),
+                ("/ws/g.m", FUNCTION),
+                ("/ws/script.m", "x = 1;"),
+                ("/ws/private/helper.m", FUNCTION),
+                ("/ws/sub/private/hidden.m", FUNCTION),
+                ("/ws/+pkg/f.m", FUNCTION),
+            ],
+        );
+        let names = |from: &str| -> Vec<String> {
+            project
+                .visible_functions(Some(Path::new(from)))
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        assert_eq!(names("/ws/main.m"), ["helper", "script"]);
+        assert_eq!(names("/ws/other.m"), ["g", "helper", "main", "script"]);
+    }
+
+    #[test]
+    fn subfunctions_come_first() {
+        let project = project(
+            Vec::new(),
+            &[
+                ("/ws/main.m", "function main\n  g();\nend\nfunction g\nend"),
+                ("/ws/g.m", FUNCTION),
+                ("/ws/private/g.m", FUNCTION),
+            ],
+        );
+        assert_eq!(
+            resolve(&project, "g", "/ws/main.m"),
+            Some(("/ws/main.m".into(), DefinitionKind::Local))
+        );
+    }
+
+    #[test]
+    fn private_functions_are_only_visible_next_door() {
+        let project = project(
+            vec![LoadPathEntry::Dir("/lib".into())],
+            &[
+                ("/ws/main.m", FUNCTION),
+                ("/ws/private/helper.m", FUNCTION),
+                ("/ws/private/other.m", FUNCTION),
+                ("/lib/helper.m", FUNCTION),
+            ],
+        );
+        assert_eq!(
+            resolve(&project, "helper", "/ws/main.m"),
+            Some(("/ws/private/helper.m".into(), DefinitionKind::Private))
+        );
+        assert_eq!(
+            resolve(&project, "helper", "/ws/private/other.m"),
+            Some(("/ws/private/helper.m".into(), DefinitionKind::Private))
+        );
+        assert_eq!(
+            resolve(&project, "helper", "/lib/x.m"),
+            Some(("/lib/helper.m".into(), DefinitionKind::Function))
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let project = project(
+            Vec::new(),
+            &[
+                ("/ws/@point/point.m", FUNCTION),
+                ("/ws/@point/norm.m", FUNCTION),
+                ("/ws/@point/display.m", FUNCTION),
+                ("/ws/norm.m", FUNCTION),
+                ("/ws/Shape.m", "classdef Shape\nend"),
+            ],
+        );
+        assert_eq!(
+            resolve(&project, "point", "/ws/main.m"),
+            Some(("/ws/@point/point.m".into(), DefinitionKind::Constructor))
+        );
+        assert_eq!(
+            resolve(&project, "Shape", "/ws/main.m"),
+            Some(("/ws/Shape.m".into(), DefinitionKind::Constructor))
+        );
+        assert_eq!(
+            resolve(&project, "norm", "/ws/@point/display.m"),
+            Some(("/ws/@point/norm.m".into(), DefinitionKind::Method))
+        );
+        assert_eq!(
+            resolve(&project, "norm", "/ws/main.m"),
+            Some(("/ws/norm.m".into(), DefinitionKind::Function))
+        );
+    }
+
+    #[test]
+    fn load_path_order() {
+        let project = project(
+            vec![
+                LoadPathEntry::Dir("/first".into()),
+                LoadPathEntry::Tree("/tree".into()),
+            ],
+            &[
+                ("/ws/run_me.m", "x = 1;"),
+                ("/first/f.m", FUNCTION),
+                ("/tree/sub/f.m", FUNCTION),
+                ("/tree/sub/g.m", FUNCTION),
+                ("/tree/@c/h.m", FUNCTION),
+                ("/tree/+pkg/h.m", FUNCTION),
+            ],
+        );
+        assert_eq!(
+            project.load_path(),
+            ["/ws", "/first", "/tree", "/tree/sub"].map(PathBuf::from)
+        );
+        assert_eq!(
+            resolve(&project, "run_me", "/ws/main.m"),
+            Some(("/ws/run_me.m".into(), DefinitionKind::Script))
+        );
+        assert_eq!(
+            resolve(&project, "f", "/ws/main.m"),
+            Some(("/first/f.m".into(), DefinitionKind::Function))
+        );
+        assert_eq!(
+            resolve(&project, "g", "/ws/main.m"),
+            Some(("/tree/sub/g.m".into(), DefinitionKind::Function))
+        );
+        assert_eq!(resolve(&project, "h", "/ws/main.m"), None);
+        assert_eq!(
+            resolve(&project, "pkg.h", "/ws/main.m"),
+            Some(("/tree/+pkg/h.m".into(), DefinitionKind::Function))
+        );
+    }
+
+    #[test]
+    fn unlisted_functions_come_last() {
+        let mut project = project(
+            Vec::new(),
+            &[
+                ("/ws/src/util.m", FUNCTION),
+                ("/ws/src/+p/other.m", FUNCTION),
+            ],
+        );
+        assert_eq!(
+            resolve(&project, "util", "/ws/main.m"),
+            Some(("/ws/src/util.m".into(), DefinitionKind::Unlisted))
+        );
+        assert_eq!(resolve(&project, "other", "/ws/main.m"), None);
+
+        project.set_load_path(vec![LoadPathEntry::Dir("/ws/src".into())]);
+        assert_eq!(
+            resolve(&project, "util", "/ws/main.m"),
+            Some(("/ws/src/util.m".into(), DefinitionKind::Function))
+        );
+
+        project.remove(Path::new("/ws/src/util.m"));
+        assert_eq!(resolve(&project, "util", "/ws/main.m"), None);
+    }
+
+    #[test]
+    fn excluded_directories() {
+        let config = LoadPathConfig {
+            roots: vec!["/ws".into()],
+            load_path: vec![LoadPathEntry::Dir("/lib".into())],
+            excluded: vec!["/ws/build".into(), "/lib/vendor".into()],
+        };
+        assert!(config.contains(Path::new("/ws/main.m")));
+        assert!(config.contains(Path::new("/ws/builder/f.m")));
+        assert!(!config.contains(Path::new("/ws/build/f.m")));
+        assert!(!config.contains(Path::new("/lib/vendor/sub/f.m")));
+        assert!(config.contains(Path::new("/lib/f.m")));
+    }
+}
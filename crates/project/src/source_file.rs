@@ -0,0 +1,164 @@
+//! What a single `.m` file defines, judging by its contents and by where it is.
+
+use ast::TypedSyntaxNode;
+use std::path::{Component, Path};
+use syntax::{SyntaxElement, SyntaxKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// Starts with a function definition. The first function is the one named by the file, the
+    /// others are its subfunctions.
+    Function,
+    /// Code run in the workspace of its caller.
+    Script,
+    Classdef,
+}
+
+impl FileKind {
+    #[must_use]
+    pub fn of(root: &ast::Root) -> Self {
+        if starts_with_classdef(root) {
+            Self::Classdef
+        } else if matches!(root.stmts().next(), Some(ast::Stmt::FnDef(_))) {
+            Self::Function
+        } else {
+            Self::Script
+        }
+    }
+}
+
+/// The parser does not know about `classdef` yet, so we only look at the first word.
+fn starts_with_classdef(root: &ast::Root) -> bool {
+    root.syntax()
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .find(|token| !matches!(token.kind(), SyntaxKind::LexToken(kind) if kind.is_trivia()))
+        .is_some_and(|token| token.text() == "classdef")
+}
+
+/// The special directories a file is in, which change who can see it and under which name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placement {
+    /// The dotted name of the `+package` directories, e.g. `a.b` for `+a/+b/f.m`.
+    pub package: Option<String>,
+    /// The class of an `@class` directory.
+    pub class: Option<String>,
+    /// Whether the file is in a `private` directory, only visible from the directory above it.
+    pub is_private: bool,
+}
+
+impl Placement {
+    #[must_use]
+    pub fn of(path: &Path) -> Self {
+        let mut dirs: Vec<&str> = path
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .map(|component| match component {
+                Component::Normal(dir) => dir.to_str().unwrap_or_default(),
+                _ => "",
+            })
+            .collect();
+
+        let is_private = dirs.last() == Some(&"private");
+        if is_private {
+            dirs.pop();
+        }
+        let class = dirs
+            .last()
+            .and_then(|dir| dir.strip_prefix('@'))
+            .map(ToString::to_string);
+        if class.is_some() {
+            dirs.pop();
+        }
+        let packages: Vec<&str> = dirs
+            .iter()
+            .rev()
+            .map_while(|dir| dir.strip_prefix('+'))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+
+        Self {
+            package: (!packages.is_empty()).then(|| packages.join(".")),
+            class,
+            is_private,
+        }
+    }
+}
+
+/// What the project needs to know about a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub kind: FileKind,
+    /// The names of the functions defined in the file, the main one first.
+    pub functions: Vec<String>,
+}
+
+impl SourceFile {
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        let Some(root) = ast::Root::cast(parser::parse(text).syntax()) else {
+            return Self {
+                kind: FileKind::Script,
+                functions: Vec::new(),
+            };
+        };
+        let functions = root
+            .syntax()
+            .descendants()
+            .filter_map(ast::FnDef::cast)
+            .filter_map(|fn_def| Some(fn_def.name()?.text().to_string()))
+            .collect();
+        Self {
+            kind: FileKind::of(&root),
+            functions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        assert_eq!(SourceFile::new("x = 1;").kind, FileKind::Script);
+        assert_eq!(
+            SourceFile::new("1;\nfunction f\nend").kind,
+            FileKind::Script
+        );
+        assert_eq!(
+            SourceFile::new("% help\nfunction f\nend").kind,
+            FileKind::Function
+        );
+        assert_eq!(
+            SourceFile::new("classdef Shape\nend").kind,
+            FileKind::Classdef
+        );
+    }
+
+    #[test]
+    fn functions_start_with_the_main_one() {
+        let file = SourceFile::new("function f\n  g();\n\nfunction g\n  h();\n\nfunction h\n");
+        assert_eq!(file.functions, ["f", "g", "h"]);
+    }
+
+    #[test]
+    fn placements() {
+        assert_eq!(Placement::of(Path::new("/ws/f.m")), Placement::default());
+        assert_eq!(
+            Placement::of(Path::new("/ws/+a/+b/@c/private/f.m")),
+            Placement {
+                package: Some("a.b".to_string()),
+                class: Some("c".to_string()),
+                is_private: true,
+            }
+        );
+        assert_eq!(
+            Placement::of(Path::new("/ws/+a/lib/+b/f.m")).package,
+            Some("b".to_string())
+        );
+    }
+}
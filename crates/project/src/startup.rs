@@ -0,0 +1,166 @@
+//! The directories a startup script adds to the load path.
+//!
+//! We do not run the script, so only `addpath` calls whose arguments can be worked out without
+//! running anything are understood: string literals, `pwd`, `fullfile`, `genpath` and
+//! concatenations of those.
+
+use crate::LoadPathEntry;
+use ast::TypedSyntaxNode;
+use std::path::{MAIN_SEPARATOR_STR, Path};
+
+/// Octave separates the directories of a single `addpath` argument with this.
+const PATH_SEPARATOR: char = if cfg!(windows) { ';' } else { ':' };
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LoadPathChanges {
+    /// The directories that end up before the rest of the load path, in order.
+    pub prepended: Vec<LoadPathEntry>,
+    /// The directories that end up after the rest of the load path, in order.
+    pub appended: Vec<LoadPathEntry>,
+}
+
+/// Follows the `addpath` calls of a startup script. Relative paths are relative to `cwd`.
+#[must_use]
+pub fn load_path_changes(text: &str, cwd: &Path) -> LoadPathChanges {
+    let mut changes = LoadPathChanges::default();
+    let Some(root) = ast::Root::cast(parser::parse(text).syntax()) else {
+        return changes;
+    };
+
+    for call in root.syntax().descendants().filter_map(ast::CallExpr::cast) {
+        if callee(&call).as_deref() != Some("addpath") {
+            continue;
+        }
+
+        let mut entries = Vec::new();
+        let mut append = false;
+        for arg in call.args() {
+            match &arg {
+                ast::Expr::StringLiteral(string) if string.value().eq_ignore_ascii_case("-end") => {
+                    append = true;
+                }
+                ast::Expr::StringLiteral(string)
+                    if string.value().eq_ignore_ascii_case("-begin") =>
+                {
+                    append = false;
+                }
+                ast::Expr::Literal(literal) => append = literal.parse() == Some(1),
+                _ => entries.extend(entries_of(&arg, cwd)),
+            }
+        }
+
+        if append {
+            changes.appended.extend(entries);
+        } else {
+            entries.append(&mut changes.prepended);
+            changes.prepended = entries;
+        }
+    }
+    changes
+}
+
+fn callee(call: &ast::CallExpr) -> Option<String> {
+    match call.func()? {
+        ast::Expr::VariableRef(var) => Some(var.name()?.text().to_string()),
+        _ => None,
+    }
+}
+
+fn entries_of(arg: &ast::Expr, cwd: &Path) -> Vec<LoadPathEntry> {
+    if let ast::Expr::CallExpr(call) = arg
+        && callee(call).as_deref() == Some("genpath")
+    {
+        return call
+            .args()
+            .next()
+            .and_then(|dir| string_value(&dir, cwd))
+            .map(|dir| vec![LoadPathEntry::Tree(cwd.join(dir))])
+            .unwrap_or_default();
+    }
+
+    string_value(arg, cwd)
+        .into_iter()
+        .flat_map(|dirs| {
+            dirs.split(PATH_SEPARATOR)
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| LoadPathEntry::Dir(cwd.join(dir)))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn string_value(expr: &ast::Expr, cwd: &Path) -> Option<String> {
+    match expr {
+        ast::Expr::StringLiteral(string) => Some(string.value()),
+        ast::Expr::ParenExpr(paren) => string_value(&paren.expr()?, cwd),
+        ast::Expr::MatrixExpr(matrix) => matrix
+            .elements()
+            .map(|element| string_value(&element, cwd))
+            .collect(),
+        ast::Expr::VariableRef(var) if var.name()?.text() == "pwd" => {
+            Some(cwd.to_str()?.to_string())
+        }
+        ast::Expr::CallExpr(call) => match callee(call)?.as_str() {
+            "pwd" => Some(cwd.to_str()?.to_string()),
+            "fullfile" => {
+                let parts = call
+                    .args()
+                    .map(|part| string_value(&part, cwd))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(parts.join(MAIN_SEPARATOR_STR))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn dir(path: &str) -> LoadPathEntry {
+        LoadPathEntry::Dir(PathBuf::from(path))
+    }
+
+    #[test]
+    fn later_calls_come_first() {
+        let changes = load_path_changes("addpath('a', 'b');\naddpath('c');", Path::new("/ws"));
+        assert_eq!(
+            changes.prepended,
+            [dir("/ws/c"), dir("/ws/a"), dir("/ws/b")]
+        );
+        assert_eq!(changes.appended, []);
+    }
+
+    #[test]
+    fn appending() {
+        let changes = load_path_changes(
+            "addpath('a', '-end');\naddpath('b', 1);\naddpath('c', '-begin');",
+            Path::new("/ws"),
+        );
+        assert_eq!(changes.prepended, [dir("/ws/c")]);
+        assert_eq!(changes.appended, [dir("/ws/a"), dir("/ws/b")]);
+    }
+
+    #[test]
+    fn computed_paths() {
+        let changes = load_path_changes(
+            "addpath(genpath('src'));\n\
+             addpath(fullfile(pwd, 'lib'));\n\
+             addpath('/opt/x:/opt/y');\n\
+             addpath(some_variable);",
+            Path::new("/ws"),
+        );
+        assert_eq!(
+            changes.prepended,
+            [
+                dir("/opt/x"),
+                dir("/opt/y"),
+                dir("/ws/lib"),
+                LoadPathEntry::Tree(PathBuf::from("/ws/src")),
+            ]
+        );
+    }
+}
@@ -28,6 +28,8 @@ impl rowan::Language for OctaveLanguage {
 }
 
 pub type SyntaxNode = rowan::SyntaxNode<OctaveLanguage>;
+/// A node that outlives its tree, identified by its kind and range.
+pub type SyntaxNodePtr = rowan::ast::SyntaxNodePtr<OctaveLanguage>;
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SyntaxKind {
     LexToken(TokenKind),
@@ -76,6 +78,12 @@ pub enum SyntaxConstruct {
     CallExpr,
     PostfixExpr,
     StringLiteral,
+    /// A handle to a named function, such as `@sin`.
+    FnHandle,
+    /// Access to a field of a struct, such as `s.name`.
+    FieldExpr,
+    /// `end` inside an index, standing for the last index, as in `x(end)`.
+    EndIndex,
     Block,
     FnDef,
     IfStmt,
@@ -83,9 +91,12 @@ pub enum SyntaxConstruct {
     WhileLoop,
     BreakStmt,
     ContinueStmt,
+    ReturnStmt,
     SwitchStmt,
     TryStmt,
     UnwindProtectStmt,
+    /// A `global` or `persistent` declaration.
+    Declaration,
 }
 
 impl From<SyntaxKind> for u16 {
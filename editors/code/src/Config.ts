@@ -2,6 +2,31 @@ import * as vscode from "vscode";
 
 const extensionName = "octave-lsp";
 
+export type LintLevel = "off" | "hint" | "information" | "warning" | "error";
+
+export type EndKeywords = "keep" | "generic" | "specific";
+
+/** The settings the server reads, as sent in `initializationOptions`. */
+export interface ServerSettings {
+    loadPath: string[];
+    startupScript: string | null;
+    lints: Record<string, LintLevel>;
+    format: {
+        indentWidth: number | null;
+        useTabs: boolean | null;
+        indentFunctionBodies: boolean | null;
+        endKeywords: EndKeywords | null;
+    };
+    matlabCompatible: boolean;
+    catalogue: {
+        path: string | null;
+        version: string | null;
+    };
+    outline: {
+        scriptVariables: boolean;
+    };
+}
+
 export default class Config implements vscode.WorkspaceConfiguration{
     private _config: vscode.WorkspaceConfiguration;
 
@@ -13,6 +38,42 @@ export default class Config implements vscode.WorkspaceConfiguration{
         }));
     }
 
+    get loadPath(): string[] {
+        return this.get<string[]>("loadPath", []);
+    }
+    get startupScript(): string | null {
+        return this.get<string | null>("startupScript", null);
+    }
+    get lints(): Record<string, LintLevel> {
+        return this.get<Record<string, LintLevel>>("lints", {});
+    }
+    get matlabCompatible(): boolean {
+        return this.get<boolean>("matlabCompatible", false);
+    }
+
+    /** The settings of the server, which it asks for again when they change. */
+    public serverSettings(): ServerSettings {
+        return {
+            loadPath: this.loadPath,
+            startupScript: this.startupScript,
+            lints: this.lints,
+            format: {
+                indentWidth: this.get<number | null>("format.indentWidth", null),
+                useTabs: this.get<boolean | null>("format.useTabs", null),
+                indentFunctionBodies: this.get<boolean | null>("format.indentFunctionBodies", null),
+                endKeywords: this.get<EndKeywords | null>("format.endKeywords", null),
+            },
+            matlabCompatible: this.matlabCompatible,
+            catalogue: {
+                path: this.get<string | null>("catalogue.path", null),
+                version: this.get<string | null>("catalogue.version", null),
+            },
+            outline: {
+                scriptVariables: this.get<boolean>("outline.scriptVariables", true),
+            },
+        };
+    }
+
     public get<T>(section: string): T | undefined;
     public get<T>(section: string, defaultValue: T): T;
     public get<T>(section: string, defaultValue?: T) {
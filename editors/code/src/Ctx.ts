@@ -48,6 +48,8 @@ export default class Ctx implements vscode.Disposable {
             outputChannelName: "Octave Language Server",
             outputChannel: vscode.window.createOutputChannel("Octave Language Server"),
             revealOutputChannelOn: lang_client.RevealOutputChannelOn.Error,
+            initializationOptions: config.serverSettings(),
+            synchronize: { configurationSection: "octave-lsp" },
         };
 
         const client = new lang_client.LanguageClient(
@@ -9,6 +9,6 @@ fuzz_target!(|data: &[u8]| {
         let syntax = parse.syntax();
         let _validation_errors = ast::validation::validate(&syntax);
         let root = ast::Root::cast(syntax).unwrap();
-        let (_database, _stmts) = hir::lower(&root);
+        let _body = hir::lower(&root);
     }
 });
\ No newline at end of file
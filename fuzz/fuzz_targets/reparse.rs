@@ -0,0 +1,39 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use parser::TextEdit;
+use text_size::{TextRange, TextSize};
+
+// The text to edit comes first, then each edit after a NUL: two characters pick the ends of the
+// range it replaces, and the rest of it is inserted there.
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut parts = s.split('\0');
+    let mut text = parts.next().unwrap_or_default().to_string();
+    let mut old = parser::parse(&text);
+    for part in parts {
+        let mut chars = part.chars();
+        let (Some(a), Some(b)) = (chars.next(), chars.next()) else {
+            continue;
+        };
+        let offset = |c: char| {
+            let offset = c as usize % (text.len() + 1);
+            let offset = (offset..=text.len())
+                .find(|&offset| text.is_char_boundary(offset))
+                .unwrap_or(text.len());
+            TextSize::try_from(offset).unwrap()
+        };
+        let (a, b) = (offset(a), offset(b));
+        let edit = TextEdit {
+            range: TextRange::new(a.min(b), a.max(b)),
+            insert: chars.as_str().to_string(),
+        };
+        edit.apply(&mut text);
+        let new = old.reparse(&edit, &text);
+        let full = parser::parse(&text);
+        assert_eq!(new.debug_tree(), full.debug_tree(), "{edit:?}");
+        assert_eq!(new.errors(), full.errors(), "{edit:?}");
+        old = new;
+    }
+});